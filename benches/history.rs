@@ -0,0 +1,129 @@
+//! Benchmark for the buffers that store the history of predicted and interpolated components
+//!
+//! Compares the bounded [`ReadyBuffer`] now used by the `PredictionHistory` and `ConfirmedHistory` with the
+//! unbounded [`ReadyBuffer`] that was used before (which cloned the value and put it back in the buffer
+//! on every rollback check).
+use divan::Bencher;
+use lightyear::_reexport::ReadyBuffer;
+use lightyear::prelude::Tick;
+
+/// Number of ticks simulated in each benchmark iteration
+const NUM_TICKS: u16 = 1000;
+/// Number of ticks between the client's tick and the tick of the server updates
+const SERVER_DELAY_TICKS: u16 = 8;
+const HISTORY_DEPTH: usize = 128;
+
+/// Number of ticks between two server updates
+const SERVER_UPDATE_INTERVALS: &[u16] = &[1, 4, 16];
+
+fn main() {
+    divan::main();
+}
+
+/// A predicted component (roughly the size of a Transform)
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Component([f32; 12]);
+
+fn component(tick: u16) -> Component {
+    Component([tick as f32; 12])
+}
+
+/// The predicted component is updated every tick, and compared with the server state on every server update
+mod prediction {
+    use super::*;
+
+    /// Previous `PredictionHistory::pop_until_tick`: pop the value, and put a clone back in the buffer
+    fn pop_until_tick(history: &mut ReadyBuffer<Tick, Component>, tick: Tick) -> Option<Component> {
+        history.pop_until(&tick).map(|(tick, value)| {
+            history.add_item(tick, value.clone());
+            value
+        })
+    }
+
+    #[divan::bench(args = SERVER_UPDATE_INTERVALS)]
+    fn unbounded(bencher: Bencher, interval: u16) {
+        bencher.bench_local(|| {
+            let mut history = ReadyBuffer::new();
+            let mut mismatches = 0;
+            for tick in 0..NUM_TICKS {
+                history.add_item(Tick(tick), component(tick));
+                if tick % interval == 0 && tick >= SERVER_DELAY_TICKS {
+                    let server_tick = tick - SERVER_DELAY_TICKS;
+                    if pop_until_tick(&mut history, Tick(server_tick))
+                        != Some(component(server_tick))
+                    {
+                        mismatches += 1;
+                    }
+                }
+            }
+            divan::black_box(mismatches)
+        });
+    }
+
+    #[divan::bench(args = SERVER_UPDATE_INTERVALS)]
+    fn bounded(bencher: Bencher, interval: u16) {
+        bencher.bench_local(|| {
+            let mut history = ReadyBuffer::with_capacity(HISTORY_DEPTH);
+            let mut mismatches = 0;
+            for tick in 0..NUM_TICKS {
+                history.add_item(Tick(tick), component(tick));
+                if tick % interval == 0 && tick >= SERVER_DELAY_TICKS {
+                    let server_tick = tick - SERVER_DELAY_TICKS;
+                    if history
+                        .clear_until(&Tick(server_tick))
+                        .map(|(_, value)| value)
+                        != Some(&component(server_tick))
+                    {
+                        mismatches += 1;
+                    }
+                }
+            }
+            divan::black_box(mismatches)
+        });
+    }
+}
+
+/// The server updates are added to the history, and consumed when the interpolation tick reaches them
+mod interpolation {
+    use super::*;
+
+    /// Same steps as `update_interpolate_status`: the start is the most recent update before the interpolation tick,
+    /// and the end is the next update, which is only popped when it replaces the current end
+    fn interpolate(mut history: ReadyBuffer<Tick, Component>, interval: u16) {
+        let mut start: Option<(Tick, Component)> = None;
+        let mut end: Option<(Tick, Component)> = None;
+        for tick in 0..NUM_TICKS {
+            if tick % interval == 0 {
+                history.add_item(Tick(tick), component(tick));
+            }
+            let interpolation_tick = Tick(tick.saturating_sub(SERVER_DELAY_TICKS));
+            if end
+                .as_ref()
+                .map_or(false, |(end_tick, _)| interpolation_tick >= *end_tick)
+            {
+                start = end.take();
+            }
+            if let Some((new_tick, value)) = history.pop_until(&interpolation_tick) {
+                if start.as_ref().map_or(true, |(tick, _)| *tick <= new_tick) {
+                    start = Some((new_tick, value));
+                }
+            }
+            if let Some(next) = history.heap.peek() {
+                if end.as_ref().map_or(true, |(tick, _)| next.key < *tick) {
+                    end = history.heap.pop().map(|item| (item.key, item.item));
+                }
+            }
+            divan::black_box((&start, &end));
+        }
+    }
+
+    #[divan::bench(args = SERVER_UPDATE_INTERVALS)]
+    fn unbounded(bencher: Bencher, interval: u16) {
+        bencher.bench_local(|| interpolate(ReadyBuffer::new(), interval));
+    }
+
+    #[divan::bench(args = SERVER_UPDATE_INTERVALS)]
+    fn bounded(bencher: Bencher, interval: u16) {
+        bencher.bench_local(|| interpolate(ReadyBuffer::with_capacity(HISTORY_DEPTH), interval));
+    }
+}
@@ -33,6 +33,7 @@ impl ClientPluginGroup {
             interpolation: InterpolationConfig {
                 delay: InterpolationDelay::default().with_send_interval_ratio(2.0),
                 custom_interpolation_logic: false,
+                ..default()
             },
             ..default()
         };
@@ -28,6 +28,7 @@ impl ClientPluginGroup {
                 delay: InterpolationDelay::default().with_send_interval_ratio(2.0),
                 // do not do linear interpolation per component, instead we provide our own interpolation logic
                 custom_interpolation_logic: true,
+                ..default()
             },
             ..default()
         };
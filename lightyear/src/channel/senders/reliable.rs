@@ -3,7 +3,7 @@ use std::collections::VecDeque;
 use std::collections::{BTreeMap, HashSet};
 
 use bytes::Bytes;
-use crossbeam_channel::Receiver;
+use crossbeam_channel::{Receiver, Sender};
 use tracing::{info, trace};
 
 use crate::channel::builder::ReliableSettings;
@@ -59,6 +59,9 @@ pub struct ReliableSender {
     /// Used to split a message into fragments if the message is too big
     fragment_sender: FragmentSender,
 
+    /// Get notified whenever a message (all of its fragments) has been acked by the remote
+    ack_senders: Vec<Sender<MessageId>>,
+
     current_rtt: Duration,
     current_time: WrappedTime,
 }
@@ -73,10 +76,27 @@ impl ReliableSender {
             fragmented_messages_to_send: Default::default(),
             message_ids_to_send: Default::default(),
             fragment_sender: FragmentSender::new(),
+            ack_senders: Vec::new(),
             current_rtt: Duration::default(),
             current_time: WrappedTime::default(),
         }
     }
+
+    /// Stop sending (and re-sending) all the messages that haven't been acked yet.
+    ///
+    /// This is only valid for unordered channels, where the receiver does not wait for the missing messages.
+    pub(crate) fn clear_unacked_messages(&mut self) {
+        self.unacked_messages.clear();
+        self.single_messages_to_send.clear();
+        self.fragmented_messages_to_send.clear();
+        self.message_ids_to_send.clear();
+    }
+
+    fn notify_ack(&self, message_id: MessageId) {
+        for sender in &self.ack_senders {
+            sender.send(message_id).unwrap();
+        }
+    }
 }
 
 // Stragegy:
@@ -243,6 +263,7 @@ impl ChannelSend for ReliableSender {
                         )
                     }
                     self.unacked_messages.remove(&message_ack.message_id);
+                    self.notify_ack(message_ack.message_id);
                 }
                 UnackedMessage::Fragmented(fragment_acks) => {
                     let Some(fragment_id) = message_ack.fragment_id else {
@@ -254,6 +275,7 @@ impl ChannelSend for ReliableSender {
                         // all fragments were acked
                         if fragment_acks.iter().all(|f| f.acked) {
                             self.unacked_messages.remove(&message_ack.message_id);
+                            self.notify_ack(message_ack.message_id);
                         }
                     }
                 }
@@ -265,8 +287,11 @@ impl ChannelSend for ReliableSender {
         !self.single_messages_to_send.is_empty() || !self.fragmented_messages_to_send.is_empty()
     }
 
+    /// Create a new receiver that will receive a message id when a message is acked
     fn subscribe_acks(&mut self) -> Receiver<MessageId> {
-        todo!()
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        self.ack_senders.push(sender);
+        receiver
     }
 }
 
@@ -289,6 +314,7 @@ mod tests {
         });
         sender.current_rtt = Duration::from_millis(100);
         sender.current_time = WrappedTime::new(0);
+        let ack_receiver = sender.subscribe_acks();
 
         // Buffer a new message
         let message1 = Bytes::from("hello");
@@ -319,10 +345,25 @@ mod tests {
             fragment_id: None,
         });
         assert_eq!(sender.unacked_messages.len(), 0);
+        assert_eq!(ack_receiver.try_recv(), Ok(MessageId(0)));
+        // duplicate acks are not notified again
+        sender.notify_message_delivered(&MessageAck {
+            message_id: MessageId(0),
+            fragment_id: None,
+        });
+        assert!(ack_receiver.try_recv().is_err());
 
         // Advance by a time that is above the resend threshold
         sender.current_time += Duration::from_millis(200);
         // this time there are no new messages to send
         assert_eq!(sender.single_messages_to_send.len(), 1);
+
+        // Messages that are cleared are not re-sent
+        sender.buffer_send(Bytes::from("world"), 1.0);
+        sender.clear_unacked_messages();
+        sender.current_time += Duration::from_millis(200);
+        sender.collect_messages_to_send();
+        assert!(!sender.has_messages_to_send());
+        assert_eq!(sender.next_send_message_id, MessageId(2));
     }
 }
@@ -5,6 +5,7 @@ use std::fmt::Debug;
 
 use bevy::prelude::{Component, Entity};
 
+use crate::client::prediction::correction::CorrectionConfig;
 use crate::prelude::{Message, Tick};
 
 /// Marks an entity that directly applies the replication updates from the remote
@@ -42,6 +43,28 @@ pub trait SyncMetadata<C> {
     type Corrector: LerpFn<C> + 'static;
 
     fn mode() -> ComponentSyncMode;
+
+    /// Returns true if the mismatch between the value of the component that was predicted for a tick
+    /// and the confirmed value received from the server for that tick should trigger a rollback.
+    ///
+    /// By default, any difference triggers a rollback. Use the `should_rollback` sync attribute of the
+    /// component protocol to provide a custom comparison (for example with a tolerance for floating-point values):
+    /// `#[sync(full, should_rollback = "my_fn")]`, where `my_fn` is a `fn(&C, &C) -> bool`
+    fn should_rollback(predicted: &C, confirmed: &C) -> bool
+    where
+        C: PartialEq,
+    {
+        predicted != confirmed
+    }
+
+    /// How the visual correction is applied to the component after a rollback
+    /// (only if the component has a corrector other than `InstantCorrector`).
+    ///
+    /// Use the `correction` sync attribute of the component protocol to provide a custom configuration:
+    /// `#[sync(full, corrector = "InterpolatedCorrector", correction = "my_fn")]`, where `my_fn` is a `fn() -> CorrectionConfig<C>`
+    fn correction() -> CorrectionConfig<C> {
+        CorrectionConfig::default()
+    }
 }
 
 #[derive(Debug, Default, PartialEq)]
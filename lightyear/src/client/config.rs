@@ -1,5 +1,6 @@
 //! Defines client-specific configuration options
 use bevy::prelude::Resource;
+use bevy::utils::Duration;
 use governor::Quota;
 use nonzero_ext::nonzero;
 
@@ -8,6 +9,7 @@ use crate::client::interpolation::plugin::InterpolationConfig;
 use crate::client::prediction::plugin::PredictionConfig;
 use crate::client::sync::SyncConfig;
 use crate::connection::client::NetConfig;
+use crate::packet::compression::CompressionConfig;
 use crate::shared::config::SharedConfig;
 use crate::shared::ping::manager::PingConfig;
 
@@ -20,6 +22,8 @@ pub struct NetcodeConfig {
     /// A negative value means no timeout.
     /// This is used for Authentication::Manual tokens
     pub client_timeout_secs: i32,
+    /// Hash of the [`Protocol`](crate::protocol::Protocol), set by the [`ClientPlugin`](crate::client::plugin::ClientPlugin)
+    pub(crate) protocol_hash: u64,
 }
 
 impl Default for NetcodeConfig {
@@ -28,6 +32,7 @@ impl Default for NetcodeConfig {
             num_disconnect_packets: 10,
             keepalive_packet_send_rate: 1.0 / 10.0,
             client_timeout_secs: 3,
+            protocol_hash: 0,
         }
     }
 }
@@ -37,6 +42,7 @@ impl NetcodeConfig {
         crate::connection::netcode::ClientConfig::default()
             .num_disconnect_packets(self.num_disconnect_packets)
             .packet_send_rate(self.keepalive_packet_send_rate)
+            .protocol_hash(self.protocol_hash)
     }
 }
 
@@ -46,6 +52,9 @@ pub struct PacketConfig {
     pub send_bandwidth_cap: Quota,
     /// If false, there is no bandwidth cap and all messages are sent as soon as possible
     pub bandwidth_cap_enabled: bool,
+    /// Compression of the packets, applied before they are sent through the transport.
+    /// The client and the server must use the same compression (it is part of the protocol hash).
+    pub compression: CompressionConfig,
 }
 
 impl Default for PacketConfig {
@@ -54,6 +63,7 @@ impl Default for PacketConfig {
             // 56 KB/s bandwidth cap
             send_bandwidth_cap: Quota::per_second(nonzero!(56000u32)),
             bandwidth_cap_enabled: false,
+            compression: CompressionConfig::default(),
         }
     }
 }
@@ -74,6 +84,80 @@ impl PacketConfig {
         self.bandwidth_cap_enabled = true;
         self
     }
+
+    pub fn with_compression(mut self, compression: CompressionConfig) -> Self {
+        self.compression = compression;
+        self
+    }
+}
+
+/// Policy used to reconnect automatically when the connection to the server is lost.
+///
+/// Each attempt creates a new connection from [`ClientConfig::net`], so a new connect token is generated
+/// from the [`Authentication`](crate::connection::client::Authentication). You can update the `Authentication`
+/// in the [`ClientConfig`] resource (for example with a newly-fetched token) before the next attempt.
+///
+/// The local state of the client (replicated entities, prediction, etc.) is kept while reconnecting.
+/// If the server keeps the client's session during its disconnect grace period, the client will only receive
+/// the updates that it missed.
+#[derive(Clone, Debug)]
+pub struct ReconnectConfig {
+    /// Maximum number of reconnection attempts. 0 disables automatic reconnection
+    pub max_attempts: u32,
+    /// Delay before the first reconnection attempt
+    pub initial_delay: Duration,
+    /// The delay is multiplied by this factor after each failed attempt
+    pub backoff_factor: f32,
+    /// Maximum delay between two attempts.
+    /// An attempt that is not successful by the time of the next attempt is abandoned.
+    pub max_delay: Duration,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 0,
+            initial_delay: Duration::from_millis(500),
+            backoff_factor: 2.0,
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+impl ReconnectConfig {
+    /// Set the maximum number of reconnection attempts after the connection is lost.
+    ///
+    /// The client never reconnects automatically if it disconnected voluntarily, or if the server
+    /// kicked it or denied the connection.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Set the delay before the first reconnection attempt
+    pub fn with_initial_delay(mut self, initial_delay: Duration) -> Self {
+        self.initial_delay = initial_delay;
+        self
+    }
+
+    /// Set the factor by which the delay is multiplied after each failed attempt
+    pub fn with_backoff_factor(mut self, backoff_factor: f32) -> Self {
+        self.backoff_factor = backoff_factor;
+        self
+    }
+
+    /// Set the maximum delay between two attempts
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Delay to wait after the given number of failed attempts
+    pub(crate) fn delay(&self, attempts: u32) -> Duration {
+        self.initial_delay
+            .mul_f32(self.backoff_factor.powi(attempts as i32))
+            .min(self.max_delay)
+    }
 }
 
 /// The configuration object that lets you create a `ClientPlugin` with the desired settings.
@@ -97,4 +181,5 @@ pub struct ClientConfig {
     pub sync: SyncConfig,
     pub prediction: PredictionConfig,
     pub interpolation: InterpolationConfig,
+    pub reconnect: ReconnectConfig,
 }
@@ -6,9 +6,9 @@ use bevy::prelude::{Entity, Resource, World};
 use bevy::reflect::Reflect;
 use bevy::utils::Duration;
 use serde::Serialize;
-use tracing::{debug, trace, trace_span};
+use tracing::{debug, trace, trace_span, warn};
 
-use crate::_reexport::{EntityUpdatesChannel, PingChannel, ReplicationSend};
+use crate::_reexport::{EntityActionsChannel, EntityUpdatesChannel, PingChannel, ReplicationSend};
 use crate::channel::senders::ChannelSend;
 use crate::client::config::PacketConfig;
 use crate::client::message::ClientMessage;
@@ -26,8 +26,8 @@ use crate::serialize::reader::ReadBuffer;
 use crate::server::message::ServerMessage;
 use crate::shared::events::connection::ConnectionEvents;
 use crate::shared::ping::manager::{PingConfig, PingManager};
-use crate::shared::ping::message::SyncMessage;
-use crate::shared::replication::components::{Replicate, ReplicationGroupId};
+use crate::shared::ping::message::{InterpolationTime, SyncMessage};
+use crate::shared::replication::components::{Replicate, ReplicateCache, ReplicationGroupId};
 use crate::shared::replication::receive::ReplicationReceiver;
 use crate::shared::replication::send::ReplicationSender;
 use crate::shared::replication::ReplicationMessage;
@@ -69,6 +69,10 @@ pub struct ConnectionManager<P: Protocol> {
     pub(crate) input_buffer: InputBuffer<P::Input>,
     pub(crate) sync_manager: SyncManager,
     // TODO: maybe don't do any replication until connection is synced?
+    /// True if this is the local client of a host-server (see [`Mode::HostServer`](crate::prelude::Mode::HostServer))
+    pub(crate) is_local: bool,
+    /// Messages of the local client of a host-server, that are handed to the server without being serialized
+    pub(crate) local_messages: Vec<(ChannelKind, ClientMessage<P>)>,
 }
 
 impl<P: Protocol> ConnectionManager<P> {
@@ -80,7 +84,9 @@ impl<P: Protocol> ConnectionManager<P> {
         input_delay_ticks: u16,
     ) -> Self {
         // create the message manager and the channels
+        let compression = packet_config.compression.clone();
         let mut message_manager = MessageManager::new(channel_registry, packet_config.into());
+        message_manager.set_compression(compression);
         // get the acks-tracker for entity updates
         let update_acks_tracker = message_manager
             .channels
@@ -88,11 +94,21 @@ impl<P: Protocol> ConnectionManager<P> {
             .unwrap()
             .sender
             .subscribe_acks();
+        // get the acks-tracker for entity actions
+        let actions_acks_tracker = message_manager
+            .channels
+            .get_mut(&ChannelKind::of::<EntityActionsChannel>())
+            .unwrap()
+            .sender
+            .subscribe_acks();
         // get a channel to get notified when a replication update message gets actually send (to update priority)
         let replication_update_send_receiver =
             message_manager.get_replication_update_send_receiver();
-        let replication_sender =
-            ReplicationSender::new(update_acks_tracker, replication_update_send_receiver);
+        let replication_sender = ReplicationSender::new(
+            update_acks_tracker,
+            actions_acks_tracker,
+            replication_update_send_receiver,
+        );
         let replication_receiver = ReplicationReceiver::new();
         Self {
             message_manager,
@@ -102,13 +118,17 @@ impl<P: Protocol> ConnectionManager<P> {
             input_buffer: InputBuffer::default(),
             sync_manager: SyncManager::new(sync_config, input_delay_ticks),
             events: ConnectionEvents::default(),
+            is_local: false,
+            local_messages: vec![],
         }
     }
 
     #[doc(hidden)]
     /// Whether or not the connection is synced with the server
+    ///
+    /// The local client of a host-server shares the tick of the server, so it is always synced.
     pub fn is_synced(&self) -> bool {
-        self.sync_manager.is_synced()
+        self.is_local || self.sync_manager.is_synced()
     }
 
     pub(crate) fn received_new_server_tick(&self) -> bool {
@@ -186,6 +206,10 @@ impl<P: Protocol> ConnectionManager<P> {
             .to_string();
         let message = ClientMessage::<P>::Message(message, target);
         message.emit_send_logs(&channel_name);
+        if self.is_local {
+            self.local_messages.push((channel, message));
+            return Ok(());
+        }
         self.message_manager.buffer_send(message, channel)?;
         Ok(())
     }
@@ -203,6 +227,13 @@ impl<P: Protocol> ConnectionManager<P> {
         //     // self.replication_sender.pending_unique_components.clear();
         //     return Ok(());
         // }
+        // ask the server to send the full value of the delta-compressed components that we could not reconstruct
+        for reset in self.replication_receiver.take_delta_resets() {
+            let message = ClientMessage::<P>::DeltaReset(reset);
+            message.emit_send_logs("EntityUpdatesChannel");
+            self.message_manager
+                .buffer_send(message, ChannelKind::of::<EntityUpdatesChannel>())?;
+        }
         self.replication_sender
             .finalize(tick)
             .into_iter()
@@ -229,8 +260,10 @@ impl<P: Protocol> ConnectionManager<P> {
                 // keep track of the group associated with the message, so we can handle receiving an ACK for that message_id later
                 if should_track_ack {
                     self.replication_sender
-                        .updates_message_id_to_group_id
-                        .insert(message_id, (group_id, bevy_tick));
+                        .track_update_message(message_id, group_id, bevy_tick, tick);
+                } else {
+                    self.replication_sender
+                        .track_actions_message(message_id, group_id);
                 }
                 Ok(())
             })
@@ -272,6 +305,18 @@ impl<P: Protocol> ConnectionManager<P> {
                     self.message_manager.buffer_send(message, channel)?;
                     Ok::<(), anyhow::Error>(())
                 })?;
+
+            // send the interpolation time to the server, so that it can perform lag compensation
+            if self.sync_manager.is_synced() {
+                let interpolation_time = InterpolationTime {
+                    tick: self.sync_manager.interpolation_tick(tick_manager),
+                    overstep: self.sync_manager.interpolation_overstep(tick_manager),
+                };
+                let message =
+                    ClientMessage::<P>::Sync(SyncMessage::InterpolationTime(interpolation_time));
+                let channel = ChannelKind::of::<PingChannel>();
+                self.message_manager.buffer_send(message, channel)?;
+            }
         }
         let payloads = self.message_manager.send_packets(tick_manager.tick());
 
@@ -310,6 +355,9 @@ impl<P: Protocol> ConnectionManager<P> {
                             // buffer the replication message
                             self.replication_receiver.recv_message(replication, tick);
                         }
+                        ServerMessage::DeltaReset(reset) => {
+                            self.replication_sender.recv_delta_reset(reset);
+                        }
                         ServerMessage::Sync(ref sync) => {
                             match sync {
                                 SyncMessage::Ping(ping) => {
@@ -335,6 +383,8 @@ impl<P: Protocol> ConnectionManager<P> {
                                         time = ?pong.pong_sent_time,
                                         "Updated server pong generation")
                                 }
+                                // the server does not send its interpolation time
+                                SyncMessage::InterpolationTime(_) => {}
                             }
                         }
                     }
@@ -366,6 +416,19 @@ impl<P: Protocol> ConnectionManager<P> {
             }
         }
 
+        // the server took back the authority over these entities, and already stopped using our version of them:
+        // removing their `Replicate` component must not be replicated
+        for (entity, _) in self
+            .events
+            .authority_changes
+            .iter()
+            .filter(|(_, has_authority)| !has_authority)
+        {
+            self.replication_sender
+                .replicate_component_cache
+                .remove(entity);
+        }
+
         // TODO: do i really need this? I could just create events in this function directly?
         //  why do i need to make events a field of the connection?
         //  is it because of push_connection?
@@ -435,6 +498,10 @@ impl<P: Protocol> ReplicationSend<P> for ConnectionManager<P> {
         //     .or_default()
         //     .update_collect_changes_since_this_tick(system_current_tick);
         replication_sender.prepare_entity_spawn(entity, group_id);
+        // if we were given authority over this entity, the server should re-use its existing entity
+        if let Some(remote_entity) = self.replication_receiver.authority_entities.get(&entity) {
+            replication_sender.prepare_authority_spawn(entity, group_id, *remote_entity);
+        }
 
         // also set the priority for the group when we spawn it
         self.update_priority(
@@ -450,12 +517,23 @@ impl<P: Protocol> ReplicationSend<P> for ConnectionManager<P> {
     fn prepare_entity_despawn(
         &mut self,
         entity: Entity,
-        replicate: &Replicate<P>,
+        group_id: ReplicationGroupId,
         target: NetworkTarget,
         system_current_tick: BevyTick,
     ) -> Result<()> {
         // trace!(?entity, "Send entity despawn for tick {:?}", self.tick());
-        let group_id = replicate.replication_group.group_id(Some(entity));
+        // the server owns the entities we have authority over: it only loses our updates
+        if self
+            .replication_receiver
+            .authority_entities
+            .contains_key(&entity)
+        {
+            warn!(
+                ?entity,
+                "Despawned an entity that we have authority over: the despawn is not replicated"
+            );
+            return Ok(());
+        }
         let replication_sender = &mut self.replication_sender;
         // update the collect changes tick
         // replication_sender
@@ -468,6 +546,30 @@ impl<P: Protocol> ReplicationSend<P> for ConnectionManager<P> {
         Ok(())
     }
 
+    fn prepare_entity_detach(
+        &mut self,
+        entity: Entity,
+        group_id: ReplicationGroupId,
+        target: NetworkTarget,
+        system_current_tick: BevyTick,
+    ) -> Result<()> {
+        // the authority over the entity is controlled by the server
+        if self
+            .replication_receiver
+            .authority_entities
+            .contains_key(&entity)
+        {
+            warn!(
+                ?entity,
+                "Removed Replicate from an entity that we have authority over: the removal is not replicated"
+            );
+            return Ok(());
+        }
+        self.replication_sender
+            .prepare_entity_detach(entity, group_id);
+        Ok(())
+    }
+
     fn prepare_component_insert(
         &mut self,
         entity: Entity,
@@ -560,7 +662,7 @@ impl<P: Protocol> ReplicationSend<P> for ConnectionManager<P> {
         let _span = trace_span!("buffer_replication_messages").entered();
         self.buffer_replication_messages(tick, bevy_tick)
     }
-    fn get_mut_replicate_component_cache(&mut self) -> &mut EntityHashMap<Replicate<P>> {
+    fn get_mut_replicate_component_cache(&mut self) -> &mut EntityHashMap<ReplicateCache> {
         &mut self.replication_sender.replicate_component_cache
     }
     fn cleanup(&mut self, tick: Tick) {
@@ -2,6 +2,7 @@ use bevy::app::{App, Plugin, PostUpdate};
 use bevy::diagnostic::Diagnostics;
 use bevy::prelude::{Real, Res, ResMut, Time};
 
+use crate::client::connection::ConnectionManager;
 use crate::connection::client::{ClientConnection, NetClient};
 use crate::prelude::Protocol;
 use crate::transport::io::IoDiagnosticsPlugin;
@@ -18,18 +19,22 @@ impl<P> Default for ClientDiagnosticsPlugin<P> {
     }
 }
 
-fn io_diagnostics_system(
+fn io_diagnostics_system<P: Protocol>(
     mut netclient: ResMut<ClientConnection>,
+    mut connection: ResMut<ConnectionManager<P>>,
     time: Res<Time<Real>>,
     mut diagnostics: Diagnostics,
 ) {
     if let Some(io) = netclient.io_mut() {
+        if let Some(stats) = connection.message_manager.compression_stats_mut() {
+            io.stats.compression = std::mem::take(stats);
+        }
         IoDiagnosticsPlugin::update_diagnostics(&mut io.stats, &time, &mut diagnostics);
     }
 }
 impl<P: Protocol> Plugin for ClientDiagnosticsPlugin<P> {
     fn build(&self, app: &mut App) {
         app.add_plugins(IoDiagnosticsPlugin);
-        app.add_systems(PostUpdate, io_diagnostics_system);
+        app.add_systems(PostUpdate, io_diagnostics_system::<P>);
     }
 }
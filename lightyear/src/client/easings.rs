@@ -1,8 +1,9 @@
-pub(crate) fn ease_out_quad(x: f32) -> f32 {
+//! Easing functions that can be used for the visual correction (see [`CorrectionConfig`](crate::client::prediction::correction::CorrectionConfig))
+pub fn ease_out_quad(x: f32) -> f32 {
     1.0 - (1.0 - x) * (1.0 - x)
 }
 
-pub(crate) fn ease_out_expo(x: f32) -> f32 {
+pub fn ease_out_expo(x: f32) -> f32 {
     if x >= 0.99 {
         1.0
     } else {
@@ -10,10 +11,10 @@ pub(crate) fn ease_out_expo(x: f32) -> f32 {
     }
 }
 
-pub(crate) fn ease_out_cubic(x: f32) -> f32 {
+pub fn ease_out_cubic(x: f32) -> f32 {
     1.0 - (1.0 - x).powf(3.0)
 }
 
-pub(crate) fn ease_out_quart(x: f32) -> f32 {
+pub fn ease_out_quart(x: f32) -> f32 {
     1.0 - (1.0 - x).powf(4.0)
 }
@@ -56,11 +56,21 @@ pub type InputEvent<I> = crate::shared::events::components::InputEvent<I, ()>;
 pub type EntitySpawnEvent = crate::shared::events::components::EntitySpawnEvent<()>;
 /// Bevy [`Event`](bevy::prelude::Event) emitted on the client when a EntityDespawn replication message is received
 pub type EntityDespawnEvent = crate::shared::events::components::EntityDespawnEvent<()>;
+/// Bevy [`Event`](bevy::prelude::Event) emitted on the client when the server stops replicating an entity without despawning it
+pub type EntityDetachEvent = crate::shared::events::components::EntityDetachEvent<()>;
+/// Bevy [`Event`](bevy::prelude::Event) emitted on the client when the client gains or loses the authority over an entity
+pub type AuthorityChangeEvent = crate::shared::events::components::AuthorityChangeEvent<()>;
 /// Bevy [`Event`](bevy::prelude::Event) emitted on the client when a ComponentUpdate replication message is received
 pub type ComponentUpdateEvent<C> = crate::shared::events::components::ComponentUpdateEvent<C, ()>;
 /// Bevy [`Event`](bevy::prelude::Event) emitted on the client when a ComponentInsert replication message is received
 pub type ComponentInsertEvent<C> = crate::shared::events::components::ComponentInsertEvent<C, ()>;
 /// Bevy [`Event`](bevy::prelude::Event) emitted on the client when a ComponentRemove replication message is received
 pub type ComponentRemoveEvent<C> = crate::shared::events::components::ComponentRemoveEvent<C, ()>;
+/// Bevy [`Event`](bevy::prelude::Event) emitted on the client when a replicated resource is inserted
+pub type ResourceInsertEvent<R> = crate::shared::events::components::ResourceInsertEvent<R, ()>;
+/// Bevy [`Event`](bevy::prelude::Event) emitted on the client when a replicated resource is updated
+pub type ResourceUpdateEvent<R> = crate::shared::events::components::ResourceUpdateEvent<R, ()>;
+/// Bevy [`Event`](bevy::prelude::Event) emitted on the client when a replicated resource is removed
+pub type ResourceRemoveEvent<R> = crate::shared::events::components::ResourceRemoveEvent<R, ()>;
 /// Bevy [`Event`](bevy::prelude::Event) emitted on the client when a (non-replication) message is received
 pub type MessageEvent<M> = crate::shared::events::components::MessageEvent<M, ()>;
@@ -0,0 +1,193 @@
+//! Host-server mode: the server and a local client run in the same [`App`]
+//! (see [`Mode::HostServer`](crate::prelude::Mode::HostServer)).
+//!
+//! The local client is a regular [`ClientId`](crate::prelude::ClientId) from the server's point of view,
+//! but it doesn't use a transport:
+//! - the messages are handed directly between the client and the server [`ConnectionManager`]s, without being serialized
+//! - the inputs are written directly in the server's input buffer, on the tick where they are generated
+//! - the local client is never a target of replication; it shares the server's entities instead.
+//!   Entities that would be predicted or interpolated by the local client get the [`Predicted`] or [`Interpolated`]
+//!   marker component, so that the same systems can run on the host and on the remote clients.
+//!
+//! The local client cannot be kicked with [`ServerConnections::kick`](crate::prelude::server::ServerConnections::kick);
+//! disconnect it with [`ClientConnection::disconnect`](crate::prelude::client::ClientConnection) instead.
+use bevy::prelude::*;
+use tracing::debug;
+
+use crate::client::connection::ConnectionManager;
+use crate::client::events::{ConnectEvent, DisconnectEvent};
+use crate::client::input::InputSystemSet;
+use crate::client::interpolation::Interpolated;
+use crate::client::metadata::GlobalMetadata;
+use crate::client::prediction::Predicted;
+use crate::connection::client::{ClientConnection, NetClient};
+use crate::connection::server::ServerConnections;
+use crate::connection::DisconnectReason;
+use crate::prelude::{MainSet, ReplicationSet, TickManager, TimeManager};
+use crate::protocol::message::MessageProtocol;
+use crate::protocol::Protocol;
+use crate::server;
+use crate::server::room::RoomManager;
+use crate::server::visibility::VisibilityManager;
+use crate::shared::events::connection::ConnectionEvents;
+use crate::shared::replication::components::Replicate;
+
+pub(crate) struct HostServerPlugin<P: Protocol> {
+    _marker: std::marker::PhantomData<P>,
+}
+
+impl<P: Protocol> Default for HostServerPlugin<P> {
+    fn default() -> Self {
+        Self {
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<P: Protocol> Plugin for HostServerPlugin<P> {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PreUpdate,
+            // the server must receive the messages of the local client in the same frame
+            exchange_local_messages::<P>.before(MainSet::Receive),
+        )
+        .add_systems(
+            FixedPreUpdate,
+            write_local_inputs::<P>
+                .after(InputSystemSet::BufferInputs)
+                .before(server::input::InputSystemSet::WriteInputEvents),
+        )
+        .add_systems(
+            PostUpdate,
+            add_local_markers::<P>.before(ReplicationSet::All),
+        );
+    }
+}
+
+/// Handle the connection of the local client, and exchange the messages between the local client and the server
+fn exchange_local_messages<P: Protocol>(world: &mut World, mut was_connected: Local<bool>) {
+    let netclient = world.resource::<ClientConnection>();
+    let client_id = netclient.id();
+    let connected = netclient.is_connected();
+    let reason = netclient
+        .disconnect_reason()
+        .unwrap_or(DisconnectReason::ConnectionClosed);
+
+    // CONNECTION EVENTS
+    if connected && !*was_connected {
+        debug!(?client_id, "Local client connected");
+        world
+            .resource_mut::<ServerConnections>()
+            .global_id_map
+            .reserve(client_id);
+        world
+            .resource_mut::<server::connection::ConnectionManager<P>>()
+            .add_local_client(client_id);
+        world.resource_mut::<GlobalMetadata>().client_id = Some(client_id);
+        world
+            .resource_mut::<Events<ConnectEvent>>()
+            .send(ConnectEvent::new(()));
+    }
+    if !connected && *was_connected {
+        debug!(?client_id, ?reason, "Local client disconnected");
+        world
+            .resource_mut::<server::connection::ConnectionManager<P>>()
+            .remove(client_id, reason);
+        world
+            .resource_mut::<RoomManager>()
+            .client_disconnect(client_id);
+        world
+            .resource_mut::<VisibilityManager>()
+            .client_disconnect(client_id);
+        world
+            .resource_mut::<ServerConnections>()
+            .global_id_map
+            .release(client_id);
+        world.resource_mut::<GlobalMetadata>().client_id = None;
+        world
+            .resource_mut::<ConnectionManager<P>>()
+            .local_messages
+            .clear();
+        world
+            .resource_mut::<Events<DisconnectEvent>>()
+            .send(DisconnectEvent::new((), reason));
+    }
+    *was_connected = connected;
+    if !connected {
+        return;
+    }
+
+    // CLIENT -> SERVER
+    let client_messages =
+        std::mem::take(&mut world.resource_mut::<ConnectionManager<P>>().local_messages);
+    let server_messages = world.resource_scope(
+        |world: &mut World, mut server: Mut<server::connection::ConnectionManager<P>>| {
+            let tick = world.resource::<TickManager>().tick();
+            let time_manager = world.resource::<TimeManager>();
+            let Ok(connection) = server.connection_mut(client_id) else {
+                return vec![];
+            };
+            for (channel_kind, message) in client_messages {
+                connection.receive_message(channel_kind, tick, message, time_manager);
+            }
+            std::mem::take(&mut connection.local_messages)
+        },
+    );
+
+    // SERVER -> CLIENT
+    if !server_messages.is_empty() {
+        let mut events = ConnectionEvents::<P>::new();
+        for (channel_kind, message) in server_messages {
+            events.push_message(channel_kind, message);
+        }
+        P::Message::push_message_events(world, &mut events);
+    }
+}
+
+/// Write the inputs of the local client in the server's input buffer, for the current tick
+fn write_local_inputs<P: Protocol>(
+    tick_manager: Res<TickManager>,
+    client: Res<ConnectionManager<P>>,
+    mut server: ResMut<server::connection::ConnectionManager<P>>,
+) {
+    let Some(client_id) = server.local_client else {
+        return;
+    };
+    let tick = tick_manager.tick();
+    if let Ok(connection) = server.connection_mut(client_id) {
+        connection.input_buffer.set(tick, client.get_input(tick));
+    }
+}
+
+/// Add the [`Predicted`] or [`Interpolated`] markers on the entities that the local client would predict
+/// or interpolate. The entities are not duplicated: the markers point to the entity itself.
+fn add_local_markers<P: Protocol>(
+    mut commands: Commands,
+    metadata: Res<GlobalMetadata>,
+    query: Query<(Entity, Ref<Replicate<P>>)>,
+) {
+    let Some(client_id) = metadata.client_id else {
+        return;
+    };
+    // when the local client connects, we also need to check the entities that already exist
+    for (entity, replicate) in query
+        .iter()
+        .filter(|(_, replicate)| metadata.is_changed() || replicate.is_changed())
+    {
+        let mut entity_commands = commands.entity(entity);
+        if replicate.prediction_target.should_send_to(&client_id) {
+            entity_commands.insert(Predicted {
+                confirmed_entity: Some(entity),
+            });
+        } else {
+            entity_commands.remove::<Predicted>();
+        }
+        if replicate.interpolation_target.should_send_to(&client_id) {
+            entity_commands.insert(Interpolated {
+                confirmed_entity: entity,
+            });
+        } else {
+            entity_commands.remove::<Interpolated>();
+        }
+    }
+}
@@ -221,6 +221,12 @@ fn prepare_input_message<P: Protocol>(
     tick_manager: Res<TickManager>,
 ) {
     let current_tick = tick_manager.tick();
+    // the inputs of the local client of a host-server are written directly in the server's input buffer
+    // (see `HostServerPlugin`), we only need to delete the old input values
+    if connection.is_local {
+        connection.input_buffer.pop(current_tick);
+        return;
+    }
     // TODO: the number of messages should be in SharedConfig
     trace!(tick = ?current_tick, "prepare_input_message");
     // TODO: instead of 15, send ticks up to the latest yet ACK-ed input tick
@@ -306,6 +306,7 @@ pub(crate) fn interpolate<C: Component + Clone, P: Protocol>(
 //             incoming_latency: Duration::from_millis(40),
 //             incoming_jitter: Duration::from_millis(5),
 //             incoming_loss: 0.05,
+//             ..Default::default()
 //         };
 //         let sync_config = SyncConfig::default().speedup_factor(1.0);
 //         let prediction_config = PredictionConfig::default().disable(true);
@@ -7,8 +7,10 @@ use tracing::{debug, trace};
 
 use crate::client::components::{ComponentSyncMode, SyncComponent};
 use crate::client::components::{Confirmed, SyncMetadata};
+use crate::client::config::ClientConfig;
 use crate::client::connection::ConnectionManager;
 use crate::client::interpolation::interpolate::InterpolateStatus;
+use crate::client::interpolation::plugin::InterpolationConfig;
 use crate::client::interpolation::resource::InterpolationManager;
 use crate::client::interpolation::Interpolated;
 use crate::prelude::TickManager;
@@ -19,14 +21,9 @@ use crate::utils::ready_buffer::ReadyBuffer;
 /// To know if we need to do rollback, we need to compare the interpolated entity's history with the server's state updates
 #[derive(Component, Debug)]
 pub struct ConfirmedHistory<T: SyncComponent> {
-    // TODO: here we can use a sequence buffer. We won't store more than a couple
-
-    // TODO: add a max size for the buffer
-    // We want to avoid using a SequenceBuffer for optimization (we don't want to store a copy of the component for each history tick)
-    // We can afford to use a ReadyBuffer because we will get server updates with monotically increasing ticks
-    // therefore we can get rid of the old ticks before the server update
-
-    // We will only store the history for the ticks where the component got updated
+    // We only store the history for the ticks where the component got updated.
+    // The updates are consumed as the interpolation tick advances; the buffer is bounded in case they are not
+    // (for example if the interpolation logic is custom).
     pub buffer: ReadyBuffer<Tick, T>,
 }
 
@@ -45,14 +42,19 @@ impl<T: SyncComponent> PartialEq for ConfirmedHistory<T> {
 
 impl<T: SyncComponent> ConfirmedHistory<T> {
     pub fn new() -> Self {
+        Self::with_capacity(InterpolationConfig::default().history_depth as usize)
+    }
+
+    /// Create an empty history that stores at most `capacity` updates
+    pub fn with_capacity(capacity: usize) -> Self {
         Self {
-            buffer: ReadyBuffer::new(),
+            buffer: ReadyBuffer::with_capacity(capacity),
         }
     }
 
     /// Reset the history for this component
     pub(crate) fn clear(&mut self) {
-        self.buffer = ReadyBuffer::new();
+        self.buffer.clear();
     }
 
     pub(crate) fn peek(&mut self) -> Option<(Tick, &T)> {
@@ -79,6 +81,7 @@ impl<T: SyncComponent> ConfirmedHistory<T> {
 pub(crate) fn add_component_history<C: SyncComponent, P: Protocol>(
     // TODO: unfortunately we need this to be mutable because of the MapEntities trait even though it's not actually needed...
     mut manager: ResMut<InterpolationManager>,
+    config: Res<ClientConfig>,
     tick_manager: Res<TickManager>,
     mut commands: Commands,
     connection: Res<ConnectionManager<P>>,
@@ -101,7 +104,9 @@ pub(crate) fn add_component_history<C: SyncComponent, P: Protocol>(
                     let mut interpolated_entity_mut =
                         commands.get_entity(interpolated_entity).unwrap();
                     // insert history
-                    let history = ConfirmedHistory::<C>::new();
+                    let history = ConfirmedHistory::<C>::with_capacity(
+                        config.interpolation.history_depth as usize,
+                    );
                     // map any entities from confirmed to interpolated
                     let mut new_component = confirmed_component.deref().clone();
                     new_component.map_entities(&mut manager.interpolated_entity_map);
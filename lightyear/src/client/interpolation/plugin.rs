@@ -66,6 +66,9 @@ pub struct InterpolationConfig {
     /// If true, disable the interpolation logic (but still keep the internal component history buffers)
     /// The user will have to manually implement
     pub custom_interpolation_logic: bool,
+    /// Maximum number of server updates stored in the [`ConfirmedHistory`](super::ConfirmedHistory) of each interpolated component.
+    /// The updates are consumed as the interpolation tick advances, so this only needs to cover the interpolation delay
+    pub history_depth: u16,
     // How long are we keeping the history of the confirmed entities so we can interpolate between them?
     // pub(crate) interpolation_buffer_size: Duration,
 }
@@ -76,6 +79,7 @@ impl Default for InterpolationConfig {
         Self {
             delay: InterpolationDelay::default(),
             custom_interpolation_logic: false,
+            history_depth: 32,
             // interpolation_buffer_size: Duration::from_millis(100),
         }
     }
@@ -86,6 +90,12 @@ impl InterpolationConfig {
         self.delay = delay;
         self
     }
+
+    /// Update the maximum number of server updates stored in the confirmed history of each component
+    pub fn with_history_depth(mut self, history_depth: u16) -> Self {
+        self.history_depth = history_depth;
+        self
+    }
 }
 
 pub struct InterpolationPlugin<P: Protocol> {
@@ -9,6 +9,7 @@ use crate::_reexport::{BitSerializable, MessageProtocol, ReadBuffer, WriteBuffer
 use crate::prelude::{ChannelKind, NetworkTarget};
 use crate::protocol::Protocol;
 use crate::shared::ping::message::SyncMessage;
+use crate::shared::replication::delta::DeltaReset;
 use crate::shared::replication::{ReplicationMessage, ReplicationMessageData};
 
 pub(crate) struct MessageMetadata {
@@ -29,6 +30,9 @@ pub enum ClientMessage<P: Protocol> {
     // the reason why we include sync here instead of doing another MessageManager is so that
     // the sync messages can be added to packets that have other messages
     Sync(SyncMessage),
+    #[bitcode_hint(frequency = 1)]
+    #[bitcode(with_serde)]
+    DeltaReset(DeltaReset<P::ComponentKinds>),
 }
 
 impl<P: Protocol> BitSerializable for ClientMessage<P> {
@@ -142,7 +146,13 @@ impl<P: Protocol> ClientMessage<P> {
                     #[cfg(metrics)]
                     metrics::counter!("send_pong", "channel" => channel_name).increment(1);
                 }
+                SyncMessage::InterpolationTime(_) => {
+                    trace!(channel = ?channel_name, "Sending interpolation time");
+                }
             },
+            ClientMessage::DeltaReset(reset) => {
+                trace!(channel = ?channel_name, group_id = ?reset.group_id, components = ?reset.components, "Sending delta reset");
+            }
         }
     }
 }
@@ -20,7 +20,8 @@ pub mod prediction;
 pub mod sync;
 
 mod diagnostics;
-mod easings;
+pub mod easings;
+pub(crate) mod host_server;
 #[cfg_attr(docsrs, doc(cfg(feature = "leafwing")))]
 #[cfg(feature = "leafwing")]
 pub mod input_leafwing;
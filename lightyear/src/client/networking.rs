@@ -4,22 +4,30 @@ use std::ops::DerefMut;
 use bevy::ecs::system::SystemChangeTick;
 use bevy::prelude::ResMut;
 use bevy::prelude::*;
+use bevy::utils::Duration;
 #[cfg(feature = "xpbd_2d")]
 use bevy_xpbd_2d::prelude::PhysicsTime;
-use tracing::{error, trace};
+use tracing::{debug, error, info, trace, warn};
 
 use crate::_reexport::ReplicationSend;
 use crate::client::config::ClientConfig;
 use crate::client::connection::ConnectionManager;
-use crate::client::events::{EntityDespawnEvent, EntitySpawnEvent};
+use crate::client::events::{
+    AuthorityChangeEvent, ConnectEvent, DisconnectEvent, EntityDespawnEvent, EntityDetachEvent,
+    EntitySpawnEvent,
+};
 use crate::connection::client::{ClientConnection, NetClient};
+use crate::connection::DisconnectReason;
 use crate::prelude::{MainSet, TickManager, TimeManager};
 use crate::protocol::component::ComponentProtocol;
 use crate::protocol::message::MessageProtocol;
 use crate::protocol::Protocol;
-use crate::shared::events::connection::{IterEntityDespawnEvent, IterEntitySpawnEvent};
+use crate::shared::events::connection::{
+    IterEntityDespawnEvent, IterEntityDetachEvent, IterEntitySpawnEvent,
+};
 use crate::shared::tick_manager::TickEvent;
 use crate::shared::time_manager::is_ready_to_send;
+use crate::transport::PacketSender;
 
 pub(crate) struct ClientNetworkingPlugin<P: Protocol> {
     marker: std::marker::PhantomData<P>,
@@ -48,10 +56,13 @@ impl<P: Protocol> Plugin for ClientNetworkingPlugin<P> {
                 ),
             )
             // SYSTEMS
+            // RESOURCES
+            .init_resource::<ReconnectState>()
             .add_systems(
                 PreUpdate,
                 (
                     receive::<P>.in_set(MainSet::Receive),
+                    reconnect.after(MainSet::Receive),
                     apply_deferred.in_set(MainSet::ReceiveFlush),
                 ),
             )
@@ -66,7 +77,14 @@ impl<P: Protocol> Plugin for ClientNetworkingPlugin<P> {
     }
 }
 
-pub(crate) fn receive<P: Protocol>(world: &mut World) {
+/// Connection state observed during the previous [`receive`], used to emit the connection events
+#[derive(Default)]
+pub(crate) struct ConnectionStatus {
+    connected: bool,
+    disconnect_reason: Option<DisconnectReason>,
+}
+
+pub(crate) fn receive<P: Protocol>(world: &mut World, mut status: Local<ConnectionStatus>) {
     trace!("Receive server packets");
     // TODO: here we can control time elapsed from the client's perspective?
 
@@ -95,6 +113,26 @@ pub(crate) fn receive<P: Protocol>(world: &mut World) {
                                                 error!("Error updating netcode: {}", e);
                                             });
 
+                                        // CONNECTION EVENTS
+                                        let new_status = ConnectionStatus {
+                                            connected: netcode.is_connected(),
+                                            disconnect_reason: netcode.disconnect_reason(),
+                                        };
+                                        if !status.connected && new_status.connected {
+                                            debug!("Client connected event");
+                                            world.resource_mut::<Events<ConnectEvent>>().send(ConnectEvent::new(()));
+                                        }
+                                        // the connection was closed, or the connection attempt failed
+                                        let disconnect_reason = new_status
+                                            .disconnect_reason
+                                            .filter(|_| status.disconnect_reason.is_none())
+                                            .or((status.connected && !new_status.connected).then_some(DisconnectReason::ConnectionClosed));
+                                        if let Some(reason) = disconnect_reason {
+                                            debug!(?reason, "Client disconnected event");
+                                            world.resource_mut::<Events<DisconnectEvent>>().send(DisconnectEvent::new((), reason));
+                                        }
+                                        *status = new_status;
+
                                         // only start the connection (sending messages, sending pings, starting sync, etc.)
                                         // once we are connected
                                         if netcode.is_connected() {
@@ -106,9 +144,10 @@ pub(crate) fn receive<P: Protocol>(world: &mut World) {
 
                                         // RECV PACKETS: buffer packets into message managers
                                         while let Some(packet) = netcode.recv() {
-                                            connection
-                                                .recv_packet(packet, tick_manager.as_ref())
-                                                .unwrap();
+                                            // a packet that can't be read (for example because it can't be decompressed) is dropped
+                                            if let Err(e) = connection.recv_packet(packet, tick_manager.as_ref()) {
+                                                error!("could not recv packet: {:?}", e);
+                                            }
                                         }
 
                                         // RECEIVE: receive packets from message managers
@@ -121,21 +160,6 @@ pub(crate) fn receive<P: Protocol>(world: &mut World) {
                                         // TODO: run these in EventsPlugin!
                                         // HANDLE EVENTS
                                         if !events.is_empty() {
-                                            // NOTE: maybe no need to send those events, because the client knows when it's connected/disconnected?
-                                            // if events.has_connection() {
-                                            //     let mut connect_event_writer =
-                                            //         world.get_resource_mut::<Events<ConnectEvent>>().unwrap();
-                                            //     debug!("Client connected event");
-                                            //     connect_event_writer.send(ConnectEvent::new(()));
-                                            // }
-                                            //
-                                            // if events.has_disconnection() {
-                                            //     let mut disconnect_event_writer =
-                                            //         world.get_resource_mut::<Events<DisconnectEvent>>().unwrap();
-                                            //     debug!("Client disconnected event");
-                                            //     disconnect_event_writer.send(DisconnectEvent::new(()));
-                                            // }
-
                                             // Message Events
                                             P::Message::push_message_events(world, &mut events);
 
@@ -160,6 +184,30 @@ pub(crate) fn receive<P: Protocol>(world: &mut World) {
                                                         .send(EntityDespawnEvent::new(entity, ()));
                                                 }
                                             }
+                                            // AuthorityChange event
+                                            if !events.authority_changes.is_empty() {
+                                                let mut authority_event_writer = world
+                                                    .get_resource_mut::<Events<AuthorityChangeEvent>>()
+                                                    .unwrap();
+                                                for (entity, has_authority) in
+                                                    std::mem::take(&mut events.authority_changes)
+                                                {
+                                                    authority_event_writer.send(
+                                                        AuthorityChangeEvent::new(entity, has_authority, ()),
+                                                    );
+                                                }
+                                            }
+                                            // DetachEntity event
+                                            if events.has_entity_detach() {
+                                                let mut entity_detach_event_writer = world
+                                                    .get_resource_mut::<Events<EntityDetachEvent>>()
+                                                    .unwrap();
+                                                for (entity, _) in events.drain_entity_detach()
+                                                {
+                                                    entity_detach_event_writer
+                                                        .send(EntityDetachEvent::new(entity, ()));
+                                                }
+                                            }
 
                                             // Update component events (updates, inserts, removes)
                                             P::Components::push_component_events(
@@ -179,6 +227,67 @@ pub(crate) fn receive<P: Protocol>(world: &mut World) {
     );
 }
 
+/// State of the automatic reconnection to the server
+#[derive(Resource, Default, Debug)]
+pub(crate) struct ReconnectState {
+    /// True if the client was connected during the previous frame
+    was_connected: bool,
+    /// Number of reconnection attempts since the connection was lost
+    attempts: u32,
+    /// Time remaining before the next reconnection attempt
+    next_attempt: Option<Duration>,
+}
+
+/// Reconnect to the server when the connection is lost, according to the [`ReconnectConfig`](crate::client::config::ReconnectConfig)
+pub(crate) fn reconnect(
+    config: Res<ClientConfig>,
+    time: Res<Time<Real>>,
+    mut netclient: ResMut<ClientConnection>,
+    mut state: ResMut<ReconnectState>,
+) {
+    if netclient.is_connected() {
+        if state.attempts > 0 {
+            info!(attempts = state.attempts, "Reconnected to the server");
+        }
+        *state = ReconnectState {
+            was_connected: true,
+            ..default()
+        };
+        return;
+    }
+    // the server refused the connection or kicked the client, or the client disconnected voluntarily
+    if matches!(netclient.disconnect_reason(), Some(reason) if reason.is_final()) {
+        state.next_attempt = None;
+        return;
+    }
+    if std::mem::take(&mut state.was_connected) && config.reconnect.max_attempts > 0 {
+        info!("Connection to the server lost, reconnecting");
+        state.next_attempt = Some(config.reconnect.delay(0));
+    }
+    let Some(remaining) = state.next_attempt.as_mut() else {
+        return;
+    };
+    *remaining = remaining.saturating_sub(time.delta());
+    if !remaining.is_zero() {
+        return;
+    }
+    if state.attempts >= config.reconnect.max_attempts {
+        warn!(
+            attempts = state.attempts,
+            "Could not reconnect to the server"
+        );
+        state.next_attempt = None;
+        return;
+    }
+    state.attempts += 1;
+    // build a new connection so that a new connect token is generated
+    *netclient = config.net.clone().build_client();
+    if let Err(e) = netclient.connect() {
+        error!("Error reconnecting to the server: {}", e);
+    }
+    state.next_attempt = Some(config.reconnect.delay(state.attempts));
+}
+
 pub(crate) fn send<P: Protocol>(
     mut netcode: ResMut<ClientConnection>,
     system_change_tick: SystemChangeTick,
@@ -202,6 +311,12 @@ pub(crate) fn send<P: Protocol>(
             error!("Error sending packet: {}", e);
         });
     }
+    // send the packets that were buffered by the io (for example by the link conditioner)
+    if let Some(io) = netcode.io_mut() {
+        let _ = io.flush().map_err(|e| {
+            error!("Error flushing io: {}", e);
+        });
+    }
 
     // no need to clear the connection, because we already std::mem::take it
     // client.connection.clear();
@@ -7,15 +7,17 @@ use bevy::prelude::*;
 use crate::client::connection::ConnectionManager;
 use crate::client::diagnostics::ClientDiagnosticsPlugin;
 use crate::client::events::ClientEventsPlugin;
+use crate::client::host_server::HostServerPlugin;
 use crate::client::input::InputPlugin;
 use crate::client::interpolation::plugin::InterpolationPlugin;
-use crate::client::metadata::MetadataPlugin;
+use crate::client::metadata::{GlobalMetadata, MetadataPlugin};
 use crate::client::networking::ClientNetworkingPlugin;
 use crate::client::prediction::plugin::PredictionPlugin;
 use crate::client::replication::ClientReplicationPlugin;
 use crate::protocol::component::ComponentProtocol;
 use crate::protocol::message::MessageProtocol;
 use crate::protocol::Protocol;
+use crate::shared::config::Mode;
 use crate::shared::events::connection::ConnectionEvents;
 use crate::shared::events::plugin::EventsPlugin;
 use crate::shared::plugin::SharedPlugin;
@@ -55,23 +57,45 @@ impl<P: Protocol> ClientPlugin<P> {
 //  before the plugin is ready
 impl<P: Protocol> Plugin for ClientPlugin<P> {
     fn build(&self, app: &mut App) {
-        let config = self.config.lock().unwrap().deref_mut().take().unwrap();
+        let mut config = self.config.lock().unwrap().deref_mut().take().unwrap();
+        // the server will refuse the connection if the client uses a different protocol (or compression)
+        let protocol_hash = config
+            .client_config
+            .packet
+            .compression
+            .protocol_hash(config.protocol.protocol_hash());
+        config.client_config.net.set_protocol_hash(protocol_hash);
 
         let netclient = config.client_config.net.clone().build_client();
         let tick_duration = config.client_config.shared.tick.tick_duration;
+        let mode = config.client_config.shared.mode;
+        app.insert_resource(config.client_config.clone());
+        let mut connection_manager = ConnectionManager::<P>::new(
+            config.protocol.channel_registry(),
+            config.client_config.packet,
+            config.client_config.sync,
+            config.client_config.ping,
+            config.client_config.prediction.input_delay_ticks,
+        );
+
+        if mode == Mode::HostServer {
+            // the local client shares the time, the ticks and the entities of the server, so we only
+            // add the plugins that handle the inputs and the events of the client
+            connection_manager.is_local = true;
+            app.insert_resource(netclient)
+                .insert_resource(connection_manager)
+                .init_resource::<GlobalMetadata>()
+                .add_plugins(ClientEventsPlugin::<P>::default())
+                .add_plugins(InputPlugin::<P>::default())
+                .add_plugins(HostServerPlugin::<P>::default());
+            return;
+        }
 
         app
             // RESOURCES //
-            .insert_resource(config.client_config.clone())
             // TODO: move these into the Networking/Replication plugins
             .insert_resource(netclient)
-            .insert_resource(ConnectionManager::<P>::new(
-                config.protocol.channel_registry(),
-                config.client_config.packet,
-                config.client_config.sync,
-                config.client_config.ping,
-                config.client_config.prediction.input_delay_ticks,
-            ))
+            .insert_resource(connection_manager)
             // PLUGINS //
             .add_plugins(SharedPlugin::<P> {
                 config: config.client_config.shared.clone(),
@@ -1,5 +1,9 @@
 //! This module provides the ability to smooth the rollback (from the Predicted state to the Corrected state) over a certain amount of ticks, instead
 //! of just snapping back instantly to the Corrected state
+//!
+//! The correction can be configured for each component with the [`CorrectionConfig`] returned by
+//! [`SyncMetadata::correction`]: use the `correction` sync attribute of the component protocol
+//! (`#[sync(full, corrector = "InterpolatedCorrector", correction = "my_fn")]`, where `my_fn` is a `fn() -> CorrectionConfig<C>`)
 
 // maybe multiple correction_modes:
 // - instant (default)
@@ -12,6 +16,7 @@ use tracing::debug;
 use crate::_reexport::ComponentProtocol;
 use crate::client::components::{LerpFn, SyncComponent, SyncMetadata};
 use crate::client::easings::ease_out_quad;
+use crate::client::prediction::plugin::PredictionConfig;
 use crate::prelude::{Tick, TickManager};
 use crate::protocol::Protocol;
 
@@ -61,6 +66,103 @@ pub struct InterpolatedCorrector;
 //     // }
 // }
 
+/// How the visual value is computed during the correction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CorrectionMode {
+    /// Interpolate between the original prediction and the corrected state
+    /// (with the corrector of the component, and the easing function of the [`CorrectionConfig`])
+    Interpolate,
+    /// Apply the initial error (the difference between the original prediction and the corrected state) as a visual
+    /// offset on top of the corrected state, and decay it exponentially: the offset is halved every `half_life_ticks`.
+    /// Contrary to [`CorrectionMode::Interpolate`], the entity keeps moving at the speed of the corrected state during the correction.
+    ///
+    /// The corrector of the component must be linear (for example the [`LinearInterpolator`](crate::client::interpolation::LinearInterpolator)),
+    /// because the offset is extrapolated with it. The remaining offset is dropped at the end of the correction.
+    /// If `half_life_ticks` is not positive, the correction is applied instantly.
+    ErrorOffset { half_life_ticks: f32 },
+}
+
+/// Configuration of the visual correction of a component after a rollback
+pub struct CorrectionConfig<C> {
+    /// Easing function applied to the progress of the correction (in [0.0, 1.0]) in [`CorrectionMode::Interpolate`]
+    pub easing: fn(f32) -> f32,
+    /// Maximum number of ticks over which the correction is applied.
+    /// The number of ticks is otherwise [`PredictionConfig::correction_ticks_factor`] times the number of rolled back ticks
+    pub max_ticks: Option<u16>,
+    /// Function that computes the distance between the original prediction and the corrected state, and the distance
+    /// above which the correction is applied instantly (for example when the entity teleports)
+    pub snap_threshold: Option<(fn(&C, &C) -> f32, f32)>,
+    pub mode: CorrectionMode,
+}
+
+impl<C> Default for CorrectionConfig<C> {
+    fn default() -> Self {
+        Self {
+            easing: ease_out_quad,
+            max_ticks: None,
+            snap_threshold: None,
+            mode: CorrectionMode::Interpolate,
+        }
+    }
+}
+
+impl<C> Clone for CorrectionConfig<C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C> Copy for CorrectionConfig<C> {}
+
+impl<C> CorrectionConfig<C> {
+    pub fn with_easing(mut self, easing: fn(f32) -> f32) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    pub fn with_max_ticks(mut self, max_ticks: u16) -> Self {
+        self.max_ticks = Some(max_ticks);
+        self
+    }
+
+    /// Apply the correction instantly if `distance(original_prediction, corrected) > threshold`
+    pub fn with_snap_threshold(mut self, distance: fn(&C, &C) -> f32, threshold: f32) -> Self {
+        self.snap_threshold = Some((distance, threshold));
+        self
+    }
+
+    pub fn with_mode(mut self, mode: CorrectionMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Number of ticks over which the correction is applied, after rolling back `rollback_ticks` ticks.
+    /// (0 means that the correction is instant)
+    pub(crate) fn correction_ticks(
+        &self,
+        rollback_ticks: i16,
+        prediction: &PredictionConfig,
+    ) -> i16 {
+        let correction_ticks =
+            (rollback_ticks as f32 * prediction.correction_ticks_factor).round() as i16;
+        self.max_ticks.map_or(correction_ticks, |max_ticks| {
+            correction_ticks.min(max_ticks as i16)
+        })
+    }
+
+    fn should_snap(&self, original_prediction: &C, corrected: &C) -> bool {
+        // without a positive half-life, the offset decays instantly
+        if let CorrectionMode::ErrorOffset { half_life_ticks } = self.mode {
+            if half_life_ticks <= 0.0 || half_life_ticks.is_nan() {
+                return true;
+            }
+        }
+        self.snap_threshold.map_or(false, |(distance, threshold)| {
+            distance(original_prediction, corrected) > threshold
+        })
+    }
+}
+
 #[derive(Component, Debug)]
 pub struct Correction<C: Component> {
     /// This is what the original predicted value was before any correction was applied
@@ -78,6 +180,30 @@ pub struct Correction<C: Component> {
     /// (interpolated between the original prediction and the final correction)
     /// and the final correction value
     pub current_correction: Option<C>,
+
+    /// This is the corrected value when the visual correction started. It is used to compute the initial error
+    /// in [`CorrectionMode::ErrorOffset`]
+    pub original_correction: Option<C>,
+}
+
+/// Visual value for [`CorrectionMode::ErrorOffset`]: `corrected + remaining * (original_prediction - original_correction)`
+///
+/// We only have access to the lerp function of the component, so we compute it with:
+/// - `offset = lerp(original_correction, original_prediction, remaining) = original_correction + remaining * error`
+/// - `mid = lerp(corrected, offset, 0.5) = (corrected + offset) / 2`
+/// - `lerp(original_correction, mid, 2.0) = 2 * mid - original_correction = corrected + remaining * error`
+fn error_offset<C, P: Protocol>(
+    original_prediction: &C,
+    original_correction: &C,
+    corrected: &C,
+    remaining: f32,
+) -> C
+where
+    P::Components: SyncMetadata<C>,
+{
+    let offset = P::Components::correct(original_correction, original_prediction, remaining);
+    let mid = P::Components::correct(corrected, &offset, 0.5);
+    P::Components::correct(original_correction, &mid, 2.0)
 }
 
 /// Visually update the component to the a value that is interpolated between the original prediction
@@ -89,12 +215,26 @@ pub(crate) fn get_visually_corrected_state<C: SyncComponent, P: Protocol>(
 ) where
     P::Components: SyncMetadata<C>,
 {
+    let config = P::Components::correction();
     for (entity, mut component, mut correction) in query.iter_mut() {
+        // the correction just started (or restarted), check the error between the prediction and the corrected state
+        if correction.current_visual.is_none()
+            && config.should_snap(&correction.original_prediction, component.as_ref())
+        {
+            debug!(
+                ?entity,
+                "Error is above the snap threshold. Removing Correction for: {:?}",
+                component.name()
+            );
+            commands.entity(entity).remove::<Correction<C>>();
+            continue;
+        }
         let current_tick = tick_manager.tick();
-        let mut t = (current_tick - correction.original_tick) as f32
-            / (correction.final_correction_tick - correction.original_tick) as f32;
+        let elapsed_ticks = (current_tick - correction.original_tick) as f32;
+        let mut t =
+            elapsed_ticks / (correction.final_correction_tick - correction.original_tick) as f32;
         t = t.clamp(0.0, 1.0);
-        let t = ease_out_quad(t);
+        let t = (config.easing)(t);
         if t == 1.0 || &correction.original_prediction == component.as_ref() {
             debug!(
                 ?t,
@@ -109,8 +249,24 @@ pub(crate) fn get_visually_corrected_state<C: SyncComponent, P: Protocol>(
             correction.current_correction = Some(component.clone());
             // TODO: avoid all these clones
             // visually update the component
-            let visual =
-                P::Components::correct(&correction.original_prediction, component.as_ref(), t);
+            let visual = match config.mode {
+                CorrectionMode::Interpolate => {
+                    P::Components::correct(&correction.original_prediction, component.as_ref(), t)
+                }
+                CorrectionMode::ErrorOffset { half_life_ticks } => {
+                    let correction = correction.as_mut();
+                    let original_correction = correction
+                        .original_correction
+                        .get_or_insert_with(|| component.clone());
+                    let remaining = 0.5_f32.powf(elapsed_ticks / half_life_ticks);
+                    error_offset::<C, P>(
+                        &correction.original_prediction,
+                        original_correction,
+                        component.as_ref(),
+                        remaining,
+                    )
+                }
+            };
             // store the current visual value
             correction.current_visual = Some(visual.clone());
             // set the component value to the visual value
@@ -140,3 +296,91 @@ pub(crate) fn restore_corrected_state<C: SyncComponent>(
 // - we compute the final_correction_tick = current_tick + correction_ticks
 // - during rollback, the Predicted entity will take the Corrected position.
 // - in PostUpdate, during the correction_ticks, we will interpolated between the old
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::prelude::*;
+    use bevy::utils::Duration;
+
+    use crate::prelude::client::PredictionConfig;
+    use crate::prelude::{Tick, TickConfig, TickManager};
+    use crate::tests::protocol::*;
+
+    use super::*;
+
+    fn setup(component: Component7, original_prediction: Component7) -> (World, Entity) {
+        let mut world = World::new();
+        world.insert_resource(TickManager::from_config(TickConfig::new(
+            Duration::from_millis(10),
+        )));
+        let entity = world
+            .spawn((
+                component,
+                Correction {
+                    original_prediction,
+                    original_tick: Tick(0),
+                    final_correction_tick: Tick(10),
+                    current_visual: None,
+                    current_correction: None,
+                    original_correction: None,
+                },
+            ))
+            .id();
+        (world, entity)
+    }
+
+    fn step(world: &mut World, ticks: u16) {
+        for _ in 0..ticks {
+            world.resource_mut::<TickManager>().increment_tick();
+        }
+        world.run_system_once(get_visually_corrected_state::<Component7, MyProtocol>);
+    }
+
+    #[test]
+    fn test_correction_ticks() {
+        let prediction = PredictionConfig::default().with_correction_ticks_factor(2.0);
+        let config = CorrectionConfig::<Component7>::default();
+        assert_eq!(config.correction_ticks(5, &prediction), 10);
+        assert_eq!(config.with_max_ticks(4).correction_ticks(5, &prediction), 4);
+    }
+
+    #[test]
+    fn test_error_offset_correction() {
+        let (mut world, entity) = setup(Component7(4.0), Component7(0.0));
+        step(&mut world, 2);
+        // the initial error of -4.0 is halved after 2 ticks
+        assert_eq!(world.get::<Component7>(entity), Some(&Component7(2.0)));
+        assert_eq!(
+            world
+                .get::<Correction<Component7>>(entity)
+                .unwrap()
+                .current_correction,
+            Some(Component7(4.0))
+        );
+
+        // the corrected state keeps moving, the offset keeps decaying
+        *world.get_mut::<Component7>(entity).unwrap() = Component7(5.0);
+        step(&mut world, 2);
+        assert_eq!(world.get::<Component7>(entity), Some(&Component7(4.0)));
+    }
+
+    #[test]
+    fn test_zero_half_life_snaps() {
+        let config = CorrectionConfig::<Component7>::default();
+        assert!(!config.should_snap(&Component7(1.0), &Component7(0.0)));
+        let config = config.with_mode(CorrectionMode::ErrorOffset {
+            half_life_ticks: 0.0,
+        });
+        assert!(config.should_snap(&Component7(1.0), &Component7(0.0)));
+    }
+
+    #[test]
+    fn test_snap_correction() {
+        let (mut world, entity) = setup(Component7(50.0), Component7(0.0));
+        step(&mut world, 2);
+        // the error is above the snap threshold: the corrected state is applied instantly
+        assert_eq!(world.get::<Component7>(entity), Some(&Component7(50.0)));
+        assert!(world.get::<Correction<Component7>>(entity).is_none());
+    }
+}
@@ -2,13 +2,13 @@ use std::marker::PhantomData;
 
 use bevy::ecs::system::{Command, EntityCommands};
 use bevy::prelude::{
-    Commands, Component, Entity, Query, RemovedComponents, ResMut, With, Without, World,
+    Commands, Component, Entity, Query, RemovedComponents, Res, ResMut, With, Without, World,
 };
 use tracing::{debug, error, trace};
 
 use crate::client::components::{ComponentSyncMode, Confirmed, SyncComponent, SyncMetadata};
 use crate::client::prediction::resource::PredictionManager;
-use crate::client::prediction::Predicted;
+use crate::client::prediction::{Predicted, Rollback};
 use crate::prelude::{ShouldBePredicted, TickManager};
 use crate::protocol::Protocol;
 use crate::shared::tick_manager::Tick;
@@ -145,9 +145,14 @@ pub(crate) fn remove_component_for_despawn_predicted<C: SyncComponent, P: Protoc
 /// For those components, we just re-add them from the cache at the start of rollback
 pub(crate) fn restore_components_if_despawn_rolled_back<C: SyncComponent>(
     mut commands: Commands,
+    rollback: Res<Rollback>,
     mut query: Query<(Entity, &mut RemovedCache<C>), Without<C>>,
 ) {
     for (entity, mut cache) in query.iter_mut() {
+        // in selective rollback, the entities that are not re-simulated keep their current state
+        if !rollback.is_rolling_back(entity) {
+            continue;
+        }
         debug!("restoring component after rollback");
         let Some(component) = std::mem::take(&mut cache.0) else {
             debug!("could not find component");
@@ -234,6 +239,7 @@ pub(crate) fn remove_despawn_marker(
 //             incoming_latency: Duration::from_millis(40),
 //             incoming_jitter: Duration::from_millis(5),
 //             incoming_loss: 0.05,
+//             ..Default::default()
 //         };
 //         let sync_config = SyncConfig::default().speedup_factor(1.0);
 //         let prediction_config = PredictionConfig::default().disable(false);
@@ -424,6 +430,7 @@ pub(crate) fn remove_despawn_marker(
 //             incoming_latency: Duration::from_millis(40),
 //             incoming_jitter: Duration::from_millis(5),
 //             incoming_loss: 0.05,
+//             ..Default::default()
 //         };
 //         let sync_config = SyncConfig::default().speedup_factor(1.0);
 //         let prediction_config = PredictionConfig::default().disable(false);
@@ -1,6 +1,7 @@
 //! Handles client-side prediction
 use std::fmt::Debug;
 
+use bevy::ecs::entity::EntityHashSet;
 use bevy::prelude::*;
 use tracing::error;
 
@@ -21,6 +22,7 @@ pub(crate) mod correction;
 mod despawn;
 pub mod plugin;
 pub mod predicted_history;
+pub mod predicted_resource;
 pub mod prespawn;
 pub(crate) mod resource;
 pub(crate) mod rollback;
@@ -38,8 +40,58 @@ pub struct Predicted {
 pub struct Rollback {
     pub state: RollbackState,
     // pub rollback_groups: EntityHashMap<ReplicationGroupId, RollbackState>,
+    /// If true, only the mispredicted entities (and the entities they interact with) are rolled back
+    /// (see [`PredictionConfig::selective_rollback`](plugin::PredictionConfig::selective_rollback))
+    pub(crate) selective: bool,
+    /// Entities that are re-simulated during the current rollback, in selective mode
+    pub(crate) entities: EntityHashSet,
 }
 
+impl Rollback {
+    pub(crate) fn new(selective: bool) -> Self {
+        Self {
+            state: RollbackState::Default,
+            selective,
+            entities: EntityHashSet::default(),
+        }
+    }
+
+    /// Returns true if the entity is re-simulated during the current rollback.
+    ///
+    /// Outside of selective rollback, all entities are re-simulated when we are in rollback.
+    pub fn is_rolling_back(&self, entity: Entity) -> bool {
+        matches!(self.state, RollbackState::ShouldRollback { .. })
+            && (!self.selective || self.entities.contains(&entity))
+    }
+}
+
+/// Entities that interact with this predicted entity (for example because they can collide).
+///
+/// In selective rollback mode, when this entity is rolled back, the entities of the set are rolled back as well
+/// (and the entities that they interact with, etc.).
+/// The interactions are not symmetric: add the component on both entities if they can affect each other.
+#[derive(Component, Debug, Default, Clone, PartialEq)]
+pub struct RollbackInteractions(pub EntityHashSet);
+
+/// Marker component that is added during a selective rollback on the predicted entities that are not re-simulated.
+/// It is removed at the end of the rollback.
+///
+/// The marker is stored in a sparse set, so that adding and removing it doesn't copy the components of the entities
+/// to another table.
+#[derive(Component, Debug)]
+#[component(storage = "SparseSet")]
+pub struct SkipRollback;
+
+/// Query filter to exclude the predicted entities that are not re-simulated during a selective rollback.
+///
+/// Outside of rollback (or if [`PredictionConfig::selective_rollback`](plugin::PredictionConfig::selective_rollback)
+/// is disabled) the filter does not exclude any entity, so it can be added to all the systems that simulate
+/// predicted entities in `FixedUpdate`:
+/// ```rust,ignore
+/// fn movement(mut query: Query<&mut Position, (With<Predicted>, RollbackFilter)>) {}
+/// ```
+pub type RollbackFilter = Without<SkipRollback>;
+
 /// Resource that will track whether we should do rollback or not
 /// (We have this as a resource because if any predicted entity needs to be rolled-back; we should roll back all predicted entities)
 #[derive(Debug, Copy, Clone)]
@@ -31,8 +31,8 @@ use crate::shared::sets::MainSet;
 
 use super::predicted_history::{add_component_history, apply_confirmed_update};
 use super::rollback::{
-    check_rollback, increment_rollback_tick, prepare_rollback, prepare_rollback_prespawn,
-    run_rollback,
+    add_rollback_interactions, check_rollback, increment_rollback_tick, prepare_rollback,
+    prepare_rollback_authority, prepare_rollback_prespawn, run_rollback,
 };
 use super::{
     clean_pre_predicted_entity, handle_pre_prediction, spawn_predicted_entity, ComponentSyncMode,
@@ -40,7 +40,7 @@ use super::{
 };
 
 /// Configuration to specify how the prediction plugin should behave
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy)]
 pub struct PredictionConfig {
     /// If true, we completely disable the prediction plugin
     pub disable: bool,
@@ -57,6 +57,31 @@ pub struct PredictionConfig {
     /// (i.e. if the client is 10 ticks head and correction_ticks is 1.0, then the correction will be done over 10 ticks)
     // Number of ticks it will take to visually update the Predicted state to the new Corrected state
     pub correction_ticks_factor: f32,
+    /// Maximum number of updates stored in the [`PredictionHistory`](super::PredictionHistory) of each predicted component.
+    /// The history only needs to go back to the latest server update; if it is too short, the rollback check
+    /// won't find the predicted value and will always trigger a rollback
+    pub history_depth: u16,
+    /// If true, a misprediction only rolls back the mispredicted entities, and the entities they interact with
+    /// (see [`RollbackInteractions`](super::RollbackInteractions)), instead of all the predicted entities.
+    ///
+    /// The other predicted entities get the [`SkipRollback`](super::SkipRollback) marker during the rollback;
+    /// the systems that run in `FixedUpdate` should exclude them with the [`RollbackFilter`](super::RollbackFilter).
+    /// Pre-spawned entities don't have a confirmed state to compare against, so they are always rolled back.
+    /// Resources can't be rolled back in this mode (see [`PredictedResourcePlugin`](super::predicted_resource::PredictedResourcePlugin)).
+    pub selective_rollback: bool,
+}
+
+impl Default for PredictionConfig {
+    fn default() -> Self {
+        Self {
+            disable: false,
+            always_rollback: false,
+            input_delay_ticks: 0,
+            correction_ticks_factor: 0.0,
+            history_depth: 128,
+            selective_rollback: false,
+        }
+    }
 }
 
 impl PredictionConfig {
@@ -81,6 +106,17 @@ impl PredictionConfig {
         self.correction_ticks_factor = factor;
         self
     }
+
+    pub fn selective_rollback(mut self, selective_rollback: bool) -> Self {
+        self.selective_rollback = selective_rollback;
+        self
+    }
+
+    /// Update the maximum number of updates stored in the prediction history of each component
+    pub fn with_history_depth(mut self, history_depth: u16) -> Self {
+        self.history_depth = history_depth;
+        self
+    }
 }
 
 pub struct PredictionPlugin<P: Protocol> {
@@ -185,7 +221,11 @@ where
                 (
                     // for SyncMode::Full, we need to check if we need to rollback.
                     check_rollback::<C, P>.in_set(PredictionSet::CheckRollback),
-                    (prepare_rollback::<C, P>, prepare_rollback_prespawn::<C, P>)
+                    (
+                        prepare_rollback::<C, P>,
+                        prepare_rollback_prespawn::<C, P>,
+                        prepare_rollback_authority::<C, P>,
+                    )
                         .in_set(PredictionSet::PrepareRollback),
                 ),
             );
@@ -241,9 +281,7 @@ impl<P: Protocol> Plugin for PredictionPlugin<P> {
 
         // RESOURCES
         app.init_resource::<PredictionManager>();
-        app.insert_resource(Rollback {
-            state: RollbackState::Default,
-        });
+        app.insert_resource(Rollback::new(self.config.selective_rollback));
 
         // PreUpdate systems:
         // 1. Receive confirmed entities, add Confirmed and Predicted components
@@ -296,6 +334,10 @@ impl<P: Protocol> Plugin for PredictionPlugin<P> {
                     despawn_confirmed,
                 )
                     .in_set(PredictionSet::SpawnPrediction),
+                add_rollback_interactions
+                    .after(PredictionSet::CheckRollback)
+                    .before(PredictionSet::PrepareRollback)
+                    .run_if(is_in_rollback),
                 run_rollback.in_set(PredictionSet::Rollback),
             ),
         );
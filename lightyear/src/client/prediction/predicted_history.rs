@@ -7,6 +7,8 @@ use bevy::prelude::{
 use tracing::{debug, error};
 
 use crate::client::components::{SyncComponent, SyncMetadata};
+use crate::client::config::ClientConfig;
+use crate::client::prediction::plugin::PredictionConfig;
 use crate::client::prediction::resource::PredictionManager;
 use crate::prelude::{Named, PreSpawnedPlayerObject, ShouldBePredicted, TickManager};
 use crate::protocol::Protocol;
@@ -27,20 +29,15 @@ pub enum ComponentState<T> {
 /// To know if we need to do rollback, we need to compare the predicted entity's history with the server's state updates
 #[derive(Component, Debug)]
 pub struct PredictionHistory<T: PartialEq> {
-    // TODO: add a max size for the buffer
-    // We want to avoid using a SequenceBuffer for optimization (we don't want to store a copy of the component for each history tick)
-    // We can afford to use a ReadyBuffer because we will get server updates with monotically increasing ticks
-    // therefore we can get rid of the old ticks before the server update
-
-    // We will only store the history for the ticks where the component got updated
+    // We only store the history for the ticks where the component got updated.
+    // We will get server updates with monotonically increasing ticks, so we can get rid of the ticks before
+    // the server update. The buffer is bounded in case we don't receive server updates for a while.
     pub buffer: ReadyBuffer<Tick, ComponentState<T>>,
 }
 
 impl<T: PartialEq> Default for PredictionHistory<T> {
     fn default() -> Self {
-        Self {
-            buffer: ReadyBuffer::new(),
-        }
+        Self::with_capacity(PredictionConfig::default().history_depth as usize)
     }
 }
 
@@ -54,26 +51,30 @@ impl<T: SyncComponent> PartialEq for PredictionHistory<T> {
     }
 }
 
+impl<T: PartialEq> PredictionHistory<T> {
+    /// Create an empty history that stores at most `capacity` updates
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buffer: ReadyBuffer::with_capacity(capacity),
+        }
+    }
+}
+
 impl<T: SyncComponent> PredictionHistory<T> {
     /// Reset the history for this component
     pub(crate) fn clear(&mut self) {
-        self.buffer = ReadyBuffer::new();
+        self.buffer.clear();
     }
 
     /// Get the value of the component at the specified tick.
-    /// Clears the history buffer of all ticks older or equal than the specified tick.
-    /// NOTE: Stores the returned value in the provided tick!!!
+    /// Clears the history buffer of all ticks older than the specified tick, but keeps the returned value.
     ///
     /// CAREFUL:
     /// the component history will only contain the ticks where the component got updated, and otherwise
     /// contains gaps. Therefore, we need to always leave a value in the history buffer so that we can
     /// get the values for the future ticks
-    pub(crate) fn pop_until_tick(&mut self, tick: Tick) -> Option<ComponentState<T>> {
-        self.buffer.pop_until(&tick).map(|(tick, state)| {
-            // TODO: this clone is pretty bad and avoidable. Probably switch to a sequence buffer?
-            self.buffer.add_item(tick, state.clone());
-            state
-        })
+    pub(crate) fn pop_until_tick(&mut self, tick: Tick) -> Option<&ComponentState<T>> {
+        self.buffer.clear_until(&tick).map(|(_, state)| state)
     }
 
     // /// Get the value of the component at the specified tick.
@@ -120,6 +121,7 @@ pub(crate) fn add_component_history<C: SyncComponent, P: Protocol>(
     // TODO: unfortunately we need this to be mutable because of the MapEntities trait even though it's not actually needed...
     mut manager: ResMut<PredictionManager>,
     mut commands: Commands,
+    config: Res<ClientConfig>,
     tick_manager: Res<TickManager>,
     predicted_entities: Query<
         (Entity, Option<Ref<C>>),
@@ -134,11 +136,18 @@ pub(crate) fn add_component_history<C: SyncComponent, P: Protocol>(
     P::Components: SyncMetadata<C>,
 {
     let tick = tick_manager.tick();
+    let history_depth = config.prediction.history_depth as usize;
     for (confirmed_entity, confirmed, confirmed_component) in confirmed_entities.iter() {
         if let Some(p) = confirmed.predicted {
             if let Ok((predicted_entity, predicted_component)) = predicted_entities.get(p) {
                 // if component got added on predicted side, add history
-                add_history::<C, P>(tick, predicted_entity, &predicted_component, &mut commands);
+                add_history::<C, P>(
+                    tick,
+                    history_depth,
+                    predicted_entity,
+                    &predicted_component,
+                    &mut commands,
+                );
 
                 // if component got added on confirmed side
                 // - full: sync component and add history
@@ -156,7 +165,8 @@ pub(crate) fn add_component_history<C: SyncComponent, P: Protocol>(
                             ComponentSyncMode::Full => {
                                 // insert history, it will be quickly filled by a rollback (since it starts empty before the current client tick)
                                 // TODO: then there's no need to add the component here, since it's going to get added during rollback anyway
-                                let mut history = PredictionHistory::<C>::default();
+                                let mut history =
+                                    PredictionHistory::<C>::with_capacity(history_depth);
                                 history.buffer.add_item(
                                     tick_manager.tick(),
                                     ComponentState::Updated(confirmed_component.deref().clone()),
@@ -189,6 +199,7 @@ pub(crate) fn add_component_history<C: SyncComponent, P: Protocol>(
 #[allow(clippy::type_complexity)]
 pub fn add_prespawned_component_history<C: SyncComponent, P: Protocol>(
     mut commands: Commands,
+    config: Res<ClientConfig>,
     tick_manager: Res<TickManager>,
     prespawned_query: Query<
         (Entity, Option<Ref<C>>),
@@ -206,6 +217,7 @@ pub fn add_prespawned_component_history<C: SyncComponent, P: Protocol>(
     for (predicted_entity, predicted_component) in prespawned_query.iter() {
         add_history::<C, P>(
             tick_manager.tick(),
+            config.prediction.history_depth as usize,
             predicted_entity,
             &predicted_component,
             &mut commands,
@@ -216,6 +228,7 @@ pub fn add_prespawned_component_history<C: SyncComponent, P: Protocol>(
 /// Add history when a predicted component gets added
 fn add_history<C: SyncComponent, P: Protocol>(
     tick: Tick,
+    history_depth: usize,
     predicted_entity: Entity,
     predicted_component: &Option<Ref<C>>,
     commands: &mut Commands,
@@ -229,7 +242,7 @@ fn add_history<C: SyncComponent, P: Protocol>(
             if predicted_component.is_added() {
                 debug!(?kind, ?tick, ?predicted_entity, "Adding prediction history");
                 // insert history, it will be quickly filled by a rollback (since it starts empty before the current client tick)
-                let mut history = PredictionHistory::<C>::default();
+                let mut history = PredictionHistory::<C>::with_capacity(history_depth);
                 history.buffer.add_item(
                     tick,
                     ComponentState::Updated(predicted_component.deref().clone()),
@@ -0,0 +1,376 @@
+//! Rollback of bevy [`Resource`]s
+//!
+//! Rollback only restores the components that have a [`PredictionHistory`](super::PredictionHistory) on [`Predicted`](super::Predicted)
+//! entities. Global state that is modified by the predicted systems (RNG state, score, physics caches, etc.) would not be rewound,
+//! and the re-simulated ticks would diverge from the original simulation.
+//!
+//! Add the [`PredictedResourcePlugin`] for each resource that participates in prediction:
+//! - the value of the resource is recorded in a [`PredictedResourceHistory`] after every tick, which stores at most
+//!   [`PredictionConfig::history_depth`](super::plugin::PredictionConfig::history_depth) updates
+//! - on rollback, the resource is restored to its value at the rollback tick (or removed if it didn't exist) before the ticks are re-simulated
+//!
+//! ```rust,ignore
+//! #[derive(Resource, Clone, PartialEq)]
+//! struct Score(u32);
+//!
+//! app.add_plugins(PredictedResourcePlugin::<Score, MyProtocol>::default());
+//! ```
+//!
+//! The resource is only rolled back on the client, and only when a rollback is triggered by a predicted component.
+//!
+//! Resources are global, so they can't be rolled back for only some of the entities: the plugin panics if
+//! [`PredictionConfig::selective_rollback`](super::plugin::PredictionConfig::selective_rollback) is enabled.
+use bevy::prelude::*;
+use tracing::{debug, trace};
+
+use crate::client::config::ClientConfig;
+use crate::client::connection::ConnectionManager;
+use crate::client::prediction::plugin::{PredictionConfig, PredictionSet};
+use crate::client::prediction::predicted_history::ComponentState;
+use crate::client::prediction::{Rollback, RollbackState};
+use crate::prelude::{Protocol, Tick, TickManager};
+use crate::utils::ready_buffer::ReadyBuffer;
+
+/// Plugin that registers the resource `R` for rollback (see the [module-level documentation](self))
+///
+/// # Panics
+///
+/// The app panics on startup if [`PredictionConfig::selective_rollback`](super::plugin::PredictionConfig::selective_rollback)
+/// is enabled.
+pub struct PredictedResourcePlugin<R: Resource + Clone + PartialEq, P: Protocol> {
+    _marker: std::marker::PhantomData<(R, P)>,
+}
+
+impl<R: Resource + Clone + PartialEq, P: Protocol> Default for PredictedResourcePlugin<R, P> {
+    fn default() -> Self {
+        Self {
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+/// History of the values of a predicted resource.
+///
+/// We only store the ticks where the resource was updated (or removed)
+#[derive(Resource, Debug)]
+pub struct PredictedResourceHistory<R: PartialEq> {
+    buffer: ReadyBuffer<Tick, ComponentState<R>>,
+}
+
+impl<R: PartialEq> Default for PredictedResourceHistory<R> {
+    fn default() -> Self {
+        Self::with_capacity(PredictionConfig::default().history_depth as usize)
+    }
+}
+
+impl<R: PartialEq> PredictedResourceHistory<R> {
+    /// Create an empty history that stores at most `capacity` updates
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buffer: ReadyBuffer::with_capacity(capacity),
+        }
+    }
+}
+
+impl<R: Clone + PartialEq> PredictedResourceHistory<R> {
+    /// Number of updates stored in the history
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Record the state of the resource at the end of `tick`
+    pub(crate) fn add(&mut self, tick: Tick, state: ComponentState<R>) {
+        // the ticks are recorded in order, but the same tick can be recorded twice
+        self.buffer.drain_after(&tick);
+        self.buffer.add_item(tick, state);
+    }
+
+    /// State of the resource that was last recorded
+    pub(crate) fn latest(&self) -> Option<&ComponentState<R>> {
+        self.buffer
+            .heap
+            .iter()
+            .max_by_key(|item| item.key)
+            .map(|item| &item.item)
+    }
+
+    /// Remove the values that are not needed to get the state of the resource at `tick` or later
+    /// (we keep the most recent value that is older or equal to `tick`)
+    pub(crate) fn clear_until(&mut self, tick: Tick) {
+        self.buffer.clear_until(&tick);
+    }
+
+    /// Get the state of the resource at the end of `tick`, and remove the values that were recorded after `tick`
+    /// (they will be recorded again during the rollback)
+    pub(crate) fn rollback_to(&mut self, tick: Tick) -> Option<ComponentState<R>> {
+        self.buffer.drain_after(&(tick + 1));
+        self.buffer
+            .clear_until(&tick)
+            .map(|(_, state)| state.clone())
+    }
+}
+
+impl<R: Resource + Clone + PartialEq, P: Protocol> Plugin for PredictedResourcePlugin<R, P> {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PredictedResourceHistory<R>>();
+        app.add_systems(Startup, setup_resource_history::<R>);
+        app.add_systems(
+            PreUpdate,
+            (
+                prepare_rollback_resource::<R>.in_set(PredictionSet::PrepareRollback),
+                clear_resource_history::<R, P>
+                    .after(PredictionSet::Rollback)
+                    .run_if(resource_exists::<ConnectionManager<P>>),
+            ),
+        );
+        app.add_systems(
+            FixedPostUpdate,
+            update_resource_history::<R>
+                .in_set(PredictionSet::UpdateHistory)
+                .run_if(resource_exists::<Rollback>),
+        );
+    }
+}
+
+/// Check that the resource can be rolled back, and bound its history with the configured history depth.
+///
+/// This runs on startup so that it doesn't depend on the order in which the plugins are added.
+pub(crate) fn setup_resource_history<R: Resource + Clone + PartialEq>(
+    config: Option<Res<ClientConfig>>,
+    mut history: ResMut<PredictedResourceHistory<R>>,
+) {
+    let Some(config) = config else {
+        return;
+    };
+    // restoring the resource during a selective rollback would also rewind the updates made by the
+    // entities that are not re-simulated
+    assert!(
+        !config.prediction.selective_rollback,
+        "the resource {} can't be rolled back with selective rollback",
+        std::any::type_name::<R>()
+    );
+    *history = PredictedResourceHistory::with_capacity(config.prediction.history_depth as usize);
+}
+
+/// After each tick, record the value of the resource if it changed
+pub(crate) fn update_resource_history<R: Resource + Clone + PartialEq>(
+    resource: Option<Res<R>>,
+    mut history: ResMut<PredictedResourceHistory<R>>,
+    tick_manager: Res<TickManager>,
+    rollback: Res<Rollback>,
+) {
+    // tick for which we will record the history
+    let tick = match rollback.state {
+        RollbackState::Default => tick_manager.tick(),
+        RollbackState::ShouldRollback { current_tick } => current_tick,
+    };
+    match resource {
+        Some(resource) => {
+            if resource.is_changed() {
+                history.add(tick, ComponentState::Updated(resource.clone()));
+            }
+        }
+        None => {
+            if matches!(history.latest(), Some(ComponentState::Updated(_))) {
+                history.add(tick, ComponentState::Removed);
+            }
+        }
+    }
+}
+
+/// Restore the resource to its value at the rollback tick
+pub(crate) fn prepare_rollback_resource<R: Resource + Clone + PartialEq>(
+    mut commands: Commands,
+    mut history: ResMut<PredictedResourceHistory<R>>,
+    rollback: Res<Rollback>,
+) {
+    let RollbackState::ShouldRollback { current_tick } = rollback.state else {
+        return;
+    };
+    // we restore the state at the end of the tick before the first re-simulated tick
+    let rollback_tick = current_tick - 1;
+    match history.rollback_to(rollback_tick) {
+        Some(ComponentState::Updated(resource)) => {
+            debug!(?rollback_tick, resource = ?std::any::type_name::<R>(), "Restoring predicted resource");
+            commands.insert_resource(resource);
+        }
+        Some(ComponentState::Removed) => {
+            commands.remove_resource::<R>();
+        }
+        None => {
+            trace!(?rollback_tick, "No history for the predicted resource");
+        }
+    }
+}
+
+/// The rollbacks never go further back than the latest tick received from the server,
+/// so we can remove the older values of the history
+pub(crate) fn clear_resource_history<R: Resource + Clone + PartialEq, P: Protocol>(
+    connection: Res<ConnectionManager<P>>,
+    mut history: ResMut<PredictedResourceHistory<R>>,
+) {
+    if connection.received_new_server_tick() {
+        history.clear_until(connection.latest_received_server_tick());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::Duration;
+
+    use super::*;
+    use crate::client::prediction::plugin::is_in_rollback;
+    use crate::prelude::client::*;
+    use crate::prelude::*;
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    #[derive(Resource, Clone, Debug, PartialEq)]
+    struct Counter(u32);
+
+    #[derive(Resource, Default)]
+    struct Rollbacks(u32);
+
+    fn increment_counter(mut counter: ResMut<Counter>) {
+        counter.0 += 1;
+    }
+
+    fn count_rollbacks(rollback: Option<Res<Rollback>>, mut rollbacks: ResMut<Rollbacks>) {
+        if is_in_rollback(rollback) {
+            rollbacks.0 += 1;
+        }
+    }
+
+    fn increment_predicted(mut query: Query<&mut Component1, With<Predicted>>) {
+        for mut component in query.iter_mut() {
+            component.0 += 1.0;
+        }
+    }
+
+    fn increment_server(mut query: Query<&mut Component1>) {
+        for mut component in query.iter_mut() {
+            component.0 += 2.0;
+        }
+    }
+
+    #[test]
+    fn test_history() {
+        let mut history = PredictedResourceHistory::<Counter>::default();
+        history.add(Tick(1), ComponentState::Updated(Counter(1)));
+        history.add(Tick(3), ComponentState::Updated(Counter(3)));
+        history.add(Tick(5), ComponentState::Removed);
+        // the same tick is recorded twice
+        history.add(Tick(5), ComponentState::Updated(Counter(5)));
+        assert_eq!(history.len(), 3);
+
+        history.clear_until(Tick(2));
+        assert_eq!(history.len(), 3);
+        history.clear_until(Tick(4));
+        assert_eq!(history.len(), 2);
+
+        assert_eq!(
+            history.rollback_to(Tick(4)),
+            Some(ComponentState::Updated(Counter(3)))
+        );
+        assert_eq!(history.len(), 1);
+        // there is no value for ticks before the start of the history
+        assert_eq!(history.rollback_to(Tick(2)), None);
+    }
+
+    #[test]
+    fn test_history_capacity() {
+        let mut history = PredictedResourceHistory::<Counter>::with_capacity(2);
+        history.add(Tick(1), ComponentState::Updated(Counter(1)));
+        history.add(Tick(2), ComponentState::Updated(Counter(2)));
+        history.add(Tick(3), ComponentState::Updated(Counter(3)));
+        // the oldest update is dropped
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.latest(), Some(&ComponentState::Updated(Counter(3))));
+        assert_eq!(history.rollback_to(Tick(1)), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "selective rollback")]
+    fn test_selective_rollback_rejected() {
+        let mut stepper = BevyStepper::new(
+            SharedConfig::default(),
+            SyncConfig::default(),
+            PredictionConfig::default().selective_rollback(true),
+            InterpolationConfig::default(),
+            LinkConditionerConfig::default(),
+            Duration::from_millis(10),
+        );
+        stepper
+            .client_app
+            .add_plugins(PredictedResourcePlugin::<Counter, MyProtocol>::default());
+        // the check runs on startup
+        stepper.client_app.update();
+    }
+
+    /// The resource is restored on rollback, so that the re-simulated ticks don't apply the updates twice
+    #[test]
+    fn test_resource_rollback() {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default(),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            LinkConditionerConfig::default(),
+            frame_duration,
+        );
+        stepper
+            .client_app
+            .add_plugins(PredictedResourcePlugin::<Counter, MyProtocol>::default())
+            .insert_resource(Counter(0))
+            .init_resource::<Rollbacks>()
+            .add_systems(
+                FixedUpdate,
+                (increment_counter, increment_predicted, count_rollbacks),
+            );
+        stepper
+            .server_app
+            .add_systems(FixedUpdate, increment_server);
+        stepper.init();
+
+        // the server and client updates of the predicted entity never match, so we rollback on every server update
+        stepper.server_app.world.spawn((
+            Component1(0.0),
+            Replicate {
+                prediction_target: NetworkTarget::All,
+                ..default()
+            },
+        ));
+        for _ in 0..20 {
+            stepper.frame_step();
+        }
+        let start_tick = stepper.client_tick();
+        let start_counter = stepper.client_app.world.resource::<Counter>().0;
+        let start_rollbacks = stepper.client_app.world.resource::<Rollbacks>().0;
+        for _ in 0..20 {
+            stepper.frame_step();
+        }
+        let ticks = (stepper.client_tick() - start_tick) as u32;
+        let counter = stepper.client_app.world.resource::<Counter>().0;
+        assert!(stepper.client_app.world.resource::<Rollbacks>().0 > start_rollbacks);
+        assert_eq!(counter - start_counter, ticks);
+        // the history only keeps the ticks that could still be rolled back
+        assert!(
+            stepper
+                .client_app
+                .world
+                .resource::<PredictedResourceHistory<Counter>>()
+                .len()
+                <= 10
+        );
+    }
+}
@@ -18,9 +18,10 @@ use crate::client::prediction::resource::PredictionManager;
 use crate::prelude::client::SyncMetadata;
 use crate::prelude::{PreSpawnedPlayerObject, TickManager};
 use crate::protocol::Protocol;
+use crate::shared::replication::authority::HasAuthority;
 
 use super::predicted_history::PredictionHistory;
-use super::{Predicted, Rollback, RollbackState};
+use super::{Predicted, Rollback, RollbackInteractions, RollbackState, SkipRollback};
 
 #[allow(clippy::type_complexity)]
 #[allow(clippy::too_many_arguments)]
@@ -31,7 +32,11 @@ pub(crate) fn check_rollback<C: SyncComponent, P: Protocol>(
 
     // We also snap the value of the component to the server state if we are in rollback
     // We use Option<> because the predicted component could have been removed while it still exists in Confirmed
-    mut predicted_query: Query<&mut PredictionHistory<C>, (With<Predicted>, Without<Confirmed>)>,
+    // the entities that we have authority over are not updated by the server
+    mut predicted_query: Query<
+        &mut PredictionHistory<C>,
+        (With<Predicted>, Without<Confirmed>, Without<HasAuthority>),
+    >,
     confirmed_query: Query<(Entity, Option<&C>, Ref<Confirmed>)>,
     mut rollback: ResMut<Rollback>,
 ) where
@@ -92,9 +97,18 @@ pub(crate) fn check_rollback<C: SyncComponent, P: Protocol>(
         // That is not the case, because if we do rollback we will need to snap the client entity to the server state
         // So either way we will need to do an operation.
         match rollback.state {
-            // 3.a We are still not sure if we should do rollback. Compare history against confirmed
+            // 3.a We already know we should do rollback (because of another entity/component), and all the
+            // predicted entities will be rolled back: start the rollback
+            RollbackState::ShouldRollback { .. } if !rollback.selective => {
+                trace!(
+                   "Rollback check: should roll back for component between predicted and confirmed on tick {:?} for component {:?}. Current tick: {:?}",
+                   tick, kind, current_tick
+                   );
+            }
+            // 3.b We are still not sure if we should do rollback, or we need to know which entities were mispredicted
+            // (selective rollback). Compare history against confirmed
             // We rollback if there's no history (newly added predicted entity, or if there is a mismatch)
-            RollbackState::Default => {
+            _ => {
                 let history_value = predicted_history.pop_until_tick(tick);
                 let predicted_exist = history_value.is_some();
                 let confirmed_exist = confirmed_component.is_some();
@@ -102,11 +116,13 @@ pub(crate) fn check_rollback<C: SyncComponent, P: Protocol>(
                     // TODO: history-value should not be empty here; should we panic if it is?
                     // confirm does not exist. rollback if history value is not Removed
                     None => history_value.map_or(false, |history_value| {
-                        history_value != ComponentState::Removed
+                        *history_value != ComponentState::Removed
                     }),
                     // confirm exist. rollback if history value is different
                     Some(c) => history_value.map_or(true, |history_value| match history_value {
-                        ComponentState::Updated(history_value) => history_value != *c,
+                        ComponentState::Updated(history_value) => {
+                            P::Components::should_rollback(history_value, c)
+                        }
                         ComponentState::Removed => true,
                     }),
                 };
@@ -117,20 +133,16 @@ pub(crate) fn check_rollback<C: SyncComponent, P: Protocol>(
                    confirmed_entity, tick, kind, current_tick
                    );
                     // TODO: try atomic enum update
-                    rollback.state = RollbackState::ShouldRollback {
-                        // we already rolled-back the state for the entity's latest_tick
-                        // after this we will start right away with a physics update, so we need to start taking the inputs from the next tick
-                        current_tick: tick + 1,
-                    };
+                    if matches!(rollback.state, RollbackState::Default) {
+                        rollback.state = RollbackState::ShouldRollback {
+                            // we already rolled-back the state for the entity's latest_tick
+                            // after this we will start right away with a physics update, so we need to start taking the inputs from the next tick
+                            current_tick: tick + 1,
+                        };
+                    }
+                    rollback.entities.insert(p);
                 }
             }
-            // 3.b We already know we should do rollback (because of another entity/component), start the rollback
-            RollbackState::ShouldRollback { .. } => {
-                trace!(
-                   "Rollback check: should roll back for component between predicted and confirmed on tick {:?} for component {:?}. Current tick: {:?}",
-                   tick, kind, current_tick
-                   );
-            }
         };
     }
 }
@@ -155,6 +167,7 @@ pub(crate) fn prepare_rollback<C: SyncComponent, P: Protocol>(
             With<Predicted>,
             Without<Confirmed>,
             Without<PreSpawnedPlayerObject>,
+            Without<HasAuthority>,
         ),
     >,
     confirmed_query: Query<(Entity, Option<&C>, Ref<Confirmed>)>,
@@ -188,6 +201,10 @@ pub(crate) fn prepare_rollback<C: SyncComponent, P: Protocol>(
         let Some(p) = confirmed.predicted else {
             continue;
         };
+        // in selective rollback, we only snap the entities that will be re-simulated
+        if !rollback.is_rolling_back(p) {
+            continue;
+        }
 
         // 1. Get the predicted entity, and it's history
         let Ok((predicted_entity, predicted_component, mut predicted_history, mut correction)) =
@@ -230,9 +247,8 @@ pub(crate) fn prepare_rollback<C: SyncComponent, P: Protocol>(
                         // }
 
                         // insert the Correction information only if the component exists on both confirmed and predicted
-                        let correction_ticks = ((current_tick - rollback_tick) as f32
-                            * config.prediction.correction_ticks_factor)
-                            .round() as i16;
+                        let correction_ticks = P::Components::correction()
+                            .correction_ticks(current_tick - rollback_tick, &config.prediction);
 
                         // no need to add the Correction if the correction is instant
                         if correction_ticks != 0 && P::Components::has_correction() {
@@ -248,6 +264,7 @@ pub(crate) fn prepare_rollback<C: SyncComponent, P: Protocol>(
                                 correction.final_correction_tick = final_correction_tick;
                                 // TODO: can set this to None, shouldnt make any diff
                                 correction.current_correction = Some(c.clone());
+                                correction.original_correction = None;
                             } else {
                                 debug!("inserting new correction");
                                 entity_mut.insert(Correction {
@@ -256,6 +273,7 @@ pub(crate) fn prepare_rollback<C: SyncComponent, P: Protocol>(
                                     final_correction_tick,
                                     current_visual: None,
                                     current_correction: None,
+                                    original_correction: None,
                                 });
                             }
                         }
@@ -356,7 +374,7 @@ pub(crate) fn prepare_rollback_prespawn<C: SyncComponent, P: Protocol>(
         }
 
         // 1. restore the component to the historical value
-        match predicted_history.pop_until_tick(rollback_tick) {
+        match predicted_history.pop_until_tick(rollback_tick).cloned() {
             None | Some(ComponentState::Removed) => {
                 if predicted_component.is_some() {
                     debug!(?prespawned_entity, ?kind, "Component for prespawned entity didn't exist at time of rollback, removing it");
@@ -370,9 +388,8 @@ pub(crate) fn prepare_rollback_prespawn<C: SyncComponent, P: Protocol>(
                     // TODO: do we need to do a correction in this case?
 
                     // insert the Correction information only if the component exists on both confirmed and predicted
-                    let correction_ticks = ((current_tick - rollback_tick) as f32
-                        * config.prediction.correction_ticks_factor)
-                        .round() as i16;
+                    let correction_ticks = P::Components::correction()
+                        .correction_ticks(current_tick - rollback_tick, &config.prediction);
 
                     // no need to add the Correction if the correction is instant
                     if correction_ticks != 0 && P::Components::has_correction() {
@@ -388,6 +405,7 @@ pub(crate) fn prepare_rollback_prespawn<C: SyncComponent, P: Protocol>(
                             correction.final_correction_tick = final_correction_tick;
                             // TODO: can set this to None, shouldnt make any diff
                             correction.current_correction = Some(c.clone());
+                            correction.original_correction = None;
                         } else {
                             debug!("inserting new correction");
                             commands.entity(prespawned_entity).insert(Correction {
@@ -396,6 +414,7 @@ pub(crate) fn prepare_rollback_prespawn<C: SyncComponent, P: Protocol>(
                                 final_correction_tick,
                                 current_visual: None,
                                 current_correction: None,
+                                original_correction: None,
                             });
                         }
                     }
@@ -418,7 +437,101 @@ pub(crate) fn prepare_rollback_prespawn<C: SyncComponent, P: Protocol>(
     }
 }
 
+/// The predicted entities that we have authority over don't receive any updates from the server,
+/// so their `Confirmed` entity is stale: we roll them back to their own history instead.
+#[allow(clippy::type_complexity)]
+pub(crate) fn prepare_rollback_authority<C: SyncComponent, P: Protocol>(
+    mut commands: Commands,
+    mut predicted_query: Query<
+        (Entity, Option<&mut C>, &mut PredictionHistory<C>),
+        (With<Predicted>, With<HasAuthority>, Without<Confirmed>),
+    >,
+    rollback: Res<Rollback>,
+) where
+    <P as Protocol>::ComponentKinds: FromType<C>,
+    P::Components: SyncMetadata<C>,
+{
+    if P::Components::mode() != ComponentSyncMode::Full {
+        return;
+    }
+    let RollbackState::ShouldRollback {
+        current_tick: rollback_tick_plus_one,
+    } = rollback.state
+    else {
+        error!("prepare_rollback_authority should only be called when we are in rollback");
+        return;
+    };
+    let rollback_tick = rollback_tick_plus_one - 1;
+
+    for (entity, predicted_component, mut predicted_history) in predicted_query.iter_mut() {
+        if !rollback.is_rolling_back(entity) {
+            continue;
+        }
+        // the re-simulation will write the new history after the rollback tick
+        let Some(state) = predicted_history.pop_until_tick(rollback_tick).cloned() else {
+            continue;
+        };
+        predicted_history.clear();
+        predicted_history
+            .buffer
+            .add_item(rollback_tick, state.clone());
+        match state {
+            ComponentState::Removed => {
+                if predicted_component.is_some() {
+                    commands.entity(entity).remove::<C>();
+                }
+            }
+            ComponentState::Updated(c) => match predicted_component {
+                Some(mut predicted_component) => *predicted_component = c,
+                None => {
+                    commands.entity(entity).insert(c);
+                }
+            },
+        }
+    }
+}
+
+/// In selective rollback, also roll back the entities that interact with the mispredicted entities
+pub(crate) fn add_rollback_interactions(
+    mut rollback: ResMut<Rollback>,
+    interactions: Query<&RollbackInteractions, With<Predicted>>,
+    prespawned: Query<Entity, (With<PreSpawnedPlayerObject>, Without<Predicted>)>,
+) {
+    if !rollback.selective {
+        return;
+    }
+    let mut to_visit: Vec<Entity> = rollback.entities.iter().copied().collect();
+    while let Some(entity) = to_visit.pop() {
+        let Ok(interactions) = interactions.get(entity) else {
+            continue;
+        };
+        for other in interactions.0.iter() {
+            if rollback.entities.insert(*other) {
+                to_visit.push(*other);
+            }
+        }
+    }
+    // pre-spawned entities don't have a confirmed state to compare against, so they are always rolled back
+    rollback.entities.extend(prespawned.iter());
+    debug!(entities = ?rollback.entities, "Selective rollback");
+}
+
 pub(crate) fn run_rollback(world: &mut World) {
+    // in selective rollback, mark the predicted entities that should not be re-simulated
+    let skipped: Vec<Entity> = if world.resource::<Rollback>().selective {
+        let mut query = world.query_filtered::<Entity, With<Predicted>>();
+        let rollback = world.resource::<Rollback>();
+        query
+            .iter(world)
+            .filter(|entity| !rollback.entities.contains(entity))
+            .collect()
+    } else {
+        vec![]
+    };
+    for entity in skipped.iter() {
+        world.entity_mut(*entity).insert(SkipRollback);
+    }
+
     let tick_manager = world.get_resource::<TickManager>().unwrap();
     let rollback = world.get_resource::<Rollback>().unwrap();
     let current_tick = tick_manager.tick();
@@ -449,9 +562,16 @@ pub(crate) fn run_rollback(world: &mut World) {
         debug!("Finished rollback. Current tick: {:?}", current_tick);
     }
 
+    for entity in skipped {
+        if let Some(mut entity_mut) = world.get_entity_mut(entity) {
+            entity_mut.remove::<SkipRollback>();
+        }
+    }
+
     // revert the state of Rollback for the next frame
     let mut rollback = world.get_resource_mut::<Rollback>().unwrap();
     rollback.state = RollbackState::Default;
+    rollback.entities.clear();
 }
 
 pub(crate) fn increment_rollback_tick(mut rollback: ResMut<Rollback>) {
@@ -508,6 +628,7 @@ pub(crate) fn increment_rollback_tick(mut rollback: ResMut<Rollback>) {
 //             incoming_latency: Duration::from_millis(40),
 //             incoming_jitter: Duration::from_millis(5),
 //             incoming_loss: 0.05,
+//             ..Default::default()
 //         };
 //         let sync_config = SyncConfig::default().speedup_factor(1.0);
 //         let prediction_config = PredictionConfig::default().disable(false);
@@ -905,3 +1026,237 @@ pub(crate) fn increment_rollback_tick(mut rollback: ResMut<Rollback>) {
 //         Ok(())
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::entity::{EntityHashMap, EntityHashSet};
+    use bevy::prelude::*;
+    use bevy::utils::Duration;
+
+    use crate::client::components::SyncMetadata;
+    use crate::client::prediction::plugin::is_in_rollback;
+    use crate::prelude::client::*;
+    use crate::prelude::*;
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::Rollback;
+
+    #[derive(Resource, Default)]
+    struct Rollbacks(u32);
+
+    /// Number of ticks that each predicted entity was re-simulated for
+    #[derive(Resource, Default)]
+    struct Resimulated(EntityHashMap<u32>);
+
+    fn count_rollbacks(rollback: Option<Res<Rollback>>, mut rollbacks: ResMut<Rollbacks>) {
+        if is_in_rollback(rollback) {
+            rollbacks.0 += 1;
+        }
+    }
+
+    /// The client predicts slightly different values than the server
+    fn increment_predicted(
+        mut query: Query<
+            (Option<&mut Component1>, Option<&mut Component7>),
+            (With<Predicted>, RollbackFilter),
+        >,
+    ) {
+        for (component1, component7) in query.iter_mut() {
+            if let Some(mut component1) = component1 {
+                component1.0 += 1.001;
+            }
+            if let Some(mut component7) = component7 {
+                component7.0 += 1.001;
+            }
+        }
+    }
+
+    fn count_resimulated(
+        rollback: Res<Rollback>,
+        query: Query<Entity, (With<Predicted>, RollbackFilter)>,
+        mut resimulated: ResMut<Resimulated>,
+    ) {
+        for entity in query.iter() {
+            if rollback.is_rolling_back(entity) {
+                *resimulated.0.entry(entity).or_default() += 1;
+            }
+        }
+    }
+
+    fn increment_server(
+        mut query: Query<(Option<&mut Component1>, Option<&mut Component7>), With<Replicate>>,
+    ) {
+        for (component1, component7) in query.iter_mut() {
+            if let Some(mut component1) = component1 {
+                component1.0 += 1.0;
+            }
+            if let Some(mut component7) = component7 {
+                component7.0 += 1.0;
+            }
+        }
+    }
+
+    #[test]
+    fn test_should_rollback_hook() {
+        // default comparison
+        assert!(
+            <MyComponentsProtocol as SyncMetadata<Component1>>::should_rollback(
+                &Component1(1.0),
+                &Component1(1.000001)
+            )
+        );
+        // custom comparison
+        assert!(
+            !<MyComponentsProtocol as SyncMetadata<Component7>>::should_rollback(
+                &Component7(1.0),
+                &Component7(1.05)
+            )
+        );
+        assert!(
+            <MyComponentsProtocol as SyncMetadata<Component7>>::should_rollback(
+                &Component7(1.0),
+                &Component7(1.2)
+            )
+        );
+    }
+
+    /// Count the rollbacks when the client's prediction drifts slightly from the server's state
+    fn rollbacks_with_drift(component: impl Bundle) -> u32 {
+        let frame_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(frame_duration),
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default(),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            LinkConditionerConfig::default(),
+            frame_duration,
+        );
+        stepper
+            .client_app
+            .init_resource::<Rollbacks>()
+            .add_systems(FixedUpdate, (increment_predicted, count_rollbacks));
+        stepper
+            .server_app
+            .add_systems(FixedUpdate, increment_server);
+        stepper.init();
+
+        stepper.server_app.world.spawn((
+            component,
+            Replicate {
+                prediction_target: NetworkTarget::All,
+                ..default()
+            },
+        ));
+        // the first server update triggers a rollback, because there is no history yet
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        let start = stepper.client_app.world.resource::<Rollbacks>().0;
+        for _ in 0..20 {
+            stepper.frame_step();
+        }
+        stepper.client_app.world.resource::<Rollbacks>().0 - start
+    }
+
+    #[test]
+    fn test_rollback_with_tolerance() {
+        // exact comparison: every server update triggers a rollback
+        assert!(rollbacks_with_drift(Component1(0.0)) > 0);
+        // the drift stays within the tolerance: no rollback
+        assert_eq!(rollbacks_with_drift(Component7(0.0)), 0);
+    }
+
+    /// Run the selective rollback with an entity that is mispredicted, and one that is predicted correctly.
+    /// Returns the number of re-simulated ticks for each of them.
+    fn selective_rollback(interaction: bool) -> (u32, u32) {
+        let frame_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(frame_duration),
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default(),
+            PredictionConfig::default().selective_rollback(true),
+            InterpolationConfig::default(),
+            LinkConditionerConfig::default(),
+            frame_duration,
+        );
+        stepper
+            .client_app
+            .init_resource::<Rollbacks>()
+            .init_resource::<Resimulated>()
+            .add_systems(
+                FixedUpdate,
+                (increment_predicted, count_rollbacks, count_resimulated),
+            );
+        stepper
+            .server_app
+            .add_systems(FixedUpdate, increment_server);
+        stepper.init();
+
+        // Component1 drifts from the server's value, Component7 stays within the tolerance
+        stepper.server_app.world.spawn((
+            Component1(0.0),
+            Replicate {
+                prediction_target: NetworkTarget::All,
+                ..default()
+            },
+        ));
+        stepper.server_app.world.spawn((
+            Component7(0.0),
+            Replicate {
+                prediction_target: NetworkTarget::All,
+                ..default()
+            },
+        ));
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        let world = &mut stepper.client_app.world;
+        let mispredicted = world
+            .query_filtered::<Entity, (With<Predicted>, With<Component1>)>()
+            .single(world);
+        let correct = world
+            .query_filtered::<Entity, (With<Predicted>, With<Component7>)>()
+            .single(world);
+        if interaction {
+            world
+                .entity_mut(mispredicted)
+                .insert(RollbackInteractions(EntityHashSet::from_iter([correct])));
+        }
+        world.resource_mut::<Resimulated>().0.clear();
+        let start = world.resource::<Rollbacks>().0;
+
+        for _ in 0..20 {
+            stepper.frame_step();
+        }
+        let world = &mut stepper.client_app.world;
+        assert!(world.resource::<Rollbacks>().0 > start);
+        // the markers are removed after the rollback
+        assert_eq!(world.query::<&SkipRollback>().iter(world).count(), 0);
+        let resimulated = &world.resource::<Resimulated>().0;
+        (
+            resimulated.get(&mispredicted).copied().unwrap_or_default(),
+            resimulated.get(&correct).copied().unwrap_or_default(),
+        )
+    }
+
+    #[test]
+    fn test_selective_rollback() {
+        // only the mispredicted entity is re-simulated
+        let (mispredicted, correct) = selective_rollback(false);
+        assert!(mispredicted > 0);
+        assert_eq!(correct, 0);
+
+        // the entities that interact with the mispredicted entity are re-simulated too
+        let (mispredicted, correct) = selective_rollback(true);
+        assert!(mispredicted > 0);
+        assert_eq!(correct, mispredicted);
+    }
+}
@@ -1,6 +1,7 @@
 use bevy::prelude::*;
 use bevy::utils::Duration;
 
+use crate::_reexport::MessageProtocol;
 use crate::client::connection::ConnectionManager;
 use crate::client::sync::client_is_synced;
 use crate::prelude::{Protocol, ReplicationSet};
@@ -42,5 +43,8 @@ impl<P: Protocol> Plugin for ClientReplicationPlugin<P> {
                 //  But then pre-predicted entities that are spawned right away will not be replicated?
                 ReplicationSet::All.run_if(client_is_synced::<P>),
             );
+
+        // REPLICATED RESOURCES
+        P::Message::add_resource_receive_systems(app);
     }
 }
@@ -17,7 +17,7 @@ use crate::utils::ready_buffer::ReadyBuffer;
 
 /// Run condition to run systems only if the client is synced
 pub fn client_is_synced<P: Protocol>(connection: Res<ConnectionManager<P>>) -> bool {
-    connection.sync_manager.is_synced()
+    connection.is_synced()
 }
 
 /// Configuration for the sync manager, which is in charge of syncing the client's tick/time with the server's tick/time
@@ -580,6 +580,7 @@ mod tests {
             incoming_latency: Duration::from_millis(20),
             incoming_jitter: Duration::from_millis(0),
             incoming_loss: 0.0,
+            ..Default::default()
         };
         let mut stepper = BevyStepper::new(
             shared_config,
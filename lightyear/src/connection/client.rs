@@ -6,7 +6,8 @@ use bevy::prelude::Resource;
 
 use crate::_reexport::ReadWordBuffer;
 use crate::client::config::NetcodeConfig;
-use crate::connection::netcode::{ClientId, ConnectToken};
+use crate::connection::netcode::{ClientId, ConnectToken, TokenRequest};
+use crate::connection::DisconnectReason;
 
 #[cfg(feature = "steam")]
 use crate::connection::steam::client::SteamConfig;
@@ -21,6 +22,13 @@ pub trait NetClient: Send + Sync {
     /// Connect to server
     fn connect(&mut self) -> Result<()>;
 
+    /// Disconnect from the server
+    fn disconnect(&mut self) -> Result<()> {
+        Err(anyhow::anyhow!(
+            "this transport does not support disconnecting"
+        ))
+    }
+
     /// Returns true if the client is connected to the server
     fn is_connected(&self) -> bool;
 
@@ -36,6 +44,13 @@ pub trait NetClient: Send + Sync {
     /// Get the id of the client
     fn id(&self) -> ClientId;
 
+    /// Returns why the client was disconnected from the server (or could not connect to it).
+    ///
+    /// This is reset when the client starts a new connection.
+    fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        None
+    }
+
     /// Get the local address of the client
     fn local_addr(&self) -> SocketAddr;
 
@@ -60,7 +75,26 @@ pub enum NetConfig {
         config: NetcodeConfig,
         io: IoConfig,
     },
+    /// Join a server on the local network, negotiating the connect token with its discovery responder
+    /// at `discovery_addr` (see [`lan`](crate::connection::lan)).
+    ///
+    /// The server assigns the id of the client. The clones of the config share the same `session`, so the
+    /// client keeps its id (and resumes its session on the server) when it reconnects; use a new
+    /// [`LanSession`](crate::connection::lan::LanSession) to join as a new client.
+    #[cfg(not(target_family = "wasm"))]
+    Lan {
+        discovery_addr: SocketAddr,
+        session: crate::connection::lan::LanSession,
+        config: NetcodeConfig,
+        io: IoConfig,
+    },
+    /// Local client of a host-server, that runs in the same app as the server and doesn't use a transport
+    /// (see [`Mode::HostServer`](crate::prelude::Mode::HostServer))
+    Local { id: ClientId },
     // TODO: for steam, we can use a pass-through io that just computes stats?
+    /// Connect to the server with the steam networking sockets.
+    ///
+    /// The [`protocol hash`](crate::protocol::Protocol::protocol_hash) is not checked by the server.
     #[cfg(feature = "steam")]
     Steam {
         config: SteamConfig,
@@ -79,6 +113,21 @@ impl Default for NetConfig {
 }
 
 impl NetConfig {
+    /// Set the hash of the protocol that is sent to the server when connecting.
+    ///
+    /// The steam transport does not check the protocol hash.
+    pub(crate) fn set_protocol_hash(&mut self, protocol_hash: u64) {
+        match self {
+            NetConfig::Netcode { config, .. } => config.protocol_hash = protocol_hash,
+            #[cfg(not(target_family = "wasm"))]
+            NetConfig::Lan { config, .. } => config.protocol_hash = protocol_hash,
+            // the local client uses the same protocol as the server
+            NetConfig::Local { .. } => {}
+            #[cfg(feature = "steam")]
+            NetConfig::Steam { .. } => {}
+        }
+    }
+
     pub fn build_client(self) -> ClientConnection {
         match self {
             NetConfig::Netcode {
@@ -86,6 +135,11 @@ impl NetConfig {
                 config,
                 io: io_config,
             } => {
+                assert!(
+                    !matches!(auth, Authentication::RequestConnectToken),
+                    "Authentication::RequestConnectToken cannot provide a connect token, \
+                    use Authentication::TokenService to request one from a ConnectTokenIssuer"
+                );
                 let token = auth
                     .clone()
                     .get_token(config.client_timeout_secs)
@@ -94,15 +148,46 @@ impl NetConfig {
                 let netcode =
                     super::netcode::NetcodeClient::with_config(&token_bytes, config.build())
                         .expect("could not create netcode client");
+                let token_request = match auth {
+                    Authentication::TokenService {
+                        token_server_addr,
+                        credentials,
+                    } => Some(TokenRequest {
+                        token_server_addr,
+                        credentials,
+                    }),
+                    _ => None,
+                };
                 let client = super::netcode::Client {
                     client: netcode,
                     io_config,
                     io: None,
+                    token_request,
+                    pending_token: None,
                 };
                 ClientConnection {
                     client: Box::new(client),
                 }
             }
+            #[cfg(not(target_family = "wasm"))]
+            NetConfig::Lan {
+                discovery_addr,
+                session,
+                config,
+                io,
+            } => {
+                let client = super::lan::client::Client::new(discovery_addr, session, config, io)
+                    .expect("could not create LAN client");
+                ClientConnection {
+                    client: Box::new(client),
+                }
+            }
+            NetConfig::Local { id } => {
+                let client = super::local::LocalClient::new(id);
+                ClientConnection {
+                    client: Box::new(client),
+                }
+            }
             #[cfg(feature = "steam")]
             NetConfig::Steam {
                 config,
@@ -124,6 +209,10 @@ impl NetClient for ClientConnection {
         self.client.connect()
     }
 
+    fn disconnect(&mut self) -> Result<()> {
+        self.client.disconnect()
+    }
+
     fn is_connected(&self) -> bool {
         self.client.is_connected()
     }
@@ -144,6 +233,10 @@ impl NetClient for ClientConnection {
         self.client.id()
     }
 
+    fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        self.client.disconnect_reason()
+    }
+
     fn local_addr(&self) -> SocketAddr {
         self.client.local_addr()
     }
@@ -171,8 +264,20 @@ pub enum Authentication {
         protocol_id: u64,
     },
     #[default]
-    /// Request a connect token from the backend
+    /// No way of getting a connect token was provided.
+    ///
+    /// Building a client with this authentication panics: use [`Authentication::TokenService`]
+    /// to request a connect token from a backend.
     RequestConnectToken,
+    /// Request a connect token from a [`ConnectTokenIssuer`](crate::connection::netcode::ConnectTokenIssuer)
+    /// that is listening at `token_server_addr`.
+    ///
+    /// A new token is requested in the background every time the client connects.
+    TokenService {
+        token_server_addr: SocketAddr,
+        /// Credentials used by the token service to authenticate the client
+        credentials: Vec<u8>,
+    },
 }
 
 impl Authentication {
@@ -188,8 +293,10 @@ impl Authentication {
                 .timeout_seconds(client_timeout_secs)
                 .generate()
                 .ok(),
-            Authentication::RequestConnectToken => {
-                // create a fake connect token so that we have a NetcodeClient
+            Authentication::RequestConnectToken => None,
+            Authentication::TokenService { .. } => {
+                // create a placeholder connect token so that we have a NetcodeClient;
+                // the real token is fetched from the token service when the client connects
                 ConnectToken::build(
                     SocketAddr::from_str("0.0.0.0:0").unwrap(),
                     0,
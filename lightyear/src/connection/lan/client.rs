@@ -0,0 +1,106 @@
+//! Client that negotiates its connect token with the LAN discovery responder of the server
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Result;
+
+use super::{negotiate_connect_token, LanSession};
+use crate::client::config::NetcodeConfig;
+use crate::connection::client::NetClient;
+use crate::connection::netcode::{ClientId, ConnectToken, NetcodeClient};
+use crate::connection::DisconnectReason;
+use crate::packet::packet::Packet;
+use crate::prelude::{generate_key, Io, IoConfig};
+
+/// Maximum time spent negotiating the connect token with the server
+const NEGOTIATION_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(crate) struct Client {
+    inner: crate::connection::netcode::Client<()>,
+    discovery_addr: SocketAddr,
+    session: LanSession,
+}
+
+impl Client {
+    pub(crate) fn new(
+        discovery_addr: SocketAddr,
+        session: LanSession,
+        config: NetcodeConfig,
+        io_config: IoConfig,
+    ) -> Result<Self> {
+        // create a placeholder connect token so that we have a NetcodeClient;
+        // the real token is negotiated with the server when the client connects
+        let token = ConnectToken::build(
+            SocketAddr::from_str("0.0.0.0:0").unwrap(),
+            0,
+            0,
+            generate_key(),
+        )
+        .timeout_seconds(config.client_timeout_secs)
+        .generate()?;
+        let token_bytes = token.try_into_bytes()?;
+        let client = NetcodeClient::with_config(&token_bytes, config.build())?;
+        Ok(Self {
+            inner: crate::connection::netcode::Client {
+                client,
+                io_config,
+                io: None,
+                token_request: None,
+                pending_token: None,
+            },
+            discovery_addr,
+            session,
+        })
+    }
+}
+
+impl NetClient for Client {
+    fn connect(&mut self) -> Result<()> {
+        let (discovery_addr, session) = (self.discovery_addr, self.session.clone());
+        // the negotiation is blocking, so it runs in a separate thread
+        self.inner.connect_with_token(move || {
+            negotiate_connect_token(discovery_addr, &session, NEGOTIATION_TIMEOUT)
+        })
+    }
+
+    fn disconnect(&mut self) -> Result<()> {
+        self.inner.disconnect()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn try_update(&mut self, delta_ms: f64) -> Result<()> {
+        self.inner.try_update(delta_ms)
+    }
+
+    fn recv(&mut self) -> Option<Packet> {
+        self.inner.recv()
+    }
+
+    fn send(&mut self, buf: &[u8]) -> Result<()> {
+        self.inner.send(buf)
+    }
+
+    fn id(&self) -> ClientId {
+        self.inner.id()
+    }
+
+    fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        self.inner.disconnect_reason()
+    }
+
+    fn local_addr(&self) -> SocketAddr {
+        self.inner.local_addr()
+    }
+
+    fn io(&self) -> Option<&Io> {
+        self.inner.io()
+    }
+
+    fn io_mut(&mut self) -> Option<&mut Io> {
+        self.inner.io_mut()
+    }
+}
@@ -0,0 +1,868 @@
+//! Discovery of the servers on the local network, and negotiation of the connect tokens
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Cursor, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use chacha20poly1305::XNonce;
+use governor::{DefaultKeyedRateLimiter, Quota, RateLimiter};
+use ring::agreement::{agree_ephemeral, EphemeralPrivateKey, UnparsedPublicKey, X25519};
+use ring::digest::{Context, SHA256};
+use ring::hmac;
+use ring::rand::SystemRandom;
+use tracing::{debug, error, info};
+
+use super::MAGIC;
+use crate::connection::netcode::crypto::{xchacha_decrypt, xchacha_encrypt};
+use crate::connection::netcode::token::AddressList;
+use crate::connection::netcode::{
+    ClientId, ConnectToken, ConnectTokenIssuer, Error, Key, Result, MAX_PACKET_SIZE,
+    NETCODE_VERSION, PRIVATE_KEY_BYTES,
+};
+
+const QUERY: u8 = 0;
+const SERVER_INFO: u8 = 1;
+const KEY_REQUEST: u8 = 2;
+const KEY_RESPONSE: u8 = 3;
+const CHALLENGE: u8 = 4;
+/// Key exchange response to a client that resumed its session
+const RESUME_RESPONSE: u8 = 5;
+
+const HEADER_BYTES: usize = MAGIC.len() + 1;
+const PUBLIC_KEY_BYTES: usize = 32;
+const NONCE_BYTES: usize = 24;
+const MAC_BYTES: usize = 16;
+const MAX_NAME_BYTES: usize = u8::MAX as usize;
+/// Size of the encrypted private data of a connect token
+const PRIVATE_DATA_BYTES: usize = 1024;
+/// Size of a cookie: the time at which it was created, followed by its HMAC-SHA256 tag
+const COOKIE_BYTES: usize = 8 + 32;
+/// Size of the identifier of a session: the start of the SHA-256 digest of the session key
+const SESSION_ID_BYTES: usize = 16;
+/// Size of the proof that the client knows the session key: HMAC-SHA256 of its public key
+const SESSION_PROOF_BYTES: usize = 32;
+/// Size of the biggest server info response; the queries are padded to this size
+const MAX_SERVER_INFO_BYTES: usize = HEADER_BYTES + 8 + 2 + 2 + 2 + 1 + MAX_NAME_BYTES;
+/// Size of the public fields of the connect token sent in the key exchange response:
+/// game port, protocol id, create and expire timestamps, nonce, timeout and private data
+const TOKEN_FIELDS_BYTES: usize = 2 + 8 + 8 + 8 + NONCE_BYTES + 4 + PRIVATE_DATA_BYTES;
+/// Size of the encrypted client-to-server and server-to-client keys of the connect token
+const ENCRYPTED_KEYS_BYTES: usize = 2 * PRIVATE_KEY_BYTES + MAC_BYTES;
+/// Size of the key exchange response; the key exchange requests are padded to this size
+const KEY_RESPONSE_BYTES: usize =
+    HEADER_BYTES + PUBLIC_KEY_BYTES + ENCRYPTED_KEYS_BYTES + TOKEN_FIELDS_BYTES;
+/// Size of the biggest discovery message (the key exchange request)
+const MAX_MESSAGE_BYTES: usize = KEY_RESPONSE_BYTES;
+// the discovery messages must not be fragmented
+const _: () = assert!(MAX_MESSAGE_BYTES <= MAX_PACKET_SIZE);
+
+/// How often the responder thread checks if it should stop
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How often the key exchange request is re-sent if the server doesn't answer
+const RESEND_INTERVAL: Duration = Duration::from_millis(250);
+/// How long a cookie sent in a challenge stays valid, in seconds
+const COOKIE_LIFETIME_SECS: u64 = 10;
+/// Number of messages per second that the responder accepts from a single ip address
+const MESSAGES_PER_SECOND: u32 = 10;
+/// How often the rate limiter forgets the ip addresses that stopped sending messages
+const RATE_LIMIT_CLEANUP_INTERVAL: Duration = Duration::from_secs(10);
+/// Maximum number of sessions that the responder remembers; the oldest sessions can't be resumed anymore
+const MAX_SESSIONS: usize = 1024;
+
+/// Information about a server on the local network, returned by [`scan`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerInfo {
+    /// Name of the server
+    pub name: String,
+    /// Address of the game server
+    pub addr: SocketAddr,
+    /// Address of the discovery responder of the server, used to negotiate the connect token
+    pub discovery_addr: SocketAddr,
+    /// Number of connected players
+    pub players: u16,
+    /// Maximum number of players
+    pub max_players: u16,
+    /// Hash of the server's [`Protocol`](crate::protocol::Protocol); the server will only accept
+    /// clients that use the same protocol
+    pub protocol_hash: u64,
+}
+
+/// Answers the discovery queries and the key exchange requests of the clients on the local network.
+///
+/// The responder runs in a separate thread, and is stopped when it is dropped.
+pub struct LanResponder {
+    local_addr: SocketAddr,
+    players: Arc<AtomicU16>,
+    shutdown: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+/// State shared with the responder thread
+struct Responder {
+    socket: UdpSocket,
+    name: String,
+    game_port: u16,
+    max_players: u16,
+    protocol_hash: u64,
+    players: Arc<AtomicU16>,
+    issuer: ConnectTokenIssuer,
+    local_network_only: bool,
+    rate_limiter: DefaultKeyedRateLimiter<IpAddr>,
+    /// Key used to authenticate the cookies sent in the challenges
+    cookie_key: hmac::Key,
+    /// The cookies store the time elapsed since this instant
+    start: Instant,
+    /// Id and key of the sessions that can be resumed, by session id
+    sessions: HashMap<[u8; SESSION_ID_BYTES], (ClientId, Key)>,
+    /// Ids of the sessions, from the oldest to the newest
+    session_ids: VecDeque<[u8; SESSION_ID_BYTES]>,
+}
+
+/// Session of a client on a LAN server, that lets the client keep its [`ClientId`] when it reconnects.
+///
+/// The server assigns the id of the client when it first joins, and the client proves that it owns
+/// the session with a secret key (the client-to-server key of the first connect token of the session)
+/// when it reconnects. Another client can't take over the session (and the rooms, authority and inputs
+/// of the client) without that key.
+///
+/// The clones of a `LanSession` share the same session.
+#[derive(Clone, Default)]
+pub struct LanSession(Arc<Mutex<Option<Key>>>);
+
+impl LanSession {
+    fn key(&self) -> Option<Key> {
+        *self.0.lock().unwrap()
+    }
+
+    fn set_key(&self, key: Key) {
+        *self.0.lock().unwrap() = Some(key);
+    }
+}
+
+impl LanResponder {
+    /// Start answering discovery queries on `addr`, for the game server listening on `game_port`.
+    ///
+    /// The `issuer` generates the connect tokens sent to the clients; it receives the client id chosen by the
+    /// responder as credentials (8 little-endian bytes): a random id for a new client, or the id of the session
+    /// that the client resumed (see [`LanSession`]).
+    /// The server address of the tokens is replaced by the address at which the client reached the responder.
+    ///
+    /// If `local_network_only` is true, the messages coming from addresses outside of the local network
+    /// (private, loopback and link-local addresses) are ignored.
+    pub fn start(
+        addr: SocketAddr,
+        name: String,
+        game_port: u16,
+        max_players: u16,
+        protocol_hash: u64,
+        issuer: ConnectTokenIssuer,
+        local_network_only: bool,
+    ) -> Result<Self> {
+        if name.len() > MAX_NAME_BYTES {
+            return Err(Error::SizeMismatch(MAX_NAME_BYTES, name.len()));
+        }
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        let local_addr = socket.local_addr()?;
+        let players = Arc::new(AtomicU16::new(0));
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let mut responder = Responder {
+            socket,
+            name,
+            game_port,
+            max_players,
+            protocol_hash,
+            players: players.clone(),
+            issuer,
+            local_network_only,
+            rate_limiter: RateLimiter::keyed(Quota::per_second(
+                NonZeroU32::new(MESSAGES_PER_SECOND).unwrap(),
+            )),
+            cookie_key: hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new())
+                .map_err(key_exchange_error)?,
+            start: Instant::now(),
+            sessions: HashMap::default(),
+            session_ids: VecDeque::default(),
+        };
+        let thread_shutdown = shutdown.clone();
+        let handle = std::thread::Builder::new()
+            .name("lan-responder".to_string())
+            .spawn(move || {
+                info!("Answering LAN discovery queries on {}", local_addr);
+                let mut buf = [0u8; MAX_MESSAGE_BYTES];
+                let mut last_cleanup = Instant::now();
+                while !thread_shutdown.load(Ordering::Relaxed) {
+                    match responder.socket.recv_from(&mut buf) {
+                        Ok((len, from)) => {
+                            if let Err(e) = responder.handle_message(&buf[..len], from) {
+                                debug!(?from, "Invalid LAN discovery message: {}", e);
+                            }
+                        }
+                        Err(e)
+                            if matches!(
+                                e.kind(),
+                                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                            ) => {}
+                        Err(e) => error!("Error receiving LAN discovery message: {}", e),
+                    }
+                    if last_cleanup.elapsed() >= RATE_LIMIT_CLEANUP_INTERVAL {
+                        responder.rate_limiter.retain_recent();
+                        last_cleanup = Instant::now();
+                    }
+                }
+            })?;
+        Ok(Self {
+            local_addr,
+            players,
+            shutdown,
+            handle: Some(handle),
+        })
+    }
+
+    /// Address that the responder is bound to
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Update the number of players sent to the clients
+    pub fn set_players(&self, players: u16) {
+        self.players.store(players, Ordering::Relaxed);
+    }
+}
+
+impl Drop for LanResponder {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Responder {
+    fn handle_message(&mut self, message: &[u8], from: SocketAddr) -> Result<()> {
+        if self.local_network_only && !is_local_network(from.ip()) {
+            return Err(invalid_data("the sender is not on the local network"));
+        }
+        if self.rate_limiter.check_key(&from.ip()).is_err() {
+            return Err(invalid_data("too many messages from the sender"));
+        }
+        let mut reader = Cursor::new(message);
+        match read_header(&mut reader)? {
+            QUERY => {
+                let mut response = header(SERVER_INFO);
+                response.write_u64::<LittleEndian>(self.protocol_hash)?;
+                response.write_u16::<LittleEndian>(self.game_port)?;
+                response.write_u16::<LittleEndian>(self.players.load(Ordering::Relaxed))?;
+                response.write_u16::<LittleEndian>(self.max_players)?;
+                response.write_u8(self.name.len() as u8)?;
+                response.write_all(self.name.as_bytes())?;
+                self.respond(message, &response, from)?;
+            }
+            KEY_REQUEST => {
+                // check the size before doing any expensive work
+                if message.len() < KEY_RESPONSE_BYTES {
+                    return Err(invalid_data("the key exchange request is not padded"));
+                }
+                let mut client_public_key = [0u8; PUBLIC_KEY_BYTES];
+                reader.read_exact(&mut client_public_key)?;
+                let mut session_id = [0u8; SESSION_ID_BYTES];
+                reader.read_exact(&mut session_id)?;
+                let mut session_proof = [0u8; SESSION_PROOF_BYTES];
+                reader.read_exact(&mut session_proof)?;
+                let mut cookie = [0u8; COOKIE_BYTES];
+                reader.read_exact(&mut cookie)?;
+                // the client must prove that it can receive messages at its address before we do
+                // the key exchange, so that the responder can't be used with spoofed addresses
+                if !self.verify_cookie(&cookie, from) {
+                    let mut response = header(CHALLENGE);
+                    response.write_all(&self.cookie(from, self.start.elapsed().as_secs()))?;
+                    return self.respond(message, &response, from);
+                }
+                let (server_public_key, key) = key_exchange(&client_public_key)?;
+                let resumed_id =
+                    self.resumed_client_id(&session_id, &session_proof, &client_public_key);
+                let client_id = resumed_id.unwrap_or_else(|| rand::random::<u64>().max(1));
+                // the ip of the server is replaced by the client
+                let token = self.issuer.issue(&client_id.to_le_bytes())?;
+                if resumed_id.is_none() {
+                    self.add_session(client_id, token.client_to_server_key);
+                }
+
+                // only the keys are secret, the rest of the token is sent in plain text and authenticated
+                let mut fields = Vec::with_capacity(TOKEN_FIELDS_BYTES);
+                fields.write_u16::<LittleEndian>(self.game_port)?;
+                fields.write_u64::<LittleEndian>(token.protocol_id)?;
+                fields.write_u64::<LittleEndian>(token.create_timestamp)?;
+                fields.write_u64::<LittleEndian>(token.expire_timestamp)?;
+                fields.write_all(&token.nonce)?;
+                fields.write_i32::<LittleEndian>(token.timeout_seconds)?;
+                fields.write_all(&token.private_data)?;
+                let mut keys = [0u8; ENCRYPTED_KEYS_BYTES];
+                keys[..PRIVATE_KEY_BYTES].copy_from_slice(&token.client_to_server_key);
+                keys[PRIVATE_KEY_BYTES..2 * PRIVATE_KEY_BYTES]
+                    .copy_from_slice(&token.server_to_client_key);
+                // the key is only used once, so the nonce doesn't need to be unique
+                xchacha_encrypt(&mut keys, Some(&fields), XNonce::default(), &key)?;
+
+                let mut response = header(if resumed_id.is_some() {
+                    RESUME_RESPONSE
+                } else {
+                    KEY_RESPONSE
+                });
+                response.write_all(&server_public_key)?;
+                response.write_all(&keys)?;
+                response.write_all(&fields)?;
+                self.respond(message, &response, from)?;
+                debug!(
+                    ?from,
+                    ?client_id,
+                    resumed = resumed_id.is_some(),
+                    "Sent connect token to LAN client"
+                );
+            }
+            kind => return Err(invalid_data(format!("unexpected message kind {kind}"))),
+        }
+        Ok(())
+    }
+
+    /// Returns the id of the session that the client wants to resume, if it proved that it knows the key
+    /// of the session
+    fn resumed_client_id(
+        &self,
+        session_id: &[u8; SESSION_ID_BYTES],
+        session_proof: &[u8; SESSION_PROOF_BYTES],
+        client_public_key: &[u8; PUBLIC_KEY_BYTES],
+    ) -> Option<ClientId> {
+        let (client_id, session_key) = self.sessions.get(session_id)?;
+        hmac::verify(
+            &hmac::Key::new(hmac::HMAC_SHA256, session_key),
+            client_public_key,
+            session_proof,
+        )
+        .ok()?;
+        Some(*client_id)
+    }
+
+    /// Remember a new session, so that the client can resume it when it reconnects
+    fn add_session(&mut self, client_id: ClientId, session_key: Key) {
+        if self.session_ids.len() >= MAX_SESSIONS {
+            if let Some(oldest) = self.session_ids.pop_front() {
+                self.sessions.remove(&oldest);
+            }
+        }
+        let session_id = session_id(&session_key);
+        self.sessions.insert(session_id, (client_id, session_key));
+        self.session_ids.push_back(session_id);
+    }
+
+    /// Send the response to a request.
+    ///
+    /// The response is never bigger than the request, so that the responder can't be used to amplify
+    /// a reflection attack.
+    fn respond(&self, request: &[u8], response: &[u8], to: SocketAddr) -> Result<()> {
+        if response.len() > request.len() {
+            return Err(invalid_data("the request is smaller than the response"));
+        }
+        self.socket.send_to(response, to)?;
+        Ok(())
+    }
+
+    /// Create a cookie for the client at `addr`, `timestamp` seconds after the start of the responder
+    fn cookie(&self, addr: SocketAddr, timestamp: u64) -> [u8; COOKIE_BYTES] {
+        let mut cookie = [0u8; COOKIE_BYTES];
+        cookie[..8].copy_from_slice(&timestamp.to_le_bytes());
+        let tag = hmac::sign(&self.cookie_key, &cookie_data(addr, timestamp));
+        cookie[8..].copy_from_slice(tag.as_ref());
+        cookie
+    }
+
+    fn verify_cookie(&self, cookie: &[u8; COOKIE_BYTES], addr: SocketAddr) -> bool {
+        let mut timestamp = [0u8; 8];
+        timestamp.copy_from_slice(&cookie[..8]);
+        let timestamp = u64::from_le_bytes(timestamp);
+        let now = self.start.elapsed().as_secs();
+        timestamp <= now
+            && now - timestamp <= COOKIE_LIFETIME_SECS
+            && hmac::verify(
+                &self.cookie_key,
+                &cookie_data(addr, timestamp),
+                &cookie[8..],
+            )
+            .is_ok()
+    }
+}
+
+/// Data authenticated by a cookie: the address of the client and the time at which the cookie was created
+fn cookie_data(addr: SocketAddr, timestamp: u64) -> Vec<u8> {
+    let mut data = match addr.ip() {
+        IpAddr::V4(ip) => ip.octets().to_vec(),
+        IpAddr::V6(ip) => ip.octets().to_vec(),
+    };
+    data.extend_from_slice(&addr.port().to_le_bytes());
+    data.extend_from_slice(&timestamp.to_le_bytes());
+    data
+}
+
+/// Public identifier of the session that uses `session_key`
+fn session_id(session_key: &Key) -> [u8; SESSION_ID_BYTES] {
+    let mut context = Context::new(&SHA256);
+    context.update(MAGIC);
+    context.update(session_key);
+    let mut session_id = [0u8; SESSION_ID_BYTES];
+    session_id.copy_from_slice(&context.finish().as_ref()[..SESSION_ID_BYTES]);
+    session_id
+}
+
+/// Returns true if `ip` is a private, loopback or link-local address
+fn is_local_network(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_private() || ip.is_loopback() || ip.is_link_local(),
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_local_network(IpAddr::V4(ip));
+            }
+            let first_segment = ip.segments()[0];
+            // unique local (fc00::/7) and link-local (fe80::/10) addresses
+            ip.is_loopback() || first_segment & 0xfe00 == 0xfc00 || first_segment & 0xffc0 == 0xfe80
+        }
+    }
+}
+
+/// List the servers on the local network, by sending a query to `broadcast_addr` and collecting the answers
+/// received during `timeout`.
+///
+/// `broadcast_addr` is usually the broadcast address of the local network on the discovery port,
+/// for example `255.255.255.255:5002`, but it can also be the address of a single server.
+///
+/// This is a blocking call.
+pub fn scan(broadcast_addr: SocketAddr, timeout: Duration) -> Result<Vec<ServerInfo>> {
+    let socket = bind_for(broadcast_addr)?;
+    socket.set_broadcast(true)?;
+    let mut query = header(QUERY);
+    query.resize(MAX_SERVER_INFO_BYTES, 0);
+    socket.send_to(&query, broadcast_addr)?;
+
+    let mut servers: Vec<ServerInfo> = vec![];
+    let deadline = Instant::now() + timeout;
+    let mut buf = [0u8; MAX_MESSAGE_BYTES];
+    while let Some(remaining) = deadline
+        .checked_duration_since(Instant::now())
+        .filter(|d| !d.is_zero())
+    {
+        socket.set_read_timeout(Some(remaining))?;
+        let (len, from) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                break
+            }
+            Err(e) => return Err(e.into()),
+        };
+        match read_server_info(&buf[..len], from) {
+            Ok(info) => {
+                if !servers.iter().any(|s| s.discovery_addr == from) {
+                    servers.push(info);
+                }
+            }
+            Err(e) => debug!(?from, "Invalid LAN discovery response: {}", e),
+        }
+    }
+    Ok(servers)
+}
+
+/// Get a [`ConnectToken`] from the discovery responder of a server; the keys of the token are encrypted
+/// with a key negotiated with an X25519 key exchange.
+///
+/// If the client already joined the server with this `session`, it keeps the same [`ClientId`] (and the server
+/// resumes its session if it is still in its disconnect grace period). Otherwise, the server assigns a new id
+/// to the client and the session is updated.
+///
+/// This is a blocking call.
+pub fn negotiate_connect_token(
+    discovery_addr: SocketAddr,
+    session: &LanSession,
+    timeout: Duration,
+) -> Result<ConnectToken> {
+    let rng = SystemRandom::new();
+    let private_key = EphemeralPrivateKey::generate(&X25519, &rng).map_err(key_exchange_error)?;
+    let public_key = private_key
+        .compute_public_key()
+        .map_err(key_exchange_error)?;
+    // prove that we know the key of the session, without revealing it
+    let session_key = session.key();
+    let (session_id, session_proof) = match &session_key {
+        Some(key) => {
+            let mut proof = [0u8; SESSION_PROOF_BYTES];
+            proof.copy_from_slice(
+                hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), public_key.as_ref()).as_ref(),
+            );
+            (session_id(key), proof)
+        }
+        None => ([0u8; SESSION_ID_BYTES], [0u8; SESSION_PROOF_BYTES]),
+    };
+
+    let socket = bind_for(discovery_addr)?;
+    socket.connect(discovery_addr)?;
+    socket.set_read_timeout(Some(RESEND_INTERVAL))?;
+    let deadline = Instant::now() + timeout;
+    // the first request has an empty cookie, the server answers with a challenge containing a valid one
+    let mut cookie = [0u8; COOKIE_BYTES];
+    let mut buf = [0u8; MAX_MESSAGE_BYTES];
+    let (len, resumed) = loop {
+        if Instant::now() >= deadline {
+            return Err(io::Error::from(io::ErrorKind::TimedOut).into());
+        }
+        let mut request = header(KEY_REQUEST);
+        request.write_all(public_key.as_ref())?;
+        request.write_all(&session_id)?;
+        request.write_all(&session_proof)?;
+        request.write_all(&cookie)?;
+        // pad the request so that the response is not bigger than the request
+        request.resize(KEY_RESPONSE_BYTES, 0);
+        socket.send(&request)?;
+        match socket.recv(&mut buf) {
+            Ok(len) => {
+                let mut reader = Cursor::new(&buf[..len]);
+                match read_header(&mut reader)? {
+                    CHALLENGE => reader.read_exact(&mut cookie)?,
+                    KEY_RESPONSE => break (len, false),
+                    RESUME_RESPONSE => break (len, true),
+                    _ => return Err(invalid_data("expected a key exchange response")),
+                }
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(e) => return Err(e.into()),
+        }
+    };
+
+    let mut reader = Cursor::new(&buf[HEADER_BYTES..len]);
+    let mut server_public_key = [0u8; PUBLIC_KEY_BYTES];
+    reader.read_exact(&mut server_public_key)?;
+    let mut keys = [0u8; ENCRYPTED_KEYS_BYTES];
+    reader.read_exact(&mut keys)?;
+    let fields_start = reader.position() as usize;
+    let game_port = reader.read_u16::<LittleEndian>()?;
+    let protocol_id = reader.read_u64::<LittleEndian>()?;
+    let create_timestamp = reader.read_u64::<LittleEndian>()?;
+    let expire_timestamp = reader.read_u64::<LittleEndian>()?;
+    let mut nonce = [0u8; NONCE_BYTES];
+    reader.read_exact(&mut nonce)?;
+    let timeout_seconds = reader.read_i32::<LittleEndian>()?;
+    let mut private_data = [0u8; PRIVATE_DATA_BYTES];
+    reader.read_exact(&mut private_data)?;
+    let fields = &reader.get_ref()[fields_start..];
+
+    let key = agree_ephemeral(
+        private_key,
+        &UnparsedPublicKey::new(&X25519, server_public_key),
+        |shared_secret| derive_key(shared_secret, public_key.as_ref(), &server_public_key),
+    )
+    .map_err(key_exchange_error)?;
+    xchacha_decrypt(&mut keys, Some(fields), XNonce::default(), &key)?;
+    let mut client_to_server_key = Key::default();
+    client_to_server_key.copy_from_slice(&keys[..PRIVATE_KEY_BYTES]);
+    let mut server_to_client_key = Key::default();
+    server_to_client_key.copy_from_slice(&keys[PRIVATE_KEY_BYTES..2 * PRIVATE_KEY_BYTES]);
+    if !resumed {
+        // the server started a new session, identified by the key of this token
+        session.set_key(client_to_server_key);
+    }
+
+    Ok(ConnectToken {
+        version_info: *NETCODE_VERSION,
+        protocol_id,
+        create_timestamp,
+        expire_timestamp,
+        nonce: XNonce::from(nonce),
+        private_data,
+        timeout_seconds,
+        // the server doesn't know at which address the client can reach it
+        server_addresses: AddressList::new(SocketAddr::new(discovery_addr.ip(), game_port))?,
+        client_to_server_key,
+        server_to_client_key,
+    })
+}
+
+/// Server side of the key exchange: generate an ephemeral key pair, and derive the shared key from the
+/// client's public key.
+///
+/// Returns the public key of the server and the shared key.
+fn key_exchange(
+    client_public_key: &[u8; PUBLIC_KEY_BYTES],
+) -> Result<([u8; PUBLIC_KEY_BYTES], Key)> {
+    let rng = SystemRandom::new();
+    let private_key = EphemeralPrivateKey::generate(&X25519, &rng).map_err(key_exchange_error)?;
+    let mut public_key = [0u8; PUBLIC_KEY_BYTES];
+    public_key.copy_from_slice(
+        private_key
+            .compute_public_key()
+            .map_err(key_exchange_error)?
+            .as_ref(),
+    );
+    let key = agree_ephemeral(
+        private_key,
+        &UnparsedPublicKey::new(&X25519, client_public_key),
+        |shared_secret| derive_key(shared_secret, client_public_key, &public_key),
+    )
+    .map_err(key_exchange_error)?;
+    Ok((public_key, key))
+}
+
+/// Derive the key used to encrypt the connect token from the X25519 shared secret and the public keys
+fn derive_key(shared_secret: &[u8], client_public_key: &[u8], server_public_key: &[u8]) -> Key {
+    let mut context = Context::new(&SHA256);
+    context.update(MAGIC);
+    context.update(shared_secret);
+    context.update(client_public_key);
+    context.update(server_public_key);
+    let mut key = Key::default();
+    key.copy_from_slice(context.finish().as_ref());
+    key
+}
+
+fn read_server_info(message: &[u8], from: SocketAddr) -> Result<ServerInfo> {
+    let mut reader = Cursor::new(message);
+    if read_header(&mut reader)? != SERVER_INFO {
+        return Err(invalid_data("expected a server info response"));
+    }
+    let protocol_hash = reader.read_u64::<LittleEndian>()?;
+    let game_port = reader.read_u16::<LittleEndian>()?;
+    let players = reader.read_u16::<LittleEndian>()?;
+    let max_players = reader.read_u16::<LittleEndian>()?;
+    let mut name = vec![0; reader.read_u8()? as usize];
+    reader.read_exact(&mut name)?;
+    Ok(ServerInfo {
+        name: String::from_utf8_lossy(&name).into_owned(),
+        addr: SocketAddr::new(from.ip(), game_port),
+        discovery_addr: from,
+        players,
+        max_players,
+        protocol_hash,
+    })
+}
+
+fn header(kind: u8) -> Vec<u8> {
+    let mut message = MAGIC.to_vec();
+    message.push(kind);
+    message
+}
+
+fn read_header(reader: &mut Cursor<&[u8]>) -> Result<u8> {
+    let mut magic = [0u8; MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("invalid magic bytes"));
+    }
+    Ok(reader.read_u8()?)
+}
+
+/// Bind a socket on an ephemeral port, with the same ip version as `addr`
+fn bind_for(addr: SocketAddr) -> io::Result<UdpSocket> {
+    let ip = match addr {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    UdpSocket::bind(SocketAddr::new(ip, 0))
+}
+
+fn invalid_data(message: impl Into<String>) -> Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into()).into()
+}
+
+fn key_exchange_error(_: ring::error::Unspecified) -> Error {
+    invalid_data("key exchange failed")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::connection::netcode::generate_key;
+    use crate::connection::netcode::token::ConnectTokenPrivate;
+
+    use super::*;
+
+    const PRIVATE_KEY: Key = [3; PRIVATE_KEY_BYTES];
+
+    fn responder(players: u16) -> LanResponder {
+        let issuer = ConnectTokenIssuer::new(
+            SocketAddr::from(([0, 0, 0, 0], 6000)),
+            7,
+            PRIVATE_KEY,
+            |credentials| Some(u64::from_le_bytes(credentials.try_into().ok()?)),
+        );
+        let responder = LanResponder::start(
+            SocketAddr::from(([127, 0, 0, 1], 0)),
+            "my server".to_string(),
+            6000,
+            8,
+            0xabcd,
+            issuer,
+            true,
+        )
+        .unwrap();
+        responder.set_players(players);
+        responder
+    }
+
+    /// Send a message to the responder, and return its answer if there is one
+    fn send(responder: &LanResponder, message: &[u8]) -> Option<Vec<u8>> {
+        let socket = bind_for(responder.local_addr()).unwrap();
+        socket.connect(responder.local_addr()).unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_millis(300)))
+            .unwrap();
+        socket.send(message).unwrap();
+        let mut buf = [0u8; MAX_MESSAGE_BYTES];
+        socket.recv(&mut buf).ok().map(|len| buf[..len].to_vec())
+    }
+
+    #[test]
+    fn test_scan() {
+        let responder = responder(3);
+        let servers = scan(responder.local_addr(), Duration::from_millis(500)).unwrap();
+        assert_eq!(
+            servers,
+            vec![ServerInfo {
+                name: "my server".to_string(),
+                addr: SocketAddr::from(([127, 0, 0, 1], 6000)),
+                discovery_addr: responder.local_addr(),
+                players: 3,
+                max_players: 8,
+                protocol_hash: 0xabcd,
+            }]
+        );
+    }
+
+    /// Decrypt the private data of a token issued by the responder
+    fn decrypt(token: &ConnectToken) -> ConnectTokenPrivate {
+        ConnectTokenPrivate::decrypt(
+            &mut token.private_data.clone(),
+            token.protocol_id,
+            token.expire_timestamp,
+            token.nonce,
+            &PRIVATE_KEY,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_negotiate_connect_token() {
+        let responder = responder(0);
+        let session = LanSession::default();
+        let token =
+            negotiate_connect_token(responder.local_addr(), &session, Duration::from_secs(2))
+                .unwrap();
+        assert_eq!(token.protocol_id, 7);
+        assert_eq!(
+            token.server_addresses[0],
+            SocketAddr::from(([127, 0, 0, 1], 6000))
+        );
+        // the token is valid for the server, with an id chosen by the server
+        let private = decrypt(&token);
+        assert_ne!(private.client_id, 0);
+        assert_eq!(private.client_to_server_key, token.client_to_server_key);
+        assert_eq!(private.server_to_client_key, token.server_to_client_key);
+        // the token starts a new session
+        assert_eq!(session.key(), Some(token.client_to_server_key));
+        drop(responder);
+    }
+
+    /// A client that reconnects with its session keeps its id, and the other clients can't use that id
+    #[test]
+    fn test_resume_session() {
+        let responder = responder(0);
+        let timeout = Duration::from_secs(2);
+        let session = LanSession::default();
+        let token = negotiate_connect_token(responder.local_addr(), &session, timeout).unwrap();
+        let client_id = decrypt(&token).client_id;
+        let session_key = session.key().unwrap();
+
+        let token = negotiate_connect_token(responder.local_addr(), &session, timeout).unwrap();
+        assert_eq!(decrypt(&token).client_id, client_id);
+        assert_eq!(session.key(), Some(session_key));
+
+        // another client gets a new id
+        let token =
+            negotiate_connect_token(responder.local_addr(), &LanSession::default(), timeout)
+                .unwrap();
+        assert_ne!(decrypt(&token).client_id, client_id);
+
+        // a client that knows the (public) session id, but not the session key, doesn't resume the session
+        let socket = bind_for(responder.local_addr()).unwrap();
+        socket.connect(responder.local_addr()).unwrap();
+        socket.set_read_timeout(Some(timeout)).unwrap();
+        let mut buf = [0u8; MAX_MESSAGE_BYTES];
+        let mut cookie = [0u8; COOKIE_BYTES];
+        for expected_kind in [CHALLENGE, KEY_RESPONSE] {
+            let mut request = header(KEY_REQUEST);
+            request.extend_from_slice(&[1; PUBLIC_KEY_BYTES]);
+            request.extend_from_slice(&session_id(&session_key));
+            request.extend_from_slice(&[2; SESSION_PROOF_BYTES]);
+            request.extend_from_slice(&cookie);
+            request.resize(KEY_RESPONSE_BYTES, 0);
+            socket.send(&request).unwrap();
+            let len = socket.recv(&mut buf).unwrap();
+            assert_eq!(buf[HEADER_BYTES - 1], expected_kind);
+            if expected_kind == CHALLENGE {
+                cookie.copy_from_slice(&buf[HEADER_BYTES..len]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_no_amplification() {
+        let responder = responder(0);
+        // messages that are smaller than the response are ignored
+        assert_eq!(send(&responder, &header(QUERY)), None);
+        let mut request = header(KEY_REQUEST);
+        request.extend_from_slice(&[1; PUBLIC_KEY_BYTES]);
+        request.extend_from_slice(&[0; SESSION_ID_BYTES + SESSION_PROOF_BYTES]);
+        request.extend_from_slice(&[0; COOKIE_BYTES]);
+        assert_eq!(send(&responder, &request), None);
+
+        // a padded key exchange request without a valid cookie only gets a small challenge
+        request.resize(KEY_RESPONSE_BYTES, 0);
+        let challenge = send(&responder, &request).unwrap();
+        assert_eq!(challenge.len(), HEADER_BYTES + COOKIE_BYTES);
+        assert_eq!(challenge[HEADER_BYTES - 1], CHALLENGE);
+    }
+
+    #[test]
+    fn test_token_fields_size() {
+        let token =
+            ConnectToken::build(SocketAddr::from(([0, 0, 0, 0], 6000)), 7, 1, generate_key())
+                .generate()
+                .unwrap();
+        assert_eq!(token.private_data.len(), PRIVATE_DATA_BYTES);
+        assert_eq!(token.nonce.len(), NONCE_BYTES);
+    }
+
+    #[test]
+    fn test_is_local_network() {
+        assert!(is_local_network(IpAddr::from([192, 168, 1, 2])));
+        assert!(is_local_network(IpAddr::from([10, 0, 0, 1])));
+        assert!(is_local_network(IpAddr::from([127, 0, 0, 1])));
+        assert!(is_local_network(IpAddr::from([169, 254, 0, 1])));
+        assert!(!is_local_network(IpAddr::from([8, 8, 8, 8])));
+        assert!(is_local_network(IpAddr::V6(Ipv6Addr::LOCALHOST)));
+        assert!(is_local_network("fe80::1".parse::<IpAddr>().unwrap()));
+        assert!(is_local_network("fd00::1".parse::<IpAddr>().unwrap()));
+        assert!(is_local_network(
+            "::ffff:192.168.1.2".parse::<IpAddr>().unwrap()
+        ));
+        assert!(!is_local_network("2001:db8::1".parse::<IpAddr>().unwrap()));
+    }
+}
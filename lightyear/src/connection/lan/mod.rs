@@ -0,0 +1,56 @@
+/*! LAN discovery and direct connection, without a backend issuing connect tokens
+
+For LAN parties or couch co-op, running a web backend that authenticates clients and issues [`ConnectToken`](crate::connection::netcode::ConnectToken)s
+is overkill. In LAN mode:
+- the server runs a discovery responder on a separate UDP socket (by default on port [`DEFAULT_DISCOVERY_PORT`]),
+  that answers the broadcast queries of the clients with a [`ServerInfo`] (name, number of players, protocol hash)
+- clients list the servers on the local network with [`scan`]
+- when connecting, the client performs a key exchange (X25519) with the discovery responder of the server, which
+  returns a [`ConnectToken`](crate::connection::netcode::ConnectToken) encrypted with the negotiated key.
+  The client then connects to the server with the netcode protocol over the existing transport (usually
+  [`TransportConfig::UdpSocket`](crate::prelude::TransportConfig::UdpSocket)).
+
+The key exchange is not authenticated: it protects the connection against passive eavesdroppers on the local network,
+but not against an attacker that can impersonate the server. Any client that can reach the discovery responder can connect.
+The server assigns the [`ClientId`](crate::prelude::ClientId) of each client; a client keeps its id when it reconnects
+by proving that it owns its [`LanSession`].
+
+The responder is hardened against being used in reflection attacks:
+- every request is padded so that the response is never bigger than the request
+- the key exchange only happens once the client has echoed a cookie sent in a challenge, which proves that it can receive
+  messages at its source address
+- the number of messages accepted from each ip address is limited
+- by default, the messages that don't come from the local network are ignored
+  (see [`LanConfig::local_network_only`](crate::prelude::server::LanConfig::local_network_only))
+
+Use [`NetConfig::Lan`](crate::prelude::client::NetConfig::Lan) on the client and
+[`NetConfig::Lan`](crate::prelude::server::NetConfig::Lan) on the server.
+
+The wire format of the discovery messages is:
+- a header: the [`MAGIC`] bytes followed by a message kind byte
+- query (kind 0): zero padding, up to the size of the biggest server info response
+- server info (kind 1): protocol hash (`u64`), game port (`u16`), players (`u16`), max players (`u16`), then the length of the name (`u8`) followed by the name
+- key exchange request (kind 2): the public key of the client, the session id (the first 16 bytes of the SHA-256 digest of the
+  [`MAGIC`] bytes and the session key) and the session proof (HMAC-SHA256 of the public key of the client with the session key),
+  or zeroes if the client has no session, then the cookie (zeroes in the first request) and zero padding up to the size of
+  the key exchange response
+- challenge (kind 4): a cookie (`u64` timestamp and HMAC-SHA256 tag) that the client must send back in its key exchange request
+- key exchange response (kind 3): the public key of the server, the client-to-server and server-to-client keys of the connect token
+  encrypted with the negotiated key, then the game port (`u16`) and the other fields of the connect token in plain text (authenticated
+  as associated data): protocol id, create and expire timestamps, nonce, timeout and encrypted private data. The whole response fits in
+  a single packet of [`MAX_PACKET_SIZE`](crate::connection::netcode::MAX_PACKET_SIZE) bytes
+- resumed key exchange response (kind 5): same as the key exchange response, sent when the client resumed its session
+
+All integers are little-endian.
+*/
+pub use discovery::{negotiate_connect_token, scan, LanResponder, LanSession, ServerInfo};
+
+pub(crate) mod client;
+mod discovery;
+pub(crate) mod server;
+
+/// Default port of the LAN discovery responder
+pub const DEFAULT_DISCOVERY_PORT: u16 = 5002;
+
+/// Bytes at the start of every LAN discovery message
+pub const MAGIC: &[u8; 6] = b"LYLAN1";
@@ -0,0 +1,102 @@
+//! Netcode server that can be discovered by the clients on the local network
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use anyhow::{Context, Result};
+
+use super::LanResponder;
+use crate::connection::netcode::{generate_key, ClientId, ConnectTokenIssuer, USER_DATA_BYTES};
+use crate::connection::server::NetServer;
+use crate::connection::DisconnectReason;
+use crate::packet::packet::Packet;
+use crate::prelude::Io;
+use crate::server::config::LanConfig;
+
+pub(crate) struct Server {
+    inner: crate::connection::netcode::Server,
+    responder: LanResponder,
+}
+
+impl Server {
+    pub(crate) fn new(config: LanConfig, io: Io) -> Result<Self> {
+        let mut netcode = config.netcode;
+        netcode.max_clients = config.max_players.into();
+        let private_key = *netcode.private_key.get_or_insert_with(generate_key);
+        // the server doesn't know at which address the clients reach it, so the tokens use an
+        // unspecified address that the clients replace with the address of the responder
+        let server_addr =
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), io.local_addr().port());
+        // any client that completes the key exchange is allowed to connect, with the id chosen by
+        // the responder
+        let issuer = ConnectTokenIssuer::new(
+            server_addr,
+            netcode.protocol_id,
+            private_key,
+            |credentials| Some(u64::from_le_bytes(credentials.try_into().ok()?)),
+        )
+        .timeout_seconds(netcode.client_timeout_secs);
+        let responder = LanResponder::start(
+            config.discovery_addr,
+            config.name,
+            server_addr.port(),
+            config.max_players,
+            netcode.protocol_hash,
+            issuer,
+            config.local_network_only,
+        )
+        .context("could not start the LAN discovery responder")?;
+        Ok(Self {
+            inner: crate::connection::netcode::Server::new(netcode, io),
+            responder,
+        })
+    }
+}
+
+impl NetServer for Server {
+    fn start(&mut self) -> Result<()> {
+        self.inner.start()
+    }
+
+    fn connected_client_ids(&self) -> Vec<ClientId> {
+        self.inner.connected_client_ids()
+    }
+
+    fn try_update(&mut self, delta_ms: f64) -> Result<()> {
+        self.inner.try_update(delta_ms)?;
+        let players = self.inner.connected_client_ids().len();
+        self.responder
+            .set_players(players.try_into().unwrap_or(u16::MAX));
+        Ok(())
+    }
+
+    fn recv(&mut self) -> Option<(Packet, ClientId)> {
+        self.inner.recv()
+    }
+
+    fn send(&mut self, buf: &[u8], client_id: ClientId) -> Result<()> {
+        self.inner.send(buf, client_id)
+    }
+
+    fn new_connections(&self) -> Vec<ClientId> {
+        self.inner.new_connections()
+    }
+
+    fn new_disconnections(&self) -> Vec<(ClientId, DisconnectReason)> {
+        self.inner.new_disconnections()
+    }
+
+    fn disconnect(&mut self, client_id: ClientId, reason: DisconnectReason) -> Result<()> {
+        self.inner.disconnect(client_id, reason)
+    }
+
+    fn user_data(&self, client_id: ClientId) -> Option<[u8; USER_DATA_BYTES]> {
+        self.inner.user_data(client_id)
+    }
+
+    fn io(&self) -> &Io {
+        self.inner.io()
+    }
+
+    fn io_mut(&mut self) -> Option<&mut Io> {
+        self.inner.io_mut()
+    }
+}
@@ -0,0 +1,83 @@
+//! Connection of the local client of a host-server, which runs in the same app as the server
+//! (see [`Mode::HostServer`](crate::prelude::Mode::HostServer)).
+//!
+//! The local client doesn't use a transport: the messages are exchanged directly with the server
+//! (see [`host_server`](crate::client::host_server)).
+use std::net::SocketAddr;
+
+use anyhow::Result;
+
+use crate::connection::client::NetClient;
+use crate::connection::netcode::ClientId;
+use crate::connection::DisconnectReason;
+use crate::packet::packet::Packet;
+use crate::transport::io::Io;
+use crate::transport::LOCAL_SOCKET;
+
+pub(crate) struct LocalClient {
+    id: ClientId,
+    connected: bool,
+    disconnect_reason: Option<DisconnectReason>,
+}
+
+impl LocalClient {
+    pub(crate) fn new(id: ClientId) -> Self {
+        Self {
+            id,
+            connected: false,
+            disconnect_reason: None,
+        }
+    }
+}
+
+impl NetClient for LocalClient {
+    fn connect(&mut self) -> Result<()> {
+        self.connected = true;
+        self.disconnect_reason = None;
+        Ok(())
+    }
+
+    fn disconnect(&mut self) -> Result<()> {
+        if self.connected {
+            self.connected = false;
+            self.disconnect_reason = Some(DisconnectReason::ClientDisconnected);
+        }
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    fn try_update(&mut self, delta_ms: f64) -> Result<()> {
+        Ok(())
+    }
+
+    fn recv(&mut self) -> Option<Packet> {
+        None
+    }
+
+    fn send(&mut self, buf: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    fn id(&self) -> ClientId {
+        self.id
+    }
+
+    fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        self.disconnect_reason
+    }
+
+    fn local_addr(&self) -> SocketAddr {
+        LOCAL_SOCKET
+    }
+
+    fn io(&self) -> Option<&Io> {
+        None
+    }
+
+    fn io_mut(&mut self) -> Option<&mut Io> {
+        None
+    }
+}
@@ -1,9 +1,92 @@
 /*!  A connection is an abstraction over an unreliable transport of a connection between a client and server
 */
 pub(crate) mod client;
+#[cfg(not(target_family = "wasm"))]
+pub mod lan;
+pub(crate) mod local;
 pub mod netcode;
 
 pub(crate) mod server;
 
 #[cfg(feature = "steam")]
 pub(crate) mod steam;
+
+/// Reason why the connection between a client and the server was closed (or could not be established)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// The client closed the connection
+    ClientDisconnected,
+    /// The server disconnected the client, with an application-defined reason code
+    Kicked(u8),
+    /// The server is shutting down
+    ServerShutdown,
+    /// The server refused the connection, with the reason code returned by the connection validator
+    Denied(u8),
+    /// The server refused the connection because it already has the maximum number of clients
+    ServerFull,
+    /// The token service refused to give a connect token to the client
+    TokenRequestDenied,
+    /// The server disconnected the client because it exceeded its rate limits
+    /// (see [`RateLimitPolicy::Disconnect`](crate::server::config::RateLimitPolicy::Disconnect))
+    RateLimited,
+    /// No packets were received from the other peer for too long
+    Timeout,
+    /// The connect token expired before the connection could be established
+    ConnectTokenExpired,
+    /// The transport closed the connection without providing a more specific reason
+    ConnectionClosed,
+    /// The client and the server were built with different protocols
+    /// (see [`Protocol::protocol_hash`](crate::protocol::Protocol::protocol_hash))
+    ProtocolMismatch,
+}
+
+impl DisconnectReason {
+    /// Returns true if the client should not try to reconnect automatically after being disconnected for this reason
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            DisconnectReason::ClientDisconnected
+                | DisconnectReason::Kicked(_)
+                | DisconnectReason::Denied(_)
+                | DisconnectReason::ServerFull
+                | DisconnectReason::TokenRequestDenied
+                | DisconnectReason::RateLimited
+                | DisconnectReason::ProtocolMismatch
+        )
+    }
+
+    /// Encode the reason as a kind byte and a code byte, to send it in a disconnect packet
+    pub(crate) fn to_bytes(self) -> [u8; 2] {
+        match self {
+            DisconnectReason::ClientDisconnected => [0, 0],
+            DisconnectReason::Kicked(code) => [1, code],
+            DisconnectReason::ServerShutdown => [2, 0],
+            DisconnectReason::Denied(code) => [3, code],
+            DisconnectReason::Timeout => [4, 0],
+            DisconnectReason::ConnectTokenExpired => [5, 0],
+            DisconnectReason::ConnectionClosed => [6, 0],
+            DisconnectReason::ProtocolMismatch => [7, 0],
+            DisconnectReason::ServerFull => [8, 0],
+            DisconnectReason::TokenRequestDenied => [9, 0],
+            DisconnectReason::RateLimited => [10, 0],
+        }
+    }
+
+    pub(crate) fn from_bytes(bytes: [u8; 2]) -> Option<Self> {
+        let [kind, code] = bytes;
+        Some(match kind {
+            0 => DisconnectReason::ClientDisconnected,
+            1 => DisconnectReason::Kicked(code),
+            2 => DisconnectReason::ServerShutdown,
+            3 => DisconnectReason::Denied(code),
+            4 => DisconnectReason::Timeout,
+            5 => DisconnectReason::ConnectTokenExpired,
+            6 => DisconnectReason::ConnectionClosed,
+            7 => DisconnectReason::ProtocolMismatch,
+            8 => DisconnectReason::ServerFull,
+            9 => DisconnectReason::TokenRequestDenied,
+            10 => DisconnectReason::RateLimited,
+            _ => return None,
+        })
+    }
+}
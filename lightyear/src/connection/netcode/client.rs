@@ -4,9 +4,11 @@ use std::{collections::VecDeque, net::SocketAddr};
 
 use anyhow::Context;
 use bevy::prelude::Resource;
+use crossbeam_channel::{Receiver, TryRecvError};
 use tracing::{debug, error, info, trace};
 
 use crate::connection::client::NetClient;
+use crate::connection::DisconnectReason;
 use crate::prelude::IoConfig;
 use crate::serialize::reader::ReadBuffer;
 use crate::serialize::wordbuffer::reader::{BufferPool, ReadWordBuffer};
@@ -21,11 +23,15 @@ use super::{
     },
     replay::ReplayProtection,
     token::{ChallengeToken, ConnectToken},
+    token_service::request_connect_token,
     utils, ClientId, MAX_PACKET_SIZE, MAX_PKT_BUF_SIZE, PACKET_SEND_RATE_SEC,
 };
 
 type Callback<Ctx> = Box<dyn FnMut(ClientState, ClientState, &mut Ctx) + Send + Sync + 'static>;
 
+/// Maximum time spent waiting for the token service when requesting a connect token
+const TOKEN_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Configuration for a client.
 ///
 /// * `num_disconnect_packets` - The number of redundant disconnect packets that will be sent to a server when the clients wants to disconnect.
@@ -56,6 +62,7 @@ type Callback<Ctx> = Box<dyn FnMut(ClientState, ClientState, &mut Ctx) + Send +
 pub struct ClientConfig<Ctx> {
     num_disconnect_packets: usize,
     packet_send_rate: f64,
+    protocol_hash: u64,
     context: Ctx,
     on_state_change: Option<Callback<Ctx>>,
 }
@@ -65,6 +72,7 @@ impl Default for ClientConfig<()> {
         Self {
             num_disconnect_packets: 10,
             packet_send_rate: PACKET_SEND_RATE_SEC,
+            protocol_hash: 0,
             context: (),
             on_state_change: None,
         }
@@ -81,6 +89,7 @@ impl<Ctx> ClientConfig<Ctx> {
         Self {
             num_disconnect_packets: 10,
             packet_send_rate: PACKET_SEND_RATE_SEC,
+            protocol_hash: 0,
             context: ctx,
             on_state_change: None,
         }
@@ -97,6 +106,13 @@ impl<Ctx> ClientConfig<Ctx> {
         self.packet_send_rate = rate_seconds;
         self
     }
+    /// Set the hash of the protocol used by the client, that will be sent to the server in the connection request.
+    /// The server denies the connection if it doesn't match its own protocol hash.
+    /// The default is `0`.
+    pub fn protocol_hash(mut self, protocol_hash: u64) -> Self {
+        self.protocol_hash = protocol_hash;
+        self
+    }
     /// Set a callback that will be called when the client changes states.
     pub fn on_state_change<F>(mut self, cb: F) -> Self
     where
@@ -194,6 +210,8 @@ pub struct NetcodeClient<Ctx = ()> {
     replay_protection: ReplayProtection,
     should_disconnect: bool,
     should_disconnect_state: ClientState,
+    should_disconnect_reason: DisconnectReason,
+    disconnect_reason: Option<DisconnectReason>,
     packet_queue: VecDeque<crate::packet::packet::Packet>,
     buffer_pool: BufferPool,
     cfg: ClientConfig<Ctx>,
@@ -229,6 +247,8 @@ impl<Ctx> NetcodeClient<Ctx> {
             replay_protection: ReplayProtection::new(),
             should_disconnect: false,
             should_disconnect_state: ClientState::Disconnected,
+            should_disconnect_reason: DisconnectReason::ConnectionClosed,
+            disconnect_reason: None,
             packet_queue: VecDeque::new(),
             buffer_pool: BufferPool::default(),
             cfg,
@@ -307,6 +327,7 @@ impl<Ctx> NetcodeClient<Ctx> {
         self.last_receive_time = self.time;
         self.should_disconnect = false;
         self.should_disconnect_state = ClientState::Disconnected;
+        self.should_disconnect_reason = DisconnectReason::ConnectionClosed;
         self.challenge_token_sequence = 0;
         self.replay_protection = ReplayProtection::new();
     }
@@ -327,6 +348,7 @@ impl<Ctx> NetcodeClient<Ctx> {
                 debug!("client sending connection request packet to server");
                 RequestPacket::create(
                     self.token.protocol_id,
+                    self.cfg.protocol_hash,
                     self.token.expire_timestamp,
                     self.token.nonce,
                     self.token.private_data,
@@ -378,11 +400,13 @@ impl<Ctx> NetcodeClient<Ctx> {
         }
         match (packet, self.state) {
             (
-                Packet::Denied(_),
+                Packet::Denied(pkt),
                 ClientState::SendingConnectionRequest | ClientState::SendingChallengeResponse,
             ) => {
+                debug!(reason = ?pkt.reason, "client connection denied by server");
                 self.should_disconnect = true;
                 self.should_disconnect_state = ClientState::ConnectionDenied;
+                self.should_disconnect_reason = pkt.reason;
             }
             (Packet::Challenge(pkt), ClientState::SendingConnectionRequest) => {
                 debug!("client received connection challenge packet from server");
@@ -417,10 +441,11 @@ impl<Ctx> NetcodeClient<Ctx> {
                 // TODO: control the size/memory of the packet queue?
                 self.packet_queue.push_back(packet);
             }
-            (Packet::Disconnect(_), ClientState::Connected) => {
-                debug!("client received disconnect packet from server");
+            (Packet::Disconnect(pkt), ClientState::Connected) => {
+                debug!(reason = ?pkt.reason, "client received disconnect packet from server");
                 self.should_disconnect = true;
                 self.should_disconnect_state = ClientState::Disconnected;
+                self.should_disconnect_reason = pkt.reason;
             }
             _ => return Ok(()),
         }
@@ -437,6 +462,7 @@ impl<Ctx> NetcodeClient<Ctx> {
                 if is_token_expired =>
             {
                 info!("client connect failed. connect token expired");
+                self.disconnect_reason = Some(DisconnectReason::ConnectTokenExpired);
                 ClientState::ConnectTokenExpired
             }
             _ if self.should_disconnect => {
@@ -447,6 +473,7 @@ impl<Ctx> NetcodeClient<Ctx> {
                 if self.connect_to_next_server().is_ok() {
                     return;
                 };
+                self.disconnect_reason = Some(self.should_disconnect_reason);
                 self.should_disconnect_state
             }
             ClientState::SendingConnectionRequest if is_connection_timed_out => {
@@ -454,6 +481,7 @@ impl<Ctx> NetcodeClient<Ctx> {
                 if self.connect_to_next_server().is_ok() {
                     return;
                 };
+                self.disconnect_reason = Some(DisconnectReason::Timeout);
                 ClientState::ConnectionRequestTimedOut
             }
             ClientState::SendingChallengeResponse if is_connection_timed_out => {
@@ -461,10 +489,12 @@ impl<Ctx> NetcodeClient<Ctx> {
                 if self.connect_to_next_server().is_ok() {
                     return;
                 };
+                self.disconnect_reason = Some(DisconnectReason::Timeout);
                 ClientState::ChallengeResponseTimedOut
             }
             ClientState::Connected if is_connection_timed_out => {
                 info!("client connection timed out");
+                self.disconnect_reason = Some(DisconnectReason::Timeout);
                 ClientState::ConnectionTimedOut
             }
             _ => return,
@@ -517,6 +547,7 @@ impl<Ctx> NetcodeClient<Ctx> {
     /// This function does not perform any IO, it only readies the client to send/receive packets on the next call to [`update`](NetcodeClient::update). <br>
     pub fn connect(&mut self) {
         self.reset_connection();
+        self.disconnect_reason = None;
         self.set_state(ClientState::SendingConnectionRequest);
         info!(
             "client connecting to server {} [{}/{}]",
@@ -525,6 +556,25 @@ impl<Ctx> NetcodeClient<Ctx> {
             self.token.server_addresses.len()
         );
     }
+    /// Replace the connect token used to connect to the server.
+    ///
+    /// Must be called before [`connect`](NetcodeClient::connect).
+    pub(crate) fn set_token(&mut self, token: ConnectToken) {
+        self.token = token;
+        self.server_addr_idx = 0;
+    }
+    /// Abort the connection attempt before any packet was sent to the server
+    /// (for example because no connect token could be obtained).
+    pub(crate) fn abort_connect(&mut self, reason: DisconnectReason) {
+        self.disconnect_reason = Some(reason);
+        let state = match reason {
+            DisconnectReason::Denied(_) | DisconnectReason::TokenRequestDenied => {
+                ClientState::ConnectionDenied
+            }
+            _ => ClientState::Disconnected,
+        };
+        self.reset(state);
+    }
     /// Updates the client.
     ///
     /// * Updates the client's elapsed time.
@@ -549,6 +599,7 @@ impl<Ctx> NetcodeClient<Ctx> {
         self.time += delta_ms;
         self.recv_packets(io)?;
         self.send_packets(io)?;
+        io.flush()?;
         self.update_state();
         Ok(())
     }
@@ -613,8 +664,12 @@ impl<Ctx> NetcodeClient<Ctx> {
             self.cfg.num_disconnect_packets
         );
         for _ in 0..self.cfg.num_disconnect_packets {
-            self.send_packet(DisconnectPacket::create(), io)?;
+            self.send_packet(
+                DisconnectPacket::create(DisconnectReason::ClientDisconnected),
+                io,
+            )?;
         }
+        self.disconnect_reason = Some(DisconnectReason::ClientDisconnected);
         self.reset(ClientState::Disconnected);
         Ok(())
     }
@@ -627,6 +682,11 @@ impl<Ctx> NetcodeClient<Ctx> {
     pub fn is_error(&self) -> bool {
         self.state < ClientState::Disconnected
     }
+    /// Returns why the client was disconnected from the server (or could not connect to it),
+    /// until the next call to [`connect`](NetcodeClient::connect)
+    pub fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        self.disconnect_reason
+    }
     /// Returns true if the client is in a pending state.
     pub fn is_pending(&self) -> bool {
         self.state == ClientState::SendingConnectionRequest
@@ -648,10 +708,53 @@ pub struct Client<Ctx> {
     pub client: NetcodeClient<Ctx>,
     pub io_config: IoConfig,
     pub io: Option<Io>,
+    /// If set, a new connect token is requested from the token service before each connection
+    pub token_request: Option<TokenRequest>,
+    /// Connect token that is being requested from the token service or negotiated with the LAN server
+    pub(crate) pending_token: Option<Receiver<Result<ConnectToken>>>,
+}
+
+/// Parameters used to fetch a connect token from a [`ConnectTokenIssuer`](super::ConnectTokenIssuer)
+#[derive(Clone, Debug)]
+pub struct TokenRequest {
+    pub token_server_addr: SocketAddr,
+    pub credentials: Vec<u8>,
+}
+
+impl<Ctx> Client<Ctx> {
+    /// Connect to the server with the connect token returned by `get_token`.
+    ///
+    /// `get_token` is blocking, so it runs in a dedicated thread (to avoid blocking the threads of
+    /// the task pools) and the client only starts connecting to the server once the token is received
+    /// (see `try_update`)
+    pub(crate) fn connect_with_token(
+        &mut self,
+        get_token: impl FnOnce() -> Result<ConnectToken> + Send + 'static,
+    ) -> anyhow::Result<()> {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        std::thread::Builder::new()
+            .name("lightyear-connect-token".to_string())
+            .spawn(move || {
+                let _ = sender.send(get_token());
+            })
+            .context("could not spawn the thread requesting the connect token")?;
+        self.io = Some(Io::from_config(self.io_config.clone()));
+        self.pending_token = Some(receiver);
+        Ok(())
+    }
 }
 
 impl<Ctx: Send + Sync> NetClient for Client<Ctx> {
     fn connect(&mut self) -> anyhow::Result<()> {
+        if let Some(request) = self.token_request.clone() {
+            return self.connect_with_token(move || {
+                request_connect_token(
+                    request.token_server_addr,
+                    &request.credentials,
+                    TOKEN_REQUEST_TIMEOUT,
+                )
+            });
+        }
         self.io = Some(Io::from_config(self.io_config.clone()));
         self.client.connect();
         // TODO: have a separate explicit function to start listening on the io
@@ -664,6 +767,30 @@ impl<Ctx: Send + Sync> NetClient for Client<Ctx> {
     }
 
     fn try_update(&mut self, delta_ms: f64) -> anyhow::Result<()> {
+        if let Some(receiver) = &self.pending_token {
+            let token = match receiver.try_recv() {
+                Err(TryRecvError::Empty) => return Ok(()),
+                Err(TryRecvError::Disconnected) => {
+                    Err(Error::Io(std::io::ErrorKind::Interrupted.into()))
+                }
+                Ok(token) => token,
+            };
+            self.pending_token = None;
+            match token {
+                Ok(token) => {
+                    self.client.set_token(token);
+                    self.client.connect();
+                }
+                Err(e) => {
+                    let reason = match e {
+                        Error::TokenRequestDenied => DisconnectReason::TokenRequestDenied,
+                        _ => DisconnectReason::ConnectionClosed,
+                    };
+                    self.client.abort_connect(reason);
+                    return Err(e).context("could not get a connect token");
+                }
+            }
+        }
         let io = self.io.as_mut().context("io is not initialized")?;
         self.client
             .try_update(delta_ms, io)
@@ -684,6 +811,17 @@ impl<Ctx: Send + Sync> NetClient for Client<Ctx> {
         self.client.id()
     }
 
+    fn disconnect(&mut self) -> anyhow::Result<()> {
+        // stop waiting for the connect token
+        self.pending_token = None;
+        let io = self.io.as_mut().context("io is not initialized")?;
+        self.client.disconnect(io).context("could not disconnect")
+    }
+
+    fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        self.client.disconnect_reason()
+    }
+
     fn local_addr(&self) -> SocketAddr {
         self.io.as_ref().map_or(LOCAL_SOCKET, |io| io.local_addr())
     }
@@ -21,6 +21,8 @@ pub enum Error {
     Crypto(#[from] super::crypto::Error),
     #[error("invalid packet: {0}")]
     Packet(#[from] super::packet::Error),
+    #[error("the connect token request was denied")]
+    TokenRequestDenied,
     #[error(transparent)]
     Io(#[from] std::io::Error),
 }
@@ -123,20 +123,27 @@ loop {
 ```
 */
 
-pub use client::{Client, ClientConfig, ClientState, NetcodeClient};
+pub use client::{Client, ClientConfig, ClientState, NetcodeClient, TokenRequest};
 pub use crypto::{generate_key, try_generate_key, Key};
 pub use error::{Error, Result};
-pub use server::{Callback, ClientId, NetcodeServer, Server, ServerConfig};
+pub use server::{
+    Callback, ClientId, ConnectionValidator, DisconnectCallback, NetcodeServer, Server,
+    ServerConfig, MAX_CLIENTS,
+};
 pub use token::{ConnectToken, ConnectTokenBuilder, InvalidTokenError};
+pub use token_service::{
+    request_connect_token, AuthenticateFn, ConnectTokenIssuer, ConnectTokenListener,
+};
 
 mod bytes;
 mod client;
-mod crypto;
+pub(crate) mod crypto;
 mod error;
 mod packet;
 mod replay;
 mod server;
-mod token;
+pub(crate) mod token;
+mod token_service;
 mod utils;
 
 pub(crate) const MAC_BYTES: usize = 16;
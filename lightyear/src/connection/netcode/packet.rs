@@ -8,6 +8,7 @@ use chacha20poly1305::XNonce;
 use tracing::debug;
 
 use crate::connection::netcode::ClientId;
+use crate::connection::DisconnectReason;
 
 use super::{
     bytes::Bytes,
@@ -77,6 +78,8 @@ where
 pub struct RequestPacket {
     pub version_info: [u8; NETCODE_VERSION.len()],
     pub protocol_id: u64,
+    /// Hash of the client's [`Protocol`](crate::protocol::Protocol), checked by the server
+    pub protocol_hash: u64,
     pub expire_timestamp: u64,
     pub token_nonce: XNonce,
     pub token_data: Box<[u8; ConnectTokenPrivate::SIZE]>,
@@ -85,6 +88,7 @@ pub struct RequestPacket {
 impl RequestPacket {
     pub fn create(
         protocol_id: u64,
+        protocol_hash: u64,
         expire_timestamp: u64,
         token_nonce: XNonce,
         token_data: [u8; ConnectTokenPrivate::SIZE],
@@ -92,6 +96,7 @@ impl RequestPacket {
         Packet::Request(RequestPacket {
             version_info: *NETCODE_VERSION,
             protocol_id,
+            protocol_hash,
             expire_timestamp,
             token_nonce,
             token_data: Box::new(token_data),
@@ -132,6 +137,7 @@ impl Bytes for RequestPacket {
     fn write_to(&self, writer: &mut impl WriteBytesExt) -> Result<(), Self::Error> {
         writer.write_all(&self.version_info)?;
         writer.write_u64::<LittleEndian>(self.protocol_id)?;
+        writer.write_u64::<LittleEndian>(self.protocol_hash)?;
         writer.write_u64::<LittleEndian>(self.expire_timestamp)?;
         writer.write_all(&self.token_nonce)?;
         writer.write_all(&self.token_data[..])?;
@@ -142,6 +148,7 @@ impl Bytes for RequestPacket {
         let mut version_info = [0; NETCODE_VERSION.len()];
         reader.read_exact(&mut version_info)?;
         let protocol_id = reader.read_u64::<LittleEndian>()?;
+        let protocol_hash = reader.read_u64::<LittleEndian>()?;
         let expire_timestamp = reader.read_u64::<LittleEndian>()?;
         let mut nonce = [0; size_of::<XNonce>()];
         reader.read_exact(&mut nonce)?;
@@ -151,6 +158,7 @@ impl Bytes for RequestPacket {
         Ok(Self {
             version_info,
             protocol_id,
+            protocol_hash,
             expire_timestamp,
             token_nonce,
             token_data: Box::new(token_data),
@@ -158,22 +166,29 @@ impl Bytes for RequestPacket {
     }
 }
 
-pub struct DeniedPacket {}
+pub struct DeniedPacket {
+    /// Why the connection was denied
+    pub reason: DisconnectReason,
+}
 
 impl DeniedPacket {
-    pub fn create() -> Packet<'static> {
-        Packet::Denied(DeniedPacket {})
+    pub fn create(reason: DisconnectReason) -> Packet<'static> {
+        Packet::Denied(DeniedPacket { reason })
     }
 }
 
 impl Bytes for DeniedPacket {
     type Error = io::Error;
-    fn write_to(&self, _writer: &mut impl WriteBytesExt) -> Result<(), Self::Error> {
-        Ok(())
+    fn write_to(&self, writer: &mut impl WriteBytesExt) -> Result<(), Self::Error> {
+        writer.write_all(&self.reason.to_bytes())
     }
 
-    fn read_from(_reader: &mut impl byteorder::ReadBytesExt) -> Result<Self, io::Error> {
-        Ok(Self {})
+    fn read_from(reader: &mut impl byteorder::ReadBytesExt) -> Result<Self, io::Error> {
+        let mut bytes = [0; 2];
+        reader.read_exact(&mut bytes)?;
+        let reason = DisconnectReason::from_bytes(bytes)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid denied reason"))?;
+        Ok(Self { reason })
     }
 }
 
@@ -270,22 +285,30 @@ impl PayloadPacket<'_> {
     }
 }
 
-pub struct DisconnectPacket {}
+pub struct DisconnectPacket {
+    /// Why the connection was closed
+    pub reason: DisconnectReason,
+}
 
 impl DisconnectPacket {
-    pub fn create() -> Packet<'static> {
-        Packet::Disconnect(Self {})
+    pub fn create(reason: DisconnectReason) -> Packet<'static> {
+        Packet::Disconnect(Self { reason })
     }
 }
 
 impl Bytes for DisconnectPacket {
     type Error = io::Error;
-    fn write_to(&self, _writer: &mut impl WriteBytesExt) -> Result<(), Self::Error> {
-        Ok(())
+    fn write_to(&self, writer: &mut impl WriteBytesExt) -> Result<(), Self::Error> {
+        writer.write_all(&self.reason.to_bytes())
     }
 
-    fn read_from(_reader: &mut impl byteorder::ReadBytesExt) -> Result<Self, io::Error> {
-        Ok(Self {})
+    fn read_from(reader: &mut impl byteorder::ReadBytesExt) -> Result<Self, io::Error> {
+        let mut bytes = [0; 2];
+        reader.read_exact(&mut bytes)?;
+        let reason = DisconnectReason::from_bytes(bytes).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "invalid disconnect reason")
+        })?;
+        Ok(Self { reason })
     }
 }
 
@@ -535,6 +558,7 @@ mod tests {
         let packet = Packet::Request(RequestPacket {
             version_info: *NETCODE_VERSION,
             protocol_id,
+            protocol_hash: 0xabcd,
             expire_timestamp,
             token_nonce: nonce,
             token_data: Box::new(token_data),
@@ -561,6 +585,7 @@ mod tests {
 
         assert_eq!(req_pkt.version_info, *NETCODE_VERSION);
         assert_eq!(req_pkt.protocol_id, protocol_id);
+        assert_eq!(req_pkt.protocol_hash, 0xabcd);
         assert_eq!(req_pkt.expire_timestamp, expire_timestamp);
         assert_eq!(req_pkt.token_nonce, nonce);
 
@@ -585,7 +610,9 @@ mod tests {
         let sequence = 0u64;
         let mut replay_protection = ReplayProtection::new();
 
-        let packet = Packet::Denied(DeniedPacket {});
+        let packet = Packet::Denied(DeniedPacket {
+            reason: DisconnectReason::Denied(7),
+        });
 
         let mut buf = [0u8; MAX_PKT_BUF_SIZE];
         let size = packet
@@ -602,9 +629,10 @@ mod tests {
         )
         .unwrap();
 
-        let Packet::Denied(_denied_pkt) = packet else {
+        let Packet::Denied(denied_pkt) = packet else {
             panic!("wrong packet type");
         };
+        assert_eq!(denied_pkt.reason, DisconnectReason::Denied(7));
     }
 
     #[test]
@@ -679,7 +707,7 @@ mod tests {
         let sequence = 0u64;
         let mut replay_protection = ReplayProtection::new();
 
-        let packet = Packet::Disconnect(DisconnectPacket {});
+        let packet = DisconnectPacket::create(DisconnectReason::Kicked(3));
 
         let mut buf = [0u8; MAX_PKT_BUF_SIZE];
         let size = packet
@@ -696,9 +724,10 @@ mod tests {
         )
         .unwrap();
 
-        let Packet::Disconnect(_disconnect_pkt) = packet else {
+        let Packet::Disconnect(disconnect_pkt) = packet else {
             panic!("wrong packet type");
         };
+        assert_eq!(disconnect_pkt.reason, DisconnectReason::Kicked(3));
     }
 
     #[test]
@@ -1,5 +1,6 @@
 use std::collections::{HashMap, VecDeque};
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::Context;
@@ -8,6 +9,7 @@ use tracing::{debug, error, trace};
 
 use crate::connection::netcode::token::TOKEN_EXPIRE_SEC;
 use crate::connection::server::NetServer;
+use crate::connection::DisconnectReason;
 use crate::serialize::reader::ReadBuffer;
 use crate::serialize::wordbuffer::reader::{BufferPool, ReadWordBuffer};
 use crate::server::config::NetcodeConfig;
@@ -25,7 +27,7 @@ use super::{
     },
     replay::ReplayProtection,
     token::{ChallengeToken, ConnectToken, ConnectTokenBuilder, ConnectTokenPrivate},
-    MAC_BYTES, MAX_PACKET_SIZE, MAX_PKT_BUF_SIZE, PACKET_SEND_RATE_SEC,
+    MAC_BYTES, MAX_PACKET_SIZE, MAX_PKT_BUF_SIZE, PACKET_SEND_RATE_SEC, USER_DATA_BYTES,
 };
 
 pub const MAX_CLIENTS: usize = 256;
@@ -122,6 +124,9 @@ struct ConnectionCache {
     // we are not using a free-list here to not allocate memory up-front, since `ReplayProtection` is biggish (~2kb)
     replay_protection: HashMap<ClientId, ReplayProtection>,
 
+    // user data from the connect token of the connected clients
+    user_data: HashMap<ClientId, [u8; USER_DATA_BYTES]>,
+
     // packet queue for all clients
     packet_queue: VecDeque<(crate::packet::packet::Packet, ClientId)>,
 
@@ -138,6 +143,7 @@ impl ConnectionCache {
             clients: HashMap::with_capacity(MAX_CLIENTS),
             client_id_map: HashMap::with_capacity(MAX_CLIENTS),
             replay_protection: HashMap::with_capacity(MAX_CLIENTS),
+            user_data: HashMap::with_capacity(MAX_CLIENTS),
             packet_queue: VecDeque::with_capacity(MAX_CLIENTS * 2),
             buffer_pool: BufferPool::default(),
             time: server_time,
@@ -151,13 +157,21 @@ impl ConnectionCache {
         send_key: Key,
         receive_key: Key,
     ) {
-        if let Some((_, ref mut existing)) = self.find_by_addr(&addr) {
-            existing.client_id = client_id;
-            existing.timeout = timeout;
-            existing.send_key = send_key;
-            existing.receive_key = receive_key;
-            existing.last_access_time = self.time;
-            return;
+        // a new connection request from the same address (for example a client that reconnects with a new token)
+        // replaces the pending connection
+        if let Some(existing_id) = self.client_id_map.get(&addr).copied() {
+            if existing_id == client_id {
+                if let Some(existing) = self.clients.get_mut(&client_id) {
+                    existing.timeout = timeout;
+                    existing.send_key = send_key;
+                    existing.receive_key = receive_key;
+                    existing.last_access_time = self.time;
+                    return;
+                }
+            } else {
+                self.clients.remove(&existing_id);
+                self.replay_protection.remove(&existing_id);
+            }
         }
         let conn = Connection {
             confirmed: false,
@@ -187,6 +201,19 @@ impl ConnectionCache {
         }
         self.client_id_map.remove(&conn.addr);
         self.replay_protection.remove(&client_id);
+        self.user_data.remove(&client_id);
+        self.clients.remove(&client_id);
+    }
+    /// Remove a client that is not connected yet
+    fn remove_pending(&mut self, client_id: ClientId) {
+        let Some(conn) = self.clients.get(&client_id) else {
+            return;
+        };
+        if conn.is_connected() {
+            return;
+        }
+        self.client_id_map.remove(&conn.addr);
+        self.replay_protection.remove(&client_id);
         self.clients.remove(&client_id);
     }
 
@@ -218,12 +245,22 @@ impl ConnectionCache {
 
 pub type Callback<Ctx> = Box<dyn FnMut(ClientId, &mut Ctx) + Send + Sync + 'static>;
 
+/// Callback called with the id of the client that was disconnected, and the reason of the disconnection
+pub type DisconnectCallback<Ctx> =
+    Box<dyn FnMut(ClientId, DisconnectReason, &mut Ctx) + Send + Sync + 'static>;
+
+/// Callback that decides if a client is allowed to connect, from its id and the user data of its connect token.
+///
+/// Returns `Err(reason)` to deny the connection; the reason code is sent to the client.
+pub type ConnectionValidator =
+    dyn Fn(ClientId, &[u8; USER_DATA_BYTES]) -> std::result::Result<(), u8> + Send + Sync;
+
 /// Configuration for a server.
 ///
 /// * `num_disconnect_packets` - The number of redundant disconnect packets that will be sent to a client when the server is disconnecting it.
 /// * `keep_alive_send_rate` - The rate at which keep-alive packets will be sent to clients.
 /// * `on_connect` - A callback that will be called when a client is connected to the server.
-/// * `on_disconnect` - A callback that will be called when a client is disconnected from the server.
+/// * `on_disconnect` - A callback that will be called when a client is disconnected from the server, with the reason of the disconnection.
 ///
 /// # Example
 /// ```
@@ -249,7 +286,10 @@ pub struct ServerConfig<Ctx> {
     server_addr: SocketAddr,
     context: Ctx,
     on_connect: Option<Callback<Ctx>>,
-    on_disconnect: Option<Callback<Ctx>>,
+    on_disconnect: Option<DisconnectCallback<Ctx>>,
+    validator: Option<Arc<ConnectionValidator>>,
+    protocol_hash: u64,
+    max_clients: usize,
 }
 
 impl Default for ServerConfig<()> {
@@ -263,6 +303,9 @@ impl Default for ServerConfig<()> {
             context: (),
             on_connect: None,
             on_disconnect: None,
+            validator: None,
+            protocol_hash: 0,
+            max_clients: MAX_CLIENTS,
         }
     }
 }
@@ -283,6 +326,9 @@ impl<Ctx> ServerConfig<Ctx> {
             context: ctx,
             on_connect: None,
             on_disconnect: None,
+            validator: None,
+            protocol_hash: 0,
+            max_clients: MAX_CLIENTS,
         }
     }
     /// Set the number of redundant disconnect packets that will be sent to a client when the server is disconnecting it. <br>
@@ -327,16 +373,38 @@ impl<Ctx> ServerConfig<Ctx> {
         self
     }
     /// Provide a callback that will be called when a client is disconnected from the server. <br>
-    /// The callback will be called with the client index and the context that was provided (provide a `None` context if you don't need one).
+    /// The callback will be called with the client index, the reason of the disconnection and the context that was provided (provide a `None` context if you don't need one).
     ///
     /// See [`ServerConfig`] for an example.
     pub fn on_disconnect<F>(mut self, cb: F) -> Self
     where
-        F: FnMut(ClientId, &mut Ctx) + Send + Sync + 'static,
+        F: FnMut(ClientId, DisconnectReason, &mut Ctx) + Send + Sync + 'static,
     {
         self.on_disconnect = Some(Box::new(cb));
         self
     }
+    /// Provide a callback that decides if a client is allowed to connect. <br>
+    /// The callback will be called with the client id and the user data of its connect token,
+    /// before the client is connected.
+    ///
+    /// See [`ConnectionValidator`] for more details.
+    pub fn validate_connection(mut self, validator: Arc<ConnectionValidator>) -> Self {
+        self.validator = Some(validator);
+        self
+    }
+    /// Set the hash of the protocol used by the server. <br>
+    /// Clients that send a different protocol hash in their connection request are denied with
+    /// [`DisconnectReason::ProtocolMismatch`]. The default is `0`.
+    pub fn protocol_hash(mut self, protocol_hash: u64) -> Self {
+        self.protocol_hash = protocol_hash;
+        self
+    }
+    /// Set the maximum number of clients that can be connected at the same time. <br>
+    /// The other clients are denied with [`DisconnectReason::ServerFull`]. The default is 256 clients.
+    pub fn max_clients(mut self, max_clients: usize) -> Self {
+        self.max_clients = max_clients;
+        self
+    }
 }
 
 /// The `netcode` server.
@@ -452,9 +520,9 @@ impl<Ctx> NetcodeServer<Ctx> {
             cb(client_id, &mut self.cfg.context)
         }
     }
-    fn on_disconnect(&mut self, client_id: ClientId) {
+    fn on_disconnect(&mut self, client_id: ClientId, reason: DisconnectReason) {
         if let Some(cb) = self.cfg.on_disconnect.as_mut() {
-            cb(client_id, &mut self.cfg.context)
+            cb(client_id, reason, &mut self.cfg.context)
         }
     }
     fn touch_client(&mut self, client_id: Option<ClientId>) -> Result<()> {
@@ -502,10 +570,12 @@ impl<Ctx> NetcodeServer<Ctx> {
                 }
                 Ok(())
             }
-            Packet::Disconnect(_) => {
+            Packet::Disconnect(packet) => {
                 if let Some(idx) = client_id {
-                    debug!("server disconnected client {idx}");
-                    self.on_disconnect(idx);
+                    // the reason sent by the client is not trusted: it could claim to be kicked or timed out
+                    // to control whether its session is kept after the disconnection
+                    debug!(reason = ?packet.reason, "client {idx} disconnected");
+                    self.on_disconnect(idx, DisconnectReason::ClientDisconnected);
                     self.conn_cache.remove(idx);
                 }
                 Ok(())
@@ -546,34 +616,6 @@ impl<Ctx> NetcodeServer<Ctx> {
         Ok(())
     }
 
-    // /// A client is requesting a connection to the server via a secure io. Provide them with a ConnectToken
-    // fn process_token_request(
-    //     &mut self,
-    //     from_addr: SocketAddr,
-    //     sender: &mut impl PacketSender,
-    // ) -> Result<()> {
-    //     info!("Received token request from {}", from_addr);
-    //     let client_id = self.conn_cache.new_id();
-    //     let token = self
-    //         .token(client_id, self.local_addr())
-    //         .expire_seconds(self.cfg.token_expire_secs) // defaults to 30 seconds, negative for no expiry
-    //         .timeout_seconds(self.cfg.client_timeout_secs) // defaults to 15 seconds, negative for no timeout
-    //         .generate()
-    //         .unwrap();
-    //     let token_bytes = token.try_into_bytes()?;
-    //
-    //     // TODO: this is complete garbage, fix this.
-    //
-    //     // we can't send packets bigger than the MTU, so we will send one packet with 1000 bytes
-    //     // and another packet with 1048 bytes
-    //     let packet_1 = &token_bytes[..1000];
-    //     let packet_2 = &token_bytes[1000..];
-    //     sender.send(&packet_1, &from_addr).map_err(Error::from)?;
-    //     sender.send(&packet_2, &from_addr).map_err(Error::from)?;
-    //     info!("Generated a ConnectToken.");
-    //     Ok(())
-    // }
-
     fn process_connection_request(
         &mut self,
         from_addr: SocketAddr,
@@ -627,10 +669,24 @@ impl<Ctx> NetcodeServer<Ctx> {
             debug!("server ignored connection request. connect token has already been used");
             return Ok(());
         };
-        if self.num_connected_clients() >= MAX_CLIENTS {
+        if packet.protocol_hash != self.cfg.protocol_hash {
+            debug!(
+                client_hash = packet.protocol_hash,
+                server_hash = self.cfg.protocol_hash,
+                "server denied connection request. protocol mismatch"
+            );
+            self.send_to_addr(
+                DeniedPacket::create(DisconnectReason::ProtocolMismatch),
+                from_addr,
+                token.server_to_client_key,
+                sender,
+            )?;
+            return Ok(());
+        };
+        if self.num_connected_clients() >= self.cfg.max_clients {
             debug!("server denied connection request. server is full");
             self.send_to_addr(
-                DeniedPacket::create(),
+                DeniedPacket::create(DisconnectReason::ServerFull),
                 from_addr,
                 token.server_to_client_key,
                 sender,
@@ -684,10 +740,10 @@ impl<Ctx> NetcodeServer<Ctx> {
             return Ok(());
         };
 
-        if self.num_connected_clients() >= MAX_CLIENTS {
+        if self.num_connected_clients() >= self.cfg.max_clients {
             debug!("server denied connection response. server is full");
             self.send_to_addr(
-                DeniedPacket::create(),
+                DeniedPacket::create(DisconnectReason::ServerFull),
                 from_addr,
                 self.conn_cache
                     .clients
@@ -698,6 +754,26 @@ impl<Ctx> NetcodeServer<Ctx> {
             )?;
             return Ok(());
         };
+        if let Some(validator) = self.cfg.validator.as_ref() {
+            if let Err(reason) = validator(id, &challenge_token.user_data) {
+                debug!(
+                    reason,
+                    "server denied connection response for client {}", id
+                );
+                let send_key = conn.send_key;
+                self.send_to_addr(
+                    DeniedPacket::create(DisconnectReason::Denied(reason)),
+                    from_addr,
+                    send_key,
+                    sender,
+                )?;
+                self.conn_cache.remove_pending(id);
+                return Ok(());
+            }
+        }
+        self.conn_cache
+            .user_data
+            .insert(id, challenge_token.user_data);
         let client = self
             .conn_cache
             .clients
@@ -726,7 +802,7 @@ impl<Ctx> NetcodeServer<Ctx> {
                 && client.last_receive_time + (client.timeout as f64) < self.time
             {
                 debug!("server timed out client {id}");
-                self.on_disconnect(id);
+                self.on_disconnect(id, DisconnectReason::Timeout);
                 self.conn_cache.remove(id);
             }
         }
@@ -756,11 +832,8 @@ impl<Ctx> NetcodeServer<Ctx> {
         sender: &mut impl PacketSender,
     ) -> Result<()> {
         if buf.len() <= 1 {
-            // TODO: make token request something else than this?
-            // if buf == [u8::MAX].as_slice() {
-            //     self.process_token_request(addr, sender)?;
-            // }
             // Too small to be a packet
+            // (connect tokens are issued separately by a `ConnectTokenIssuer`)
             return Ok(());
         }
         let (key, replay_protection) = match self.conn_cache.find_by_addr(&addr) {
@@ -840,6 +913,7 @@ impl<Ctx> NetcodeServer<Ctx> {
         self.check_for_timeouts();
         self.recv_packets(sender, receiver)?;
         self.send_packets(io)?;
+        io.flush()?;
         Ok(())
     }
     /// Receives a packet from a client, if one is available in the queue.
@@ -947,19 +1021,24 @@ impl<Ctx> NetcodeServer<Ctx> {
     }
     /// Disconnects a client.
     ///
-    /// The server will send a number of redundant disconnect packets to the client, and then remove its connection info.
-    pub fn disconnect(&mut self, client_id: ClientId, io: &mut Io) -> Result<()> {
+    /// The server will send a number of redundant disconnect packets (containing the `reason`) to the client, and then remove its connection info.
+    pub fn disconnect(
+        &mut self,
+        client_id: ClientId,
+        reason: DisconnectReason,
+        io: &mut Io,
+    ) -> Result<()> {
         let Some(conn) = self.conn_cache.clients.get_mut(&client_id) else {
             return Ok(());
         };
         if !conn.is_connected() {
             return Ok(());
         }
-        debug!("server disconnecting client {client_id}");
+        debug!(?reason, "server disconnecting client {client_id}");
         for _ in 0..self.cfg.num_disconnect_packets {
-            self.send_to_client(DisconnectPacket::create(), client_id, io)?;
+            self.send_to_client(DisconnectPacket::create(reason), client_id, io)?;
         }
-        self.on_disconnect(client_id);
+        self.on_disconnect(client_id, reason);
         self.conn_cache.remove(client_id);
         Ok(())
     }
@@ -971,7 +1050,7 @@ impl<Ctx> NetcodeServer<Ctx> {
                 continue;
             };
             if conn.is_connected() {
-                self.disconnect(id, io)?;
+                self.disconnect(id, DisconnectReason::ServerShutdown, io)?;
             }
         }
         Ok(())
@@ -1004,6 +1083,11 @@ impl<Ctx> NetcodeServer<Ctx> {
         self.conn_cache.clients.get(&client_id).map(|c| c.addr)
     }
 
+    /// Gets the user data from the connect token of a connected client.
+    pub fn user_data(&self, client_id: ClientId) -> Option<[u8; USER_DATA_BYTES]> {
+        self.conn_cache.user_data.get(&client_id).copied()
+    }
+
     /// Gets the address of the server
     pub fn local_addr(&self) -> SocketAddr {
         self.cfg.server_addr
@@ -1013,13 +1097,15 @@ impl<Ctx> NetcodeServer<Ctx> {
 #[derive(Default)]
 pub(crate) struct NetcodeServerContext {
     pub(crate) connections: Vec<ClientId>,
-    pub(crate) disconnections: Vec<ClientId>,
+    pub(crate) disconnections: Vec<(ClientId, DisconnectReason)>,
 }
 
 #[derive(Resource)]
 pub struct Server {
     server: NetcodeServer<NetcodeServerContext>,
     io: Io,
+    /// Clients that were disconnected by the server outside of [`NetServer::try_update`]
+    pending_disconnections: Vec<(ClientId, DisconnectReason)>,
 }
 
 impl NetServer for Server {
@@ -1035,6 +1121,11 @@ impl NetServer for Server {
         // reset the new connections/disconnections
         self.server.cfg.context.connections.clear();
         self.server.cfg.context.disconnections.clear();
+        self.server
+            .cfg
+            .context
+            .disconnections
+            .append(&mut self.pending_disconnections);
 
         self.server
             .try_update(delta_ms, &mut self.io)
@@ -1055,13 +1146,34 @@ impl NetServer for Server {
         self.server.cfg.context.connections.clone()
     }
 
-    fn new_disconnections(&self) -> Vec<ClientId> {
+    fn new_disconnections(&self) -> Vec<(ClientId, DisconnectReason)> {
         self.server.cfg.context.disconnections.clone()
     }
 
+    fn disconnect(&mut self, client_id: ClientId, reason: DisconnectReason) -> anyhow::Result<()> {
+        self.server
+            .disconnect(client_id, reason, &mut self.io)
+            .context("could not disconnect client")?;
+        // the new disconnections are reset at the start of the next update, so we report
+        // this disconnection during the next update instead
+        let disconnections = &mut self.server.cfg.context.disconnections;
+        if matches!(disconnections.last(), Some((id, _)) if *id == client_id) {
+            self.pending_disconnections.extend(disconnections.pop());
+        }
+        Ok(())
+    }
+
+    fn user_data(&self, client_id: ClientId) -> Option<[u8; USER_DATA_BYTES]> {
+        self.server.user_data(client_id)
+    }
+
     fn io(&self) -> &Io {
         &self.io
     }
+
+    fn io_mut(&mut self) -> Option<&mut Io> {
+        Some(&mut self.io)
+    }
 }
 
 impl Server {
@@ -1073,14 +1185,23 @@ impl Server {
             .on_connect(|id, ctx| {
                 ctx.connections.push(id);
             })
-            .on_disconnect(|id, ctx| {
-                ctx.disconnections.push(id);
+            .on_disconnect(|id, reason, ctx| {
+                ctx.disconnections.push((id, reason));
             });
         cfg = cfg.keep_alive_send_rate(config.keep_alive_send_rate);
         cfg = cfg.num_disconnect_packets(config.num_disconnect_packets);
+        cfg = cfg.protocol_hash(config.protocol_hash);
+        cfg = cfg.max_clients(config.max_clients);
+        if let Some(validator) = config.validator {
+            cfg = cfg.validate_connection(validator.0);
+        }
         let server = NetcodeServer::with_config(config.protocol_id, private_key, cfg)
             .expect("Could not create server netcode");
 
-        Self { server, io }
+        Self {
+            server,
+            io,
+            pending_disconnections: vec![],
+        }
     }
 }
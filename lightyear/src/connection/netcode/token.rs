@@ -1,7 +1,7 @@
 use std::{
     io::{self, Write},
     mem::size_of,
-    net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs},
 };
 
 use byteorder::{LittleEndian, WriteBytesExt};
@@ -428,6 +428,19 @@ impl ConnectToken {
         ConnectTokenBuilder::new(server_addresses, protocol_id, client_id, private_key)
     }
 
+    /// Replace the ip of the public server addresses of the token, keeping their ports.
+    ///
+    /// Used when the server doesn't know at which address the client can reach it.
+    pub(crate) fn set_server_ip(&mut self, ip: IpAddr) -> Result<(), Error> {
+        let addrs: Vec<SocketAddr> = self
+            .server_addresses
+            .iter()
+            .map(|(_, addr)| SocketAddr::new(ip, addr.port()))
+            .collect();
+        self.server_addresses = AddressList::new(&addrs[..])?;
+        Ok(())
+    }
+
     /// Tries to convert the token into a 2048-byte array.
     pub fn try_into_bytes(self) -> Result<[u8; CONNECT_TOKEN_BYTES], io::Error> {
         let mut buf = [0u8; CONNECT_TOKEN_BYTES];
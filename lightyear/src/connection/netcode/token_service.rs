@@ -0,0 +1,296 @@
+//! Service that issues [`ConnectToken`]s to clients
+//!
+//! In the netcode protocol, clients get their [`ConnectToken`] from a backend that authenticates them.
+//! The [`ConnectTokenIssuer`] is a minimal version of that backend: it authenticates clients via a user-provided
+//! callback, and builds the [`ConnectToken`] with the server's private key.
+//!
+//! It can be used in-process with [`ConnectTokenIssuer::issue`], or run on a separate TCP listener with
+//! [`ConnectTokenIssuer::listen`]. Clients fetch their token from the listener with [`request_connect_token`]
+//! (which is done automatically by the client when using
+//! [`Authentication::TokenService`](crate::prelude::client::Authentication::TokenService)).
+//!
+//! The TCP listener does not encrypt the traffic (the token contains the keys used to encrypt the netcode packets):
+//! it should only be used on localhost or behind a TLS-terminating proxy.
+//!
+//! The wire format is:
+//! - request: the length of the credentials as a big-endian `u16`, followed by the credentials
+//! - response: a status byte (0 if the request was accepted, 1 if it was denied), followed by the serialized
+//!   [`ConnectToken`] if the request was accepted
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use tracing::{debug, error, info};
+
+use super::{
+    error::{Error, Result},
+    ClientId, ConnectToken, Key, CONNECTION_TIMEOUT_SEC, CONNECT_TOKEN_BYTES,
+};
+use crate::connection::netcode::token::TOKEN_EXPIRE_SEC;
+
+/// Status byte sent when the token request was accepted
+const TOKEN_ACCEPTED: u8 = 0;
+/// Status byte sent when the token request was denied
+const TOKEN_DENIED: u8 = 1;
+/// Maximum size of the credentials sent by the client
+pub const MAX_CREDENTIALS_BYTES: usize = 1024;
+/// Maximum time spent reading a request or writing a response
+const STREAM_TIMEOUT: Duration = Duration::from_secs(5);
+/// Maximum number of token requests that are handled at the same time by the listener
+const MAX_CONCURRENT_REQUESTS: usize = 64;
+
+/// Callback used to authenticate a client from its credentials.
+/// Returns the id of the client, or None if the client is not allowed to connect.
+pub type AuthenticateFn = dyn Fn(&[u8]) -> Option<ClientId> + Send + Sync;
+
+/// Issues [`ConnectToken`]s to the clients that are authenticated by the user-provided callback
+#[derive(Clone)]
+pub struct ConnectTokenIssuer {
+    server_addr: SocketAddr,
+    protocol_id: u64,
+    private_key: Key,
+    expire_secs: i32,
+    timeout_secs: i32,
+    authenticate: Arc<AuthenticateFn>,
+}
+
+impl ConnectTokenIssuer {
+    /// Create a new issuer for the server at `server_addr`.
+    ///
+    /// The `protocol_id` and `private_key` must be the same as the ones used by the server.
+    pub fn new(
+        server_addr: SocketAddr,
+        protocol_id: u64,
+        private_key: Key,
+        authenticate: impl Fn(&[u8]) -> Option<ClientId> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            server_addr,
+            protocol_id,
+            private_key,
+            expire_secs: TOKEN_EXPIRE_SEC,
+            timeout_secs: CONNECTION_TIMEOUT_SEC,
+            authenticate: Arc::new(authenticate),
+        }
+    }
+
+    /// Set the duration (in seconds) after which the tokens will expire.
+    /// The default is 30 seconds.
+    pub fn expire_seconds(mut self, expire_secs: i32) -> Self {
+        self.expire_secs = expire_secs;
+        self
+    }
+
+    /// Set the duration (in seconds) after which a connection without any received packets will time out.
+    /// The default is 15 seconds.
+    pub fn timeout_seconds(mut self, timeout_secs: i32) -> Self {
+        self.timeout_secs = timeout_secs;
+        self
+    }
+
+    /// Authenticate the client from its credentials and generate a [`ConnectToken`] for it
+    pub fn issue(&self, credentials: &[u8]) -> Result<ConnectToken> {
+        let client_id = (self.authenticate)(credentials).ok_or(Error::TokenRequestDenied)?;
+        ConnectToken::build(
+            self.server_addr,
+            self.protocol_id,
+            client_id,
+            self.private_key,
+        )
+        .expire_seconds(self.expire_secs)
+        .timeout_seconds(self.timeout_secs)
+        .generate()
+    }
+
+    /// Start listening for token requests on a TCP socket, in a separate thread.
+    ///
+    /// Each request is handled in its own thread, so that a slow client cannot block the other ones.
+    /// The listener stops when the returned [`ConnectTokenListener`] is dropped.
+    pub fn listen(self, addr: impl ToSocketAddrs) -> Result<ConnectTokenListener> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let thread_shutdown = shutdown.clone();
+        let handle = std::thread::Builder::new()
+            .name("connect-token-issuer".to_string())
+            .spawn(move || {
+                info!("Listening for connect token requests on {}", local_addr);
+                let pending_requests = Arc::new(AtomicUsize::new(0));
+                for stream in listener.incoming() {
+                    if thread_shutdown.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            error!("Error accepting connect token request: {}", e);
+                            continue;
+                        }
+                    };
+                    if pending_requests.fetch_add(1, Ordering::AcqRel) >= MAX_CONCURRENT_REQUESTS {
+                        pending_requests.fetch_sub(1, Ordering::AcqRel);
+                        debug!(addr = ?stream.peer_addr().ok(), "too many pending connect token requests, dropping request");
+                        continue;
+                    }
+                    let issuer = self.clone();
+                    let handler_pending_requests = pending_requests.clone();
+                    let spawned = std::thread::Builder::new()
+                        .name("connect-token-request".to_string())
+                        .spawn(move || {
+                            if let Err(e) = issuer.handle_request(stream) {
+                                error!("Error handling connect token request: {}", e);
+                            }
+                            handler_pending_requests.fetch_sub(1, Ordering::AcqRel);
+                        });
+                    if let Err(e) = spawned {
+                        pending_requests.fetch_sub(1, Ordering::AcqRel);
+                        error!("Could not spawn the connect token request handler: {}", e);
+                    }
+                }
+            })?;
+        Ok(ConnectTokenListener {
+            local_addr,
+            shutdown,
+            handle: Some(handle),
+        })
+    }
+
+    fn handle_request(&self, mut stream: TcpStream) -> Result<()> {
+        stream.set_read_timeout(Some(STREAM_TIMEOUT))?;
+        stream.set_write_timeout(Some(STREAM_TIMEOUT))?;
+        let mut len = [0; 2];
+        stream.read_exact(&mut len)?;
+        let len = u16::from_be_bytes(len) as usize;
+        if len > MAX_CREDENTIALS_BYTES {
+            return Err(Error::SizeMismatch(MAX_CREDENTIALS_BYTES, len));
+        }
+        let mut credentials = vec![0; len];
+        stream.read_exact(&mut credentials)?;
+        match self.issue(&credentials) {
+            Ok(token) => {
+                stream.write_all(&[TOKEN_ACCEPTED])?;
+                stream.write_all(&token.try_into_bytes()?)?;
+            }
+            Err(Error::TokenRequestDenied) => {
+                debug!(addr = ?stream.peer_addr().ok(), "denied connect token request");
+                stream.write_all(&[TOKEN_DENIED])?;
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+}
+
+/// Handle to a [`ConnectTokenIssuer`] that is listening on a TCP socket.
+///
+/// The listener is stopped when the handle is dropped.
+pub struct ConnectTokenListener {
+    local_addr: SocketAddr,
+    shutdown: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ConnectTokenListener {
+    /// Address that the listener is bound to
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for ConnectTokenListener {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        // wake up the listener thread, which is blocked waiting for a new connection
+        let mut wake_addr = self.local_addr;
+        if wake_addr.ip().is_unspecified() {
+            wake_addr.set_ip(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        }
+        if TcpStream::connect_timeout(&wake_addr, STREAM_TIMEOUT).is_ok() {
+            if let Some(handle) = self.handle.take() {
+                let _ = handle.join();
+            }
+        }
+    }
+}
+
+/// Request a [`ConnectToken`] from a [`ConnectTokenIssuer`] that is listening at `token_server_addr`.
+///
+/// This is a blocking call.
+pub fn request_connect_token(
+    token_server_addr: SocketAddr,
+    credentials: &[u8],
+    timeout: Duration,
+) -> Result<ConnectToken> {
+    if credentials.len() > MAX_CREDENTIALS_BYTES {
+        return Err(Error::SizeMismatch(
+            MAX_CREDENTIALS_BYTES,
+            credentials.len(),
+        ));
+    }
+    let mut stream = TcpStream::connect_timeout(&token_server_addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    stream.write_all(&(credentials.len() as u16).to_be_bytes())?;
+    stream.write_all(credentials)?;
+    let mut status = [0; 1];
+    stream.read_exact(&mut status)?;
+    if status[0] != TOKEN_ACCEPTED {
+        return Err(Error::TokenRequestDenied);
+    }
+    let mut token_bytes = [0; CONNECT_TOKEN_BYTES];
+    stream.read_exact(&mut token_bytes)?;
+    ConnectToken::try_from_bytes(&token_bytes).map_err(Error::InvalidToken)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::connection::netcode::generate_key;
+
+    use super::*;
+
+    fn issuer() -> ConnectTokenIssuer {
+        let server_addr = SocketAddr::from(([127, 0, 0, 1], 5000));
+        ConnectTokenIssuer::new(server_addr, 0, generate_key(), |credentials| {
+            // the credentials are the client id
+            let id: [u8; 8] = credentials.try_into().ok()?;
+            Some(u64::from_le_bytes(id)).filter(|id| *id != 0)
+        })
+    }
+
+    #[test]
+    fn test_issue_token() {
+        let issuer = issuer();
+        let token = issuer.issue(&7u64.to_le_bytes()).unwrap();
+        assert_eq!(token.server_addresses[0], issuer.server_addr);
+        assert!(matches!(
+            issuer.issue(&0u64.to_le_bytes()),
+            Err(Error::TokenRequestDenied)
+        ));
+    }
+
+    #[test]
+    fn test_request_token_over_tcp() {
+        let listener = issuer().listen("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr();
+        let token = request_connect_token(addr, &7u64.to_le_bytes(), STREAM_TIMEOUT).unwrap();
+        assert_eq!(token.timeout_seconds, CONNECTION_TIMEOUT_SEC);
+        assert!(matches!(
+            request_connect_token(addr, b"invalid", STREAM_TIMEOUT),
+            Err(Error::TokenRequestDenied)
+        ));
+        drop(listener);
+        assert!(request_connect_token(addr, &7u64.to_le_bytes(), STREAM_TIMEOUT).is_err());
+    }
+
+    /// A client that doesn't send its request does not block the requests of the other clients
+    #[test]
+    fn test_stalled_request_does_not_block_listener() {
+        let listener = issuer().listen("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr();
+        let _stalled = TcpStream::connect(addr).unwrap();
+        assert!(request_connect_token(addr, &7u64.to_le_bytes(), Duration::from_secs(1)).is_ok());
+    }
+}
@@ -1,16 +1,19 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use bevy::ecs::entity::EntityHash;
 use bevy::prelude::{Entity, Resource};
 
 use crate::_reexport::ReadWordBuffer;
 use crate::connection::client::ClientConnection;
-use crate::connection::netcode::ClientId;
+use crate::connection::netcode::{ClientId, USER_DATA_BYTES};
+use crate::connection::DisconnectReason;
 
 #[cfg(feature = "steam")]
 use crate::connection::steam::server::SteamConfig;
 use crate::packet::packet::Packet;
 
 use crate::prelude::{Io, IoConfig, LinkConditionerConfig};
+#[cfg(not(target_family = "wasm"))]
+use crate::server::config::LanConfig;
 use crate::server::config::NetcodeConfig;
 use crate::utils::free_list::FreeList;
 
@@ -32,9 +35,27 @@ pub trait NetServer: Send + Sync {
 
     fn new_connections(&self) -> Vec<ClientId>;
 
-    fn new_disconnections(&self) -> Vec<ClientId>;
+    /// Return the clients that were disconnected since the last update, with the reason of the disconnection
+    fn new_disconnections(&self) -> Vec<(ClientId, DisconnectReason)>;
+
+    /// Disconnect a client, sending it the reason of the disconnection if the transport supports it
+    fn disconnect(&mut self, client_id: ClientId, reason: DisconnectReason) -> Result<()> {
+        Err(anyhow::anyhow!(
+            "this transport does not support disconnecting clients"
+        ))
+    }
+
+    /// Get the user data that the client provided when connecting (for example in its connect token)
+    fn user_data(&self, client_id: ClientId) -> Option<[u8; USER_DATA_BYTES]> {
+        None
+    }
 
     fn io(&self) -> &Io;
+
+    /// Get mutable access to the inner io, if the transport uses one
+    fn io_mut(&mut self) -> Option<&mut Io> {
+        None
+    }
 }
 
 /// A wrapper around a `Box<dyn NetServer>`
@@ -50,6 +71,16 @@ pub enum NetConfig {
         config: NetcodeConfig,
         io: IoConfig,
     },
+    /// Netcode server that can be discovered and joined by the clients on the local network
+    /// (see [`lan`](crate::connection::lan))
+    #[cfg(not(target_family = "wasm"))]
+    Lan {
+        config: LanConfig,
+        io: IoConfig,
+    },
+    /// Accept the connections of the clients with the steam networking sockets.
+    ///
+    /// The [`protocol hash`](crate::protocol::Protocol::protocol_hash) of the clients is not checked.
     #[cfg(feature = "steam")]
     Steam {
         config: SteamConfig,
@@ -67,6 +98,19 @@ impl Default for NetConfig {
 }
 
 impl NetConfig {
+    /// Set the hash of the protocol that the clients must match to be allowed to connect.
+    ///
+    /// The steam transport does not check the protocol hash.
+    pub(crate) fn set_protocol_hash(&mut self, protocol_hash: u64) {
+        match self {
+            NetConfig::Netcode { config, .. } => config.protocol_hash = protocol_hash,
+            #[cfg(not(target_family = "wasm"))]
+            NetConfig::Lan { config, .. } => config.netcode.protocol_hash = protocol_hash,
+            #[cfg(feature = "steam")]
+            NetConfig::Steam { .. } => {}
+        }
+    }
+
     pub fn build_server(self) -> ServerConnection {
         match self {
             NetConfig::Netcode { config, io } => {
@@ -76,6 +120,16 @@ impl NetConfig {
                     server: Box::new(server),
                 }
             }
+            #[cfg(not(target_family = "wasm"))]
+            NetConfig::Lan { config, io } => {
+                let io = io.get_io();
+                // TODO: handle errors
+                let server = super::lan::server::Server::new(config, io)
+                    .expect("could not create LAN server");
+                ServerConnection {
+                    server: Box::new(server),
+                }
+            }
             // TODO: might want to distinguish between steam with direct ip connections
             //  vs steam with p2p connections
             #[cfg(feature = "steam")]
@@ -119,13 +173,25 @@ impl NetServer for ServerConnection {
         self.server.new_connections()
     }
 
-    fn new_disconnections(&self) -> Vec<ClientId> {
+    fn new_disconnections(&self) -> Vec<(ClientId, DisconnectReason)> {
         self.server.new_disconnections()
     }
 
+    fn disconnect(&mut self, client_id: ClientId, reason: DisconnectReason) -> Result<()> {
+        self.server.disconnect(client_id, reason)
+    }
+
+    fn user_data(&self, client_id: ClientId) -> Option<[u8; USER_DATA_BYTES]> {
+        self.server.user_data(client_id)
+    }
+
     fn io(&self) -> &Io {
         self.server.io()
     }
+
+    fn io_mut(&mut self) -> Option<&mut Io> {
+        self.server.io_mut()
+    }
 }
 
 type EntityHashMap<K, V> = hashbrown::HashMap<K, V, EntityHash>;
@@ -153,6 +219,27 @@ impl ServerConnections {
             global_id_map: client_map::GlobalClientIdMap::new(),
         }
     }
+
+    /// Disconnect a client, with an application-defined reason code.
+    ///
+    /// The client receives [`DisconnectReason::Kicked`] in its [`DisconnectEvent`](crate::client::events::DisconnectEvent)
+    /// and does not try to reconnect. Its session is removed without waiting for the disconnect grace period.
+    pub fn kick(&mut self, client_id: ClientId, reason: u8) -> Result<()> {
+        self.disconnect(client_id, DisconnectReason::Kicked(reason))
+    }
+
+    /// Disconnect a client, sending it the reason of the disconnection
+    pub(crate) fn disconnect(
+        &mut self,
+        client_id: ClientId,
+        reason: DisconnectReason,
+    ) -> Result<()> {
+        let (server_idx, local_client_id) = self
+            .global_id_map
+            .get_local(client_id)
+            .context("client not found")?;
+        self.servers[server_idx].disconnect(local_client_id, reason)
+    }
 }
 
 /// Since we use multiple independent [`ServerConnection`]s and each of them have their own id space, there might be collisions
@@ -162,7 +249,7 @@ pub(crate) mod client_map {
     use super::EntityHashMap;
     use crate::prelude::ClientId;
     use bevy::prelude::Entity;
-    use bevy::utils::HashMap;
+    use bevy::utils::{HashMap, HashSet};
     use tracing::info;
 
     pub(crate) type ServerConnectionIdx = usize;
@@ -174,6 +261,9 @@ pub(crate) mod client_map {
             HashMap<(ServerConnectionIdx, ServerConnectionClientId), GlobalClientId>,
         global_to_connection:
             EntityHashMap<GlobalClientId, (ServerConnectionIdx, ServerConnectionClientId)>,
+        /// Ids that are used by clients that don't go through a [`ServerConnection`](super::ServerConnection)
+        /// (the local client of a host-server)
+        reserved: HashSet<GlobalClientId>,
     }
 
     impl GlobalClientIdMap {
@@ -181,6 +271,7 @@ pub(crate) mod client_map {
             GlobalClientIdMap {
                 connection_to_global: HashMap::default(),
                 global_to_connection: EntityHashMap::default(),
+                reserved: HashSet::default(),
             }
         }
 
@@ -189,19 +280,20 @@ pub(crate) mod client_map {
             server_idx: ServerConnectionIdx,
             client_id: ServerConnectionClientId,
         ) -> GlobalClientId {
+            // the client reconnected to a session that was kept during the disconnect grace period
+            if let Some(global_id) = self.connection_to_global.get(&(server_idx, client_id)) {
+                return *global_id;
+            }
             // generate a new global id for the newly-connected client
 
             // by default, try to reuse the same id as the connection's id
-            let global_id = if !self.global_to_connection.contains_key(&client_id) {
+            let global_id = if !self.is_used(client_id) {
                 client_id as GlobalClientId
             } else {
                 // there is a conflict! we need to find a new id
                 // we will just try randomly until we find one that isn't in use
                 let mut client_id = rand::random::<u64>();
-                while self
-                    .global_to_connection
-                    .contains_key(&(client_id as GlobalClientId))
-                {
+                while self.is_used(client_id as GlobalClientId) {
                     client_id = rand::random::<u64>();
                 }
                 info!("ClientId already used (presumably by another ServerConnection)! Generating a new ClientId: {:?}", client_id);
@@ -214,6 +306,20 @@ pub(crate) mod client_map {
             global_id
         }
 
+        #[inline]
+        fn is_used(&self, client_id: GlobalClientId) -> bool {
+            self.global_to_connection.contains_key(&client_id) || self.reserved.contains(&client_id)
+        }
+
+        /// Reserve a global id, so that it is not assigned to the clients of the [`ServerConnection`](super::ServerConnection)s
+        pub(crate) fn reserve(&mut self, client_id: GlobalClientId) {
+            self.reserved.insert(client_id);
+        }
+
+        pub(crate) fn release(&mut self, client_id: GlobalClientId) {
+            self.reserved.remove(&client_id);
+        }
+
         #[inline]
         pub(crate) fn get_global(
             &self,
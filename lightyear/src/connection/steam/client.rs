@@ -1,5 +1,6 @@
 use crate::_reexport::{ReadBuffer, ReadWordBuffer};
 use crate::connection::client::NetClient;
+use crate::connection::DisconnectReason;
 use crate::packet::packet::Packet;
 use crate::prelude::{ClientId, Io, LinkConditionerConfig};
 use crate::serialize::wordbuffer::reader::BufferPool;
@@ -16,7 +17,7 @@ use steamworks::networking_types::{
 use steamworks::{ClientManager, SingleClient};
 use tracing::{info, warn};
 
-use super::{get_networking_options, SingleClientThreadSafe};
+use super::{get_networking_options, read_disconnect_message, SingleClientThreadSafe};
 
 const MAX_MESSAGE_BATCH_SIZE: usize = 512;
 
@@ -40,6 +41,9 @@ pub struct Client {
     single_client: SingleClientThreadSafe,
     config: SteamConfig,
     connection: Option<NetConnection<ClientManager>>,
+    disconnect_reason: Option<DisconnectReason>,
+    /// Reason of the disconnection sent by the server before closing the connection
+    peer_disconnect_reason: Option<DisconnectReason>,
     packet_queue: VecDeque<Packet>,
     buffer_pool: BufferPool,
     conditioner: Option<LinkConditionerConfig>,
@@ -55,6 +59,8 @@ impl Client {
             single_client: SingleClientThreadSafe(single),
             config,
             connection: None,
+            disconnect_reason: None,
+            peer_disconnect_reason: None,
             packet_queue: VecDeque::new(),
             buffer_pool: BufferPool::default(),
             conditioner,
@@ -76,6 +82,28 @@ impl Client {
             .map_or(Ok(NetworkingConnectionState::None), |info| info.state())
             .context("could not get connection state")
     }
+
+    /// Receive the messages from the server, and queue the packets that they contain
+    fn receive_messages(&mut self) -> Result<()> {
+        let connection = self.connection.as_mut().context("no connection")?;
+        for message in connection
+            .receive_messages(MAX_MESSAGE_BATCH_SIZE)
+            .context("failed to receive messages")?
+        {
+            // the server sends the reason of the disconnection before closing the connection
+            if let Some(reason) = read_disconnect_message(message.data()) {
+                self.peer_disconnect_reason = Some(reason);
+                continue;
+            }
+            // get a buffer from the pool to avoid new allocations
+            let mut reader = self.buffer_pool.start_read(message.data());
+            let packet = Packet::decode(&mut reader).context("could not decode packet")?;
+            // return the buffer to the pool
+            self.buffer_pool.attach(reader);
+            self.packet_queue.push_back(packet);
+        }
+        Ok(())
+    }
 }
 
 impl NetClient for Client {
@@ -87,6 +115,8 @@ impl NetClient for Client {
                 .connect_by_ip_address(self.config.server_addr, options)
                 .context("failed to create connection")?,
         );
+        self.disconnect_reason = None;
+        self.peer_disconnect_reason = None;
         info!(
             "Opened steam connection to server at address: {}",
             self.config.server_addr
@@ -94,6 +124,14 @@ impl NetClient for Client {
         Ok(())
     }
 
+    fn disconnect(&mut self) -> Result<()> {
+        if let Some(connection) = self.connection.take() {
+            connection.close(NetConnectionEnd::AppGeneric, None, false);
+            self.disconnect_reason = Some(DisconnectReason::ClientDisconnected);
+        }
+        Ok(())
+    }
+
     fn is_connected(&self) -> bool {
         matches!(
             self.connection_state(),
@@ -111,26 +149,24 @@ impl NetClient for Client {
             NetworkingConnectionState::Connecting | NetworkingConnectionState::FindingRoute => {
                 Ok(())
             }
-            NetworkingConnectionState::ClosedByPeer
-            | NetworkingConnectionState::ProblemDetectedLocally => {
+            NetworkingConnectionState::ClosedByPeer => {
+                if self.disconnect_reason.is_none() {
+                    // the message with the reason of the disconnection might not have been read yet
+                    let _ = self.receive_messages();
+                    self.disconnect_reason = Some(
+                        self.peer_disconnect_reason
+                            .take()
+                            .unwrap_or(DisconnectReason::ConnectionClosed),
+                    );
+                }
                 Err(anyhow!("connection closed"))
             }
-            NetworkingConnectionState::Connected => {
-                // receive packet
-                let connection = self.connection.as_mut().unwrap();
-                for message in connection
-                    .receive_messages(MAX_MESSAGE_BATCH_SIZE)
-                    .context("failed to receive messages")?
-                {
-                    // get a buffer from the pool to avoid new allocations
-                    let mut reader = self.buffer_pool.start_read(message.data());
-                    let packet = Packet::decode(&mut reader).context("could not decode packet")?;
-                    // return the buffer to the pool
-                    self.buffer_pool.attach(reader);
-                    self.packet_queue.push_back(packet);
-                }
-                Ok(())
+            NetworkingConnectionState::ProblemDetectedLocally => {
+                self.disconnect_reason
+                    .get_or_insert(DisconnectReason::Timeout);
+                Err(anyhow!("connection closed"))
             }
+            NetworkingConnectionState::Connected => self.receive_messages(),
         };
     }
 
@@ -151,6 +187,10 @@ impl NetClient for Client {
         self.client.user().steam_id().raw()
     }
 
+    fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        self.disconnect_reason
+    }
+
     fn local_addr(&self) -> SocketAddr {
         LOCAL_SOCKET
     }
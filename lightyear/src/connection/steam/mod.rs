@@ -1,3 +1,4 @@
+use crate::connection::DisconnectReason;
 use crate::prelude::LinkConditionerConfig;
 use steamworks::networking_types::{NetworkingConfigEntry, NetworkingConfigValue};
 
@@ -11,6 +12,27 @@ pub(crate) struct SingleClientThreadSafe(steamworks::SingleClient);
 unsafe impl Sync for SingleClientThreadSafe {}
 unsafe impl Send for SingleClientThreadSafe {}
 
+/// First byte of the message that carries the reason of a disconnection.
+///
+/// Steam only lets us close a connection with a generic end reason, so the server sends the
+/// [`DisconnectReason`] in a last reliable message before closing the connection.
+/// The message is shorter than any lightyear packet, so it cannot be confused with one.
+const DISCONNECT_MESSAGE_PREFIX: u8 = u8::MAX;
+
+/// Encode the message sent to a client right before its connection is closed
+pub(crate) fn disconnect_message(reason: DisconnectReason) -> [u8; 3] {
+    let [kind, code] = reason.to_bytes();
+    [DISCONNECT_MESSAGE_PREFIX, kind, code]
+}
+
+/// Decode the reason of a disconnection, if the message was created with [`disconnect_message`]
+pub(crate) fn read_disconnect_message(message: &[u8]) -> Option<DisconnectReason> {
+    match *message {
+        [DISCONNECT_MESSAGE_PREFIX, kind, code] => DisconnectReason::from_bytes([kind, code]),
+        _ => None,
+    }
+}
+
 pub(crate) fn get_networking_options(
     conditioner: &Option<LinkConditionerConfig>,
 ) -> Vec<NetworkingConfigEntry> {
@@ -35,6 +57,28 @@ pub(crate) fn get_networking_options(
             NetworkingConfigValue::FakePacketReorderRecv,
             100.0,
         ));
+        // the steam sockets don't go through an `Io`, so the outgoing conditioning
+        // is also applied by steam instead of by the link conditioner
+        options.push(NetworkingConfigEntry::new_float(
+            NetworkingConfigValue::FakePacketLossSend,
+            conditioner.outgoing_loss * 100.0,
+        ));
+        options.push(NetworkingConfigEntry::new_int32(
+            NetworkingConfigValue::FakePacketLagSend,
+            conditioner.outgoing_latency.as_millis() as i32,
+        ));
+        options.push(NetworkingConfigEntry::new_float(
+            NetworkingConfigValue::FakePacketReorderSend,
+            conditioner.outgoing_reordering * 100.0,
+        ));
+        options.push(NetworkingConfigEntry::new_float(
+            NetworkingConfigValue::FakePacketDupSend,
+            conditioner.outgoing_duplication * 100.0,
+        ));
+        options.push(NetworkingConfigEntry::new_float(
+            NetworkingConfigValue::FakePacketDupRecv,
+            conditioner.incoming_duplication * 100.0,
+        ));
     }
     options
 }
@@ -1,6 +1,7 @@
 use crate::_reexport::{ReadBuffer, ReadWordBuffer};
-use crate::connection::netcode::MAX_PACKET_SIZE;
+use crate::connection::netcode::{MAX_PACKET_SIZE, USER_DATA_BYTES};
 use crate::connection::server::NetServer;
+use crate::connection::DisconnectReason;
 use crate::packet::packet::Packet;
 use crate::prelude::{ClientId, Io, LinkConditionerConfig};
 use crate::serialize::wordbuffer::reader::BufferPool;
@@ -17,7 +18,7 @@ use steamworks::networking_types::{
 use steamworks::{ClientManager, Manager, ServerManager, ServerMode, SingleClient, SteamError};
 use tracing::{error, info};
 
-use super::{get_networking_options, SingleClientThreadSafe};
+use super::{disconnect_message, get_networking_options, SingleClientThreadSafe};
 
 #[derive(Debug, Clone)]
 pub struct SteamConfig {
@@ -58,7 +59,9 @@ pub struct Server {
     packet_queue: VecDeque<(Packet, ClientId)>,
     buffer_pool: BufferPool,
     new_connections: Vec<ClientId>,
-    new_disconnections: Vec<ClientId>,
+    new_disconnections: Vec<(ClientId, DisconnectReason)>,
+    /// Clients that were disconnected by the server outside of [`NetServer::try_update`]
+    pending_disconnections: Vec<(ClientId, DisconnectReason)>,
     conditioner: Option<LinkConditionerConfig>,
 }
 
@@ -86,6 +89,7 @@ impl Server {
             buffer_pool: BufferPool::default(),
             new_connections: Vec::new(),
             new_disconnections: Vec::new(),
+            pending_disconnections: Vec::new(),
             conditioner,
         })
     }
@@ -115,6 +119,8 @@ impl NetServer for Server {
         // reset connection events
         self.new_connections.clear();
         self.new_disconnections.clear();
+        self.new_disconnections
+            .append(&mut self.pending_disconnections);
 
         // process connection events
         let Some(listen_socket) = self.listen_socket.as_mut() else {
@@ -136,7 +142,8 @@ impl NetServer for Server {
                     if let Some(steam_id) = event.remote().steam_id() {
                         let client_id = steam_id.raw() as ClientId;
                         info!("Client with id: {:?} disconnected!", client_id);
-                        self.new_disconnections.push(client_id);
+                        self.new_disconnections
+                            .push((client_id, DisconnectReason::ConnectionClosed));
                         self.connections.remove(&client_id);
                     } else {
                         error!("Received disconnection attempt from invalid steam id");
@@ -210,11 +217,38 @@ impl NetServer for Server {
         self.new_connections.clone()
     }
 
-    fn new_disconnections(&self) -> Vec<ClientId> {
+    fn new_disconnections(&self) -> Vec<(ClientId, DisconnectReason)> {
         self.new_disconnections.clone()
     }
 
+    fn disconnect(&mut self, client_id: ClientId, reason: DisconnectReason) -> Result<()> {
+        let Some(connection) = self.connections.remove(&client_id) else {
+            return Err(SteamError::NoConnection.into());
+        };
+        // send the reason of the disconnection to the client; the connection lingers
+        // until the message is delivered
+        connection
+            .send_message(&disconnect_message(reason), SendFlags::RELIABLE_NO_NAGLE)
+            .context("Failed to send disconnect message")?;
+        connection.close(
+            NetConnectionEnd::AppGeneric,
+            Some(&format!("{:?}", reason)),
+            true,
+        );
+        self.pending_disconnections.push((client_id, reason));
+        Ok(())
+    }
+
+    fn user_data(&self, _client_id: ClientId) -> Option<[u8; USER_DATA_BYTES]> {
+        None
+    }
+
     fn io(&self) -> &Io {
         todo!()
     }
+
+    /// The steam sockets apply the link conditioning themselves, there is no io to flush
+    fn io_mut(&mut self) -> Option<&mut Io> {
+        None
+    }
 }
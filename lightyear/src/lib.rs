@@ -17,6 +17,7 @@ You can find more information in the [book](https://cbournhonesque.github.io/lig
 // re-exports (mostly used in the derive macro crate or for internal purposes)
 #[doc(hidden)]
 pub mod _reexport {
+    pub use anyhow;
     pub use enum_delegate;
     pub use enum_dispatch::enum_dispatch;
     pub use paste::paste;
@@ -57,6 +58,7 @@ pub mod _reexport {
         push_component_insert_events, push_component_remove_events, push_component_update_events,
     };
     pub use crate::shared::replication::components::ShouldBeInterpolated;
+    pub use crate::shared::replication::delta::{apply_serialized_diff, serialize_diff};
     pub use crate::shared::replication::systems::add_per_component_replication_send_systems;
     pub use crate::shared::replication::ReplicationSend;
     pub use crate::shared::time_manager::WrappedTime;
@@ -75,22 +77,31 @@ pub mod prelude {
     };
     pub use crate::client::prediction::prespawn::PreSpawnedPlayerObject;
     pub use crate::connection::netcode::{generate_key, ClientId, Key};
+    pub use crate::connection::DisconnectReason;
     #[cfg(feature = "leafwing")]
     pub use crate::inputs::leafwing::LeafwingUserAction;
     pub use crate::inputs::native::UserAction;
+    pub use crate::packet::compression::CompressionConfig;
     pub use crate::packet::message::Message;
     pub use crate::protocol::channel::{ChannelKind, ChannelRegistry};
     pub use crate::protocol::Protocol;
     pub use crate::protocolize;
-    pub use crate::shared::config::SharedConfig;
+    pub use crate::serialize::custom::{BitcodeSerializer, SerializeWith};
+    pub use crate::serialize::quantize::{dequantize_f32, quantize_f32, SmallestThreeQuat};
+    pub use crate::shared::config::{Mode, SharedConfig};
     pub use crate::shared::ping::manager::PingConfig;
     pub use crate::shared::plugin::{NetworkIdentity, SharedPlugin};
+    pub use crate::shared::replication::authority::{
+        AuthorityCommandsExt, AuthorityPeer, HasAuthority,
+    };
     pub use crate::shared::replication::components::{
-        NetworkTarget, ReplicationGroup, ReplicationMode, ShouldBePredicted,
+        NetworkTarget, OnReplicateRemoved, ReplicationGroup, ReplicationMode, ShouldBePredicted,
     };
+    pub use crate::shared::replication::delta::Diffable;
     pub use crate::shared::replication::entity_map::{LightyearMapEntities, RemoteEntityMap};
     pub use crate::shared::replication::hierarchy::ParentSync;
     pub use crate::shared::replication::metadata::ClientMetadata;
+    pub use crate::shared::replication::resources::{ReplicateResource, ReplicateResourceSettings};
     pub use crate::shared::sets::{FixedUpdateSet, MainSet, ReplicationSet};
     pub use crate::shared::tick_manager::TickManager;
     pub use crate::shared::tick_manager::{Tick, TickConfig};
@@ -103,10 +114,14 @@ pub mod prelude {
         pub use crate::client::components::{
             ComponentSyncMode, Confirmed, LerpFn, SyncComponent, SyncMetadata,
         };
-        pub use crate::client::config::{ClientConfig, NetcodeConfig, PacketConfig};
+        pub use crate::client::config::{
+            ClientConfig, NetcodeConfig, PacketConfig, ReconnectConfig,
+        };
         pub use crate::client::events::{
-            ComponentInsertEvent, ComponentRemoveEvent, ComponentUpdateEvent, ConnectEvent,
-            DisconnectEvent, EntityDespawnEvent, EntitySpawnEvent, InputEvent, MessageEvent,
+            AuthorityChangeEvent, ComponentInsertEvent, ComponentRemoveEvent, ComponentUpdateEvent,
+            ConnectEvent, DisconnectEvent, EntityDespawnEvent, EntityDetachEvent, EntitySpawnEvent,
+            InputEvent, MessageEvent, ResourceInsertEvent, ResourceRemoveEvent,
+            ResourceUpdateEvent,
         };
         pub use crate::client::input::{InputConfig, InputSystemSet};
         #[cfg(feature = "leafwing")]
@@ -122,28 +137,55 @@ pub mod prelude {
         };
         pub use crate::client::metadata::GlobalMetadata;
         pub use crate::client::plugin::{ClientPlugin, PluginConfig};
-        pub use crate::client::prediction::correction::Correction;
+        pub use crate::client::prediction::correction::{
+            Correction, CorrectionConfig, CorrectionMode,
+        };
         pub use crate::client::prediction::plugin::is_in_rollback;
         pub use crate::client::prediction::plugin::{PredictionConfig, PredictionSet};
         pub use crate::client::prediction::predicted_history::{ComponentState, PredictionHistory};
-        pub use crate::client::prediction::{Predicted, PredictionDespawnCommandsExt};
+        pub use crate::client::prediction::predicted_resource::{
+            PredictedResourceHistory, PredictedResourcePlugin,
+        };
+        pub use crate::client::prediction::{
+            Predicted, PredictionDespawnCommandsExt, Rollback, RollbackFilter,
+            RollbackInteractions, SkipRollback,
+        };
         pub use crate::client::sync::SyncConfig;
         pub use crate::connection::client::{
             Authentication, ClientConnection, NetClient, NetConfig,
         };
+        #[cfg(not(target_family = "wasm"))]
+        pub use crate::connection::lan::{scan, LanSession, ServerInfo, DEFAULT_DISCOVERY_PORT};
         #[cfg(feature = "steam")]
         pub use crate::connection::steam::client::SteamConfig;
     }
     pub mod server {
-        pub use crate::server::config::{NetcodeConfig, PacketConfig, ServerConfig};
+        #[cfg(not(target_family = "wasm"))]
+        pub use crate::server::config::LanConfig;
+        pub use crate::server::config::{
+            ConnectionConfig, NetcodeConfig, PacketConfig, RateLimitConfig, RateLimitPolicy,
+            ReplicationConfig, ServerConfig, Validator,
+        };
         pub use crate::server::events::{
-            ComponentInsertEvent, ComponentRemoveEvent, ComponentUpdateEvent, ConnectEvent,
-            DisconnectEvent, EntityDespawnEvent, EntitySpawnEvent, InputEvent, MessageEvent,
+            AuthorityChangeEvent, ComponentInsertEvent, ComponentRemoveEvent, ComponentUpdateEvent,
+            ConnectEvent, DisconnectEvent, EntityDespawnEvent, EntityDetachEvent, EntitySpawnEvent,
+            InputEvent, MessageEvent,
+        };
+        pub use crate::server::lag_compensation::{
+            LagCompensationConfig, LagCompensationHistory, LagCompensationPlugin,
         };
         pub use crate::server::metadata::GlobalMetadata;
         pub use crate::server::plugin::{PluginConfig, ServerPlugin};
+        pub use crate::server::rate_limit::RateLimitStats;
         pub use crate::server::room::{RoomId, RoomManager, RoomMut, RoomRef};
+        pub use crate::server::spatial_grid::{
+            GridDimensions, GridPosition, GridViewer, SpatialGrid, SpatialGridConfig,
+            SpatialGridPlugin,
+        };
+        pub use crate::server::visibility::VisibilityManager;
+        pub use crate::shared::ping::message::InterpolationTime;
 
+        pub use crate::connection::netcode::{ConnectTokenIssuer, ConnectTokenListener};
         pub use crate::connection::server::{
             NetConfig, NetServer, ServerConnection, ServerConnections,
         };
@@ -0,0 +1,323 @@
+//! Compression of the packets
+//!
+//! The data of the packets is compressed by the [`PacketBuilder`](crate::packet::packet_manager::PacketBuilder)
+//! when the packets are encoded, before they are handed to the transport (and encrypted by the netcode connection,
+//! since encrypted bytes barely compress). The header of a compressed packet is kept uncompressed, and
+//! packets that don't get smaller when compressed are sent as is.
+//!
+//! The client and the server must use the same [`CompressionConfig`](crate::packet::compression::CompressionConfig):
+//! it is included in the protocol hash, so the server denies the connection of a client that uses a different compression.
+//!
+//! Zstd can use a dictionary that was pre-trained on captured traffic (see `train_zstd_dictionary`, with the
+//! `zstd` feature), which greatly improves the compression ratio of small packets.
+use std::hash::{Hash, Hasher};
+
+use anyhow::{Context, Result};
+
+use crate::connection::netcode::MAX_PACKET_SIZE;
+
+/// Maximum size of the data of a packet once decompressed: packets are compressed after being built,
+/// so they are never bigger than [`MAX_PACKET_SIZE`]
+const MAX_DECOMPRESSED_SIZE: usize = MAX_PACKET_SIZE;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum CompressionConfig {
+    /// The packets are not compressed
+    #[default]
+    None,
+    /// Compress the packets with LZ4 (fast, but lower compression ratio)
+    Lz4,
+    /// Compress the packets with zstd
+    #[cfg_attr(docsrs, doc(cfg(feature = "zstd")))]
+    #[cfg(feature = "zstd")]
+    Zstd {
+        /// Compression level (1-22)
+        level: i32,
+        /// Dictionary that was trained on samples of the traffic of the game (see [`train_zstd_dictionary`]).
+        /// The client and the server must use the same dictionary.
+        dictionary: Option<Vec<u8>>,
+    },
+}
+
+/// Train a zstd dictionary from packets that were captured from the traffic of the game.
+///
+/// The resulting dictionary can be used in [`CompressionConfig::Zstd`]
+#[cfg_attr(docsrs, doc(cfg(feature = "zstd")))]
+#[cfg(feature = "zstd")]
+pub fn train_zstd_dictionary<S: AsRef<[u8]>>(
+    samples: &[S],
+    max_size: usize,
+) -> std::io::Result<Vec<u8>> {
+    zstd::dict::from_samples(samples, max_size)
+}
+
+/// Number of bytes of packet data before and after compression
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct CompressionStats {
+    /// Size of the data of the sent packets
+    pub bytes_sent: usize,
+    /// Size of the data of the sent packets, once compressed
+    pub compressed_bytes_sent: usize,
+    /// Size of the data of the received compressed packets, once decompressed
+    pub bytes_received: usize,
+    /// Size of the data of the received compressed packets
+    pub compressed_bytes_received: usize,
+}
+
+impl std::ops::AddAssign for CompressionStats {
+    fn add_assign(&mut self, other: Self) {
+        self.bytes_sent += other.bytes_sent;
+        self.compressed_bytes_sent += other.compressed_bytes_sent;
+        self.bytes_received += other.bytes_received;
+        self.compressed_bytes_received += other.compressed_bytes_received;
+    }
+}
+
+impl CompressionStats {
+    /// Ratio between the size of the data of the sent packets and their compressed size.
+    /// Returns None if no bytes were sent
+    pub fn compression_ratio_sent(&self) -> Option<f64> {
+        (self.compressed_bytes_sent > 0)
+            .then(|| self.bytes_sent as f64 / self.compressed_bytes_sent as f64)
+    }
+
+    /// Ratio between the size of the data of the received compressed packets and their compressed size.
+    /// Returns None if no compressed packets were received
+    pub fn compression_ratio_received(&self) -> Option<f64> {
+        (self.compressed_bytes_received > 0)
+            .then(|| self.bytes_received as f64 / self.compressed_bytes_received as f64)
+    }
+}
+
+enum Compressor {
+    Lz4,
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::bulk::Compressor<'static>),
+}
+
+impl Compressor {
+    /// Size of the buffer needed to compress an input of size `len`
+    fn max_output_size(&self, len: usize) -> usize {
+        match self {
+            Compressor::Lz4 => lz4_flex::block::get_maximum_output_size(len),
+            #[cfg(feature = "zstd")]
+            Compressor::Zstd(_) => zstd::zstd_safe::compress_bound(len),
+        }
+    }
+
+    /// Compress the input into the output buffer.
+    /// Returns None if the compression failed
+    fn compress(&mut self, input: &[u8], output: &mut [u8]) -> Option<usize> {
+        match self {
+            Compressor::Lz4 => lz4_flex::block::compress_into(input, output).ok(),
+            #[cfg(feature = "zstd")]
+            Compressor::Zstd(compressor) => compressor.compress_to_buffer(input, output).ok(),
+        }
+    }
+}
+
+enum Decompressor {
+    Lz4,
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::bulk::Decompressor<'static>),
+}
+
+impl Decompressor {
+    /// Decompress the input into the output buffer
+    fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<usize> {
+        match self {
+            Decompressor::Lz4 => lz4_flex::block::decompress_into(input, output)
+                .context("could not decompress packet"),
+            #[cfg(feature = "zstd")]
+            Decompressor::Zstd(decompressor) => decompressor
+                .decompress_to_buffer(input, output)
+                .context("could not decompress packet"),
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Combine the protocol hash with the compression settings that the remote must share to decompress our packets.
+    ///
+    /// The protocol hash is unchanged if compression is disabled.
+    pub(crate) fn protocol_hash(&self, protocol_hash: u64) -> u64 {
+        let mut hasher = seahash::SeaHasher::new();
+        protocol_hash.hash(&mut hasher);
+        match self {
+            CompressionConfig::None => return protocol_hash,
+            CompressionConfig::Lz4 => "lz4".hash(&mut hasher),
+            // the level only matters for compression
+            #[cfg(feature = "zstd")]
+            CompressionConfig::Zstd { dictionary, .. } => {
+                "zstd".hash(&mut hasher);
+                dictionary.hash(&mut hasher);
+            }
+        }
+        hasher.finish()
+    }
+
+    fn build(&self) -> Result<Option<(Compressor, Decompressor)>> {
+        Ok(match self {
+            CompressionConfig::None => None,
+            CompressionConfig::Lz4 => Some((Compressor::Lz4, Decompressor::Lz4)),
+            #[cfg(feature = "zstd")]
+            CompressionConfig::Zstd { level, dictionary } => {
+                let dictionary = dictionary.as_deref().unwrap_or_default();
+                Some((
+                    Compressor::Zstd(
+                        zstd::bulk::Compressor::with_dictionary(*level, dictionary)
+                            .context("could not create the zstd compressor")?,
+                    ),
+                    Decompressor::Zstd(
+                        zstd::bulk::Decompressor::with_dictionary(dictionary)
+                            .context("could not create the zstd decompressor")?,
+                    ),
+                ))
+            }
+        })
+    }
+}
+
+/// Compresses and decompresses the data of the packets according to the [`CompressionConfig`]
+pub(crate) struct PacketCompressor {
+    config: CompressionConfig,
+    /// Created on first use, since creating the zstd (de)compressors can fail
+    codec: Option<(Compressor, Decompressor)>,
+    buffer: Vec<u8>,
+    pub(crate) stats: CompressionStats,
+}
+
+impl PacketCompressor {
+    /// Returns None if compression is disabled
+    pub(crate) fn new(config: CompressionConfig) -> Option<Self> {
+        (config != CompressionConfig::None).then(|| Self {
+            config,
+            codec: None,
+            buffer: vec![],
+            stats: CompressionStats::default(),
+        })
+    }
+
+    fn codec(&mut self) -> Result<&mut (Compressor, Decompressor)> {
+        if self.codec.is_none() {
+            self.codec = self.config.build()?;
+        }
+        self.codec.as_mut().context("compression is disabled")
+    }
+
+    /// Compress the data of a packet.
+    ///
+    /// Returns None if the compressed data is not smaller than the data
+    pub(crate) fn compress(&mut self, data: &[u8]) -> Result<Option<Vec<u8>>> {
+        let (compressor, _) = self.codec()?;
+        let mut output = vec![0; compressor.max_output_size(data.len())];
+        let compressed = match compressor.compress(data, &mut output) {
+            Some(len) if len < data.len() => {
+                output.truncate(len);
+                Some(output)
+            }
+            _ => None,
+        };
+        self.stats.bytes_sent += data.len();
+        self.stats.compressed_bytes_sent += compressed.as_ref().map_or(data.len(), Vec::len);
+        Ok(compressed)
+    }
+
+    /// Decompress the data of a packet
+    pub(crate) fn decompress(&mut self, data: &[u8]) -> Result<&[u8]> {
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.resize(MAX_DECOMPRESSED_SIZE, 0);
+        let (_, decompressor) = self.codec()?;
+        let len = decompressor.decompress(data, &mut buffer)?;
+        self.buffer = buffer;
+        self.stats.bytes_received += len;
+        self.stats.compressed_bytes_received += data.len();
+        Ok(&self.buffer[..len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_roundtrip(compression: CompressionConfig) {
+        let mut sender = PacketCompressor::new(compression.clone()).unwrap();
+        let mut receiver = PacketCompressor::new(compression).unwrap();
+
+        // repetitive data: compresses well
+        let data = [1, 2, 3, 4].repeat(100);
+        let compressed = sender.compress(&data).unwrap().unwrap();
+        assert!(compressed.len() < 200);
+        assert_eq!(receiver.decompress(&compressed).unwrap(), data.as_slice());
+
+        // random data: doesn't compress, is sent as is
+        let random: Vec<u8> = (0..100).map(|_| rand::random()).collect();
+        assert!(sender.compress(&random).unwrap().is_none());
+
+        assert_eq!(sender.stats.bytes_sent, 500);
+        assert!(sender.stats.compressed_bytes_sent < 300);
+        assert!(sender.stats.compression_ratio_sent().unwrap() > 1.5);
+        assert!(receiver.stats.compression_ratio_received().unwrap() > 2.0);
+    }
+
+    #[test]
+    fn test_lz4_compression() {
+        check_roundtrip(CompressionConfig::Lz4);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_compression() {
+        check_roundtrip(CompressionConfig::Zstd {
+            level: 3,
+            dictionary: None,
+        });
+
+        // train a dictionary on similar packets
+        let samples: Vec<Vec<u8>> = (0..200u8)
+            .map(|i| {
+                let mut packet = b"player_position:".to_vec();
+                packet.extend_from_slice(&[i, 0, i.wrapping_mul(3), 0, 1, 1, 1, 1]);
+                packet
+            })
+            .collect();
+        let dictionary = train_zstd_dictionary(&samples, 1024).unwrap();
+        check_roundtrip(CompressionConfig::Zstd {
+            level: 3,
+            dictionary: Some(dictionary),
+        });
+    }
+
+    #[test]
+    fn test_protocol_hash() {
+        let hash = 1234;
+        assert_eq!(CompressionConfig::None.protocol_hash(hash), hash);
+        assert_ne!(CompressionConfig::Lz4.protocol_hash(hash), hash);
+        #[cfg(feature = "zstd")]
+        assert_ne!(
+            CompressionConfig::Zstd {
+                level: 3,
+                dictionary: None,
+            }
+            .protocol_hash(hash),
+            CompressionConfig::Zstd {
+                level: 3,
+                dictionary: Some(vec![1, 2, 3]),
+            }
+            .protocol_hash(hash)
+        );
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        let mut compressor = PacketCompressor::new(CompressionConfig::Lz4).unwrap();
+        assert!(compressor.decompress(&[255, 255]).is_err());
+        // the data would be bigger than a packet once decompressed
+        let mut sender = PacketCompressor::new(CompressionConfig::Lz4).unwrap();
+        let compressed = sender
+            .compress(&vec![0; 2 * MAX_DECOMPRESSED_SIZE])
+            .unwrap()
+            .unwrap();
+        assert!(compressor.decompress(&compressed).is_err());
+    }
+}
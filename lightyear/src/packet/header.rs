@@ -43,6 +43,10 @@ impl PacketHeader {
     pub fn get_packet_type(&self) -> PacketType {
         self.packet_type
     }
+
+    pub(crate) fn set_packet_type(&mut self, packet_type: PacketType) {
+        self.packet_type = packet_type;
+    }
 }
 
 // we can only send acks for the last 32 packets ids before the last received packet
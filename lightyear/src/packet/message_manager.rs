@@ -11,6 +11,7 @@ use tracing::{info, trace};
 use crate::channel::builder::ChannelContainer;
 use crate::channel::receivers::ChannelReceive;
 use crate::channel::senders::ChannelSend;
+use crate::packet::compression::{CompressionConfig, CompressionStats};
 use crate::packet::message::{FragmentData, MessageAck, MessageId, SingleData};
 use crate::packet::packet::{Packet, PacketId, MTU_PAYLOAD_BYTES};
 use crate::packet::packet_manager::{PacketBuilder, Payload, PACKET_BUFFER_CAPACITY};
@@ -64,6 +65,19 @@ impl MessageManager {
         }
     }
 
+    /// Compress the data of the packets that we send, and decompress the data of the packets that we receive
+    pub(crate) fn set_compression(&mut self, compression: CompressionConfig) {
+        self.packet_manager.set_compression(compression);
+    }
+
+    /// Statistics about the compression of the packets (None if compression is disabled)
+    pub(crate) fn compression_stats_mut(&mut self) -> Option<&mut CompressionStats> {
+        self.packet_manager
+            .compressor
+            .as_mut()
+            .map(|compressor| &mut compressor.stats)
+    }
+
     pub(crate) fn get_replication_update_send_receiver(&mut self) -> Receiver<MessageId> {
         self.priority_manager
             .subscribe_replication_update_sent_messages()
@@ -160,7 +174,7 @@ impl MessageManager {
             current_tick,
         );
 
-        let packets = self.packet_manager.build_packets(data_to_send);
+        let packets = self.packet_manager.build_packets(data_to_send)?;
 
         let mut bytes = Vec::new();
         for mut packet in packets {
@@ -179,7 +193,7 @@ impl MessageManager {
             // TODO: should we update this to include fragment info as well?
             // Step 3. Update the packet_to_message_id_map (only for channels that care about acks)
             packet
-                .message_acks()
+                .message_acks()?
                 .iter()
                 .try_for_each(|(channel_id, message_ack)| {
                     let channel_kind = self
@@ -223,6 +237,7 @@ impl MessageManager {
     /// Returns the tick of the packet
     pub fn recv_packet(&mut self, packet: Packet) -> anyhow::Result<Tick> {
         // Step 1. Parse the packet
+        let packet = self.packet_manager.decompress_packet(packet)?;
         let tick = packet.header().tick;
         trace!(?packet, "Received packet");
 
@@ -255,7 +270,7 @@ impl MessageManager {
         }
 
         // Step 4. Put the messages from the packet in the internal buffers for each channel
-        for (channel_net_id, messages) in packet.data.contents() {
+        for (channel_net_id, messages) in packet.data.contents()? {
             let channel_kind = self
                 .channel_registry
                 .get_kind_from_net_id(channel_net_id)
@@ -318,6 +333,7 @@ mod tests {
     use bevy::utils::Duration;
 
     use crate::_reexport::*;
+    use crate::packet::compression::CompressionConfig;
     use crate::packet::message::MessageId;
     use crate::packet::packet::FRAGMENT_SIZE;
     use crate::packet::priority_manager::PriorityConfig;
@@ -415,6 +431,48 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    /// The data of the packets is compressed when it gets smaller, including for fragmented messages
+    fn test_message_manager_compression() -> Result<(), anyhow::Error> {
+        let protocol = protocol();
+        let mut client_message_manager =
+            MessageManager::new(protocol.channel_registry(), PriorityConfig::default());
+        client_message_manager.set_compression(CompressionConfig::Lz4);
+        let mut server_message_manager =
+            MessageManager::new(protocol.channel_registry(), PriorityConfig::default());
+        server_message_manager.set_compression(CompressionConfig::Lz4);
+
+        let small_message = MyMessageProtocol::Message1(Message1("1".to_string()));
+        let data = "a".repeat((1.5 * FRAGMENT_SIZE as f32) as usize);
+        let big_message = MyMessageProtocol::Message1(Message1(data));
+        let channel_kind_1 = ChannelKind::of::<Channel1>();
+        client_message_manager.buffer_send(small_message.clone(), channel_kind_1)?;
+        client_message_manager.buffer_send(big_message.clone(), channel_kind_1)?;
+        let packet_bytes = client_message_manager.send_packets(Tick(0))?;
+        assert!(packet_bytes.iter().all(|bytes| bytes.len() < 200));
+
+        for packet_byte in packet_bytes.iter() {
+            let packet = Packet::decode(&mut ReadWordBuffer::start_read(packet_byte.as_slice()))?;
+            server_message_manager.recv_packet(packet)?;
+        }
+        let data = server_message_manager.read_messages();
+        let mut received = data.get(&channel_kind_1).unwrap().clone();
+        received.sort_by_key(|(_, message)| matches!(message, MyMessageProtocol::Message1(Message1(s)) if s.len() > 1));
+        assert_eq!(
+            received,
+            vec![(Tick(0), small_message), (Tick(0), big_message)]
+        );
+        let stats = client_message_manager.compression_stats_mut().unwrap();
+        assert!(stats.compression_ratio_sent().unwrap() > 2.0);
+
+        // a receiver without compression can't read the compressed packets
+        let mut uncompressed_message_manager =
+            MessageManager::new(protocol.channel_registry(), PriorityConfig::default());
+        let packet = Packet::decode(&mut ReadWordBuffer::start_read(packet_bytes[0].as_slice()))?;
+        assert!(uncompressed_message_manager.recv_packet(packet).is_err());
+        Ok(())
+    }
+
     #[test]
     /// We want to test that we can send/receive messages over a connection
     fn test_message_manager_fragment_message() -> Result<(), anyhow::Error> {
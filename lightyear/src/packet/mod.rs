@@ -19,6 +19,9 @@ Packets that are over the maximum packet size will be fragmented into multiple [
 [`FragmentedPacket`]: packet::FragmentedPacket
 */
 
+/// Compression of the data of the packets (see [`CompressionConfig`](compression::CompressionConfig))
+pub mod compression;
+
 /// Manages the [`PacketHeader`](header::PacketHeader) which includes important packet information
 pub mod header;
 
@@ -221,6 +221,30 @@ impl BitSerializable for FragmentedPacket {
     }
 }
 
+/// The data of a packet (single or fragmented), compressed by the [`PacketBuilder`](crate::packet::packet_manager::PacketBuilder)
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompressedPacket {
+    /// Type of the packet once decompressed
+    pub(crate) packet_type: PacketType,
+    pub(crate) bytes: Vec<u8>,
+}
+
+impl BitSerializable for CompressedPacket {
+    fn encode(&self, writer: &mut impl WriteBuffer) -> anyhow::Result<()> {
+        writer.encode(&self.packet_type, Fixed)?;
+        writer.encode(self.bytes.as_slice(), Fixed)
+    }
+
+    fn decode(reader: &mut impl ReadBuffer) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let packet_type = reader.decode::<PacketType>(Fixed)?;
+        let bytes = reader.decode::<Vec<u8>>(Fixed)?;
+        Ok(Self { packet_type, bytes })
+    }
+}
+
 /// Abstraction for data that is sent over the network
 ///
 /// Every packet knows how to serialize itself into a list of Single Packets that can
@@ -229,18 +253,28 @@ impl BitSerializable for FragmentedPacket {
 pub(crate) enum PacketData {
     Single(SinglePacket),
     Fragmented(FragmentedPacket),
+    /// Only exists between the moment the packet is received and the moment it is decompressed
+    Compressed(CompressedPacket),
 }
 
 impl PacketData {
-    pub(crate) fn num_messages(&self) -> usize {
+    /// Number of messages in the packet.
+    ///
+    /// Returns None if the packet is compressed (the messages are only known once it is decompressed)
+    pub(crate) fn num_messages(&self) -> Option<usize> {
         match self {
-            PacketData::Single(single_packet) => single_packet.num_messages(),
+            PacketData::Single(single_packet) => Some(single_packet.num_messages()),
             PacketData::Fragmented(fragmented_packet) => {
-                1 + fragmented_packet.packet.num_messages()
+                Some(1 + fragmented_packet.packet.num_messages())
             }
+            PacketData::Compressed(_) => None,
         }
     }
-    pub(crate) fn contents(self) -> HashMap<NetId, Vec<MessageContainer>> {
+
+    /// Messages contained in the packet, by channel.
+    ///
+    /// Errors if the packet is still compressed.
+    pub(crate) fn contents(self) -> anyhow::Result<HashMap<NetId, Vec<MessageContainer>>> {
         let mut res = HashMap::new();
         match self {
             PacketData::Single(data) => {
@@ -263,8 +297,11 @@ impl PacketData {
                         .extend(message_containers);
                 }
             }
+            PacketData::Compressed(_) => {
+                return Err(anyhow::anyhow!("the packet must be decompressed first"))
+            }
         }
-        res
+        Ok(res)
     }
 }
 
@@ -279,6 +316,7 @@ impl Packet {
         match &self.data {
             PacketData::Single(single_packet) => single_packet.data.is_empty(),
             PacketData::Fragmented(fragmented_packet) => fragmented_packet.packet.data.is_empty(),
+            PacketData::Compressed(_) => false,
         }
     }
 
@@ -288,40 +326,69 @@ impl Packet {
         // should still use gamma for packet type
         // TODO: add test
         writer.encode(&self.header, Fixed)?;
+        self.encode_data(writer)
+    }
+
+    /// Encode the data of the packet (without the header) into the write buffer
+    pub(crate) fn encode_data(&self, writer: &mut impl WriteBuffer) -> anyhow::Result<()> {
         match &self.data {
             PacketData::Single(single_packet) => single_packet.encode(writer),
             PacketData::Fragmented(fragmented_packet) => fragmented_packet.encode(writer),
+            PacketData::Compressed(compressed_packet) => compressed_packet.encode(writer),
         }
     }
 
     /// Decode a packet from the read buffer. The read buffer will only contain the bytes for a single packet
+    ///
+    /// The data of compressed packets is kept as is, it is decompressed by the [`MessageManager`](crate::packet::message_manager::MessageManager)
     pub fn decode(reader: &mut impl ReadBuffer) -> anyhow::Result<Packet> {
         let header = reader.decode::<PacketHeader>(Fixed)?;
-        let packet_type = header.get_packet_type();
+        let data = match header.get_packet_type() {
+            PacketType::Compressed => PacketData::Compressed(CompressedPacket::decode(reader)?),
+            packet_type => Self::decode_data(packet_type, reader)?,
+        };
+        Ok(Self { header, data })
+    }
+
+    /// Decode the data of a packet of type `packet_type` (used for the data of compressed packets, once decompressed)
+    pub(crate) fn decode_data(
+        packet_type: PacketType,
+        reader: &mut impl ReadBuffer,
+    ) -> anyhow::Result<PacketData> {
         match packet_type {
-            PacketType::Data => {
-                let single_packet = SinglePacket::decode(reader)?;
-                Ok(Self {
-                    header,
-                    data: PacketData::Single(single_packet),
-                })
-            }
+            PacketType::Data => Ok(PacketData::Single(SinglePacket::decode(reader)?)),
             PacketType::DataFragment => {
-                let fragmented_packet = FragmentedPacket::decode(reader)?;
-                Ok(Self {
-                    header,
-                    data: PacketData::Fragmented(fragmented_packet),
-                })
-            } // _ => Err(anyhow::anyhow!("Packet type not supported")),
+                Ok(PacketData::Fragmented(FragmentedPacket::decode(reader)?))
+            }
+            PacketType::Compressed => Err(anyhow::anyhow!(
+                "a compressed packet cannot contain another compressed packet"
+            )),
         }
     }
 
+    /// Approximate size of the packet in bytes: the header and the bytes of the messages it contains
+    pub(crate) fn size(&self) -> usize {
+        let messages_size = |packet: &SinglePacket| -> usize {
+            packet.data.values().flatten().map(|m| m.bytes.len()).sum()
+        };
+        HEADER_BYTES
+            + match &self.data {
+                PacketData::Single(single_packet) => messages_size(single_packet),
+                PacketData::Fragmented(fragmented_packet) => {
+                    fragmented_packet.fragment.bytes.len()
+                        + messages_size(&fragmented_packet.packet)
+                }
+                PacketData::Compressed(compressed_packet) => compressed_packet.bytes.len(),
+            }
+    }
+
     // #[cfg(test)]
     pub(crate) fn header(&self) -> &PacketHeader {
         &self.header
     }
 
-    pub fn add_channel(&mut self, channel: NetId) {
+    /// Errors if the packet is compressed
+    pub fn add_channel(&mut self, channel: NetId) -> anyhow::Result<()> {
         match &mut self.data {
             PacketData::Single(single_packet) => {
                 single_packet.add_channel(channel);
@@ -329,31 +396,48 @@ impl Packet {
             PacketData::Fragmented(fragmented_packet) => {
                 fragmented_packet.packet.add_channel(channel);
             }
+            PacketData::Compressed(_) => {
+                return Err(anyhow::anyhow!("cannot add data to a compressed packet"))
+            }
         }
+        Ok(())
     }
 
-    pub fn add_message(&mut self, channel: NetId, message: SingleData) {
+    /// Errors if the packet is compressed
+    pub fn add_message(&mut self, channel: NetId, message: SingleData) -> anyhow::Result<()> {
         match &mut self.data {
             PacketData::Single(single_packet) => single_packet.add_message(channel, message),
             PacketData::Fragmented(fragmented_packet) => {
                 fragmented_packet.packet.add_message(channel, message);
             }
+            PacketData::Compressed(_) => {
+                return Err(anyhow::anyhow!("cannot add data to a compressed packet"))
+            }
         }
+        Ok(())
     }
 
-    /// Number of messages currently written in the packet
+    /// Number of messages currently written in the packet.
+    /// Returns None if the packet is compressed
     #[cfg(test)]
-    pub fn num_messages(&self) -> usize {
+    pub fn num_messages(&self) -> Option<usize> {
         match &self.data {
-            PacketData::Single(single_packet) => single_packet.num_messages(),
-            PacketData::Fragmented(fragmented_packet) => fragmented_packet.packet.num_messages(),
+            PacketData::Single(single_packet) => Some(single_packet.num_messages()),
+            PacketData::Fragmented(fragmented_packet) => {
+                Some(fragmented_packet.packet.num_messages())
+            }
+            PacketData::Compressed(_) => None,
         }
     }
 
-    pub(crate) fn message_acks(&self) -> HashMap<ChannelId, Vec<MessageAck>> {
+    /// Errors if the packet is still compressed
+    pub(crate) fn message_acks(&self) -> anyhow::Result<HashMap<ChannelId, Vec<MessageAck>>> {
         match &self.data {
-            PacketData::Single(single_packet) => single_packet.message_acks(),
-            PacketData::Fragmented(fragmented_packet) => fragmented_packet.message_acks(),
+            PacketData::Single(single_packet) => Ok(single_packet.message_acks()),
+            PacketData::Fragmented(fragmented_packet) => Ok(fragmented_packet.message_acks()),
+            PacketData::Compressed(_) => {
+                Err(anyhow::anyhow!("the packet must be decompressed first"))
+            }
         }
     }
 }
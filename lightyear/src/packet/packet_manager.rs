@@ -3,16 +3,21 @@ use std::collections::{BTreeMap, VecDeque};
 use bitcode::encoding::Gamma;
 use bitcode::word_buffer::WordBuffer;
 
+use anyhow::Context;
+
 use crate::connection::netcode::MAX_PACKET_SIZE;
+use crate::packet::compression::{CompressionConfig, PacketCompressor};
 use crate::packet::header::PacketHeaderManager;
 use crate::packet::message::{FragmentData, MessageContainer, SingleData};
 use crate::packet::packet::{
-    FragmentedPacket, Packet, PacketData, SinglePacket, FRAGMENT_SIZE, MTU_PAYLOAD_BYTES,
+    CompressedPacket, FragmentedPacket, Packet, PacketData, SinglePacket, FRAGMENT_SIZE,
+    MTU_PAYLOAD_BYTES,
 };
 use crate::packet::packet_type::PacketType;
 use crate::protocol::registry::NetId;
 use crate::protocol::BitSerializable;
 use crate::serialize::reader::ReadBuffer;
+use crate::serialize::wordbuffer::reader::ReadWordBuffer;
 use crate::serialize::wordbuffer::writer::WriteWordBuffer;
 use crate::serialize::writer::WriteBuffer;
 
@@ -30,6 +35,8 @@ pub(crate) struct PacketBuilder {
     // TODO: should this be associated with Packet?
     try_write_buffer: WriteWordBuffer,
     write_buffer: WriteWordBuffer,
+    /// Compresses the data of the packets, if compression is enabled
+    pub(crate) compressor: Option<PacketCompressor>,
 }
 
 impl PacketBuilder {
@@ -39,9 +46,14 @@ impl PacketBuilder {
             // write buffer to encode packets bit by bit
             try_write_buffer: WriteBuffer::with_capacity(2 * PACKET_BUFFER_CAPACITY),
             write_buffer: WriteBuffer::with_capacity(PACKET_BUFFER_CAPACITY),
+            compressor: None,
         }
     }
 
+    pub(crate) fn set_compression(&mut self, compression: CompressionConfig) {
+        self.compressor = PacketCompressor::new(compression);
+    }
+
     /// Reset the buffers used to encode packets
     pub fn clear_try_write_buffer(&mut self) {
         self.try_write_buffer.start_write();
@@ -71,6 +83,13 @@ impl PacketBuilder {
         // TODO: CAREFUL, THIS COULD ALLOCATE A BIT MORE TO BYTE ALIGN?
         let payload = Payload::from(write_buffer.finish_write());
         assert!(payload.len() <= MAX_PACKET_SIZE, "packet = {:?}", packet);
+        if self.compressor.is_some() {
+            if let Some(compressed_payload) = self.encode_compressed_packet(packet)? {
+                if compressed_payload.len() < payload.len() {
+                    return Ok(compressed_payload);
+                }
+            }
+        }
         Ok(payload)
 
         // packet.encode(&mut self.write_buffer)?;
@@ -78,6 +97,50 @@ impl PacketBuilder {
         // Ok(bytes)
     }
 
+    /// Encode a packet whose data is compressed.
+    ///
+    /// Returns None if the data does not get smaller when compressed
+    fn encode_compressed_packet(&mut self, packet: &Packet) -> anyhow::Result<Option<Payload>> {
+        let Some(compressor) = self.compressor.as_mut() else {
+            return Ok(None);
+        };
+        let mut data_buffer = WriteWordBuffer::with_capacity(PACKET_BUFFER_CAPACITY);
+        data_buffer.set_reserved_bits(PACKET_BUFFER_CAPACITY);
+        packet.encode_data(&mut data_buffer)?;
+        let Some(bytes) = compressor.compress(data_buffer.finish_write())? else {
+            return Ok(None);
+        };
+        let mut header = packet.header.clone();
+        let packet_type = header.get_packet_type();
+        header.set_packet_type(PacketType::Compressed);
+        let compressed_packet = Packet {
+            header,
+            data: PacketData::Compressed(CompressedPacket { packet_type, bytes }),
+        };
+        let mut write_buffer = WriteWordBuffer::with_capacity(PACKET_BUFFER_CAPACITY);
+        write_buffer.set_reserved_bits(PACKET_BUFFER_CAPACITY);
+        compressed_packet.encode(&mut write_buffer)?;
+        Ok(Some(Payload::from(write_buffer.finish_write())))
+    }
+
+    /// Decompress the data of a received packet, if it was compressed
+    pub(crate) fn decompress_packet(&mut self, mut packet: Packet) -> anyhow::Result<Packet> {
+        let PacketData::Compressed(compressed_packet) = &packet.data else {
+            return Ok(packet);
+        };
+        let packet_type = compressed_packet.packet_type;
+        let compressor = self
+            .compressor
+            .as_mut()
+            .context("received a compressed packet, but compression is disabled")?;
+        let bytes = compressor.decompress(&compressed_packet.bytes)?;
+        let mut reader = ReadWordBuffer::start_read(bytes);
+        let data = Packet::decode_data(packet_type, &mut reader)?;
+        packet.header.set_packet_type(packet_type);
+        packet.data = data;
+        Ok(packet)
+    }
+
     /// Start building new packet, we start with an empty packet
     /// that can write to a given channel
     pub(crate) fn build_new_single_packet(&mut self) -> Packet {
@@ -216,7 +279,7 @@ impl PacketBuilder {
 
         // Add a channel in the list of channels contained in the packet
         // (whether or not it will contain messages)
-        packet.add_channel(*channel_id);
+        packet.add_channel(*channel_id)?;
         Ok(true)
     }
 
@@ -294,7 +357,7 @@ impl PacketBuilder {
         &mut self,
         // TODO: change into IntoIterator? the order matters though!
         data: BTreeMap<NetId, (VecDeque<SingleData>, VecDeque<FragmentData>)>,
-    ) -> Vec<Packet> {
+    ) -> anyhow::Result<Vec<Packet>> {
         let mut packets: Vec<Packet> = vec![];
         let mut single_packet: Option<Packet> = None;
 
@@ -325,7 +388,7 @@ impl PacketBuilder {
                     {
                         let message = single_messages.pop_front().unwrap();
                         // add message to packet
-                        packet.add_message(channel_id, message);
+                        packet.add_message(channel_id, message)?;
                     } else {
                         // can't add any more messages (since we sorted messages from smallest to largest)
                         // finish packet
@@ -362,7 +425,7 @@ impl PacketBuilder {
                         {
                             let message = single_messages.pop_front().unwrap();
                             // add message to packet
-                            packet.add_message(channel_id, message);
+                            packet.add_message(channel_id, message)?;
                         } else {
                             // finish packet
                             packets.push(packet);
@@ -407,7 +470,7 @@ impl PacketBuilder {
                     {
                         let message = single_messages.pop_front().unwrap();
                         // add message to packet
-                        packet.add_message(channel_id, message);
+                        packet.add_message(channel_id, message)?;
                     } else {
                         // can't add any more messages (since we sorted messages from smallest to largest)
                         // finish packet
@@ -421,7 +484,7 @@ impl PacketBuilder {
         if let Some(packet) = single_packet {
             packets.push(packet);
         }
-        packets
+        Ok(packets)
     }
 
     // /// Pack messages into packets for the current channel
@@ -658,15 +721,15 @@ mod tests {
         packet.add_message(
             *channel_id,
             SingleData::new(None, small_message.clone(), 1.0),
-        );
-        assert_eq!(packet.num_messages(), 1);
+        )?;
+        assert_eq!(packet.num_messages(), Some(1));
 
         assert!(manager.can_add_bits(small_message.len() * (u8::BITS as usize)),);
         packet.add_message(
             *channel_id,
             SingleData::new(None, small_message.clone(), 1.0),
-        );
-        assert_eq!(packet.num_messages(), 2);
+        )?;
+        assert_eq!(packet.num_messages(), Some(2));
         Ok(())
     }
 
@@ -721,13 +784,13 @@ mod tests {
             *channel_id3,
             (VecDeque::from(vec![small_message.clone()]), VecDeque::new()),
         );
-        let mut packets = manager.build_packets(data);
+        let mut packets = manager.build_packets(data).unwrap();
         // we start building the packet for channel 1, we add one small message
         // we add one more small message to the packet from channel1, then we push fragments 1 and 2 for channel 2
         // we start working on fragment 3 for channel 2, and push the packet from channel 1 (with 2 messages)
         // then we push the small message from channel 3 into fragment 3
         assert_eq!(packets.len(), 4);
-        let contents3 = packets.pop().unwrap().data.contents();
+        let contents3 = packets.pop().unwrap().data.contents().unwrap();
         assert_eq!(contents3.len(), 2);
         assert_eq!(
             contents3.get(channel_id2).unwrap(),
@@ -737,7 +800,7 @@ mod tests {
             contents3.get(channel_id3).unwrap(),
             &vec![small_message.clone().into()]
         );
-        let contents2 = packets.pop().unwrap().data.contents();
+        let contents2 = packets.pop().unwrap().data.contents().unwrap();
         assert_eq!(contents2.len(), 2);
         assert_eq!(
             contents2.get(channel_id1).unwrap(),
@@ -747,13 +810,13 @@ mod tests {
             contents2.get(channel_id2).unwrap(),
             &vec![small_message.clone().into()]
         );
-        let contents1 = packets.pop().unwrap().data.contents();
+        let contents1 = packets.pop().unwrap().data.contents().unwrap();
         assert_eq!(contents1.len(), 1);
         assert_eq!(
             contents1.get(channel_id2).unwrap(),
             &vec![fragments[1].clone().into()]
         );
-        let contents0 = packets.pop().unwrap().data.contents();
+        let contents0 = packets.pop().unwrap().data.contents().unwrap();
         assert_eq!(contents0.len(), 1);
         assert_eq!(
             contents0.get(channel_id2).unwrap(),
@@ -21,4 +21,7 @@ pub enum PacketType {
     // A packet containing actual data, but which is fragmented into multiple parts
     #[bitcode_hint(frequency = 5)]
     DataFragment,
+    // A packet whose data (Data or DataFragment) is compressed
+    #[bitcode_hint(frequency = 50)]
+    Compressed,
 }
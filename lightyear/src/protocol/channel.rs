@@ -4,7 +4,7 @@ use std::any::TypeId;
 use std::collections::HashMap;
 
 use crate::channel::builder::ChannelContainer;
-use crate::channel::builder::{Channel, ChannelBuilder, ChannelSettings};
+use crate::channel::builder::{Channel, ChannelBuilder, ChannelMode, ChannelSettings};
 use crate::protocol::registry::{NetId, TypeKind, TypeMapper};
 
 /// ChannelKind - internal wrapper around the type of the channel
@@ -90,6 +90,29 @@ impl ChannelRegistry {
         self.get_builder_from_kind(channel_kind)
     }
 
+    /// Description of the registered channels (name and mode), in the order of their network ids.
+    ///
+    /// Used to compute the [`Protocol::protocol_hash`](crate::protocol::Protocol::protocol_hash)
+    pub(crate) fn schema(&self) -> Vec<String> {
+        (0..self.kind_map.next_net_id)
+            .filter_map(|net_id| self.get_kind_from_net_id(net_id))
+            .map(|kind| {
+                let name = self.name(kind).unwrap_or("unknown");
+                let mode = match self.builder_map.get(kind).map(|b| &b.settings.mode) {
+                    Some(ChannelMode::UnorderedUnreliableWithAcks) => "UnorderedUnreliableWithAcks",
+                    Some(ChannelMode::UnorderedUnreliable) => "UnorderedUnreliable",
+                    Some(ChannelMode::SequencedUnreliable) => "SequencedUnreliable",
+                    Some(ChannelMode::UnorderedReliable(_)) => "UnorderedReliable",
+                    Some(ChannelMode::SequencedReliable(_)) => "SequencedReliable",
+                    Some(ChannelMode::OrderedReliable(_)) => "OrderedReliable",
+                    Some(ChannelMode::TickBuffered) => "TickBuffered",
+                    None => "unknown",
+                };
+                format!("{name} {mode}")
+            })
+            .collect()
+    }
+
     #[cfg(test)]
     fn len(&self) -> usize {
         self.kind_map.len()
@@ -118,6 +141,7 @@ mod tests {
         };
         registry.add::<MyChannel>(settings.clone());
         assert_eq!(registry.len(), 1);
+        assert_eq!(registry.schema(), vec!["MyChannel UnorderedUnreliable"]);
 
         let builder = registry.get_builder_from_net_id(0).unwrap();
         let channel_container: ChannelContainer = builder.build();
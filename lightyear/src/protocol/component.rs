@@ -43,6 +43,9 @@ pub trait ComponentProtocol:
     /// Map from the type-id to the component kind for each component in the protocol
     fn type_ids() -> HashMap<TypeId, <Self::Protocol as Protocol>::ComponentKinds>;
 
+    /// Description of the variants of the protocol (name, type, sync mode and delta-compression), in order
+    fn schema() -> &'static [&'static str];
+
     /// Apply a ComponentInsert to an entity
     fn insert(self, entity: &mut EntityWorldMut);
 
@@ -70,6 +73,16 @@ pub trait ComponentProtocol:
         events: &mut E,
     );
 
+    /// Returns true if the component is replicated with delta-compression
+    fn is_diffable(&self) -> bool;
+
+    /// Compute the serialized delta between `self` (the base value) and `new`.
+    /// Errors if the component is not replicated with delta-compression.
+    fn diff(&self, new: &Self) -> anyhow::Result<Vec<u8>>;
+
+    /// Apply a serialized delta to `self`
+    fn apply_diff(&mut self, delta: &[u8]) -> anyhow::Result<()>;
+
     fn add_prediction_systems(app: &mut App);
 
     /// Add all component systems for the PrepareInterpolation SystemSet
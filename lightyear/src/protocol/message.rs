@@ -44,6 +44,9 @@ pub trait MessageProtocol:
     /// Get the name of the Message
     fn name(&self) -> &'static str;
 
+    /// Description of the variants of the protocol (name and type), in order
+    fn schema() -> &'static [&'static str];
+
     /// Returns the MessageKind of the Message
     fn kind(&self) -> MessageKind;
 
@@ -54,6 +57,13 @@ pub trait MessageProtocol:
     /// Add events to the app
     fn add_events<Ctx: EventContext>(app: &mut App);
 
+    /// Add the systems that send the replicated resources to the clients
+    /// (see [`ReplicateResource`](crate::shared::replication::resources::ReplicateResource))
+    fn add_resource_send_systems(app: &mut App);
+
+    /// Add the systems that apply the replicated resources received from the server
+    fn add_resource_receive_systems(app: &mut App);
+
     /// Takes messages that were written and writes MessageEvents
     fn push_message_events<E: IterMessageEvent<Self::Protocol, Ctx>, Ctx: EventContext>(
         world: &mut World,
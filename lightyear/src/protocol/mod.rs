@@ -7,6 +7,7 @@
 
 use anyhow::Context;
 use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
 
 use bevy::prelude::{App, Resource};
 use bitcode::encoding::Fixed;
@@ -95,6 +96,22 @@ pub trait Protocol: Send + Sync + Clone + Debug + 'static {
     fn add_channel<C: Channel>(&mut self, settings: ChannelSettings) -> &mut Self;
     fn channel_registry(&self) -> &ChannelRegistry;
     fn add_per_component_replication_send_systems<R: ReplicationSend<Self>>(app: &mut App);
+
+    /// Stable hash of the channels, messages and components registered in the protocol
+    /// (names, ordering, sync modes and custom serializers).
+    ///
+    /// The client sends it when connecting; the server denies the connection with
+    /// [`DisconnectReason::ProtocolMismatch`](crate::connection::DisconnectReason::ProtocolMismatch)
+    /// if it doesn't match its own hash, instead of failing to deserialize the client's packets.
+    ///
+    /// The hash is only checked by the netcode transports: it is not checked when using steam.
+    fn protocol_hash(&self) -> u64 {
+        let mut hasher = seahash::SeaHasher::new();
+        self.channel_registry().schema().hash(&mut hasher);
+        Self::Message::schema().hash(&mut hasher);
+        Self::Components::schema().hash(&mut hasher);
+        hasher.finish()
+    }
 }
 
 // TODO: give an option to change names of types
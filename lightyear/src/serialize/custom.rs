@@ -0,0 +1,132 @@
+//! Custom serialization of the components and messages of the protocol
+//!
+//! By default, every component and message is serialized with its serde implementation.
+//! A variant of the `#[component_protocol]` or `#[message_protocol]` enum can use a different serialization
+//! with the `#[serialize(...)]` attribute:
+//! - `#[serialize(with = "MySerializer")]`: use `MySerializer`, which implements [`SerializeWith<T>`]
+//!   (for example to quantize the fields of the component)
+//! - `#[serialize(bitcode)]`: use the bitcode [`Encode`]/[`Decode`] implementation of the type
+//!
+//! ```rust,ignore
+//! pub struct PositionQuantizer;
+//!
+//! impl SerializeWith<Position> for PositionQuantizer {
+//!     fn serialize<S: Serializer>(value: &Position, serializer: S) -> Result<S::Ok, S::Error> {
+//!         (quantize_f32(value.x, -1000.0, 1000.0), quantize_f32(value.y, -1000.0, 1000.0)).serialize(serializer)
+//!     }
+//!     fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Position, D::Error> {
+//!         let (x, y) = <(u16, u16)>::deserialize(deserializer)?;
+//!         Ok(Position::new(dequantize_f32(x, -1000.0, 1000.0), dequantize_f32(y, -1000.0, 1000.0)))
+//!     }
+//! }
+//!
+//! #[component_protocol(protocol = "MyProtocol")]
+//! pub enum Components {
+//!     #[serialize(with = "PositionQuantizer")]
+//!     Position(Position),
+//!     #[serialize(bitcode)]
+//!     Inventory(Inventory),
+//! }
+//! ```
+use std::fmt::Formatter;
+use std::marker::PhantomData;
+
+use bitcode::{Decode, Encode};
+use serde::de::{Error as _, Visitor};
+use serde::ser::Error as _;
+use serde::{Deserializer, Serializer};
+
+/// Custom serialization of the type `T`, that is used instead of the serde implementation of `T`
+/// when sending it over the network
+pub trait SerializeWith<T> {
+    fn serialize<S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error>;
+
+    fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error>;
+}
+
+/// Serialize the type with its bitcode [`Encode`]/[`Decode`] implementation
+pub struct BitcodeSerializer;
+
+impl<T: Encode + Decode> SerializeWith<T> for BitcodeSerializer {
+    fn serialize<S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        let bytes = bitcode::encode(value).map_err(S::Error::custom)?;
+        serializer.serialize_bytes(&bytes)
+    }
+
+    fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+        deserializer.deserialize_bytes(BitcodeVisitor(PhantomData))
+    }
+}
+
+struct BitcodeVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Decode> Visitor<'de> for BitcodeVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        formatter.write_str("bitcode-encoded bytes")
+    }
+
+    fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        bitcode::decode(v).map_err(E::custom)
+    }
+}
+
+/// Used by the protocol macros to serialize a variant with a [`SerializeWith`] implementation
+#[doc(hidden)]
+pub fn serialize_with<W: SerializeWith<T>, T, S: Serializer>(
+    value: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    W::serialize(value, serializer)
+}
+
+/// Used by the protocol macros to deserialize a variant with a [`SerializeWith`] implementation
+#[doc(hidden)]
+pub fn deserialize_with<'de, W: SerializeWith<T>, T, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<T, D::Error> {
+    W::deserialize(deserializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::_reexport::{BitSerializable, ReadWordBuffer, WriteWordBuffer};
+    use crate::serialize::reader::ReadBuffer;
+    use crate::serialize::writer::WriteBuffer;
+    use crate::tests::protocol::*;
+
+    #[test]
+    fn test_serialize_with() {
+        let component = MyComponentsProtocol::Component6(Component6(12.5, -40.0));
+        let mut writer = WriteWordBuffer::with_capacity(20);
+        component.encode(&mut writer).unwrap();
+        let quantized_bits = writer.num_bits_written();
+        let bytes = writer.finish_write().to_vec();
+        let mut reader = ReadWordBuffer::start_read(&bytes);
+        let MyComponentsProtocol::Component6(decoded) =
+            MyComponentsProtocol::decode(&mut reader).unwrap()
+        else {
+            panic!("expected Component6");
+        };
+        assert!((decoded.0 - 12.5).abs() < 0.01);
+        assert!((decoded.1 + 40.0).abs() < 0.01);
+
+        // the quantized component (including the enum tag) is smaller than the same component
+        // serialized with its serde implementation
+        let mut writer = WriteWordBuffer::with_capacity(20);
+        writer.serialize(&Component6(12.5, -40.0)).unwrap();
+        let full_bits = writer.num_bits_written();
+        assert!(quantized_bits < full_bits);
+    }
+
+    #[test]
+    fn test_serialize_bitcode() {
+        let message = MyMessageProtocol::Message3(Message3(vec![1, 2, 3]));
+        let mut writer = WriteWordBuffer::with_capacity(20);
+        message.encode(&mut writer).unwrap();
+        let bytes = writer.finish_write().to_vec();
+        let mut reader = ReadWordBuffer::start_read(&bytes);
+        assert_eq!(MyMessageProtocol::decode(&mut reader).unwrap(), message);
+    }
+}
@@ -0,0 +1,160 @@
+//! Helpers to quantize values before sending them over the network
+//!
+//! They are meant to be used in [`SerializeWith`](crate::serialize::custom::SerializeWith) implementations.
+use bevy::math::Quat;
+use bitcode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+/// Quantize a `f32` in the range `[min, max]` into a 16-bit fixed point value.
+/// Values outside the range are clamped.
+pub fn quantize_f32(value: f32, min: f32, max: f32) -> u16 {
+    let normalized = ((value - min) / (max - min)).clamp(0.0, 1.0);
+    (normalized * u16::MAX as f32).round() as u16
+}
+
+/// Reverse of [`quantize_f32`]
+pub fn dequantize_f32(value: u16, min: f32, max: f32) -> f32 {
+    min + (value as f32 / u16::MAX as f32) * (max - min)
+}
+
+/// Quantized rotation using the 'smallest three' encoding:
+/// the largest component of the unit quaternion is dropped (it can be recomputed from the other three),
+/// and the other three components, which are all in the range `[-1/√2, 1/√2]`, are quantized to 16 bits.
+#[derive(Serialize, Deserialize, Encode, Decode, Clone, Copy, Debug, PartialEq)]
+pub struct SmallestThreeQuat {
+    /// The component that was dropped
+    #[bitcode(with_serde)]
+    largest: QuatComponent,
+    values: [u16; 3],
+}
+
+/// Component of a quaternion.
+///
+/// Using an enum (instead of an index) means that an invalid component is a deserialization error.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+enum QuatComponent {
+    X,
+    Y,
+    Z,
+    W,
+}
+
+impl QuatComponent {
+    const ALL: [QuatComponent; 4] = [Self::X, Self::Y, Self::Z, Self::W];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+const SMALLEST_THREE_BOUND: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+impl From<Quat> for SmallestThreeQuat {
+    fn from(quat: Quat) -> Self {
+        let mut components = quat.normalize().to_array();
+        let largest = QuatComponent::ALL
+            .into_iter()
+            .max_by(|a, b| {
+                components[a.index()]
+                    .abs()
+                    .total_cmp(&components[b.index()].abs())
+            })
+            .unwrap();
+        // q and -q represent the same rotation: make the dropped component positive
+        if components[largest.index()] < 0.0 {
+            components.iter_mut().for_each(|c| *c = -*c);
+        }
+        let mut values = [0; 3];
+        for (value, component) in values.iter_mut().zip(
+            components
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != largest.index())
+                .map(|(_, c)| *c),
+        ) {
+            *value = quantize_f32(component, -SMALLEST_THREE_BOUND, SMALLEST_THREE_BOUND);
+        }
+        Self { largest, values }
+    }
+}
+
+impl From<SmallestThreeQuat> for Quat {
+    fn from(quantized: SmallestThreeQuat) -> Self {
+        let mut values = quantized
+            .values
+            .map(|v| dequantize_f32(v, -SMALLEST_THREE_BOUND, SMALLEST_THREE_BOUND))
+            .into_iter();
+        let mut components = [0.0; 4];
+        for (i, component) in components.iter_mut().enumerate() {
+            if i != quantized.largest.index() {
+                *component = values.next().unwrap();
+            }
+        }
+        let sum_squares: f32 = components.iter().map(|c| c * c).sum();
+        components[quantized.largest.index()] = (1.0 - sum_squares).max(0.0).sqrt();
+        Quat::from_array(components).normalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantize_f32() {
+        for value in [-100.0, -12.34, 0.0, 56.78, 100.0] {
+            let quantized = quantize_f32(value, -100.0, 100.0);
+            let dequantized = dequantize_f32(quantized, -100.0, 100.0);
+            // precision is (max - min) / 2^16
+            assert!((dequantized - value).abs() < 0.01);
+        }
+        // values are clamped
+        assert_eq!(quantize_f32(200.0, -100.0, 100.0), u16::MAX);
+    }
+
+    #[test]
+    fn test_smallest_three_quat() {
+        for quat in [
+            Quat::IDENTITY,
+            Quat::from_rotation_y(2.5),
+            Quat::from_euler(bevy::math::EulerRot::XYZ, 0.3, -1.2, 2.8),
+            -Quat::from_rotation_x(1.0),
+        ] {
+            let quantized = SmallestThreeQuat::from(quat);
+            let dequantized = Quat::from(quantized);
+            // q and -q represent the same rotation
+            assert!(quat.dot(dequantized).abs() > 0.9999);
+        }
+    }
+
+    /// An invalid dropped component is a deserialization error
+    #[test]
+    fn test_smallest_three_quat_invalid() {
+        #[derive(Serialize)]
+        #[allow(dead_code)]
+        enum RawQuatComponent {
+            X,
+            Y,
+            Z,
+            W,
+            Invalid,
+        }
+        #[derive(Serialize)]
+        struct RawSmallestThreeQuat {
+            largest: RawQuatComponent,
+            values: [u16; 3],
+        }
+        let valid = bitcode::serialize(&RawSmallestThreeQuat {
+            largest: RawQuatComponent::X,
+            values: [0; 3],
+        })
+        .unwrap();
+        assert!(bitcode::deserialize::<SmallestThreeQuat>(&valid).is_ok());
+        let invalid = bitcode::serialize(&RawSmallestThreeQuat {
+            largest: RawQuatComponent::Invalid,
+            values: [0; 3],
+        })
+        .unwrap();
+        assert!(bitcode::deserialize::<SmallestThreeQuat>(&invalid).is_err());
+    }
+}
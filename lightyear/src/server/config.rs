@@ -1,10 +1,19 @@
 //! Defines server-specific configuration options
+use std::net::SocketAddr;
+use std::sync::Arc;
+
 use bevy::prelude::Resource;
+use bevy::utils::Duration;
 use governor::Quota;
 use nonzero_ext::nonzero;
 
-use crate::connection::netcode::Key;
+#[cfg(not(target_family = "wasm"))]
+use crate::connection::lan::DEFAULT_DISCOVERY_PORT;
+use crate::connection::netcode::{
+    ClientId, ConnectionValidator, Key, MAX_CLIENTS, USER_DATA_BYTES,
+};
 use crate::connection::server::NetConfig;
+use crate::packet::compression::CompressionConfig;
 use crate::shared::config::SharedConfig;
 use crate::shared::ping::manager::PingConfig;
 
@@ -17,6 +26,26 @@ pub struct NetcodeConfig {
     pub client_timeout_secs: i32,
     pub protocol_id: u64,
     pub private_key: Option<Key>,
+    /// Decides if a client is allowed to connect, before the connection is established
+    pub validator: Option<Validator>,
+    /// Maximum number of clients that can be connected at the same time
+    pub max_clients: usize,
+    /// Hash of the [`Protocol`](crate::protocol::Protocol), set by the [`ServerPlugin`](crate::server::plugin::ServerPlugin)
+    pub(crate) protocol_hash: u64,
+}
+
+/// Callback that decides if a client is allowed to connect, from its id and the user data of its connect token
+/// (for example to check a ban list or the version of the client, or to limit the number of players).
+///
+/// It returns `Err(reason)` to deny the connection; the client receives [`DisconnectReason::Denied`](crate::connection::DisconnectReason::Denied)
+/// with the reason code in its [`DisconnectEvent`](crate::client::events::DisconnectEvent).
+#[derive(Clone)]
+pub struct Validator(pub(crate) Arc<ConnectionValidator>);
+
+impl std::fmt::Debug for Validator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Validator").finish()
+    }
 }
 
 impl Default for NetcodeConfig {
@@ -27,6 +56,9 @@ impl Default for NetcodeConfig {
             client_timeout_secs: 10,
             protocol_id: 0,
             private_key: None,
+            validator: None,
+            max_clients: MAX_CLIENTS,
+            protocol_hash: 0,
         }
     }
 }
@@ -45,6 +77,82 @@ impl NetcodeConfig {
         self.client_timeout_secs = client_timeout_secs;
         self
     }
+
+    pub fn with_max_clients(mut self, max_clients: usize) -> Self {
+        self.max_clients = max_clients;
+        self
+    }
+
+    /// Set the callback that decides if a client is allowed to connect (see [`Validator`])
+    pub fn with_validator(
+        mut self,
+        validator: impl Fn(ClientId, &[u8; USER_DATA_BYTES]) -> Result<(), u8> + Send + Sync + 'static,
+    ) -> Self {
+        self.validator = Some(Validator(Arc::new(validator)));
+        self
+    }
+}
+
+/// Configuration of a server that can be discovered and joined by the clients on the local network,
+/// without a backend issuing connect tokens (see [`lan`](crate::connection::lan))
+#[cfg(not(target_family = "wasm"))]
+#[derive(Clone, Debug)]
+pub struct LanConfig {
+    /// Name of the server, shown to the clients that scan the local network
+    pub name: String,
+    /// Address of the socket that answers the discovery queries and the key exchange requests.
+    ///
+    /// The default binds all interfaces, because a socket bound to the address of a single interface
+    /// doesn't receive the broadcast queries on every platform; use `local_network_only` to ignore
+    /// the messages coming from outside of the local network.
+    pub discovery_addr: SocketAddr,
+    /// Maximum number of players advertised to the clients, and accepted by the server
+    pub max_players: u16,
+    /// If true, the discovery responder ignores the messages that don't come from a private, loopback
+    /// or link-local address
+    pub local_network_only: bool,
+    pub netcode: NetcodeConfig,
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl Default for LanConfig {
+    fn default() -> Self {
+        Self {
+            name: "lightyear server".to_string(),
+            discovery_addr: SocketAddr::from(([0, 0, 0, 0], DEFAULT_DISCOVERY_PORT)),
+            max_players: 16,
+            local_network_only: true,
+            netcode: NetcodeConfig::default(),
+        }
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl LanConfig {
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    pub fn with_discovery_addr(mut self, discovery_addr: SocketAddr) -> Self {
+        self.discovery_addr = discovery_addr;
+        self
+    }
+
+    pub fn with_max_players(mut self, max_players: u16) -> Self {
+        self.max_players = max_players;
+        self
+    }
+
+    pub fn with_local_network_only(mut self, local_network_only: bool) -> Self {
+        self.local_network_only = local_network_only;
+        self
+    }
+
+    pub fn with_netcode(mut self, netcode: NetcodeConfig) -> Self {
+        self.netcode = netcode;
+        self
+    }
 }
 
 /// Configuration related to sending packets
@@ -54,6 +162,9 @@ pub struct PacketConfig {
     pub per_client_send_bandwidth_cap: Quota,
     /// If false, there is no bandwidth cap and all messages are sent as soon as possible
     pub bandwidth_cap_enabled: bool,
+    /// Compression of the packets, applied before they are sent through the transport.
+    /// The client and the server must use the same compression (it is part of the protocol hash).
+    pub compression: CompressionConfig,
 }
 
 impl Default for PacketConfig {
@@ -62,6 +173,7 @@ impl Default for PacketConfig {
             // 56 KB/s bandwidth cap
             per_client_send_bandwidth_cap: Quota::per_second(nonzero!(56000u32)),
             bandwidth_cap_enabled: false,
+            compression: CompressionConfig::default(),
         }
     }
 }
@@ -82,6 +194,148 @@ impl PacketConfig {
         self.bandwidth_cap_enabled = true;
         self
     }
+
+    pub fn with_compression(mut self, compression: CompressionConfig) -> Self {
+        self.compression = compression;
+        self
+    }
+}
+
+/// What the server does with the packets and messages of a client that exceeds its rate limits
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RateLimitPolicy {
+    /// Drop the packets and messages that exceed the limits
+    #[default]
+    Drop,
+    /// Buffer the packets and messages that exceed the limits, and process them later once the client
+    /// is back under its limits. They are dropped if the buffer is full (see [`RateLimitConfig::max_throttled`]).
+    ///
+    /// Only the messages of [`UnorderedUnreliable`](crate::prelude::ChannelMode::UnorderedUnreliable) channels
+    /// are buffered, the messages of the other limited channels are dropped.
+    Throttle,
+    /// Disconnect the client, with [`DisconnectReason::RateLimited`](crate::connection::DisconnectReason::RateLimited)
+    Disconnect,
+}
+
+/// Limits on the packets and messages that the server accepts from each client, to protect the server
+/// against clients that flood it.
+///
+/// All the limits are disabled by default.
+#[derive(Clone, Debug)]
+pub struct RateLimitConfig {
+    /// Maximum number of packets received per second from each client
+    pub packets_per_second: Option<Quota>,
+    /// Maximum number of bytes received per second from each client
+    pub bytes_per_second: Option<Quota>,
+    /// Maximum number of messages received from each client per channel and per tick.
+    /// Input messages and internal sync messages (pings) are not included.
+    ///
+    /// Only the unreliable channels that don't track acks are limited; use the packet and byte
+    /// limits to protect the other channels.
+    pub messages_per_channel_per_tick: Option<u32>,
+    /// Maximum number of input messages received from each client per tick
+    pub input_messages_per_tick: Option<u32>,
+    /// What to do when a client exceeds one of the limits
+    pub policy: RateLimitPolicy,
+    /// Maximum number of packets (and of messages) buffered for each client with [`RateLimitPolicy::Throttle`]
+    pub max_throttled: usize,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            packets_per_second: None,
+            bytes_per_second: None,
+            messages_per_channel_per_tick: None,
+            input_messages_per_tick: None,
+            policy: RateLimitPolicy::default(),
+            max_throttled: 256,
+        }
+    }
+}
+
+impl RateLimitConfig {
+    pub fn with_packets_per_second(mut self, packets_per_second: u32) -> Self {
+        self.packets_per_second = Some(Quota::per_second(
+            packets_per_second
+                .try_into()
+                .expect("the limit must be positive"),
+        ));
+        self
+    }
+
+    pub fn with_bytes_per_second(mut self, bytes_per_second: u32) -> Self {
+        self.bytes_per_second = Some(Quota::per_second(
+            bytes_per_second
+                .try_into()
+                .expect("the limit must be positive"),
+        ));
+        self
+    }
+
+    pub fn with_messages_per_channel_per_tick(mut self, messages_per_tick: u32) -> Self {
+        self.messages_per_channel_per_tick = Some(messages_per_tick);
+        self
+    }
+
+    pub fn with_input_messages_per_tick(mut self, input_messages_per_tick: u32) -> Self {
+        self.input_messages_per_tick = Some(input_messages_per_tick);
+        self
+    }
+
+    pub fn with_policy(mut self, policy: RateLimitPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+/// Configuration related to replication
+#[derive(Clone, Debug)]
+pub struct ReplicationConfig {
+    /// If a client hasn't acknowledged any of the replication updates that were sent to it for this duration,
+    /// we consider that the client fell too far behind (for example because it was stalled).
+    /// Instead of replaying all the replication messages that were queued for the client, the server sends
+    /// a full snapshot of the entities that are visible to the client.
+    ///
+    /// 2 seconds by default. Set to `None` to disable the resync.
+    pub resync_threshold: Option<Duration>,
+}
+
+impl Default for ReplicationConfig {
+    fn default() -> Self {
+        Self {
+            resync_threshold: Some(Duration::from_secs(2)),
+        }
+    }
+}
+
+impl ReplicationConfig {
+    pub fn with_resync_threshold(mut self, resync_threshold: Option<Duration>) -> Self {
+        self.resync_threshold = resync_threshold;
+        self
+    }
+}
+
+/// Configuration related to the connections with the clients
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionConfig {
+    /// When a client disconnects (for example because of a timeout), the server keeps its session
+    /// (rooms, visibility, replication state, input buffers, authority) for this duration.
+    /// If the client reconnects with the same `ClientId` in the meantime, the session is resumed and
+    /// the client only receives what changed while it was gone.
+    ///
+    /// The [`DisconnectEvent`](crate::server::events::DisconnectEvent) is only emitted once the grace period expires,
+    /// and no [`ConnectEvent`](crate::server::events::ConnectEvent) is emitted when the session is resumed.
+    ///
+    /// Set to `None` to remove the session as soon as the client disconnects.
+    pub disconnect_grace_period: Option<Duration>,
+}
+
+impl ConnectionConfig {
+    pub fn with_disconnect_grace_period(mut self, grace_period: Duration) -> Self {
+        self.disconnect_grace_period = Some(grace_period);
+        self
+    }
 }
 
 /// Configuration for the server plugin
@@ -93,4 +347,7 @@ pub struct ServerConfig {
     pub net: Vec<NetConfig>,
     pub packet: PacketConfig,
     pub ping: PingConfig,
+    pub replication: ReplicationConfig,
+    pub connection: ConnectionConfig,
+    pub rate_limit: RateLimitConfig,
 }
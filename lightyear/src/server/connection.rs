@@ -3,18 +3,22 @@ use anyhow::{Context, Result};
 use bevy::ecs::component::Tick as BevyTick;
 use bevy::ecs::entity::EntityHash;
 use bevy::prelude::{Entity, Resource, World};
+use std::collections::VecDeque;
+
 use bevy::utils::HashSet;
 use hashbrown::hash_map::Entry;
 use serde::Serialize;
-use tracing::{debug, info, trace, trace_span};
+use tracing::{debug, error, info, trace, trace_span, warn};
 
 use crate::_reexport::{
-    EntityUpdatesChannel, FromType, InputMessageKind, MessageProtocol, PingChannel,
-    ReplicationSend, ShouldBeInterpolated,
+    EntityActionsChannel, EntityUpdatesChannel, FromType, InputMessageKind, MessageProtocol,
+    PingChannel, ReplicationSend, ShouldBeInterpolated,
 };
-use crate::channel::senders::ChannelSend;
+use crate::channel::builder::ChannelMode;
+use crate::channel::senders::{ChannelSend, ChannelSender};
 use crate::client::message::ClientMessage;
-use crate::connection::netcode::ClientId;
+use crate::connection::netcode::{ClientId, USER_DATA_BYTES};
+use crate::connection::DisconnectReason;
 use crate::inputs::native::input_buffer::InputBuffer;
 use crate::packet::message_manager::MessageManager;
 use crate::packet::packet::Packet;
@@ -25,23 +29,32 @@ use crate::prelude::{
 use crate::protocol::channel::ChannelRegistry;
 use crate::protocol::Protocol;
 use crate::serialize::reader::ReadBuffer;
-use crate::server::config::PacketConfig;
+use crate::server::config::{PacketConfig, RateLimitConfig};
 use crate::server::events::ServerEvents;
 use crate::server::message::ServerMessage;
+use crate::server::rate_limit::{
+    ClientRateLimiter, LimitedMessage, PacketCheck, RateLimitDecision, RateLimitStats,
+};
 use crate::shared::events::connection::ConnectionEvents;
 use crate::shared::ping::manager::{PingConfig, PingManager};
-use crate::shared::ping::message::SyncMessage;
-use crate::shared::replication::components::{NetworkTarget, Replicate, ReplicationGroupId};
+use crate::shared::ping::message::{InterpolationTime, SyncMessage};
+use crate::shared::replication::authority::AuthorityPeer;
+use crate::shared::replication::components::{
+    NetworkTarget, Replicate, ReplicateCache, ReplicationGroupId,
+};
 use crate::shared::replication::receive::ReplicationReceiver;
 use crate::shared::replication::send::ReplicationSender;
 use crate::shared::replication::ReplicationMessage;
 use crate::shared::replication::ReplicationMessageData;
 use crate::shared::tick_manager::Tick;
 use crate::shared::tick_manager::TickManager;
-use crate::shared::time_manager::TimeManager;
+use crate::shared::time_manager::{TimeManager, WrappedTime};
 
 type EntityHashMap<K, V> = hashbrown::HashMap<K, V, EntityHash>;
 
+/// Number of ticks during which the interpolation times reported by a client are kept
+pub const INTERPOLATION_TIME_HISTORY_TICKS: u16 = 64;
+
 #[derive(Resource)]
 pub struct ConnectionManager<P: Protocol> {
     pub(crate) connections: EntityHashMap<ClientId, Connection<P>>,
@@ -51,14 +64,28 @@ pub struct ConnectionManager<P: Protocol> {
     // NOTE: we put this here because we only need one per world, not one per connection
     /// Stores the last `Replicate` component for each replicated entity owned by the current world (the world that sends replication updates)
     /// Needed to know the value of the Replicate component after the entity gets despawned, to know how we replicate the EntityDespawn
-    pub replicate_component_cache: EntityHashMap<Entity, Replicate<P>>,
+    pub replicate_component_cache: EntityHashMap<Entity, ReplicateCache>,
 
     // list of clients that connected since the last time we sent replication messages
     // (we want to keep track of them because we need to replicate the entire world state to them)
     pub(crate) new_clients: Vec<ClientId>,
 
+    /// Entities whose simulation authority was given to a client.
+    /// We don't send replication updates for these entities to the client that has authority.
+    pub(crate) client_authority: EntityHashMap<Entity, ClientId>,
+
+    /// Clients that disconnected but whose session is kept until the end of the grace period
+    /// (see [`ConnectionConfig::disconnect_grace_period`](crate::server::config::ConnectionConfig::disconnect_grace_period)),
+    /// mapped to the time when their session expires and to the reason of the disconnection
+    pub(crate) suspended_sessions: EntityHashMap<ClientId, (WrappedTime, DisconnectReason)>,
+    /// Id of the local client, if the server runs in the same app as a client
+    /// (see [`Mode::HostServer`](crate::prelude::Mode::HostServer))
+    pub(crate) local_client: Option<ClientId>,
+
     packet_config: PacketConfig,
     ping_config: PingConfig,
+    /// Limits applied to the packets and messages received from each client
+    pub(crate) rate_limit_config: RateLimitConfig,
 }
 
 impl<P: Protocol> ConnectionManager<P> {
@@ -66,6 +93,7 @@ impl<P: Protocol> ConnectionManager<P> {
         channel_registry: ChannelRegistry,
         packet_config: PacketConfig,
         ping_config: PingConfig,
+        rate_limit_config: RateLimitConfig,
     ) -> Self {
         Self {
             connections: EntityHashMap::default(),
@@ -73,17 +101,29 @@ impl<P: Protocol> ConnectionManager<P> {
             events: ServerEvents::new(),
             replicate_component_cache: EntityHashMap::default(),
             new_clients: vec![],
+            client_authority: EntityHashMap::default(),
+            suspended_sessions: EntityHashMap::default(),
+            local_client: None,
             packet_config,
             ping_config,
+            rate_limit_config,
         }
     }
 
     /// Find the list of clients that should receive the replication message
+    ///
+    /// The local client of a host-server is never included, since it shares the server's entities
     pub(crate) fn apply_replication(
         &mut self,
         target: NetworkTarget,
     ) -> Box<dyn Iterator<Item = ClientId>> {
-        let connected_clients = self.connections.keys().copied().collect::<Vec<_>>();
+        let local_client = self.local_client;
+        let connected_clients = self
+            .connections
+            .keys()
+            .copied()
+            .filter(|id| Some(*id) != local_client)
+            .collect::<Vec<_>>();
         match target {
             NetworkTarget::All => {
                 // TODO: maybe only send stuff when the client is time-synced ?
@@ -103,7 +143,7 @@ impl<P: Protocol> ConnectionManager<P> {
                 )
             }
             NetworkTarget::Single(client_id) => {
-                if self.connections.contains_key(&client_id) {
+                if connected_clients.contains(&client_id) {
                     Box::new(std::iter::once(client_id))
                 } else {
                     Box::new(std::iter::empty())
@@ -136,7 +176,38 @@ impl<P: Protocol> ConnectionManager<P> {
         });
     }
 
-    pub(crate) fn add(&mut self, client_id: ClientId) {
+    /// Buffer a packet received from a client, subject to the client's rate limits
+    pub(crate) fn recv_packet(
+        &mut self,
+        client_id: ClientId,
+        packet: Packet,
+        tick_manager: &TickManager,
+    ) -> Result<()> {
+        self.connection_mut(client_id)?
+            .recv_packet_rate_limited(packet, tick_manager)
+    }
+
+    /// Counters of the packets and messages of the client that exceeded the rate limits
+    pub fn rate_limit_stats(&self, client_id: ClientId) -> Option<RateLimitStats> {
+        self.connections
+            .get(&client_id)
+            .map(|connection| connection.rate_limiter.stats)
+    }
+
+    /// Clients that exceeded their rate limits with the [`RateLimitPolicy::Disconnect`](crate::server::config::RateLimitPolicy::Disconnect) policy
+    /// and that should be disconnected. Each client is only returned once.
+    pub(crate) fn take_rate_limited_clients(&mut self) -> Vec<ClientId> {
+        self.connections
+            .iter_mut()
+            .filter(|(_, connection)| connection.rate_limiter.exceeded)
+            .map(|(client_id, connection)| {
+                connection.rate_limiter.exceeded = false;
+                *client_id
+            })
+            .collect()
+    }
+
+    pub(crate) fn add(&mut self, client_id: ClientId, user_data: Option<[u8; USER_DATA_BYTES]>) {
         if let Entry::Vacant(e) = self.connections.entry(client_id) {
             #[cfg(feature = "metrics")]
             metrics::gauge!("connected_clients").increment(1.0);
@@ -146,22 +217,234 @@ impl<P: Protocol> ConnectionManager<P> {
                 &self.channel_registry,
                 self.packet_config.clone(),
                 self.ping_config.clone(),
+                self.rate_limit_config.clone(),
             );
             connection.events.push_connection();
+            connection.user_data = user_data;
             self.new_clients.push(client_id);
             e.insert(connection);
+        } else if self.suspended_sessions.remove(&client_id).is_some() {
+            info!("Client {} reconnected, resuming its session", client_id);
         } else {
             info!("Client {} was already in the connections list", client_id);
         }
     }
 
-    pub(crate) fn remove(&mut self, client_id: ClientId) {
+    /// Add the local client of a host-server, which doesn't use a transport
+    pub(crate) fn add_local_client(&mut self, client_id: ClientId) {
+        self.add(client_id, None);
+        if let Some(connection) = self.connections.get_mut(&client_id) {
+            connection.is_local = true;
+        }
+        self.local_client = Some(client_id);
+    }
+
+    /// The client disconnected, but we keep its session (connection, rooms, input buffers, etc.)
+    /// until `expires_at` in case it reconnects.
+    ///
+    /// While the session is suspended, the packets for the client are dropped, as if they were lost.
+    pub(crate) fn suspend(
+        &mut self,
+        client_id: ClientId,
+        expires_at: WrappedTime,
+        reason: DisconnectReason,
+    ) {
+        info!(
+            ?reason,
+            "Client {} disconnected, keeping its session until {}", client_id, expires_at
+        );
+        self.suspended_sessions
+            .insert(client_id, (expires_at, reason));
+    }
+
+    /// Returns true if the client is disconnected but its session is kept in case it reconnects
+    pub fn is_suspended(&self, client_id: ClientId) -> bool {
+        self.suspended_sessions.contains_key(&client_id)
+    }
+
+    /// Returns the suspended sessions whose grace period has expired (with the reason of the disconnection),
+    /// and stop tracking them
+    pub(crate) fn expired_sessions(
+        &mut self,
+        current_time: WrappedTime,
+    ) -> Vec<(ClientId, DisconnectReason)> {
+        let expired = self
+            .suspended_sessions
+            .iter()
+            .filter(|(_, (expires_at, _))| *expires_at <= current_time)
+            .map(|(client_id, (_, reason))| (*client_id, *reason))
+            .collect::<Vec<_>>();
+        for (client_id, _) in expired.iter() {
+            self.suspended_sessions.remove(client_id);
+        }
+        expired
+    }
+
+    /// Remove the client's connection.
+    ///
+    /// Returns the entities that the client had authority over (the server takes back the authority)
+    pub(crate) fn remove(&mut self, client_id: ClientId, reason: DisconnectReason) -> Vec<Entity> {
         #[cfg(feature = "metrics")]
         metrics::gauge!("connected_clients").decrement(1.0);
 
-        info!("Client {} disconnected", client_id);
-        self.events.push_disconnects(client_id);
+        info!(?reason, "Client {} disconnected", client_id);
+        self.events.push_disconnects(client_id, reason);
         self.connections.remove(&client_id);
+        if self.local_client == Some(client_id) {
+            self.local_client = None;
+        }
+        // the server takes back the authority over the entities of the disconnected client
+        let revoked = self
+            .client_authority
+            .iter()
+            .filter_map(|(entity, c)| (*c == client_id).then_some(*entity))
+            .collect::<Vec<_>>();
+        for entity in revoked.iter() {
+            self.client_authority.remove(entity);
+        }
+        revoked
+    }
+
+    /// Find the clients whose replication updates have not been acked for more than `threshold_ticks`,
+    /// and prepare a full resync of the replicated world for them.
+    ///
+    /// Returns the list of clients that need to be resynced
+    pub(crate) fn resync_lagging_clients(
+        &mut self,
+        tick: Tick,
+        threshold_ticks: u16,
+    ) -> Vec<ClientId> {
+        let mut lagging_clients = vec![];
+        for (client_id, connection) in self.connections.iter_mut() {
+            // suspended clients are not acking anything, they will be resynced if they reconnect
+            if self.suspended_sessions.contains_key(client_id) {
+                continue;
+            }
+            if connection
+                .replication_sender
+                .is_lagging(tick, threshold_ticks)
+            {
+                warn!(
+                    ?client_id,
+                    "Client is too far behind on replication updates, sending a full resync"
+                );
+                connection.replication_sender.prepare_resync();
+                // the resync contains the full state (and replays the detach and authority actions that the
+                // client hasn't applied yet), no need to keep re-sending the backlog of actions.
+                // (the actions channel is unordered, so dropping messages doesn't block the next ones)
+                if let Some(channel) = connection
+                    .message_manager
+                    .channels
+                    .get_mut(&ChannelKind::of::<EntityActionsChannel>())
+                {
+                    if let ChannelSender::Reliable(sender) = &mut channel.sender {
+                        sender.clear_unacked_messages();
+                    }
+                }
+                lagging_clients.push(*client_id);
+            }
+        }
+        for client_id in lagging_clients.iter() {
+            // the entities will be replicated to the client as if it just connected
+            if !self.new_clients.contains(client_id) {
+                self.new_clients.push(*client_id);
+            }
+        }
+        lagging_clients
+    }
+
+    /// Returns the latest interpolation time reported by the client, i.e. the point in the server's timeline
+    /// that the client is rendering for interpolated entities.
+    ///
+    /// This can be used for lag compensation, with [`LagCompensationHistory`](crate::server::lag_compensation::LagCompensationHistory)
+    pub fn client_interpolation_time(&self, client_id: ClientId) -> Option<InterpolationTime> {
+        self.connections
+            .get(&client_id)
+            .and_then(|connection| connection.interpolation_times.back())
+            .map(|(_, interpolation_time)| *interpolation_time)
+    }
+
+    /// Returns the interpolation time that the client was rendering at the client tick `client_tick`
+    /// (for example the tick of an input message).
+    ///
+    /// Only the last [`INTERPOLATION_TIME_HISTORY_TICKS`] ticks are kept; returns None if `client_tick` is older.
+    pub fn client_interpolation_time_at(
+        &self,
+        client_id: ClientId,
+        client_tick: Tick,
+    ) -> Option<InterpolationTime> {
+        self.connections
+            .get(&client_id)?
+            .interpolation_times
+            .iter()
+            .rev()
+            .find(|(tick, _)| *tick <= client_tick)
+            .map(|(_, interpolation_time)| *interpolation_time)
+    }
+
+    /// Returns the peer that has authority over the entity
+    pub fn authority(&self, entity: Entity) -> AuthorityPeer {
+        self.client_authority
+            .get(&entity)
+            .map_or(AuthorityPeer::Server, |client_id| {
+                AuthorityPeer::Client(*client_id)
+            })
+    }
+
+    /// Transfer the simulation authority over a replicated entity to another peer.
+    ///
+    /// Returns the client that lost authority (if any)
+    pub(crate) fn transfer_authority(
+        &mut self,
+        entity: Entity,
+        group_id: ReplicationGroupId,
+        peer: AuthorityPeer,
+    ) -> Result<Option<ClientId>> {
+        if self.authority(entity) == peer {
+            return Ok(None);
+        }
+        if let AuthorityPeer::Client(client_id) = peer {
+            self.connection(client_id)?;
+        }
+        // take back the authority from the previous client
+        let previous = self.client_authority.remove(&entity);
+        if let Some(previous) = previous {
+            if let Ok(connection) = self.connection_mut(previous) {
+                connection
+                    .replication_sender
+                    .prepare_authority_change(entity, group_id, false);
+                connection.replication_receiver.revoke_authority(entity);
+                // the client's Confirmed entity did not receive any updates while it had authority:
+                // send it the full state of the group again
+                connection
+                    .replication_sender
+                    .group_channels
+                    .entry(group_id)
+                    .or_default()
+                    .collect_changes_since_this_tick = None;
+            }
+        }
+        if let AuthorityPeer::Client(client_id) = peer {
+            let connection = self.connection_mut(client_id)?;
+            connection
+                .replication_sender
+                .prepare_authority_change(entity, group_id, true);
+            connection.replication_receiver.grant_authority(entity);
+            self.client_authority.insert(entity, client_id);
+        }
+        Ok(previous)
+    }
+
+    /// Find the list of clients that should receive replication updates for the entity
+    /// (we don't send updates to the client that has authority over the entity)
+    fn apply_replication_without_authority(
+        &mut self,
+        entity: Entity,
+        target: NetworkTarget,
+    ) -> impl Iterator<Item = ClientId> {
+        let authority = self.client_authority.get(&entity).copied();
+        self.apply_replication(target)
+            .filter(move |client_id| Some(*client_id) != authority)
     }
 
     /// Get the inputs for all clients for the given tick
@@ -297,10 +580,23 @@ pub struct Connection<P: Protocol> {
     /// Stores the last input we have received from the client.
     /// In case we are missing the client input for a tick, we will fallback to using this.
     pub(crate) last_input: Option<P::Input>,
+    /// Interpolation times reported by the client during the last ticks, by client tick
+    pub(crate) interpolation_times: VecDeque<(Tick, InterpolationTime)>,
     // TODO: maybe don't do any replication until connection is synced?
 
     // messages that we have received that need to be rebroadcasted to other clients
     pub(crate) messages_to_rebroadcast: Vec<(P::Message, NetworkTarget, ChannelKind)>,
+    /// User data provided by the client when connecting
+    pub(crate) user_data: Option<[u8; USER_DATA_BYTES]>,
+    pub(crate) rate_limiter: ClientRateLimiter,
+    /// True if this is the connection of the local client of a host-server
+    pub(crate) is_local: bool,
+    /// Messages for the local client of a host-server, that are handed to the client without being serialized
+    pub(crate) local_messages: Vec<(ChannelKind, P::Message)>,
+    /// Packets that exceeded the rate limits and will be processed once the client is back under its limits
+    throttled_packets: VecDeque<Packet>,
+    /// Messages that exceeded the rate limits and will be processed once the client is back under its limits
+    throttled_messages: VecDeque<(ChannelKind, Tick, ClientMessage<P>)>,
 }
 
 impl<P: Protocol> Connection<P> {
@@ -308,9 +604,12 @@ impl<P: Protocol> Connection<P> {
         channel_registry: &ChannelRegistry,
         packet_config: PacketConfig,
         ping_config: PingConfig,
+        rate_limit_config: RateLimitConfig,
     ) -> Self {
         // create the message manager and the channels
+        let compression = packet_config.compression.clone();
         let mut message_manager = MessageManager::new(channel_registry, packet_config.into());
+        message_manager.set_compression(compression);
         // get the acks-tracker for entity updates
         let update_acks_tracker = message_manager
             .channels
@@ -318,11 +617,21 @@ impl<P: Protocol> Connection<P> {
             .unwrap()
             .sender
             .subscribe_acks();
+        // get the acks-tracker for entity actions
+        let actions_acks_tracker = message_manager
+            .channels
+            .get_mut(&ChannelKind::of::<EntityActionsChannel>())
+            .unwrap()
+            .sender
+            .subscribe_acks();
         // get a channel to get notified when a replication update message gets actually send (to update priority)
         let replication_update_send_receiver =
             message_manager.get_replication_update_send_receiver();
-        let replication_sender =
-            ReplicationSender::new(update_acks_tracker, replication_update_send_receiver);
+        let replication_sender = ReplicationSender::new(
+            update_acks_tracker,
+            actions_acks_tracker,
+            replication_update_send_receiver,
+        );
         let replication_receiver = ReplicationReceiver::new();
         Self {
             message_manager,
@@ -331,8 +640,15 @@ impl<P: Protocol> Connection<P> {
             ping_manager: PingManager::new(ping_config),
             input_buffer: InputBuffer::default(),
             last_input: None,
+            interpolation_times: VecDeque::new(),
             events: ConnectionEvents::default(),
             messages_to_rebroadcast: vec![],
+            user_data: None,
+            rate_limiter: ClientRateLimiter::new(rate_limit_config),
+            is_local: false,
+            local_messages: vec![],
+            throttled_packets: VecDeque::new(),
+            throttled_messages: VecDeque::new(),
         }
     }
 
@@ -340,6 +656,9 @@ impl<P: Protocol> Connection<P> {
         self.message_manager
             .update(time_manager, &self.ping_manager, tick_manager);
         self.ping_manager.update(time_manager);
+        let _ = self
+            .recv_throttled_packets(tick_manager)
+            .map_err(|e| error!("Error receiving throttled packets: {:?}", e));
     }
 
     pub(crate) fn buffer_message(
@@ -355,6 +674,10 @@ impl<P: Protocol> Connection<P> {
             .name(&channel)
             .unwrap_or("unknown")
             .to_string();
+        if self.is_local {
+            self.local_messages.push((channel, message));
+            return Ok(());
+        }
         let message = ServerMessage::<P>::Message(message);
         message.emit_send_logs(&channel_name);
         self.message_manager.buffer_send(message, channel)?;
@@ -366,6 +689,13 @@ impl<P: Protocol> Connection<P> {
         tick: Tick,
         bevy_tick: BevyTick,
     ) -> Result<()> {
+        // ask the client to send the full value of the delta-compressed components that we could not reconstruct
+        for reset in self.replication_receiver.take_delta_resets() {
+            let message = ServerMessage::<P>::DeltaReset(reset);
+            message.emit_send_logs("EntityUpdatesChannel");
+            self.message_manager
+                .buffer_send(message, ChannelKind::of::<EntityUpdatesChannel>())?;
+        }
         self.replication_sender
             .finalize(tick)
             .into_iter()
@@ -390,8 +720,10 @@ impl<P: Protocol> Connection<P> {
                 // keep track of the group associated with the message, so we can handle receiving an ACK for that message_id later
                 if should_track_ack {
                     self.replication_sender
-                        .updates_message_id_to_group_id
-                        .insert(message_id, (group_id, bevy_tick));
+                        .track_update_message(message_id, group_id, bevy_tick, tick);
+                } else {
+                    self.replication_sender
+                        .track_actions_message(message_id, group_id);
                 }
                 Ok(())
             })
@@ -448,6 +780,19 @@ impl<P: Protocol> Connection<P> {
         tick_manager: &TickManager,
     ) -> ConnectionEvents<P> {
         let _span = trace_span!("receive").entered();
+        self.rate_limiter.start_tick(tick_manager.tick());
+        // messages that were throttled by the rate limiter are processed first
+        for (channel_kind, tick, message) in std::mem::take(&mut self.throttled_messages) {
+            let allowed = self
+                .rate_limited_kind(&message, channel_kind)
+                .map_or(true, |kind| self.rate_limiter.allow_message(kind));
+            if allowed {
+                self.receive_message(channel_kind, tick, message, time_manager);
+            } else {
+                self.throttled_messages
+                    .push_back((channel_kind, tick, message));
+            }
+        }
         for (channel_kind, messages) in self.message_manager.read_messages::<ClientMessage<P>>() {
             let channel_name = self
                 .message_manager
@@ -459,58 +804,29 @@ impl<P: Protocol> Connection<P> {
             if !messages.is_empty() {
                 trace!(?channel_name, ?messages, "Received messages");
                 for (tick, message) in messages.into_iter() {
-                    match message {
-                        ClientMessage::Message(mut message, target) => {
-                            trace!(
-                                "remote entity map: {:?}",
-                                self.replication_receiver.remote_entity_map
-                            );
-                            // map any entities inside the message
-                            message.map_entities(&mut self.replication_receiver.remote_entity_map);
-                            if target != NetworkTarget::None {
-                                self.messages_to_rebroadcast.push((
-                                    message.clone(),
-                                    target,
-                                    channel_kind,
-                                ));
-                            }
-                            // don't put InputMessage into events else the events won't be classified as empty
-                            match message.input_message_kind() {
-                                #[cfg(feature = "leafwing")]
-                                InputMessageKind::Leafwing => {
-                                    trace!("received input message, pushing it to events");
-                                    self.events.push_input_message(message);
-                                }
-                                InputMessageKind::Native => {
-                                    let input_message = message.try_into().unwrap();
-                                    debug!("Received input message: {:?}", input_message.end_tick);
-                                    self.input_buffer.update_from_message(input_message);
-                                }
-                                InputMessageKind::None => {
-                                    // buffer the message
-                                    self.events.push_message(channel_kind, message);
-                                }
-                            }
-                        }
-                        ClientMessage::Replication(replication) => {
-                            // buffer the replication message
-                            self.replication_receiver.recv_message(replication, tick);
-                        }
-                        ClientMessage::Sync(ref sync) => {
-                            match sync {
-                                SyncMessage::Ping(ping) => {
-                                    // prepare a pong in response (but do not send yet, because we need
-                                    // to set the correct send time)
-                                    self.ping_manager.buffer_pending_pong(ping, time_manager);
-                                    trace!("buffer pong");
+                    if let Some(kind) = self.rate_limited_kind(&message, channel_kind) {
+                        if !self.rate_limiter.allow_message(kind) {
+                            let can_throttle = self.can_throttle(channel_kind);
+                            match self.rate_limiter.reject_message(kind, can_throttle) {
+                                RateLimitDecision::Throttle
+                                    if self.throttled_messages.len()
+                                        < self.rate_limiter.max_throttled() =>
+                                {
+                                    self.throttled_messages.push_back((
+                                        channel_kind,
+                                        tick,
+                                        message,
+                                    ));
                                 }
-                                SyncMessage::Pong(pong) => {
-                                    // process the pong
-                                    self.ping_manager.process_pong(pong, time_manager);
+                                RateLimitDecision::Throttle => {
+                                    self.rate_limiter.record_throttle_overflow(0, 1);
                                 }
+                                _ => {}
                             }
+                            continue;
                         }
                     }
+                    self.receive_message(channel_kind, tick, message, time_manager);
                 }
             }
         }
@@ -541,6 +857,180 @@ impl<P: Protocol> Connection<P> {
         std::mem::replace(&mut self.events, ConnectionEvents::new())
     }
 
+    /// Kind of limit that applies to the message, or None if the message is not rate-limited.
+    ///
+    /// The messages are read after their packet was acked, so only the messages of channels
+    /// that don't retransmit or report acks can be limited: dropping the other ones would lose them
+    /// for good (or make the sender believe that they were delivered).
+    fn rate_limited_kind(
+        &self,
+        message: &ClientMessage<P>,
+        channel_kind: ChannelKind,
+    ) -> Option<LimitedMessage> {
+        let watching_acks = self
+            .message_manager
+            .channel_registry
+            .get_builder_from_kind(&channel_kind)
+            .map_or(true, |builder| builder.settings.mode.is_watching_acks());
+        if watching_acks {
+            return None;
+        }
+        match message {
+            // internal sync messages and replication messages are not limited
+            ClientMessage::Sync(_)
+            | ClientMessage::Replication(_)
+            | ClientMessage::DeltaReset(_) => None,
+            ClientMessage::Message(message, _)
+                if !matches!(message.input_message_kind(), InputMessageKind::None) =>
+            {
+                Some(LimitedMessage::Input)
+            }
+            _ => Some(LimitedMessage::Channel(channel_kind)),
+        }
+    }
+
+    /// Returns true if the messages of the channel can be processed after the messages that
+    /// were received after them, i.e. if they can be throttled instead of dropped
+    fn can_throttle(&self, channel_kind: ChannelKind) -> bool {
+        self.message_manager
+            .channel_registry
+            .get_builder_from_kind(&channel_kind)
+            .map_or(false, |builder| {
+                matches!(builder.settings.mode, ChannelMode::UnorderedUnreliable)
+            })
+    }
+
+    /// Record the interpolation time that the client reported at the client tick `tick`
+    fn add_interpolation_time(&mut self, tick: Tick, interpolation_time: InterpolationTime) {
+        // the messages are not ordered: ignore the ones that are older than the latest one
+        if self
+            .interpolation_times
+            .back()
+            .map_or(false, |(latest, _)| *latest > tick)
+        {
+            return;
+        }
+        if self
+            .interpolation_times
+            .back()
+            .map_or(false, |(latest, _)| *latest == tick)
+        {
+            self.interpolation_times.pop_back();
+        }
+        self.interpolation_times
+            .push_back((tick, interpolation_time));
+        while self
+            .interpolation_times
+            .front()
+            .map_or(false, |(oldest, _)| {
+                tick - *oldest > INTERPOLATION_TIME_HISTORY_TICKS as i16
+            })
+        {
+            self.interpolation_times.pop_front();
+        }
+    }
+
+    /// Handle a message received from the client
+    pub(crate) fn receive_message(
+        &mut self,
+        channel_kind: ChannelKind,
+        tick: Tick,
+        message: ClientMessage<P>,
+        time_manager: &TimeManager,
+    ) {
+        match message {
+            ClientMessage::Message(mut message, target) => {
+                trace!(
+                    "remote entity map: {:?}",
+                    self.replication_receiver.remote_entity_map
+                );
+                // map any entities inside the message
+                message.map_entities(&mut self.replication_receiver.remote_entity_map);
+                if target != NetworkTarget::None {
+                    self.messages_to_rebroadcast
+                        .push((message.clone(), target, channel_kind));
+                }
+                // don't put InputMessage into events else the events won't be classified as empty
+                match message.input_message_kind() {
+                    #[cfg(feature = "leafwing")]
+                    InputMessageKind::Leafwing => {
+                        trace!("received input message, pushing it to events");
+                        self.events.push_input_message(message);
+                    }
+                    InputMessageKind::Native => {
+                        let input_message = message.try_into().unwrap();
+                        debug!("Received input message: {:?}", input_message.end_tick);
+                        self.input_buffer.update_from_message(input_message);
+                    }
+                    InputMessageKind::None => {
+                        // buffer the message
+                        self.events.push_message(channel_kind, message);
+                    }
+                }
+            }
+            ClientMessage::Replication(replication) => {
+                // buffer the replication message
+                self.replication_receiver.recv_message(replication, tick);
+            }
+            ClientMessage::DeltaReset(reset) => {
+                self.replication_sender.recv_delta_reset(reset);
+            }
+            ClientMessage::Sync(ref sync) => {
+                match sync {
+                    SyncMessage::Ping(ping) => {
+                        // prepare a pong in response (but do not send yet, because we need
+                        // to set the correct send time)
+                        self.ping_manager.buffer_pending_pong(ping, time_manager);
+                        trace!("buffer pong");
+                    }
+                    SyncMessage::Pong(pong) => {
+                        // process the pong
+                        self.ping_manager.process_pong(pong, time_manager);
+                    }
+                    SyncMessage::InterpolationTime(interpolation_time) => {
+                        self.add_interpolation_time(tick, *interpolation_time);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Receive a packet from the client, after checking it against the rate limits
+    pub(crate) fn recv_packet_rate_limited(
+        &mut self,
+        packet: Packet,
+        tick_manager: &TickManager,
+    ) -> Result<()> {
+        let check = self.rate_limiter.allow_packet(packet.size());
+        if check == PacketCheck::Allowed {
+            return self.recv_packet(packet, tick_manager);
+        }
+        match self.rate_limiter.reject_packet(check) {
+            RateLimitDecision::Throttle
+                if self.throttled_packets.len() < self.rate_limiter.max_throttled() =>
+            {
+                self.throttled_packets.push_back(packet);
+            }
+            RateLimitDecision::Throttle => {
+                self.rate_limiter.record_throttle_overflow(1, 0);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Receive the packets that were throttled by the rate limiter, if the client is back under its limits
+    fn recv_throttled_packets(&mut self, tick_manager: &TickManager) -> Result<()> {
+        while let Some(packet) = self.throttled_packets.front() {
+            if self.rate_limiter.allow_packet(packet.size()) != PacketCheck::Allowed {
+                break;
+            }
+            let packet = self.throttled_packets.pop_front().unwrap();
+            self.recv_packet(packet, tick_manager)?;
+        }
+        Ok(())
+    }
+
     pub fn recv_packet(&mut self, packet: Packet, tick_manager: &TickManager) -> Result<()> {
         // receive the packets, buffer them, update any sender that were waiting for their sent messages to be acked
         let tick = self.message_manager.recv_packet(packet)?;
@@ -583,51 +1073,51 @@ impl<P: Protocol> ReplicationSend<P> for ConnectionManager<P> {
         // debug!(?entity, "Spawning entity");
         let group_id = replicate.replication_group.group_id(Some(entity));
         // TODO: should we have additional state tracking so that we know we are in the process of sending this entity to clients?
-        self.apply_replication(target).try_for_each(|client_id| {
-            // trace!(
-            //     ?client_id,
-            //     ?entity,
-            //     "Send entity spawn for tick {:?}",
-            //     self.tick_manager.tick()
-            // );
-            let replication_sender = &mut self.connection_mut(client_id)?.replication_sender;
-            // update the collect changes tick
-            // replication_sender
-            //     .group_channels
-            //     .entry(group)
-            //     .or_default()
-            //     .update_collect_changes_since_this_tick(system_current_tick);
-            replication_sender.prepare_entity_spawn(entity, group_id);
-            // if we need to do prediction/interpolation, send a marker component to indicate that to the client
-            if replicate.prediction_target.should_send_to(&client_id) {
-                replication_sender.prepare_component_insert(
-                    entity,
-                    group_id,
-                    P::Components::from(ShouldBePredicted::default()),
-                );
-            }
-            if replicate.interpolation_target.should_send_to(&client_id) {
-                replication_sender.prepare_component_insert(
-                    entity,
-                    group_id,
-                    P::Components::from(ShouldBeInterpolated),
-                );
-            }
-            // also set the priority for the group when we spawn it
-            self.update_priority(group_id, client_id, replicate.replication_group.priority())?;
+        self.apply_replication_without_authority(entity, target)
+            .try_for_each(|client_id| {
+                // trace!(
+                //     ?client_id,
+                //     ?entity,
+                //     "Send entity spawn for tick {:?}",
+                //     self.tick_manager.tick()
+                // );
+                let replication_sender = &mut self.connection_mut(client_id)?.replication_sender;
+                // update the collect changes tick
+                // replication_sender
+                //     .group_channels
+                //     .entry(group)
+                //     .or_default()
+                //     .update_collect_changes_since_this_tick(system_current_tick);
+                replication_sender.prepare_entity_spawn(entity, group_id);
+                // if we need to do prediction/interpolation, send a marker component to indicate that to the client
+                if replicate.prediction_target.should_send_to(&client_id) {
+                    replication_sender.prepare_component_insert(
+                        entity,
+                        group_id,
+                        P::Components::from(ShouldBePredicted::default()),
+                    );
+                }
+                if replicate.interpolation_target.should_send_to(&client_id) {
+                    replication_sender.prepare_component_insert(
+                        entity,
+                        group_id,
+                        P::Components::from(ShouldBeInterpolated),
+                    );
+                }
+                // also set the priority for the group when we spawn it
+                self.update_priority(group_id, client_id, replicate.replication_group.priority())?;
 
-            Ok(())
-        })
+                Ok(())
+            })
     }
 
     fn prepare_entity_despawn(
         &mut self,
         entity: Entity,
-        replicate: &Replicate<P>,
+        group_id: ReplicationGroupId,
         target: NetworkTarget,
         system_current_tick: BevyTick,
     ) -> Result<()> {
-        let group_id = replicate.replication_group.group_id(Some(entity));
         self.apply_replication(target).try_for_each(|client_id| {
             // trace!(
             //     ?entity,
@@ -647,6 +1137,20 @@ impl<P: Protocol> ReplicationSend<P> for ConnectionManager<P> {
         })
     }
 
+    fn prepare_entity_detach(
+        &mut self,
+        entity: Entity,
+        group_id: ReplicationGroupId,
+        target: NetworkTarget,
+        system_current_tick: BevyTick,
+    ) -> Result<()> {
+        self.apply_replication(target).try_for_each(|client_id| {
+            let replication_sender = &mut self.connection_mut(client_id)?.replication_sender;
+            replication_sender.prepare_entity_detach(entity, group_id);
+            Ok(())
+        })
+    }
+
     // TODO: perf gain if we batch this? (send vec of components) (same for update/removes)
     fn prepare_component_insert(
         &mut self,
@@ -680,7 +1184,7 @@ impl<P: Protocol> ReplicationSend<P> for ConnectionManager<P> {
             actual_target = replicate.prediction_target.clone();
         }
 
-        self.apply_replication(actual_target)
+        self.apply_replication_without_authority(entity, actual_target)
             .try_for_each(|client_id| {
                 // trace!(
                 //     ?entity,
@@ -710,23 +1214,24 @@ impl<P: Protocol> ReplicationSend<P> for ConnectionManager<P> {
     ) -> Result<()> {
         let group_id = replicate.replication_group.group_id(Some(entity));
         debug!(?entity, ?component_kind, "Sending RemoveComponent");
-        self.apply_replication(target).try_for_each(|client_id| {
-            let replication_sender = &mut self.connection_mut(client_id)?.replication_sender;
-            // TODO: I don't think it's actually correct to only correct the changes since that action.
-            // what if we do:
-            // - Frame 1: update is ACKED
-            // - Frame 2: update
-            // - Frame 3: action
-            // - Frame 4: send
-            // then we won't send the frame-2 update because we only collect changes since frame 3
-            // replication_sender
-            //     .group_channels
-            //     .entry(group)
-            //     .or_default()
-            //     .update_collect_changes_since_this_tick(system_current_tick);
-            replication_sender.prepare_component_remove(entity, group_id, component_kind);
-            Ok(())
-        })
+        self.apply_replication_without_authority(entity, target)
+            .try_for_each(|client_id| {
+                let replication_sender = &mut self.connection_mut(client_id)?.replication_sender;
+                // TODO: I don't think it's actually correct to only correct the changes since that action.
+                // what if we do:
+                // - Frame 1: update is ACKED
+                // - Frame 2: update
+                // - Frame 3: action
+                // - Frame 4: send
+                // then we won't send the frame-2 update because we only collect changes since frame 3
+                // replication_sender
+                //     .group_channels
+                //     .entry(group)
+                //     .or_default()
+                //     .update_collect_changes_since_this_tick(system_current_tick);
+                replication_sender.prepare_component_remove(entity, group_id, component_kind);
+                Ok(())
+            })
     }
 
     fn prepare_entity_update(
@@ -748,7 +1253,7 @@ impl<P: Protocol> ReplicationSend<P> for ConnectionManager<P> {
         );
 
         let group_id = replicate.group_id(Some(entity));
-        self.apply_replication(target).try_for_each(|client_id| {
+        self.apply_replication_without_authority(entity, target).try_for_each(|client_id| {
             // TODO: should we have additional state tracking so that we know we are in the process of sending this entity to clients?
             let replication_sender = &mut self.connection_mut(client_id)?.replication_sender;
             let collect_changes_since_this_tick = replication_sender
@@ -792,7 +1297,7 @@ impl<P: Protocol> ReplicationSend<P> for ConnectionManager<P> {
 
     fn get_mut_replicate_component_cache(
         &mut self,
-    ) -> &mut bevy::ecs::entity::EntityHashMap<Replicate<P>> {
+    ) -> &mut bevy::ecs::entity::EntityHashMap<ReplicateCache> {
         &mut self.replicate_component_cache
     }
 
@@ -0,0 +1,48 @@
+use bevy::app::{App, Plugin, PostUpdate};
+use bevy::diagnostic::Diagnostics;
+use bevy::prelude::{Real, Res, ResMut, Time};
+
+use crate::connection::server::{NetServer, ServerConnections};
+use crate::prelude::Protocol;
+use crate::server::connection::ConnectionManager;
+use crate::transport::io::{IoDiagnosticsPlugin, IoStats};
+
+pub struct ServerDiagnosticsPlugin<P> {
+    _marker: std::marker::PhantomData<P>,
+}
+
+impl<P> Default for ServerDiagnosticsPlugin<P> {
+    fn default() -> Self {
+        Self {
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+/// Aggregate the io stats of all the server transports, and the compression stats of all the connections
+fn io_diagnostics_system<P: Protocol>(
+    mut netservers: ResMut<ServerConnections>,
+    mut connection_manager: ResMut<ConnectionManager<P>>,
+    time: Res<Time<Real>>,
+    mut diagnostics: Diagnostics,
+) {
+    let mut stats = IoStats::default();
+    for netserver in netservers.servers.iter_mut() {
+        if let Some(io) = netserver.io_mut() {
+            stats += std::mem::take(&mut io.stats);
+        }
+    }
+    for connection in connection_manager.connections.values_mut() {
+        if let Some(compression_stats) = connection.message_manager.compression_stats_mut() {
+            stats.compression += std::mem::take(compression_stats);
+        }
+    }
+    IoDiagnosticsPlugin::update_diagnostics(&mut stats, &time, &mut diagnostics);
+}
+
+impl<P: Protocol> Plugin for ServerDiagnosticsPlugin<P> {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(IoDiagnosticsPlugin);
+        app.add_systems(PostUpdate, io_diagnostics_system::<P>);
+    }
+}
@@ -7,6 +7,7 @@ use crate::_reexport::{
     FromType, IterComponentInsertEvent, IterComponentRemoveEvent, IterComponentUpdateEvent,
 };
 use crate::connection::netcode::ClientId;
+use crate::connection::DisconnectReason;
 #[cfg(feature = "leafwing")]
 use crate::inputs::leafwing::{InputMessage, LeafwingUserAction};
 use crate::packet::message::Message;
@@ -16,7 +17,8 @@ use crate::server::networking::clear_events;
 #[cfg(feature = "leafwing")]
 use crate::shared::events::connection::IterInputMessageEvent;
 use crate::shared::events::connection::{
-    ConnectionEvents, IterEntityDespawnEvent, IterEntitySpawnEvent, IterMessageEvent,
+    ConnectionEvents, IterEntityDespawnEvent, IterEntityDetachEvent, IterEntitySpawnEvent,
+    IterMessageEvent,
 };
 use crate::shared::events::plugin::EventsPlugin;
 
@@ -49,7 +51,7 @@ impl<P: Protocol> Plugin for ServerEventsPlugin<P> {
 #[derive(Debug)]
 pub struct ServerEvents<P: Protocol> {
     // have to handle disconnects separately because the [`ConnectionEvents`] are removed upon disconnection
-    pub disconnects: Vec<ClientId>,
+    pub disconnects: Vec<(ClientId, DisconnectReason)>,
     pub events: EntityHashMap<ClientId, ConnectionEvents<P>>,
     pub empty: bool,
 }
@@ -108,7 +110,9 @@ impl<P: Protocol> ServerEvents<P> {
             .any(|(_, connection_events)| connection_events.has_connection())
     }
 
-    pub fn iter_disconnections(&mut self) -> impl Iterator<Item = ClientId> + '_ {
+    pub fn iter_disconnections(
+        &mut self,
+    ) -> impl Iterator<Item = (ClientId, DisconnectReason)> + '_ {
         std::mem::take(&mut self.disconnects).into_iter()
     }
 
@@ -118,8 +122,8 @@ impl<P: Protocol> ServerEvents<P> {
 
     // Cannot only use the 'disconnect' field in the events, because we remove the events
     // upon disconnection
-    pub(crate) fn push_disconnects(&mut self, client_id: ClientId) {
-        self.disconnects.push(client_id);
+    pub(crate) fn push_disconnects(&mut self, client_id: ClientId, reason: DisconnectReason) {
+        self.disconnects.push((client_id, reason));
         self.events.remove(&client_id);
         self.empty = false;
     }
@@ -209,6 +213,22 @@ impl<P: Protocol> IterEntityDespawnEvent<ClientId> for ServerEvents<P> {
     }
 }
 
+impl<P: Protocol> IterEntityDetachEvent<ClientId> for ServerEvents<P> {
+    fn drain_entity_detach(&mut self) -> Box<dyn Iterator<Item = (Entity, ClientId)> + '_> {
+        Box::new(self.events.iter_mut().flat_map(|(client_id, events)| {
+            let entities = events.drain_entity_detach().map(|(entity, _)| entity);
+            let client_ids = std::iter::once(*client_id).cycle();
+            entities.zip(client_ids)
+        }))
+    }
+
+    fn has_entity_detach(&self) -> bool {
+        self.events
+            .iter()
+            .any(|(_, connection_events)| connection_events.has_entity_detach())
+    }
+}
+
 impl<P: Protocol> IterComponentUpdateEvent<P, ClientId> for ServerEvents<P> {
     fn iter_component_update<C: Component>(
         &mut self,
@@ -297,6 +317,10 @@ pub type InputEvent<I> = crate::shared::events::components::InputEvent<I, Client
 pub type EntitySpawnEvent = crate::shared::events::components::EntitySpawnEvent<ClientId>;
 /// Bevy [`Event`] emitted on the server on the frame where a EntityDepawn replication message is received
 pub type EntityDespawnEvent = crate::shared::events::components::EntityDespawnEvent<ClientId>;
+/// Bevy [`Event`] emitted on the server on the frame where a client stops replicating an entity without despawning it
+pub type EntityDetachEvent = crate::shared::events::components::EntityDetachEvent<ClientId>;
+/// Bevy [`Event`] emitted on the server when a client gains or loses the authority over an entity
+pub type AuthorityChangeEvent = crate::shared::events::components::AuthorityChangeEvent<ClientId>;
 /// Bevy [`Event`] emitted on the server on the frame where a ComponentUpdate replication message is received
 pub type ComponentUpdateEvent<C> =
     crate::shared::events::components::ComponentUpdateEvent<C, ClientId>;
@@ -0,0 +1,304 @@
+//! # Lag compensation
+//!
+//! Clients render remote entities in the past: interpolated entities are displayed at the client's
+//! interpolation time, which is behind the server's current tick. When a client performs an action that depends on
+//! what it sees on its screen (for example a hit-scan shot), the server needs to evaluate that action against the
+//! state of the world that the client was seeing, not against the current state.
+//!
+//! The [`LagCompensationPlugin`] records a bounded per-tick history of a component for every server entity that has a
+//! [`LagCompensationHistory`] component. The client regularly reports its interpolation time to the server, which can
+//! then rewind the history to what the client was seeing at the tick of its action:
+//!
+//! ```rust,ignore
+//! fn hitscan(
+//!     connection: Res<ServerConnectionManager>,
+//!     targets: Query<(Entity, &LagCompensationHistory<Collider>)>,
+//! ) {
+//!     // `input_tick` is the client tick at which the client fired the shot
+//!     let Some(interpolation_time) = connection.client_interpolation_time_at(client_id, input_tick) else {
+//!         return;
+//!     };
+//!     for (entity, history) in targets.iter() {
+//!         if let Some(collider) = history.rewind::<MyProtocol>(interpolation_time) {
+//!             // check if the shot hits the collider
+//!         }
+//!     }
+//! }
+//! ```
+use std::collections::VecDeque;
+
+use bevy::app::App;
+use bevy::prelude::{Component, FixedPostUpdate, Plugin, Query, Res, Resource};
+
+use crate::client::components::{SyncComponent, SyncMetadata};
+use crate::protocol::component::ComponentProtocol;
+use crate::protocol::Protocol;
+use crate::shared::ping::message::InterpolationTime;
+use crate::shared::tick_manager::{Tick, TickManager};
+
+#[derive(Clone, Copy, Debug)]
+pub struct LagCompensationConfig {
+    /// Number of ticks of history that are kept for each entity.
+    /// This should be higher than the maximum delay between the server's tick and the interpolation tick of the
+    /// clients (roughly RTT/2 + interpolation delay).
+    pub max_history_ticks: u16,
+}
+
+impl Default for LagCompensationConfig {
+    fn default() -> Self {
+        Self {
+            max_history_ticks: 64,
+        }
+    }
+}
+
+/// Bounded history of the values of the component `C` for the last ticks.
+///
+/// Add this component to the server entities that need to be lag-compensated.
+#[derive(Component, Debug)]
+pub struct LagCompensationHistory<C: SyncComponent> {
+    buffer: VecDeque<(Tick, C)>,
+}
+
+impl<C: SyncComponent> Default for LagCompensationHistory<C> {
+    fn default() -> Self {
+        Self {
+            buffer: VecDeque::new(),
+        }
+    }
+}
+
+impl<C: SyncComponent> LagCompensationHistory<C> {
+    /// Record the value of the component at the given tick, and remove the values that are too old
+    pub(crate) fn add(&mut self, tick: Tick, value: C, max_history_ticks: u16) {
+        // the tick could already be present if FixedUpdate ran without the tick being incremented
+        if self.buffer.back().map_or(false, |(t, _)| *t >= tick) {
+            self.buffer.pop_back();
+        }
+        self.buffer.push_back((tick, value));
+        let oldest = tick - max_history_ticks;
+        while self.buffer.front().map_or(false, |(t, _)| *t < oldest) {
+            self.buffer.pop_front();
+        }
+    }
+
+    /// Get the value of the component at the given tick.
+    /// Returns None if the tick is outside the range of the history
+    pub fn get(&self, tick: Tick) -> Option<&C> {
+        if self.buffer.front().map_or(true, |(t, _)| *t > tick) {
+            return None;
+        }
+        self.buffer
+            .iter()
+            .rev()
+            .find(|(t, _)| *t <= tick)
+            .map(|(_, value)| value)
+    }
+
+    /// Get the value of the component at the interpolation time that a client reported
+    /// (see [`ConnectionManager::client_interpolation_time`](crate::server::connection::ConnectionManager::client_interpolation_time)).
+    ///
+    /// The value is interpolated between the two surrounding ticks with the same interpolation function
+    /// that the client uses for interpolated entities.
+    pub fn rewind<P: Protocol>(&self, interpolation_time: InterpolationTime) -> Option<C>
+    where
+        P::Components: SyncMetadata<C>,
+    {
+        let start = self.get(interpolation_time.tick)?;
+        let mut next_tick = interpolation_time.tick;
+        next_tick += 1;
+        match self.get(next_tick) {
+            Some(end) if interpolation_time.overstep > 0.0 => {
+                Some(P::Components::lerp(start, end, interpolation_time.overstep))
+            }
+            _ => Some(start.clone()),
+        }
+    }
+
+    /// Remove all the values from the history
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+    }
+}
+
+/// Plugin that records the history of the component `C` for entities that have a [`LagCompensationHistory<C>`]
+///
+/// Each `LagCompensationPlugin` uses its own [`LagCompensationConfig`].
+pub struct LagCompensationPlugin<P: Protocol, C: SyncComponent> {
+    config: LagCompensationConfig,
+    _marker: std::marker::PhantomData<(P, C)>,
+}
+
+impl<P: Protocol, C: SyncComponent> LagCompensationPlugin<P, C> {
+    pub fn new(config: LagCompensationConfig) -> Self {
+        Self {
+            config,
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<P: Protocol, C: SyncComponent> Default for LagCompensationPlugin<P, C> {
+    fn default() -> Self {
+        Self::new(LagCompensationConfig::default())
+    }
+}
+
+impl<P: Protocol, C: SyncComponent> Plugin for LagCompensationPlugin<P, C>
+where
+    P::Components: SyncMetadata<C>,
+{
+    fn build(&self, app: &mut App) {
+        // RESOURCES
+        app.insert_resource(ComponentLagCompensationConfig::<C> {
+            config: self.config,
+            _marker: std::marker::PhantomData,
+        });
+        // SYSTEMS
+        // record the state of the entities at the end of the tick
+        app.add_systems(FixedPostUpdate, record_history::<C>);
+    }
+}
+
+/// The [`LagCompensationConfig`] used for the component `C`
+#[derive(Resource)]
+struct ComponentLagCompensationConfig<C> {
+    config: LagCompensationConfig,
+    _marker: std::marker::PhantomData<C>,
+}
+
+/// Record the value of the component for the current tick
+fn record_history<C: SyncComponent>(
+    config: Res<ComponentLagCompensationConfig<C>>,
+    tick_manager: Res<TickManager>,
+    mut query: Query<(&C, &mut LagCompensationHistory<C>)>,
+) {
+    let tick = tick_manager.tick();
+    for (component, mut history) in query.iter_mut() {
+        history.add(tick, component.clone(), config.config.max_history_ticks);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::Duration;
+
+    use crate::prelude::client::*;
+    use crate::prelude::*;
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        let mut history = LagCompensationHistory::<Component1>::default();
+        for i in 0..10 {
+            history.add(Tick(i), Component1(i as f32), 4);
+        }
+        // old values are removed
+        assert_eq!(history.get(Tick(4)), None);
+        assert_eq!(history.get(Tick(5)), Some(&Component1(5.0)));
+        assert_eq!(history.get(Tick(9)), Some(&Component1(9.0)));
+        // the latest value is used for ticks after the end of the history
+        assert_eq!(history.get(Tick(12)), Some(&Component1(9.0)));
+
+        assert_eq!(
+            history.rewind::<MyProtocol>(InterpolationTime {
+                tick: Tick(6),
+                overstep: 0.5
+            }),
+            Some(Component1(6.5))
+        );
+        assert_eq!(
+            history.rewind::<MyProtocol>(InterpolationTime {
+                tick: Tick(2),
+                overstep: 0.5
+            }),
+            None
+        );
+    }
+
+    /// The client reports its interpolation time, which the server can use to rewind the history
+    #[test]
+    fn test_lag_compensation() {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(20),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.0,
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default().speedup_factor(1.0),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            link_conditioner,
+            frame_duration,
+        );
+        stepper
+            .server_app
+            .add_plugins(LagCompensationPlugin::<MyProtocol, Component1>::default());
+        stepper.init();
+
+        let client_id = 111;
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((
+                Component1(0.0),
+                LagCompensationHistory::<Component1>::default(),
+            ))
+            .id();
+        for i in 1..20 {
+            stepper
+                .server_app
+                .world
+                .entity_mut(server_entity)
+                .insert(Component1(i as f32));
+            stepper.frame_step();
+        }
+
+        let interpolation_time = stepper
+            .server_app
+            .world
+            .resource::<ServerConnectionManager>()
+            .client_interpolation_time(client_id)
+            .expect("the client should have reported its interpolation time");
+        let server_tick = stepper.server_tick();
+        // the client is rendering the past
+        assert!(interpolation_time.tick < server_tick);
+        // the server keeps a short history of the interpolation times, by client tick
+        let connection_manager = stepper
+            .server_app
+            .world
+            .resource::<ServerConnectionManager>();
+        assert_eq!(
+            connection_manager.client_interpolation_time_at(client_id, stepper.client_tick()),
+            Some(interpolation_time)
+        );
+        let previous_interpolation_time = connection_manager
+            .client_interpolation_time_at(client_id, stepper.client_tick() - 5)
+            .unwrap();
+        assert!(previous_interpolation_time.tick < interpolation_time.tick);
+
+        let history = stepper
+            .server_app
+            .world
+            .get::<LagCompensationHistory<Component1>>(server_entity)
+            .unwrap();
+        assert_eq!(
+            history.get(server_tick),
+            stepper.server_app.world.get::<Component1>(server_entity)
+        );
+        assert!(history
+            .rewind::<MyProtocol>(interpolation_time)
+            .map_or(false, |c| c.0 < 19.0));
+    }
+}
@@ -7,6 +7,7 @@ use bitcode::{Decode, Encode};
 use crate::_reexport::{BitSerializable, MessageProtocol, ReadBuffer, WriteBuffer};
 use crate::prelude::Protocol;
 use crate::shared::ping::message::SyncMessage;
+use crate::shared::replication::delta::DeltaReset;
 use crate::shared::replication::{ReplicationMessage, ReplicationMessageData};
 
 #[derive(Encode, Decode, Clone, Debug)]
@@ -21,6 +22,9 @@ pub enum ServerMessage<P: Protocol> {
     // the sync messages can be added to packets that have other messages
     #[bitcode_hint(frequency = 1)]
     Sync(SyncMessage),
+    #[bitcode_hint(frequency = 1)]
+    #[bitcode(with_serde)]
+    DeltaReset(DeltaReset<P::ComponentKinds>),
 }
 
 impl<P: Protocol> BitSerializable for ServerMessage<P> {
@@ -133,7 +137,13 @@ impl<P: Protocol> ServerMessage<P> {
                     #[cfg(metrics)]
                     metrics::counter!("send_pong", "channel" => channel_name).increment(1);
                 }
+                SyncMessage::InterpolationTime(_) => {
+                    trace!(channel = ?channel_name, "Sending interpolation time");
+                }
             },
+            ServerMessage::DeltaReset(reset) => {
+                trace!(channel = ?channel_name, group_id = ?reset.group_id, components = ?reset.components, "Sending delta reset");
+            }
         }
     }
 }
@@ -7,14 +7,24 @@ pub mod config;
 
 pub mod connection;
 
+mod diagnostics;
+
 pub mod events;
 
-mod input;
+pub(crate) mod input;
+
+pub mod lag_compensation;
 
 pub mod plugin;
 
+pub mod rate_limit;
+
 pub mod room;
 
+pub mod spatial_grid;
+
+pub mod visibility;
+
 #[cfg_attr(docsrs, doc(cfg(feature = "leafwing")))]
 #[cfg(feature = "leafwing")]
 pub mod input_leafwing;
@@ -6,15 +6,24 @@ use tracing::{debug, error, trace, trace_span};
 
 use crate::_reexport::ComponentProtocol;
 use crate::connection::server::{NetConfig, NetServer, ServerConnection, ServerConnections};
+use crate::connection::DisconnectReason;
 use crate::prelude::{MainSet, TickManager, TimeManager};
 use crate::protocol::message::MessageProtocol;
 use crate::protocol::Protocol;
+use crate::server::config::ServerConfig;
 use crate::server::connection::ConnectionManager;
-use crate::server::events::{ConnectEvent, DisconnectEvent, EntityDespawnEvent, EntitySpawnEvent};
+use crate::server::events::{
+    AuthorityChangeEvent, ConnectEvent, DisconnectEvent, EntityDespawnEvent, EntityDetachEvent,
+    EntitySpawnEvent,
+};
 use crate::server::room::RoomManager;
-use crate::shared::events::connection::{IterEntityDespawnEvent, IterEntitySpawnEvent};
+use crate::server::visibility::VisibilityManager;
+use crate::shared::events::connection::{
+    IterEntityDespawnEvent, IterEntityDetachEvent, IterEntitySpawnEvent,
+};
 use crate::shared::replication::ReplicationSend;
 use crate::shared::time_manager::is_ready_to_send;
+use crate::transport::PacketSender;
 
 pub(crate) struct ServerNetworkingPlugin<P: Protocol> {
     config: Vec<NetConfig>,
@@ -76,6 +85,7 @@ pub(crate) fn receive<P: Protocol>(world: &mut World) {
                                     world.resource_scope(
                                         |world: &mut World, mut room_manager: Mut<RoomManager>| {
                                             let delta = world.resource::<Time<Virtual>>().delta();
+                                            let grace_period = world.resource::<ServerConfig>().connection.disconnect_grace_period;
                                             // UPDATE: update server state, send keep-alives, receive packets from io
                                             // update time manager
                                             time_manager.update(delta);
@@ -91,18 +101,39 @@ pub(crate) fn receive<P: Protocol>(world: &mut World) {
                                                 for local_client_id in netserver.new_connections().iter().copied() {
                                                     // map the netserver's client id to a global client id (in case multiple transports assign the same client id)
                                                     let global_id = netservers.global_id_map.insert(server_idx, local_client_id);
-                                                    connection_manager.add(global_id);
+                                                    connection_manager.add(global_id, netserver.user_data(local_client_id));
                                                 }
                                                 // handle disconnections
-                                                for local_client_id in netserver.new_disconnections().iter().copied() {
-                                                    if let Some(global_id) = netservers.global_id_map.remove_by_local(server_idx, local_client_id) {
-                                                        connection_manager.remove(global_id);
+                                                for (local_client_id, reason) in netserver.new_disconnections().iter().copied() {
+                                                    // the client won't reconnect if it was kicked or disconnected voluntarily
+                                                    let grace_period = grace_period.filter(|_| !reason.is_final());
+                                                    if let Some(grace_period) = grace_period {
+                                                        // keep the session (and the client id mapping) in case the client reconnects
+                                                        if let Some(global_id) = netservers.global_id_map.get_global(server_idx, local_client_id) {
+                                                            connection_manager.suspend(global_id, time_manager.current_time() + grace_period, reason);
+                                                        } else {
+                                                            error!("Client disconnected but could not map client_id to global_id");
+                                                        }
+                                                    } else if let Some(global_id) = netservers.global_id_map.remove_by_local(server_idx, local_client_id) {
+                                                        let revoked = connection_manager.remove(global_id, reason);
+                                                        world.send_event_batch(revoked.into_iter().map(|entity| AuthorityChangeEvent::new(entity, false, global_id)));
                                                         room_manager.client_disconnect(global_id);
+                                                        world.resource_mut::<VisibilityManager>().client_disconnect(global_id);
                                                     } else {
                                                         error!("Client disconnected but could not map client_id to global_id");
                                                     }
                                                 };
                                             }
+                                            // remove the sessions of the clients that didn't reconnect in time
+                                            for (global_id, reason) in connection_manager.expired_sessions(time_manager.current_time()) {
+                                                if let Some((server_idx, local_client_id)) = netservers.global_id_map.get_local(global_id) {
+                                                    netservers.global_id_map.remove_by_local(server_idx, local_client_id);
+                                                }
+                                                let revoked = connection_manager.remove(global_id, reason);
+                                                world.send_event_batch(revoked.into_iter().map(|entity| AuthorityChangeEvent::new(entity, false, global_id)));
+                                                room_manager.client_disconnect(global_id);
+                                                world.resource_mut::<VisibilityManager>().client_disconnect(global_id);
+                                            }
 
                                             // update connections
                                             connection_manager
@@ -113,11 +144,10 @@ pub(crate) fn receive<P: Protocol>(world: &mut World) {
                                                 while let Some((packet, client_id)) = netserver.recv() {
                                                     if let Some(global_id) = netservers.global_id_map.get_global(server_idx, client_id) {
                                                         // TODO: use connection to apply on BOTH message manager and replication manager
-                                                        connection_manager
-                                                            .connection_mut(global_id)
-                                                            .expect("connection not found")
-                                                            .recv_packet(packet, tick_manager.as_ref())
-                                                            .expect("could not recv packet");
+                                                        // a packet that can't be read (for example because it can't be decompressed) is dropped
+                                                        if let Err(e) = connection_manager.recv_packet(global_id, packet, tick_manager.as_ref()) {
+                                                            error!(?global_id, "could not recv packet: {:?}", e);
+                                                        }
                                                     } else {
                                                         error!("Global client id was not found!");
                                                     }
@@ -131,6 +161,13 @@ pub(crate) fn receive<P: Protocol>(world: &mut World) {
                                                     error!("Error during receive: {}", e);
                                                 });
 
+                                            // disconnect the clients that exceeded their rate limits
+                                            for client_id in connection_manager.take_rate_limited_clients() {
+                                                let _ = netservers
+                                                    .disconnect(client_id, DisconnectReason::RateLimited)
+                                                    .map_err(|e| error!("Error disconnecting rate-limited client: {:?}", e));
+                                            }
+
                                             // EVENTS: Write the received events into bevy events
                                             if !connection_manager.events.is_empty() {
                                                 // TODO: write these as systems? might be easier to also add the events to the app
@@ -146,16 +183,17 @@ pub(crate) fn receive<P: Protocol>(world: &mut World) {
                                                         world.get_resource_mut::<Events<ConnectEvent>>().unwrap();
                                                     for client_id in connection_manager.events.iter_connections() {
                                                         debug!("Client connected event: {}", client_id);
-                                                        connect_event_writer.send(ConnectEvent::new(client_id));
+                                                        let user_data = connection_manager.connection(client_id).ok().and_then(|c| c.user_data);
+                                                        connect_event_writer.send(ConnectEvent::with_user_data(client_id, user_data));
                                                     }
                                                 }
 
                                                 if connection_manager.events.has_disconnections() {
                                                     let mut connect_event_writer =
                                                         world.get_resource_mut::<Events<DisconnectEvent>>().unwrap();
-                                                    for client_id in connection_manager.events.iter_disconnections() {
-                                                        debug!("Client disconnected event: {}", client_id);
-                                                        connect_event_writer.send(DisconnectEvent::new(client_id));
+                                                    for (client_id, reason) in connection_manager.events.iter_disconnections() {
+                                                        debug!(?reason, "Client disconnected event: {}", client_id);
+                                                        connect_event_writer.send(DisconnectEvent::new(client_id, reason));
                                                     }
                                                 }
 
@@ -180,6 +218,15 @@ pub(crate) fn receive<P: Protocol>(world: &mut World) {
                                                         entity_despawn_event_writer.send(EntityDespawnEvent::new(entity, client_id));
                                                     }
                                                 }
+                                                // EntityDetach Events
+                                                if connection_manager.events.has_entity_detach() {
+                                                    let mut entity_detach_event_writer = world
+                                                        .get_resource_mut::<Events<EntityDetachEvent>>()
+                                                        .unwrap();
+                                                    for (entity, client_id) in connection_manager.events.drain_entity_detach() {
+                                                        entity_detach_event_writer.send(EntityDetachEvent::new(entity, client_id));
+                                                    }
+                                                }
 
                                                 // Update component events (updates, inserts, removes)
                                                 P::Components::push_component_events(world, &mut connection_manager.events);
@@ -209,12 +256,23 @@ pub(crate) fn send<P: Protocol>(
 
     // SEND_PACKETS: send buffered packets to io
     let span = trace_span!("send_packets").entered();
+    let connection_manager = &mut *connection_manager;
+    let suspended_sessions = &connection_manager.suspended_sessions;
     connection_manager
         .connections
         .iter_mut()
         .try_for_each(|(client_id, connection)| {
             let client_span =
                 trace_span!("send_packets_to_client", client_id = ?client_id).entered();
+            if connection.is_local {
+                // the messages of the local client are not sent through a transport
+                return Ok(());
+            }
+            if suspended_sessions.contains_key(client_id) {
+                // the client is disconnected: the packets are lost
+                connection.send_packets(&time_manager, &tick_manager)?;
+                return Ok(());
+            }
             let (netserver_idx, local_client_id) = netservers
                 .global_id_map
                 .get_local(*client_id)
@@ -231,6 +289,15 @@ pub(crate) fn send<P: Protocol>(
         .unwrap_or_else(|e: anyhow::Error| {
             error!("Error sending packets: {}", e);
         });
+    // send the packets that were buffered by the io (for example by the link conditioner)
+    netservers
+        .servers
+        .iter_mut()
+        .filter_map(|netserver| netserver.io_mut())
+        .try_for_each(|io| io.flush())
+        .unwrap_or_else(|e| {
+            error!("Error flushing io: {}", e);
+        });
 
     // clear the list of newly connected clients
     // (cannot just use the ConnectionEvent because it is cleared after each frame)
@@ -10,12 +10,14 @@ use crate::protocol::component::ComponentProtocol;
 use crate::protocol::message::MessageProtocol;
 use crate::protocol::Protocol;
 use crate::server::connection::ConnectionManager;
+use crate::server::diagnostics::ServerDiagnosticsPlugin;
 use crate::server::events::ServerEventsPlugin;
 use crate::server::input::InputPlugin;
 use crate::server::metadata::ClientMetadataPlugin;
 use crate::server::networking::ServerNetworkingPlugin;
 use crate::server::replication::ServerReplicationPlugin;
 use crate::server::room::RoomPlugin;
+use crate::server::visibility::VisibilityPlugin;
 use crate::shared::plugin::SharedPlugin;
 use crate::shared::replication::plugin::ReplicationPlugin;
 use crate::shared::time_manager::TimePlugin;
@@ -53,7 +55,18 @@ impl<P: Protocol> ServerPlugin<P> {
 
 impl<P: Protocol> PluginType for ServerPlugin<P> {
     fn build(&self, app: &mut App) {
-        let config = self.config.lock().unwrap().deref_mut().take().unwrap();
+        let mut config = self.config.lock().unwrap().deref_mut().take().unwrap();
+        // refuse the connections of clients that use a different protocol (or compression)
+        let protocol_hash = config
+            .server_config
+            .packet
+            .compression
+            .protocol_hash(config.protocol.protocol_hash());
+        config
+            .server_config
+            .net
+            .iter_mut()
+            .for_each(|net| net.set_protocol_hash(protocol_hash));
         let tick_duration = config.server_config.shared.tick.tick_duration;
 
         app
@@ -63,6 +76,7 @@ impl<P: Protocol> PluginType for ServerPlugin<P> {
                 config.protocol.channel_registry().clone(),
                 config.server_config.packet,
                 config.server_config.ping,
+                config.server_config.rate_limit,
             ))
             // PLUGINS
             .add_plugins(SharedPlugin::<P> {
@@ -76,6 +90,8 @@ impl<P: Protocol> PluginType for ServerPlugin<P> {
             .add_plugins(ClientMetadataPlugin::<P>::default())
             .add_plugins(InputPlugin::<P>::default())
             .add_plugins(RoomPlugin::<P>::default())
+            .add_plugins(VisibilityPlugin::<P>::default())
+            .add_plugins(ServerDiagnosticsPlugin::<P>::default())
             .add_plugins(TimePlugin {
                 send_interval: config.server_config.shared.server_send_interval,
             });
@@ -0,0 +1,286 @@
+//! Per-client rate limiting of the packets and messages received by the server
+//!
+//! See [`RateLimitConfig`] for the limits that can be configured.
+use std::num::NonZeroU32;
+
+use bevy::utils::HashMap;
+use governor::DefaultDirectRateLimiter;
+use tracing::debug;
+
+use crate::prelude::ChannelKind;
+use crate::server::config::{RateLimitConfig, RateLimitPolicy};
+use crate::shared::tick_manager::Tick;
+
+/// Counters of the packets and messages of a client that exceeded the rate limits
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitStats {
+    /// Number of packets that were dropped
+    pub dropped_packets: u64,
+    /// Number of packets that were buffered to be processed later
+    pub throttled_packets: u64,
+    /// Number of messages that were dropped
+    pub dropped_messages: u64,
+    /// Number of messages that were buffered to be processed later
+    pub throttled_messages: u64,
+    /// Number of input messages that were dropped
+    pub dropped_input_messages: u64,
+    /// Number of input messages that were buffered to be processed later
+    pub throttled_input_messages: u64,
+}
+
+/// What to do with a packet or message that exceeded the limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RateLimitDecision {
+    Drop,
+    Throttle,
+}
+
+/// Result of checking a packet against the packet and byte quotas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PacketCheck {
+    Allowed,
+    /// The packet exceeded the quotas
+    Limited,
+    /// The packet is larger than the byte quota, so it can never be allowed
+    Oversized,
+}
+
+/// Kind of message checked against the per-tick limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LimitedMessage {
+    Input,
+    Channel(ChannelKind),
+}
+
+/// Enforces the [`RateLimitConfig`] for a single client
+pub(crate) struct ClientRateLimiter {
+    config: RateLimitConfig,
+    packets: Option<DefaultDirectRateLimiter>,
+    bytes: Option<DefaultDirectRateLimiter>,
+    /// Tick of the previous call to [`ClientRateLimiter::start_tick`]
+    last_tick: Option<Tick>,
+    /// Number of ticks that elapsed since the previous call to [`ClientRateLimiter::start_tick`];
+    /// the per-tick limits are multiplied by this value
+    elapsed_ticks: u32,
+    channel_messages: HashMap<ChannelKind, u32>,
+    input_messages: u32,
+    /// True if the client exceeded one of the limits with the [`RateLimitPolicy::Disconnect`] policy
+    pub(crate) exceeded: bool,
+    pub(crate) stats: RateLimitStats,
+}
+
+impl ClientRateLimiter {
+    pub(crate) fn new(config: RateLimitConfig) -> Self {
+        Self {
+            packets: config
+                .packets_per_second
+                .map(DefaultDirectRateLimiter::direct),
+            bytes: config
+                .bytes_per_second
+                .map(DefaultDirectRateLimiter::direct),
+            config,
+            last_tick: None,
+            elapsed_ticks: 1,
+            channel_messages: HashMap::default(),
+            input_messages: 0,
+            exceeded: false,
+            stats: RateLimitStats::default(),
+        }
+    }
+
+    pub(crate) fn max_throttled(&self) -> usize {
+        self.config.max_throttled
+    }
+
+    /// Check a packet of `size` bytes against the packet and byte quotas.
+    ///
+    /// The packet quota is only consumed if the packet is within the byte quota.
+    pub(crate) fn allow_packet(&mut self, size: usize) -> PacketCheck {
+        if let (Some(limiter), Some(size)) = (self.bytes.as_ref(), NonZeroU32::new(size as u32)) {
+            match limiter.check_n(size) {
+                Ok(Ok(())) => {}
+                Ok(Err(_)) => return PacketCheck::Limited,
+                Err(_) => return PacketCheck::Oversized,
+            }
+        }
+        if self.packets.as_ref().map_or(false, |l| l.check().is_err()) {
+            return PacketCheck::Limited;
+        }
+        PacketCheck::Allowed
+    }
+
+    /// A packet exceeded the quotas: returns what to do with it according to the policy.
+    /// Oversized packets are always dropped, since they would never be allowed later.
+    pub(crate) fn reject_packet(&mut self, check: PacketCheck) -> RateLimitDecision {
+        let decision = match self.violation() {
+            RateLimitDecision::Throttle if check == PacketCheck::Oversized => {
+                RateLimitDecision::Drop
+            }
+            decision => decision,
+        };
+        match decision {
+            RateLimitDecision::Throttle => self.stats.throttled_packets += 1,
+            _ => self.stats.dropped_packets += 1,
+        }
+        decision
+    }
+
+    /// Reset the per-tick counters if the tick changed since the last call
+    pub(crate) fn start_tick(&mut self, tick: Tick) {
+        if self.last_tick == Some(tick) {
+            return;
+        }
+        self.elapsed_ticks = self
+            .last_tick
+            .map_or(1, |last_tick| (tick - last_tick).max(1) as u32);
+        self.last_tick = Some(tick);
+        self.channel_messages.clear();
+        self.input_messages = 0;
+    }
+
+    /// Returns true if the message is within the per-tick message limits
+    pub(crate) fn allow_message(&mut self, message: LimitedMessage) -> bool {
+        let (count, limit) = match message {
+            LimitedMessage::Input => (
+                &mut self.input_messages,
+                self.config.input_messages_per_tick,
+            ),
+            LimitedMessage::Channel(channel) => (
+                self.channel_messages.entry(channel).or_default(),
+                self.config.messages_per_channel_per_tick,
+            ),
+        };
+        match limit {
+            Some(limit) if *count >= limit.saturating_mul(self.elapsed_ticks) => false,
+            _ => {
+                *count += 1;
+                true
+            }
+        }
+    }
+
+    /// A message exceeded the limits: returns what to do with it according to the policy.
+    /// The messages that cannot be delivered out of order (`can_throttle` is false) are dropped
+    /// instead of being throttled.
+    pub(crate) fn reject_message(
+        &mut self,
+        message: LimitedMessage,
+        can_throttle: bool,
+    ) -> RateLimitDecision {
+        let decision = match self.violation() {
+            RateLimitDecision::Throttle if !can_throttle => RateLimitDecision::Drop,
+            decision => decision,
+        };
+        match (message, decision) {
+            (LimitedMessage::Input, RateLimitDecision::Throttle) => {
+                self.stats.throttled_input_messages += 1
+            }
+            (LimitedMessage::Input, _) => self.stats.dropped_input_messages += 1,
+            (_, RateLimitDecision::Throttle) => self.stats.throttled_messages += 1,
+            _ => self.stats.dropped_messages += 1,
+        }
+        decision
+    }
+
+    /// Record the throttled items that had to be dropped because the buffer was full
+    pub(crate) fn record_throttle_overflow(&mut self, packets: usize, messages: usize) {
+        self.stats.dropped_packets += packets as u64;
+        self.stats.dropped_messages += messages as u64;
+    }
+
+    fn violation(&mut self) -> RateLimitDecision {
+        #[cfg(feature = "metrics")]
+        metrics::counter!("rate_limited").increment(1);
+        match self.config.policy {
+            RateLimitPolicy::Drop => RateLimitDecision::Drop,
+            RateLimitPolicy::Throttle => RateLimitDecision::Throttle,
+            RateLimitPolicy::Disconnect => {
+                if !self.exceeded {
+                    debug!("client exceeded its rate limits, it will be disconnected");
+                }
+                self.exceeded = true;
+                RateLimitDecision::Drop
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::protocol::Channel1;
+
+    use super::*;
+
+    #[test]
+    fn test_message_limit_per_tick() {
+        let config = RateLimitConfig::default()
+            .with_messages_per_channel_per_tick(2)
+            .with_input_messages_per_tick(1);
+        let mut limiter = ClientRateLimiter::new(config);
+        let channel = LimitedMessage::Channel(ChannelKind::of::<Channel1>());
+
+        limiter.start_tick(Tick(10));
+        assert!(limiter.allow_message(channel));
+        assert!(limiter.allow_message(channel));
+        assert!(!limiter.allow_message(channel));
+        assert!(limiter.allow_message(LimitedMessage::Input));
+        assert!(!limiter.allow_message(LimitedMessage::Input));
+
+        // the limits are scaled by the number of ticks that elapsed
+        limiter.start_tick(Tick(12));
+        for _ in 0..4 {
+            assert!(limiter.allow_message(channel));
+        }
+        assert!(!limiter.allow_message(channel));
+        assert_eq!(
+            limiter.reject_message(channel, true),
+            RateLimitDecision::Drop
+        );
+        assert_eq!(limiter.stats.dropped_messages, 1);
+        assert!(!limiter.exceeded);
+    }
+
+    #[test]
+    fn test_packet_limit() {
+        let config = RateLimitConfig::default()
+            .with_packets_per_second(2)
+            .with_policy(RateLimitPolicy::Disconnect);
+        let mut limiter = ClientRateLimiter::new(config);
+        assert_eq!(limiter.allow_packet(100), PacketCheck::Allowed);
+        assert_eq!(limiter.allow_packet(100), PacketCheck::Allowed);
+        assert_eq!(limiter.allow_packet(100), PacketCheck::Limited);
+        assert_eq!(
+            limiter.reject_packet(PacketCheck::Limited),
+            RateLimitDecision::Drop
+        );
+        assert!(limiter.exceeded);
+        assert_eq!(limiter.stats.dropped_packets, 1);
+    }
+
+    #[test]
+    fn test_byte_limit() {
+        let config = RateLimitConfig::default()
+            .with_packets_per_second(2)
+            .with_bytes_per_second(150)
+            .with_policy(RateLimitPolicy::Throttle);
+        let mut limiter = ClientRateLimiter::new(config);
+        assert_eq!(limiter.allow_packet(100), PacketCheck::Allowed);
+        // the packet exceeds the byte quota: the packet quota is not consumed
+        assert_eq!(limiter.allow_packet(100), PacketCheck::Limited);
+        assert_eq!(limiter.allow_packet(10), PacketCheck::Allowed);
+        assert_eq!(limiter.allow_packet(10), PacketCheck::Limited);
+
+        // a packet larger than the byte quota is dropped even when throttling
+        assert_eq!(limiter.allow_packet(200), PacketCheck::Oversized);
+        assert_eq!(
+            limiter.reject_packet(PacketCheck::Oversized),
+            RateLimitDecision::Drop
+        );
+        assert_eq!(
+            limiter.reject_packet(PacketCheck::Limited),
+            RateLimitDecision::Throttle
+        );
+        assert_eq!(limiter.stats.dropped_packets, 1);
+        assert_eq!(limiter.stats.throttled_packets, 1);
+    }
+}
@@ -1,10 +1,16 @@
 use bevy::prelude::*;
 use bevy::utils::Duration;
 
+use crate::_reexport::MessageProtocol;
 use crate::prelude::{MainSet, Protocol, ReplicationSet};
+use crate::server::config::ServerConfig;
 use crate::server::connection::ConnectionManager;
 use crate::server::prediction::compute_hash;
+use crate::server::room::{ClientVisibility, RoomSystemSets};
+use crate::shared::replication::components::{Replicate, ReplicationMode};
 use crate::shared::replication::plugin::ReplicationPlugin;
+use crate::shared::tick_manager::TickManager;
+use crate::shared::time_manager::is_ready_to_send;
 
 pub struct ServerReplicationPlugin<P: Protocol> {
     tick_duration: Duration,
@@ -45,7 +51,199 @@ impl<P: Protocol> Plugin for ServerReplicationPlugin<P> {
             // SYSTEMS
             .add_systems(
                 PostUpdate,
-                (compute_hash::<P>.in_set(ReplicationSet::SetPreSpawnedHash),),
+                (
+                    compute_hash::<P>.in_set(ReplicationSet::SetPreSpawnedHash),
+                    resync_lagging_clients::<P>
+                        .after(RoomSystemSets::UpdateReplicationCaches)
+                        .before(ReplicationSet::All)
+                        .run_if(is_ready_to_send),
+                ),
             );
+
+        // REPLICATED RESOURCES
+        P::Message::add_resource_send_systems(app);
+    }
+}
+
+/// If a client hasn't acked the replication updates for longer than the [`resync_threshold`](crate::server::config::ReplicationConfig::resync_threshold),
+/// send it a full snapshot of the entities that are visible to it instead of trying to catch up
+/// with the backlog of updates.
+fn resync_lagging_clients<P: Protocol>(
+    config: Res<ServerConfig>,
+    tick_manager: Res<TickManager>,
+    mut connection_manager: ResMut<ConnectionManager<P>>,
+    mut query: Query<&mut Replicate<P>>,
+) {
+    let Some(threshold) = config.replication.resync_threshold else {
+        return;
+    };
+    let threshold_ticks = (threshold.as_secs_f32() / config.shared.tick.tick_duration.as_secs_f32())
+        .ceil()
+        .min(i16::MAX as f32) as u16;
+    let lagging_clients =
+        connection_manager.resync_lagging_clients(tick_manager.tick(), threshold_ticks);
+    if lagging_clients.is_empty() {
+        return;
+    }
+    // entities replicated via rooms are sent to the clients that gain visibility
+    for mut replicate in query.iter_mut() {
+        if matches!(
+            replicate.replication_mode,
+            ReplicationMode::Room | ReplicationMode::Manual
+        ) {
+            for client_id in lagging_clients.iter() {
+                if let Some(visibility) = replicate.replication_clients_cache.get_mut(client_id) {
+                    if matches!(visibility, ClientVisibility::Maintained) {
+                        *visibility = ClientVisibility::Gained;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::Duration;
+
+    use crate::prelude::client::*;
+    use crate::prelude::*;
+    use crate::server::config::ServerConfig;
+    use crate::tests::protocol::Replicate;
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    /// A client that stops acking the replication updates receives a full snapshot of the world
+    #[test]
+    fn test_resync_lagging_client() {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(0),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.0,
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default().speedup_factor(1.0),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            link_conditioner,
+            frame_duration,
+        );
+        stepper
+            .server_app
+            .world
+            .resource_mut::<ServerConfig>()
+            .replication
+            .resync_threshold = Some(Duration::from_millis(100));
+        stepper.init();
+
+        let client_id = 111;
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((Component1(0.0), Replicate::default()))
+            .id();
+        let server_entity_2 = stepper
+            .server_app
+            .world
+            .spawn((Component1(0.0), Replicate::default()))
+            .id();
+        let server_entity_3 = stepper
+            .server_app
+            .world
+            .spawn((
+                Component1(0.0),
+                Replicate {
+                    on_replicate_removed: OnReplicateRemoved::DetachRemote,
+                    ..Default::default()
+                },
+            ))
+            .id();
+        for _ in 0..5 {
+            stepper.frame_step();
+        }
+        let remote_entity_map = &stepper
+            .client_app
+            .world
+            .resource::<ClientConnectionManager>()
+            .replication_receiver
+            .remote_entity_map;
+        let client_entity = *remote_entity_map.get_local(server_entity).unwrap();
+        let client_entity_2 = *remote_entity_map.get_local(server_entity_2).unwrap();
+        let client_entity_3 = *remote_entity_map.get_local(server_entity_3).unwrap();
+
+        // the client stops receiving and acking packets, while the server keeps updating the world
+        for i in 1..30 {
+            stepper
+                .server_app
+                .world
+                .entity_mut(server_entity)
+                .insert(Component1(i as f32));
+            if i == 10 {
+                stepper.server_app.world.despawn(server_entity_2);
+                // the detach is not part of the resync snapshot, it must be replayed
+                stepper
+                    .server_app
+                    .world
+                    .entity_mut(server_entity_3)
+                    .remove::<Replicate>();
+            }
+            stepper.advance_time(frame_duration);
+            stepper.server_app.update();
+        }
+        assert!(
+            stepper
+                .server_app
+                .world
+                .resource::<ServerConnectionManager>()
+                .connection(client_id)
+                .unwrap()
+                .replication_sender
+                .resync_pending
+        );
+
+        // the client resumes and catches up with the resync
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        assert_eq!(
+            stepper.client_app.world.get::<Component1>(client_entity),
+            Some(&Component1(29.0))
+        );
+        assert!(stepper
+            .client_app
+            .world
+            .get_entity(client_entity_2)
+            .is_none());
+        assert!(stepper
+            .client_app
+            .world
+            .get_entity(client_entity_3)
+            .is_some());
+        assert!(stepper
+            .client_app
+            .world
+            .resource::<ClientConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity_3)
+            .is_none());
+        assert!(
+            !stepper
+                .server_app
+                .world
+                .resource::<ServerConnectionManager>()
+                .connection(client_id)
+                .unwrap()
+                .replication_sender
+                .resync_pending
+        );
     }
 }
@@ -150,6 +150,55 @@ impl RoomManager {
         }
     }
 
+    /// Returns true if the entity and the client are in at least one common room
+    pub(crate) fn entity_shares_room_with_client(
+        &self,
+        entity: Entity,
+        client_id: ClientId,
+    ) -> bool {
+        let (Some(entity_rooms), Some(client_rooms)) = (
+            self.data.entity_to_rooms.get(&entity),
+            self.data.client_to_rooms.get(&client_id),
+        ) else {
+            return false;
+        };
+        entity_rooms
+            .iter()
+            .any(|room_id| client_rooms.contains(room_id))
+    }
+
+    /// Iterate through the (entity, client) pairs that might have lost visibility because
+    /// of the room events that happened since the last send_interval
+    pub(crate) fn iter_room_leave_pairs(&self) -> impl Iterator<Item = (Entity, ClientId)> + '_ {
+        let entity_leaves =
+            self.events
+                .iter_entity_leave_room()
+                .flat_map(move |(entity, rooms)| {
+                    rooms.iter().flat_map(move |room_id| {
+                        self.data
+                            .rooms
+                            .get(room_id)
+                            .into_iter()
+                            .flat_map(move |room| room.clients.iter().map(move |c| (*entity, *c)))
+                    })
+                });
+        let client_leaves =
+            self.events
+                .iter_client_leave_room()
+                .flat_map(move |(client_id, rooms)| {
+                    rooms.iter().flat_map(move |room_id| {
+                        self.data
+                            .rooms
+                            .get(room_id)
+                            .into_iter()
+                            .flat_map(move |room| {
+                                room.entities.iter().map(move |e| (*e, *client_id))
+                            })
+                    })
+                });
+        entity_leaves.chain(client_leaves)
+    }
+
     fn add_client(&mut self, room_id: RoomId, client_id: ClientId) {
         self.data
             .client_to_rooms
@@ -364,7 +413,7 @@ pub enum ClientVisibility {
 
 /// Update each entities' replication-client-list based on the room events
 /// Note that the rooms' entities/clients have already been updated at this point
-fn update_entity_replication_cache<P: Protocol>(
+pub(crate) fn update_entity_replication_cache<P: Protocol>(
     room_manager: Res<RoomManager>,
     mut query: Query<&mut Replicate<P>>,
 ) {
@@ -488,6 +537,7 @@ mod tests {
             incoming_latency: Duration::from_millis(0),
             incoming_jitter: Duration::from_millis(0),
             incoming_loss: 0.0,
+            ..Default::default()
         };
         let sync_config = SyncConfig::default().speedup_factor(1.0);
         let prediction_config = PredictionConfig::default().disable(false);
@@ -0,0 +1,614 @@
+//! # Spatial grid
+//!
+//! Built-in interest management based on the position of entities.
+//!
+//! The world is partitioned into a grid of cells of size [`SpatialGridConfig::cell_size`].
+//! Each replicated entity that has a position component (any component that implements [`GridPosition`])
+//! is assigned to the cell that contains it.
+//! Each client can have a viewer (an entity with a [`GridViewer`] component and a position component);
+//! the client can see every cell that intersects the square (or cube) of half-width [`GridViewer::view_radius`]
+//! centered on the viewer.
+//!
+//! An entity will be replicated to a client if the entity is in one of the cells that the client can see,
+//! or if they share a [`Room`](crate::server::room::RoomManager).
+//! Just like with rooms, the entity needs to use [`ReplicationMode::Room`](crate::prelude::ReplicationMode::Room).
+//!
+//! Only the entities and viewers that changed cells are processed during each send_interval, so the grid
+//! can handle a large number of entities.
+//!
+//! ```rust,ignore
+//! app.add_plugins(SpatialGridPlugin::<MyProtocol, Position>::new(SpatialGridConfig {
+//!     cell_size: 100.0,
+//!     dimensions: GridDimensions::Two,
+//! }));
+//!
+//! // the player entity controlled by client 1 can see all entities within 250.0 units
+//! commands.spawn((Position::default(), GridViewer::new(1, 250.0)));
+//! ```
+use bevy::app::App;
+use bevy::ecs::entity::{EntityHashMap, EntityHashSet};
+use bevy::prelude::{
+    Added, Changed, Component, Entity, IVec3, IntoSystemConfigs, Or, ParamSet, Plugin, PostUpdate,
+    Query, RemovedComponents, Res, ResMut, Resource, Transform, Vec3, With,
+};
+use bevy::utils::{HashMap, HashSet};
+use tracing::trace;
+
+use crate::connection::netcode::ClientId;
+use crate::protocol::Protocol;
+use crate::server::room::{
+    update_entity_replication_cache, ClientVisibility, RoomManager, RoomSystemSets,
+};
+use crate::shared::replication::components::Replicate;
+
+/// Component that provides the position used to place an entity in the [`SpatialGrid`]
+pub trait GridPosition: Component {
+    /// Position of the entity in the world.
+    /// (For 2D games, the `z` coordinate is ignored if the grid uses [`GridDimensions::Two`])
+    fn grid_position(&self) -> Vec3;
+}
+
+impl GridPosition for Transform {
+    fn grid_position(&self) -> Vec3 {
+        self.translation
+    }
+}
+
+/// Number of dimensions used to partition the world
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GridDimensions {
+    /// Cells are squares in the (x, y) plane
+    #[default]
+    Two,
+    /// Cells are cubes
+    Three,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpatialGridConfig {
+    /// Size of the side of a cell
+    pub cell_size: f32,
+    pub dimensions: GridDimensions,
+}
+
+impl Default for SpatialGridConfig {
+    fn default() -> Self {
+        Self {
+            cell_size: 100.0,
+            dimensions: GridDimensions::Two,
+        }
+    }
+}
+
+/// Component that indicates that the entity's position is the point of view of a client.
+/// Every replicated entity within `view_radius` (rounded up to the grid's cells) will be visible
+/// to the client.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct GridViewer {
+    pub client_id: ClientId,
+    pub view_radius: f32,
+}
+
+impl GridViewer {
+    pub fn new(client_id: ClientId, view_radius: f32) -> Self {
+        Self {
+            client_id,
+            view_radius,
+        }
+    }
+}
+
+/// Inclusive range of cells that a viewer can see
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CellRange {
+    min: IVec3,
+    max: IVec3,
+}
+
+impl CellRange {
+    fn contains(&self, cell: IVec3) -> bool {
+        cell.cmpge(self.min).all() && cell.cmple(self.max).all()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = IVec3> {
+        let (min, max) = (self.min, self.max);
+        (min.x..=max.x).flat_map(move |x| {
+            (min.y..=max.y).flat_map(move |y| (min.z..=max.z).map(move |z| IVec3::new(x, y, z)))
+        })
+    }
+}
+
+/// Resource that keeps track of which entities are in which cells, and of which clients can see which cells
+#[derive(Resource, Debug, Default)]
+pub struct SpatialGrid {
+    config: SpatialGridConfig,
+    /// entities contained in each cell
+    cells: HashMap<IVec3, EntityHashSet>,
+    /// cell of each entity
+    entity_cells: EntityHashMap<IVec3>,
+    /// clients that can see each cell
+    cell_viewers: HashMap<IVec3, HashSet<ClientId>>,
+    /// cells visible by each client
+    viewers: HashMap<ClientId, CellRange>,
+    /// client associated with each viewer entity
+    viewer_entities: EntityHashMap<ClientId>,
+    /// clients that each entity is currently visible to
+    entity_visibility: EntityHashMap<HashSet<ClientId>>,
+    /// entities that each client can currently see
+    client_visibility: HashMap<ClientId, EntityHashSet>,
+    /// (entity, client) pairs whose visibility might have changed since the last update
+    pending: HashSet<(Entity, ClientId)>,
+}
+
+impl SpatialGrid {
+    pub fn new(config: SpatialGridConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// Returns true if the entity is visible to the client according to the grid
+    pub fn is_visible(&self, entity: Entity, client_id: ClientId) -> bool {
+        self.entity_visibility
+            .get(&entity)
+            .map_or(false, |clients| clients.contains(&client_id))
+    }
+
+    /// Cell that contains the entity, if the entity is tracked by the grid
+    pub fn entity_cell(&self, entity: Entity) -> Option<IVec3> {
+        self.entity_cells.get(&entity).copied()
+    }
+
+    /// Cell that contains a given position
+    pub fn cell(&self, position: Vec3) -> IVec3 {
+        let cell = (position / self.config.cell_size).floor().as_ivec3();
+        match self.config.dimensions {
+            GridDimensions::Two => cell.truncate().extend(0),
+            GridDimensions::Three => cell,
+        }
+    }
+
+    fn cell_range(&self, position: Vec3, radius: f32) -> CellRange {
+        CellRange {
+            min: self.cell(position - Vec3::splat(radius)),
+            max: self.cell(position + Vec3::splat(radius)),
+        }
+    }
+
+    /// Update the position of a replicated entity
+    pub(crate) fn update_entity(&mut self, entity: Entity, position: Vec3) {
+        let cell = self.cell(position);
+        let old_cell = self.entity_cells.insert(entity, cell);
+        if old_cell == Some(cell) {
+            return;
+        }
+        if let Some(old_cell) = old_cell {
+            if let Some(entities) = self.cells.get_mut(&old_cell) {
+                entities.remove(&entity);
+                if entities.is_empty() {
+                    self.cells.remove(&old_cell);
+                }
+            }
+        }
+        self.cells.entry(cell).or_default().insert(entity);
+        // clients that could see the entity, and clients that can see the new cell
+        if let Some(clients) = self.entity_visibility.get(&entity) {
+            self.pending
+                .extend(clients.iter().map(|client_id| (entity, *client_id)));
+        }
+        if let Some(clients) = self.cell_viewers.get(&cell) {
+            self.pending
+                .extend(clients.iter().map(|client_id| (entity, *client_id)));
+        }
+    }
+
+    /// Stop tracking an entity
+    pub(crate) fn remove_entity(&mut self, entity: Entity) {
+        if let Some(cell) = self.entity_cells.remove(&entity) {
+            if let Some(entities) = self.cells.get_mut(&cell) {
+                entities.remove(&entity);
+                if entities.is_empty() {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+        if let Some(clients) = self.entity_visibility.get(&entity) {
+            self.pending
+                .extend(clients.iter().map(|client_id| (entity, *client_id)));
+        }
+    }
+
+    /// Update the position or view radius of a client's viewer
+    pub(crate) fn update_viewer(
+        &mut self,
+        viewer_entity: Entity,
+        viewer: &GridViewer,
+        position: Vec3,
+    ) {
+        let client_id = viewer.client_id;
+        if let Some(old_client_id) = self.viewer_entities.insert(viewer_entity, client_id) {
+            if old_client_id != client_id {
+                self.remove_client(old_client_id);
+            }
+        }
+        let range = self.cell_range(position, viewer.view_radius);
+        let old_range = self.viewers.insert(client_id, range);
+        if old_range == Some(range) {
+            return;
+        }
+        // cells that the client stopped seeing
+        if let Some(old_range) = old_range {
+            for cell in old_range.iter().filter(|cell| !range.contains(*cell)) {
+                if let Some(clients) = self.cell_viewers.get_mut(&cell) {
+                    clients.remove(&client_id);
+                    if clients.is_empty() {
+                        self.cell_viewers.remove(&cell);
+                    }
+                }
+                if let Some(entities) = self.cells.get(&cell) {
+                    self.pending
+                        .extend(entities.iter().map(|entity| (*entity, client_id)));
+                }
+            }
+        }
+        // cells that the client started seeing
+        for cell in range
+            .iter()
+            .filter(|cell| !old_range.map_or(false, |old_range| old_range.contains(*cell)))
+        {
+            self.cell_viewers.entry(cell).or_default().insert(client_id);
+            if let Some(entities) = self.cells.get(&cell) {
+                self.pending
+                    .extend(entities.iter().map(|entity| (*entity, client_id)));
+            }
+        }
+    }
+
+    /// Stop tracking a viewer entity
+    pub(crate) fn remove_viewer(&mut self, viewer_entity: Entity) {
+        if let Some(client_id) = self.viewer_entities.remove(&viewer_entity) {
+            self.remove_client(client_id);
+        }
+    }
+
+    fn remove_client(&mut self, client_id: ClientId) {
+        if let Some(range) = self.viewers.remove(&client_id) {
+            for cell in range.iter() {
+                if let Some(clients) = self.cell_viewers.get_mut(&cell) {
+                    clients.remove(&client_id);
+                    if clients.is_empty() {
+                        self.cell_viewers.remove(&cell);
+                    }
+                }
+            }
+        }
+        if let Some(entities) = self.client_visibility.get(&client_id) {
+            self.pending
+                .extend(entities.iter().map(|entity| (*entity, client_id)));
+        }
+    }
+
+    /// Compute the visibility changes since the last call, for every (entity, client) pair that
+    /// might have been affected by entities or viewers moving
+    pub(crate) fn update_visibility(&mut self) -> Vec<(Entity, ClientId, ClientVisibility)> {
+        let mut changes = vec![];
+        for (entity, client_id) in std::mem::take(&mut self.pending) {
+            let visible = match (self.entity_cells.get(&entity), self.viewers.get(&client_id)) {
+                (Some(cell), Some(range)) => range.contains(*cell),
+                _ => false,
+            };
+            let was_visible = self.is_visible(entity, client_id);
+            if visible && !was_visible {
+                self.entity_visibility
+                    .entry(entity)
+                    .or_default()
+                    .insert(client_id);
+                self.client_visibility
+                    .entry(client_id)
+                    .or_default()
+                    .insert(entity);
+                changes.push((entity, client_id, ClientVisibility::Gained));
+            } else if !visible && was_visible {
+                if let Some(clients) = self.entity_visibility.get_mut(&entity) {
+                    clients.remove(&client_id);
+                    if clients.is_empty() {
+                        self.entity_visibility.remove(&entity);
+                    }
+                }
+                if let Some(entities) = self.client_visibility.get_mut(&client_id) {
+                    entities.remove(&entity);
+                    if entities.is_empty() {
+                        self.client_visibility.remove(&client_id);
+                    }
+                }
+                changes.push((entity, client_id, ClientVisibility::Lost));
+            }
+        }
+        changes
+    }
+}
+
+/// Plugin that maintains the [`SpatialGrid`] and uses it to update the replication caches of entities.
+///
+/// `C` is the component that holds the position of entities and viewers.
+pub struct SpatialGridPlugin<P: Protocol, C: GridPosition> {
+    config: SpatialGridConfig,
+    _marker: std::marker::PhantomData<(P, C)>,
+}
+
+impl<P: Protocol, C: GridPosition> SpatialGridPlugin<P, C> {
+    pub fn new(config: SpatialGridConfig) -> Self {
+        Self {
+            config,
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<P: Protocol, C: GridPosition> Plugin for SpatialGridPlugin<P, C> {
+    fn build(&self, app: &mut App) {
+        // RESOURCES
+        app.insert_resource(SpatialGrid::new(self.config));
+        // SYSTEMS
+        app.add_systems(
+            PostUpdate,
+            update_spatial_grid::<P, C>
+                .after(update_entity_replication_cache::<P>)
+                .in_set(RoomSystemSets::UpdateReplicationCaches),
+        );
+    }
+}
+
+/// Update the grid with the entities and viewers that moved, and update the
+/// replication caches of the entities whose visibility changed.
+pub(crate) fn update_spatial_grid<P: Protocol, C: GridPosition>(
+    mut grid: ResMut<SpatialGrid>,
+    room_manager: Res<RoomManager>,
+    mut queries: ParamSet<(
+        Query<(Entity, &C), (With<Replicate<P>>, Or<(Changed<C>, Added<Replicate<P>>)>)>,
+        Query<&mut Replicate<P>>,
+    )>,
+    viewers: Query<(Entity, &GridViewer, &C), Or<(Changed<GridViewer>, Changed<C>)>>,
+    mut removed_positions: RemovedComponents<C>,
+    mut removed_replicates: RemovedComponents<Replicate<P>>,
+    mut removed_viewers: RemovedComponents<GridViewer>,
+) {
+    // removals
+    for entity in removed_positions.read() {
+        grid.remove_entity(entity);
+        grid.remove_viewer(entity);
+    }
+    for entity in removed_replicates.read() {
+        grid.remove_entity(entity);
+    }
+    for entity in removed_viewers.read() {
+        grid.remove_viewer(entity);
+    }
+
+    // entities that moved
+    for (entity, position) in queries.p0().iter() {
+        grid.update_entity(entity, position.grid_position());
+    }
+    // viewers that moved
+    for (entity, viewer, position) in viewers.iter() {
+        grid.update_viewer(entity, viewer, position.grid_position());
+    }
+
+    // update the replication caches
+    let mut query = queries.p1();
+    for (entity, client_id, visibility) in grid.update_visibility() {
+        let Ok(mut replicate) = query.get_mut(entity) else {
+            continue;
+        };
+        trace!(?entity, ?client_id, ?visibility, "grid visibility change");
+        match visibility {
+            ClientVisibility::Gained => {
+                replicate
+                    .replication_clients_cache
+                    .entry(client_id)
+                    .and_modify(|visibility| {
+                        // the entity was about to be despawned for the client, but is still visible
+                        if *visibility == ClientVisibility::Lost {
+                            *visibility = ClientVisibility::Maintained;
+                        }
+                    })
+                    .or_insert(ClientVisibility::Gained);
+            }
+            ClientVisibility::Lost => {
+                // the entity is still visible via a room
+                if room_manager.entity_shares_room_with_client(entity, client_id) {
+                    continue;
+                }
+                if let Some(visibility) = replicate.replication_clients_cache.get_mut(&client_id) {
+                    *visibility = ClientVisibility::Lost;
+                }
+            }
+            ClientVisibility::Maintained => {}
+        }
+    }
+    // entities that left a room (or clients that left a room) but are still visible via the grid
+    for (entity, client_id) in room_manager.iter_room_leave_pairs() {
+        if !grid.is_visible(entity, client_id) {
+            continue;
+        }
+        if let Ok(mut replicate) = query.get_mut(entity) {
+            if let Some(visibility) = replicate.replication_clients_cache.get_mut(&client_id) {
+                if *visibility == ClientVisibility::Lost {
+                    *visibility = ClientVisibility::Maintained;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::Events;
+    use bevy::utils::Duration;
+
+    use crate::prelude::client::*;
+    use crate::prelude::*;
+    use crate::shared::replication::components::ReplicationMode;
+    use crate::tests::protocol::Replicate;
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    impl GridPosition for Component1 {
+        fn grid_position(&self) -> Vec3 {
+            Vec3::new(self.0, 0.0, 0.0)
+        }
+    }
+
+    #[test]
+    fn test_grid_visibility() {
+        let mut grid = SpatialGrid::new(SpatialGridConfig {
+            cell_size: 10.0,
+            dimensions: GridDimensions::Two,
+        });
+        let entity = Entity::from_raw(1);
+        let viewer_entity = Entity::from_raw(2);
+        let client_id = 111;
+
+        grid.update_entity(entity, Vec3::new(5.0, 5.0, 0.0));
+        grid.update_viewer(
+            viewer_entity,
+            &GridViewer::new(client_id, 10.0),
+            Vec3::new(0.0, 0.0, 0.0),
+        );
+        assert_eq!(
+            grid.update_visibility(),
+            vec![(entity, client_id, ClientVisibility::Gained)]
+        );
+        assert!(grid.is_visible(entity, client_id));
+
+        // moving within the visible cells does not change visibility
+        grid.update_entity(entity, Vec3::new(12.0, 5.0, 0.0));
+        assert!(grid.update_visibility().is_empty());
+
+        // moving out of the visible cells
+        grid.update_entity(entity, Vec3::new(25.0, 5.0, 0.0));
+        assert_eq!(
+            grid.update_visibility(),
+            vec![(entity, client_id, ClientVisibility::Lost)]
+        );
+
+        // the viewer moves closer to the entity
+        grid.update_viewer(
+            viewer_entity,
+            &GridViewer::new(client_id, 10.0),
+            Vec3::new(15.0, 0.0, 0.0),
+        );
+        assert_eq!(
+            grid.update_visibility(),
+            vec![(entity, client_id, ClientVisibility::Gained)]
+        );
+
+        // the viewer is removed
+        grid.remove_viewer(viewer_entity);
+        assert_eq!(
+            grid.update_visibility(),
+            vec![(entity, client_id, ClientVisibility::Lost)]
+        );
+        assert!(!grid.is_visible(entity, client_id));
+    }
+
+    /// An entity is replicated to a client only while it is close to the client's viewer
+    #[test]
+    fn test_spatial_grid_replication() {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(0),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.0,
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default().speedup_factor(1.0),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            link_conditioner,
+            frame_duration,
+        );
+        stepper
+            .server_app
+            .add_plugins(SpatialGridPlugin::<MyProtocol, Component1>::new(
+                SpatialGridConfig {
+                    cell_size: 10.0,
+                    dimensions: GridDimensions::Two,
+                },
+            ));
+        stepper.init();
+
+        let client_id = 111;
+        stepper
+            .server_app
+            .world
+            .spawn((Component1(0.0), GridViewer::new(client_id, 10.0)));
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((
+                Component1(100.0),
+                Replicate {
+                    replication_mode: ReplicationMode::Room,
+                    ..Default::default()
+                },
+            ))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        // the entity is too far to be replicated
+        assert!(stepper
+            .client_app
+            .world
+            .resource::<ClientConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .is_none());
+
+        // the entity moves closer
+        stepper
+            .server_app
+            .world
+            .entity_mut(server_entity)
+            .insert(Component1(5.0));
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_entity = *stepper
+            .client_app
+            .world
+            .resource::<ClientConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .expect("entity should be replicated once it is close to the viewer");
+        assert_eq!(
+            stepper
+                .client_app
+                .world
+                .resource::<Events<EntitySpawnEvent>>()
+                .len(),
+            1
+        );
+
+        // the entity moves away
+        stepper
+            .server_app
+            .world
+            .entity_mut(server_entity)
+            .insert(Component1(100.0));
+        stepper.frame_step();
+        stepper.frame_step();
+        assert!(stepper.client_app.world.get_entity(client_entity).is_none());
+    }
+}
@@ -0,0 +1,410 @@
+//! # Visibility
+//!
+//! This module lets you control directly which entities are visible to which clients, without using rooms.
+//! This is useful to express rules such as fog-of-war or line-of-sight.
+//!
+//! Entities must use [`ReplicationMode::Manual`](crate::prelude::ReplicationMode::Manual); they will only be replicated
+//! to the clients that were given visibility with [`VisibilityManager::gain_visibility`].
+//!
+//! ```rust,ignore
+//! fn update_fog_of_war(mut visibility_manager: ResMut<VisibilityManager>) {
+//!     visibility_manager.gain_visibility(client_id, entity);
+//!     visibility_manager.lose_visibility(other_client_id, entity);
+//! }
+//! ```
+use bevy::app::App;
+use bevy::ecs::entity::EntityHashMap;
+use bevy::prelude::{
+    Entity, IntoSystemConfigs, Plugin, PostUpdate, Query, RemovedComponents, Res, ResMut, Resource,
+};
+use bevy::utils::HashSet;
+
+use crate::connection::netcode::ClientId;
+use crate::protocol::Protocol;
+use crate::server::room::{ClientVisibility, RoomSystemSets};
+use crate::shared::replication::components::{DespawnTracker, Replicate, ReplicationMode};
+
+/// Tracks the visibility changes since the last send_interval.
+///
+/// If a client gains then loses visibility of an entity within the same send_interval,
+/// we don't need to send any update.
+#[derive(Debug, Default)]
+struct VisibilityEvents {
+    gained: EntityHashMap<HashSet<ClientId>>,
+    lost: EntityHashMap<HashSet<ClientId>>,
+}
+
+impl VisibilityEvents {
+    fn clear(&mut self) {
+        self.gained.clear();
+        self.lost.clear();
+    }
+
+    fn gain_visibility(&mut self, client_id: ClientId, entity: Entity) {
+        if !self
+            .lost
+            .get_mut(&entity)
+            .map_or(false, |clients| clients.remove(&client_id))
+        {
+            self.gained.entry(entity).or_default().insert(client_id);
+        }
+    }
+
+    fn lose_visibility(&mut self, client_id: ClientId, entity: Entity) {
+        if !self
+            .gained
+            .get_mut(&entity)
+            .map_or(false, |clients| clients.remove(&client_id))
+        {
+            self.lost.entry(entity).or_default().insert(client_id);
+        }
+    }
+}
+
+/// Resource that lets you control which clients can see which entities
+/// (for entities that use [`ReplicationMode::Manual`](crate::prelude::ReplicationMode::Manual))
+#[derive(Resource, Debug, Default)]
+pub struct VisibilityManager {
+    events: VisibilityEvents,
+    /// clients that each entity is currently visible to
+    visibility: EntityHashMap<HashSet<ClientId>>,
+}
+
+impl VisibilityManager {
+    /// The entity becomes visible to the client: it will be spawned on the client
+    pub fn gain_visibility(&mut self, client_id: ClientId, entity: Entity) -> &mut Self {
+        if self.visibility.entry(entity).or_default().insert(client_id) {
+            self.events.gain_visibility(client_id, entity);
+        }
+        self
+    }
+
+    /// The entity is not visible to the client anymore: it will be despawned on the client
+    pub fn lose_visibility(&mut self, client_id: ClientId, entity: Entity) -> &mut Self {
+        if self
+            .visibility
+            .get_mut(&entity)
+            .map_or(false, |clients| clients.remove(&client_id))
+        {
+            self.events.lose_visibility(client_id, entity);
+        }
+        self
+    }
+
+    /// Returns true if the entity is currently visible to the client
+    pub fn is_visible(&self, client_id: ClientId, entity: Entity) -> bool {
+        self.visibility
+            .get(&entity)
+            .map_or(false, |clients| clients.contains(&client_id))
+    }
+
+    /// Remove the client from the visibility lists of all entities
+    pub(crate) fn client_disconnect(&mut self, client_id: ClientId) {
+        self.visibility.values_mut().for_each(|clients| {
+            clients.remove(&client_id);
+        });
+        self.events
+            .gained
+            .values_mut()
+            .chain(self.events.lost.values_mut())
+            .for_each(|clients| {
+                clients.remove(&client_id);
+            });
+    }
+
+    /// Remove all the visibility information about the entity
+    pub(crate) fn entity_despawn(&mut self, entity: Entity) {
+        self.visibility.remove(&entity);
+        self.events.gained.remove(&entity);
+        self.events.lost.remove(&entity);
+    }
+}
+
+pub struct VisibilityPlugin<P: Protocol> {
+    _marker: std::marker::PhantomData<P>,
+}
+
+impl<P: Protocol> Default for VisibilityPlugin<P> {
+    fn default() -> Self {
+        Self {
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<P: Protocol> Plugin for VisibilityPlugin<P> {
+    fn build(&self, app: &mut App) {
+        // RESOURCES
+        app.init_resource::<VisibilityManager>();
+        // SYSTEMS
+        // the RoomPlugin is responsible for configuring the sets
+        app.add_systems(
+            PostUpdate,
+            (
+                update_visibility_cache::<P>.in_set(RoomSystemSets::UpdateReplicationCaches),
+                (clean_entity_despawns, clear_visibility_events)
+                    .in_set(RoomSystemSets::RoomBookkeeping),
+            ),
+        );
+    }
+}
+
+/// Update each entities' replication-client-list based on the visibility events
+/// (only for the entities that use [`ReplicationMode::Manual`]: the cache of the other entities is handled by the rooms)
+fn update_visibility_cache<P: Protocol>(
+    visibility_manager: Res<VisibilityManager>,
+    mut query: Query<&mut Replicate<P>>,
+) {
+    let is_manual =
+        |replicate: &Replicate<P>| matches!(replicate.replication_mode, ReplicationMode::Manual);
+    for (entity, clients) in visibility_manager.events.gained.iter() {
+        if let Ok(mut replicate) = query.get_mut(*entity) {
+            if !is_manual(&replicate) {
+                continue;
+            }
+            clients.iter().for_each(|client_id| {
+                // only set it to gained if it wasn't present before
+                replicate
+                    .replication_clients_cache
+                    .entry(*client_id)
+                    .or_insert(ClientVisibility::Gained);
+            });
+        }
+    }
+    for (entity, clients) in visibility_manager.events.lost.iter() {
+        if let Ok(mut replicate) = query.get_mut(*entity) {
+            if !is_manual(&replicate) {
+                continue;
+            }
+            clients.iter().for_each(|client_id| {
+                if let Some(visibility) = replicate.replication_clients_cache.get_mut(client_id) {
+                    *visibility = ClientVisibility::Lost;
+                }
+            });
+        }
+    }
+}
+
+/// Clear every visibility event that happened
+fn clear_visibility_events(mut visibility_manager: ResMut<VisibilityManager>) {
+    visibility_manager.events.clear();
+}
+
+/// Clear out the visibility metadata for any entity that was ever replicated
+fn clean_entity_despawns(
+    mut visibility_manager: ResMut<VisibilityManager>,
+    mut despawned: RemovedComponents<DespawnTracker>,
+) {
+    for entity in despawned.read() {
+        visibility_manager.entity_despawn(entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::Duration;
+
+    use crate::prelude::client::*;
+    use crate::prelude::*;
+    use crate::tests::protocol::Replicate;
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    #[test]
+    fn test_visibility_events() {
+        let mut manager = VisibilityManager::default();
+        let entity = Entity::from_raw(1);
+        let client_id = 111;
+
+        manager.gain_visibility(client_id, entity);
+        assert!(manager.is_visible(client_id, entity));
+        assert!(manager.events.gained[&entity].contains(&client_id));
+
+        // gaining and losing visibility in the same send_interval cancels out
+        manager.lose_visibility(client_id, entity);
+        assert!(!manager.is_visible(client_id, entity));
+        assert!(manager.events.gained[&entity].is_empty());
+        assert!(manager.events.lost.get(&entity).is_none());
+
+        // losing visibility of an entity that was not visible does nothing
+        manager.lose_visibility(client_id, entity);
+        assert!(manager.events.lost.get(&entity).is_none());
+    }
+
+    #[test]
+    fn test_manual_visibility() {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(0),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.0,
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default().speedup_factor(1.0),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            link_conditioner,
+            frame_duration,
+        );
+        stepper.init();
+
+        let client_id = 111;
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn(Replicate {
+                replication_mode: ReplicationMode::Manual,
+                ..Default::default()
+            })
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        // the entity is not visible to the client
+        assert!(stepper
+            .client_app
+            .world
+            .resource::<ClientConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .is_none());
+
+        // gain visibility
+        stepper
+            .server_app
+            .world
+            .resource_mut::<VisibilityManager>()
+            .gain_visibility(client_id, server_entity);
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_entity = *stepper
+            .client_app
+            .world
+            .resource::<ClientConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .expect("entity should be replicated after gaining visibility");
+        assert_eq!(
+            stepper
+                .server_app
+                .world
+                .entity(server_entity)
+                .get::<Replicate>()
+                .unwrap()
+                .replication_clients_cache
+                .get(&client_id),
+            Some(&ClientVisibility::Maintained)
+        );
+        // the despawn of the entity would only be sent to the clients that see it
+        assert_eq!(
+            stepper
+                .server_app
+                .world
+                .resource::<ServerConnectionManager>()
+                .replicate_component_cache
+                .get(&server_entity)
+                .unwrap()
+                .target,
+            NetworkTarget::Only(vec![client_id])
+        );
+
+        // lose visibility
+        stepper
+            .server_app
+            .world
+            .resource_mut::<VisibilityManager>()
+            .lose_visibility(client_id, server_entity);
+        stepper.frame_step();
+        stepper.frame_step();
+        assert!(stepper.client_app.world.get_entity(client_entity).is_none());
+        assert!(stepper
+            .server_app
+            .world
+            .entity(server_entity)
+            .get::<Replicate>()
+            .unwrap()
+            .replication_clients_cache
+            .is_empty());
+        assert_eq!(
+            stepper
+                .server_app
+                .world
+                .resource::<ServerConnectionManager>()
+                .replicate_component_cache
+                .get(&server_entity)
+                .unwrap()
+                .target,
+            NetworkTarget::Only(vec![])
+        );
+    }
+
+    /// The visibility manager doesn't change the visibility of entities that don't use `ReplicationMode::Manual`
+    #[test]
+    fn test_visibility_ignored_without_manual_mode() {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(0),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.0,
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default().speedup_factor(1.0),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            link_conditioner,
+            frame_duration,
+        );
+        stepper.init();
+
+        let client_id = 111;
+        // the entity is not in any room
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn(Replicate {
+                replication_mode: ReplicationMode::Room,
+                ..Default::default()
+            })
+            .id();
+        stepper
+            .server_app
+            .world
+            .resource_mut::<VisibilityManager>()
+            .gain_visibility(client_id, server_entity);
+        stepper.frame_step();
+        stepper.frame_step();
+        assert!(stepper
+            .server_app
+            .world
+            .entity(server_entity)
+            .get::<Replicate>()
+            .unwrap()
+            .replication_clients_cache
+            .is_empty());
+        assert!(stepper
+            .client_app
+            .world
+            .resource::<ClientConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .is_none());
+    }
+}
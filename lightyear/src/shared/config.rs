@@ -3,6 +3,27 @@ use bevy::utils::Duration;
 
 use crate::shared::tick_manager::TickConfig;
 
+/// How the server and the client are run
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// The server and the client run in separate apps (usually in separate processes),
+    /// and communicate through a transport
+    #[default]
+    Separate,
+    /// The server runs in the same app as a local player (listen server).
+    ///
+    /// The [`ServerPlugin`](crate::server::plugin::ServerPlugin) and the [`ClientPlugin`](crate::client::plugin::ClientPlugin)
+    /// are both added to the app, and the client must use [`NetConfig::Local`](crate::connection::client::NetConfig::Local).
+    /// The local client is a regular client of the server (it has a `ClientId`, can join rooms, receives messages, etc.)
+    /// but it doesn't use a transport: its messages and inputs are handed directly to the server without serialization.
+    ///
+    /// The entities of the server are not replicated to the local client, since they already exist in the app:
+    /// there are no `Confirmed` copies, and the entities that should be predicted or interpolated by the local client
+    /// get the [`Predicted`](crate::client::prediction::Predicted) or [`Interpolated`](crate::client::interpolation::Interpolated)
+    /// marker component directly.
+    HostServer,
+}
+
 /// Configuration that has to be the same between the server and the client.
 #[derive(Clone, Debug)]
 pub struct SharedConfig {
@@ -12,6 +33,8 @@ pub struct SharedConfig {
     pub server_send_interval: Duration,
     /// configuration for the [`FixedUpdate`](bevy::prelude::FixedUpdate) schedule
     pub tick: TickConfig,
+    /// whether the server and the client run in separate apps, or in the same app
+    pub mode: Mode,
 }
 
 impl Default for SharedConfig {
@@ -21,6 +44,7 @@ impl Default for SharedConfig {
             client_send_interval: Duration::from_millis(0),
             server_send_interval: Duration::from_millis(0),
             tick: TickConfig::new(Duration::from_millis(16)),
+            mode: Mode::default(),
         }
     }
 }
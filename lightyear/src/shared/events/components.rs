@@ -2,33 +2,55 @@
 
 use std::marker::PhantomData;
 
-use bevy::prelude::{Component, Entity, Event};
+use bevy::prelude::{Component, Entity, Event, Resource};
 
+use crate::connection::netcode::USER_DATA_BYTES;
+use crate::connection::DisconnectReason;
 #[cfg(feature = "leafwing")]
 use crate::inputs::leafwing::InputMessage;
 use crate::packet::message::Message;
 
 #[derive(Event)]
-pub struct ConnectEvent<Ctx = ()>(Ctx);
+pub struct ConnectEvent<Ctx = ()> {
+    context: Ctx,
+    user_data: Option<[u8; USER_DATA_BYTES]>,
+}
 
 impl<Ctx> ConnectEvent<Ctx> {
     pub fn new(context: Ctx) -> Self {
-        Self(context)
+        Self {
+            context,
+            user_data: None,
+        }
+    }
+    pub fn with_user_data(context: Ctx, user_data: Option<[u8; USER_DATA_BYTES]>) -> Self {
+        Self { context, user_data }
     }
     pub fn context(&self) -> &Ctx {
-        &self.0
+        &self.context
+    }
+    /// User data provided by the client when connecting (the `user_data` of its connect token)
+    pub fn user_data(&self) -> Option<&[u8; USER_DATA_BYTES]> {
+        self.user_data.as_ref()
     }
 }
 
 #[derive(Event)]
-pub struct DisconnectEvent<Ctx = ()>(Ctx);
+pub struct DisconnectEvent<Ctx = ()> {
+    context: Ctx,
+    reason: DisconnectReason,
+}
 
 impl<Ctx> DisconnectEvent<Ctx> {
-    pub fn new(context: Ctx) -> Self {
-        Self(context)
+    pub fn new(context: Ctx, reason: DisconnectReason) -> Self {
+        Self { context, reason }
     }
     pub fn context(&self) -> &Ctx {
-        &self.0
+        &self.context
+    }
+    /// Why the connection was closed (or could not be established)
+    pub fn reason(&self) -> DisconnectReason {
+        self.reason
     }
 }
 
@@ -114,6 +136,62 @@ impl<Ctx> EntitySpawnEvent<Ctx> {
     }
 }
 
+/// Event emitted when the remote stops replicating an entity without despawning it
+/// (see [`OnReplicateRemoved::DetachRemote`](crate::prelude::OnReplicateRemoved::DetachRemote)).
+/// The local entity is kept, but it won't receive any more replication updates.
+#[derive(Event)]
+pub struct EntityDetachEvent<Ctx = ()> {
+    entity: Entity,
+    context: Ctx,
+}
+
+impl<Ctx> EntityDetachEvent<Ctx> {
+    pub fn new(entity: Entity, context: Ctx) -> Self {
+        Self { entity, context }
+    }
+
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+
+    pub fn context(&self) -> &Ctx {
+        &self.context
+    }
+}
+
+/// Event emitted when the simulation authority over an entity changes
+/// (see [`AuthorityPeer`](crate::shared::replication::authority::AuthorityPeer))
+#[derive(Event)]
+pub struct AuthorityChangeEvent<Ctx = ()> {
+    entity: Entity,
+    has_authority: bool,
+    context: Ctx,
+}
+
+impl<Ctx> AuthorityChangeEvent<Ctx> {
+    pub fn new(entity: Entity, has_authority: bool, context: Ctx) -> Self {
+        Self {
+            entity,
+            has_authority,
+            context,
+        }
+    }
+
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+
+    /// On the client: true if the client now has authority over the entity.
+    /// On the server: true if the client in the context now has authority over the entity.
+    pub fn has_authority(&self) -> bool {
+        self.has_authority
+    }
+
+    pub fn context(&self) -> &Ctx {
+        &self.context
+    }
+}
+
 #[derive(Event)]
 pub struct EntityDespawnEvent<Ctx = ()> {
     entity: Entity,
@@ -211,3 +289,69 @@ impl<C: Component, Ctx> ComponentRemoveEvent<C, Ctx> {
         &self.context
     }
 }
+
+/// Event emitted when a replicated resource is inserted
+/// (see [`ReplicateResource`](crate::shared::replication::resources::ReplicateResource))
+#[derive(Event)]
+pub struct ResourceInsertEvent<R: Resource, Ctx = ()> {
+    context: Ctx,
+
+    _marker: PhantomData<R>,
+}
+
+impl<R: Resource, Ctx> ResourceInsertEvent<R, Ctx> {
+    pub fn new(context: Ctx) -> Self {
+        Self {
+            context,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn context(&self) -> &Ctx {
+        &self.context
+    }
+}
+
+/// Event emitted when a replicated resource is updated
+/// (see [`ReplicateResource`](crate::shared::replication::resources::ReplicateResource))
+#[derive(Event)]
+pub struct ResourceUpdateEvent<R: Resource, Ctx = ()> {
+    context: Ctx,
+
+    _marker: PhantomData<R>,
+}
+
+impl<R: Resource, Ctx> ResourceUpdateEvent<R, Ctx> {
+    pub fn new(context: Ctx) -> Self {
+        Self {
+            context,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn context(&self) -> &Ctx {
+        &self.context
+    }
+}
+
+/// Event emitted when a replicated resource is removed
+/// (see [`ReplicateResource`](crate::shared::replication::resources::ReplicateResource))
+#[derive(Event)]
+pub struct ResourceRemoveEvent<R: Resource, Ctx = ()> {
+    context: Ctx,
+
+    _marker: PhantomData<R>,
+}
+
+impl<R: Resource, Ctx> ResourceRemoveEvent<R, Ctx> {
+    pub fn new(context: Ctx) -> Self {
+        Self {
+            context,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn context(&self) -> &Ctx {
+        &self.context
+    }
+}
@@ -31,6 +31,10 @@ pub struct ConnectionEvents<P: Protocol> {
     // replication
     pub spawns: Vec<Entity>,
     pub despawns: Vec<Entity>,
+    /// entities that are not replicated anymore by the remote, but were not despawned
+    pub detaches: Vec<Entity>,
+    /// entities for which we gained (true) or lost (false) authority
+    pub authority_changes: Vec<(Entity, bool)>,
 
     // TODO: [IMPORTANT]: add ticks as well?
     // - should we just return the latest update for a given component/entity, or all of them?
@@ -74,6 +78,8 @@ impl<P: Protocol> ConnectionEvents<P> {
             // replication
             spawns: Vec::new(),
             despawns: Vec::new(),
+            detaches: Vec::new(),
+            authority_changes: Vec::new(),
             component_inserts: Default::default(),
             component_removes: Default::default(),
             component_updates: Default::default(),
@@ -90,6 +96,8 @@ impl<P: Protocol> ConnectionEvents<P> {
         self.messages.clear();
         self.spawns.clear();
         self.despawns.clear();
+        self.detaches.clear();
+        self.authority_changes.clear();
         self.component_inserts.clear();
         self.component_removes.clear();
         self.component_updates.clear();
@@ -166,6 +174,18 @@ impl<P: Protocol> ConnectionEvents<P> {
         self.empty = false;
     }
 
+    pub(crate) fn push_detach(&mut self, entity: Entity) {
+        trace!(?entity, "Received entity detach");
+        self.detaches.push(entity);
+        self.empty = false;
+    }
+
+    pub(crate) fn push_authority_change(&mut self, entity: Entity, has_authority: bool) {
+        trace!(?entity, ?has_authority, "Received authority change");
+        self.authority_changes.push((entity, has_authority));
+        self.empty = false;
+    }
+
     pub(crate) fn push_insert_component(
         &mut self,
         entity: Entity,
@@ -342,6 +362,22 @@ impl<P: Protocol> IterEntityDespawnEvent for ConnectionEvents<P> {
     }
 }
 
+pub trait IterEntityDetachEvent<Ctx: EventContext = ()> {
+    fn drain_entity_detach(&mut self) -> Box<dyn Iterator<Item = (Entity, Ctx)> + '_>;
+    fn has_entity_detach(&self) -> bool;
+}
+
+impl<P: Protocol> IterEntityDetachEvent for ConnectionEvents<P> {
+    fn drain_entity_detach(&mut self) -> Box<dyn Iterator<Item = (Entity, ())> + '_> {
+        let detaches = std::mem::take(&mut self.detaches);
+        Box::new(detaches.into_iter().map(|entity| (entity, ())))
+    }
+
+    fn has_entity_detach(&self) -> bool {
+        !self.detaches.is_empty()
+    }
+}
+
 /// Iterate through all the events for a given entity
 pub trait IterComponentUpdateEvent<P: Protocol, Ctx: EventContext = ()> {
     /// Find all the updates of component C
@@ -6,7 +6,8 @@ use bevy::prelude::Plugin;
 use crate::_reexport::{ComponentProtocol, EventContext, MessageProtocol};
 use crate::prelude::{MainSet, Protocol};
 use crate::shared::events::components::{
-    ConnectEvent, DisconnectEvent, EntityDespawnEvent, EntitySpawnEvent,
+    AuthorityChangeEvent, ConnectEvent, DisconnectEvent, EntityDespawnEvent, EntityDetachEvent,
+    EntitySpawnEvent,
 };
 
 pub struct EventsPlugin<P: Protocol, Ctx: EventContext> {
@@ -33,6 +34,8 @@ impl<P: Protocol, Ctx: EventContext> Plugin for EventsPlugin<P, Ctx> {
         app.add_event::<ConnectEvent<Ctx>>()
             .add_event::<DisconnectEvent<Ctx>>()
             .add_event::<EntitySpawnEvent<Ctx>>()
-            .add_event::<EntityDespawnEvent<Ctx>>();
+            .add_event::<EntityDespawnEvent<Ctx>>()
+            .add_event::<EntityDetachEvent<Ctx>>()
+            .add_event::<AuthorityChangeEvent<Ctx>>();
     }
 }
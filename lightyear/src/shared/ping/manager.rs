@@ -263,6 +263,8 @@ impl PingManager {
                 // process the pong
                 self.process_pong(pong, time_manager);
             }
+            // the interpolation time is handled by the connection
+            SyncMessage::InterpolationTime(_) => {}
         }
     }
 
@@ -2,6 +2,7 @@
 use bitcode::{Decode, Encode};
 
 use crate::shared::ping::store::PingId;
+use crate::shared::tick_manager::Tick;
 use crate::shared::time_manager::WrappedTime;
 
 // TODO: do we need the ping ids? we could just re-use the message id ?
@@ -22,8 +23,20 @@ pub struct Pong {
     pub pong_sent_time: WrappedTime,
 }
 
+/// Interpolation time of the client, i.e. the point in the server's timeline that the client is currently
+/// rendering for interpolated entities.
+///
+/// The client sends it regularly to the server, so that the server can perform lag compensation.
+#[derive(Encode, Decode, Clone, Copy, Debug, Default, PartialEq)]
+pub struct InterpolationTime {
+    pub tick: Tick,
+    /// Fraction of a tick elapsed since `tick`, between 0.0 and 1.0
+    pub overstep: f32,
+}
+
 #[derive(Encode, Decode, Clone, Debug)]
 pub enum SyncMessage {
     Ping(Ping),
     Pong(Pong),
+    InterpolationTime(InterpolationTime),
 }
@@ -6,6 +6,7 @@ use replication::hierarchy::HierarchySyncPlugin;
 
 use crate::client::config::ClientConfig;
 use crate::prelude::Protocol;
+use crate::server::config::ServerConfig;
 use crate::shared::config::SharedConfig;
 use crate::shared::replication;
 use crate::shared::tick_manager::TickManagerPlugin;
@@ -25,9 +26,12 @@ impl<P: Protocol> Default for SharedPlugin<P> {
 }
 
 /// You can use this as a SystemParam to identify whether you're running on the client or the server
+///
+/// In [`Mode::HostServer`](crate::prelude::Mode::HostServer), the app is both a client and a server.
 #[derive(SystemParam)]
 pub struct NetworkIdentity<'w, 's> {
     config: Option<Res<'w, ClientConfig>>,
+    server_config: Option<Res<'w, ServerConfig>>,
     _marker: std::marker::PhantomData<&'s ()>,
 }
 
@@ -36,7 +40,11 @@ impl<'w, 's> NetworkIdentity<'w, 's> {
         self.config.is_some()
     }
     pub fn is_server(&self) -> bool {
-        self.config.is_none()
+        self.server_config.is_some() || self.config.is_none()
+    }
+    /// Returns true if the app runs both the server and a local client
+    pub fn is_host_server(&self) -> bool {
+        self.config.is_some() && self.server_config.is_some()
     }
 }
 
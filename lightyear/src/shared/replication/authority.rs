@@ -0,0 +1,475 @@
+//! Authority over replicated entities
+//!
+//! By default, the server simulates the entities it replicates. The server can hand over the simulation
+//! authority of an entity to a client (for example a vehicle that the player is driving), and take it back later.
+//!
+//! While a client has authority over an entity:
+//! - the server stops sending replication updates for that entity to the client
+//! - the client replicates its own version of the entity to the server, which applies the updates to the server entity
+//!   (and keeps replicating the entity to the other clients as usual)
+//! - on the client, the entity that gets replicated to the server is the `Predicted` entity if there is one, otherwise the
+//!   `Confirmed` entity. That entity has the [`HasAuthority`] component.
+//! - the `Confirmed` entity keeps the last state received from the server; a `Predicted` entity with authority is not
+//!   compared against it, and is rolled back to its own history if another entity triggers a rollback
+//! - the server entity still belongs to the server: despawning the client entity (or removing its `Replicate` component)
+//!   is not replicated, and the server ignores despawns for the entities that a client has authority over
+//!
+//! When the server takes back the authority (or when the client disconnects), the server sends the full state of the
+//! entity's replication group to the client again, so that the client's `Confirmed` entity is up-to-date.
+//!
+//! Authority is transferred on the server with the [`AuthorityCommandsExt::transfer_authority`] command.
+//! An `AuthorityChangeEvent` is emitted on the server and on the client whenever the authority changes.
+//!
+//! ```rust,ignore
+//! commands.entity(vehicle).transfer_authority::<MyProtocol>(AuthorityPeer::Client(client_id));
+//! ```
+use std::marker::PhantomData;
+
+use bevy::ecs::system::{Command, EntityCommands};
+use bevy::prelude::{Component, Entity, Events, World};
+use tracing::error;
+
+use crate::connection::netcode::ClientId;
+use crate::protocol::Protocol;
+use crate::server::connection::ConnectionManager;
+use crate::server::events::AuthorityChangeEvent;
+use crate::shared::replication::components::Replicate;
+
+/// Marker component inserted on the client entity that the client has authority over.
+/// The client is responsible for simulating this entity, and its changes are replicated to the server.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq)]
+pub struct HasAuthority;
+
+/// Peer that has the simulation authority over an entity
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AuthorityPeer {
+    /// The server simulates the entity
+    #[default]
+    Server,
+    /// The client simulates the entity and replicates it to the server
+    Client(ClientId),
+}
+
+pub struct TransferAuthorityCommand<P: Protocol> {
+    entity: Entity,
+    peer: AuthorityPeer,
+    _marker: PhantomData<P>,
+}
+
+impl<P: Protocol> Command for TransferAuthorityCommand<P> {
+    fn apply(self, world: &mut World) {
+        let Some(replicate) = world.get::<Replicate<P>>(self.entity) else {
+            error!("Authority can only be transferred for replicated entities");
+            return;
+        };
+        let group_id = replicate.group_id(Some(self.entity));
+        match world
+            .resource_mut::<ConnectionManager<P>>()
+            .transfer_authority(self.entity, group_id, self.peer)
+        {
+            Ok(previous) => {
+                let mut events = world.resource_mut::<Events<AuthorityChangeEvent>>();
+                if let Some(previous) = previous {
+                    events.send(AuthorityChangeEvent::new(self.entity, false, previous));
+                }
+                if let AuthorityPeer::Client(client_id) = self.peer {
+                    events.send(AuthorityChangeEvent::new(self.entity, true, client_id));
+                }
+            }
+            Err(e) => {
+                error!("error transferring authority: {:?}", e);
+            }
+        }
+    }
+}
+
+pub trait AuthorityCommandsExt {
+    /// Transfer the simulation authority over the entity to another peer.
+    ///
+    /// This command must be used on the server.
+    fn transfer_authority<P: Protocol>(&mut self, peer: AuthorityPeer);
+}
+
+impl AuthorityCommandsExt for EntityCommands<'_> {
+    fn transfer_authority<P: Protocol>(&mut self, peer: AuthorityPeer) {
+        let entity = self.id();
+        self.commands().add(TransferAuthorityCommand {
+            entity,
+            peer,
+            _marker: PhantomData::<P>,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::Duration;
+
+    use crate::prelude::client::*;
+    use crate::prelude::*;
+    use crate::shared::replication::components::ReplicationGroupId;
+    use crate::tests::protocol::Replicate;
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    #[test]
+    fn test_transfer_authority() {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(0),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.0,
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default().speedup_factor(1.0),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            link_conditioner,
+            frame_duration,
+        );
+        stepper.init();
+
+        let client_id = 111;
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((Component1(0.0), Replicate::default()))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_entity = *stepper
+            .client_app
+            .world
+            .resource::<ClientConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .unwrap();
+
+        // give the authority to the client
+        TransferAuthorityCommand::<MyProtocol> {
+            entity: server_entity,
+            peer: AuthorityPeer::Client(client_id),
+            _marker: PhantomData,
+        }
+        .apply(&mut stepper.server_app.world);
+        assert_eq!(
+            stepper
+                .server_app
+                .world
+                .resource::<ServerConnectionManager>()
+                .authority(server_entity),
+            AuthorityPeer::Client(client_id)
+        );
+        stepper.frame_step();
+        stepper.frame_step();
+        assert!(stepper
+            .client_app
+            .world
+            .get::<HasAuthority>(client_entity)
+            .is_some());
+        assert_eq!(
+            stepper
+                .client_app
+                .world
+                .resource_mut::<Events<crate::client::events::AuthorityChangeEvent>>()
+                .drain()
+                .map(|event| (event.entity(), event.has_authority()))
+                .collect::<Vec<_>>(),
+            vec![(client_entity, true)]
+        );
+
+        // the client simulates the entity, and its changes are replicated to the server entity
+        stepper
+            .client_app
+            .world
+            .entity_mut(client_entity)
+            .insert(Component1(1.0));
+        // the client is ahead of the server, so the server waits until it reaches the tick of the client's messages
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        assert_eq!(
+            stepper.server_app.world.get::<Component1>(server_entity),
+            Some(&Component1(1.0))
+        );
+        // the client's entity was not spawned again on the server
+        assert_eq!(
+            stepper
+                .server_app
+                .world
+                .query::<&Component1>()
+                .iter(&stepper.server_app.world)
+                .count(),
+            1
+        );
+
+        // the server changes the entity, which is not sent to the client that has authority
+        stepper
+            .server_app
+            .world
+            .entity_mut(server_entity)
+            .insert(Component1(3.0));
+        stepper.frame_step();
+        stepper.frame_step();
+        assert_eq!(
+            stepper.client_app.world.get::<Component1>(client_entity),
+            Some(&Component1(1.0))
+        );
+
+        // the server takes back the authority: the client receives the server's state
+        TransferAuthorityCommand::<MyProtocol> {
+            entity: server_entity,
+            peer: AuthorityPeer::Server,
+            _marker: PhantomData,
+        }
+        .apply(&mut stepper.server_app.world);
+        stepper.frame_step();
+        stepper.frame_step();
+        assert!(stepper
+            .client_app
+            .world
+            .get::<HasAuthority>(client_entity)
+            .is_none());
+        assert_eq!(
+            stepper.client_app.world.get::<Component1>(client_entity),
+            Some(&Component1(3.0))
+        );
+
+        // the server's changes are replicated to the client again
+        stepper
+            .server_app
+            .world
+            .entity_mut(server_entity)
+            .insert(Component1(2.0));
+        stepper.frame_step();
+        stepper.frame_step();
+        assert_eq!(
+            stepper.client_app.world.get::<Component1>(client_entity),
+            Some(&Component1(2.0))
+        );
+    }
+
+    /// The server takes back the authority over the entities of a client that disconnects
+    #[test]
+    fn test_revoke_authority_on_disconnect() {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default().speedup_factor(1.0),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            LinkConditionerConfig::default(),
+            frame_duration,
+        );
+        stepper.init();
+
+        let client_id = 111;
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((Component1(0.0), Replicate::default()))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        TransferAuthorityCommand::<MyProtocol> {
+            entity: server_entity,
+            peer: AuthorityPeer::Client(client_id),
+            _marker: PhantomData,
+        }
+        .apply(&mut stepper.server_app.world);
+        stepper
+            .server_app
+            .world
+            .resource_mut::<Events<crate::server::events::AuthorityChangeEvent>>()
+            .clear();
+
+        stepper
+            .server_app
+            .world
+            .resource_mut::<crate::prelude::server::ServerConnections>()
+            .kick(client_id, 0)
+            .unwrap();
+        let mut events = vec![];
+        for _ in 0..10 {
+            stepper.frame_step();
+            events.extend(
+                stepper
+                    .server_app
+                    .world
+                    .resource_mut::<Events<crate::server::events::AuthorityChangeEvent>>()
+                    .drain()
+                    .map(|event| (event.entity(), event.has_authority(), *event.context())),
+            );
+        }
+        assert_eq!(
+            stepper
+                .server_app
+                .world
+                .resource::<ServerConnectionManager>()
+                .authority(server_entity),
+            AuthorityPeer::Server
+        );
+        assert_eq!(events, vec![(server_entity, false, client_id)]);
+    }
+
+    /// Spawn a replicated entity on the server and give its authority to the client.
+    /// Returns the server entity and the client entity.
+    fn setup_authority() -> (BevyStepper, Entity, Entity) {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default().speedup_factor(1.0),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            LinkConditionerConfig::default(),
+            frame_duration,
+        );
+        stepper.init();
+
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((Component1(0.0), Replicate::default()))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_entity = *stepper
+            .client_app
+            .world
+            .resource::<ClientConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .unwrap();
+        TransferAuthorityCommand::<MyProtocol> {
+            entity: server_entity,
+            peer: AuthorityPeer::Client(111),
+            _marker: PhantomData,
+        }
+        .apply(&mut stepper.server_app.world);
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        assert!(stepper
+            .client_app
+            .world
+            .get::<HasAuthority>(client_entity)
+            .is_some());
+        (stepper, server_entity, client_entity)
+    }
+
+    /// Losing the authority stops the replication of the client entity, whatever its `OnReplicateRemoved` policy
+    #[test]
+    fn test_revoke_authority() {
+        let (mut stepper, server_entity, client_entity) = setup_authority();
+        stepper
+            .client_app
+            .world
+            .get_mut::<Replicate>(client_entity)
+            .unwrap()
+            .on_replicate_removed = OnReplicateRemoved::DespawnRemote;
+        stepper.frame_step();
+        let group_id = ReplicationGroupId(client_entity.to_bits());
+        let sent_actions = |stepper: &BevyStepper| {
+            stepper
+                .client_app
+                .world
+                .resource::<ClientConnectionManager>()
+                .replication_sender
+                .group_channels
+                .get(&group_id)
+                .unwrap()
+                .actions_next_send_message_id
+        };
+        let sent_actions_before_revoke = sent_actions(&stepper);
+
+        TransferAuthorityCommand::<MyProtocol> {
+            entity: server_entity,
+            peer: AuthorityPeer::Server,
+            _marker: PhantomData,
+        }
+        .apply(&mut stepper.server_app.world);
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        assert!(stepper
+            .client_app
+            .world
+            .get::<Replicate>(client_entity)
+            .is_none());
+        // the client did not send a despawn
+        assert_eq!(sent_actions(&stepper), sent_actions_before_revoke);
+        assert!(stepper.server_app.world.get_entity(server_entity).is_some());
+
+        // the server's changes are replicated to the client again
+        stepper
+            .server_app
+            .world
+            .entity_mut(server_entity)
+            .insert(Component1(2.0));
+        stepper.frame_step();
+        stepper.frame_step();
+        assert_eq!(
+            stepper.client_app.world.get::<Component1>(client_entity),
+            Some(&Component1(2.0))
+        );
+    }
+
+    /// A client that despawns an entity it has authority over doesn't despawn the server entity
+    #[test]
+    fn test_despawn_with_authority() {
+        let (mut stepper, server_entity, client_entity) = setup_authority();
+        stepper.client_app.world.despawn(client_entity);
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        assert!(stepper.server_app.world.get_entity(server_entity).is_some());
+        assert_eq!(
+            stepper
+                .server_app
+                .world
+                .resource::<ServerConnectionManager>()
+                .authority(server_entity),
+            AuthorityPeer::Client(111)
+        );
+    }
+
+    /// The server ignores the despawns of the entities that a client has authority over
+    #[test]
+    fn test_remote_despawn_with_authority() {
+        let (mut stepper, server_entity, client_entity) = setup_authority();
+        // a client that doesn't respect the protocol sends a despawn anyway
+        let group_id =
+            crate::shared::replication::components::ReplicationGroupId(client_entity.to_bits());
+        stepper
+            .client_app
+            .world
+            .resource_mut::<ClientConnectionManager>()
+            .replication_sender
+            .prepare_entity_despawn(client_entity, group_id);
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        assert!(stepper.server_app.world.get_entity(server_entity).is_some());
+    }
+}
@@ -36,8 +36,8 @@ pub struct Replicate<P: Protocol> {
     #[doc(hidden)]
     pub replication_clients_cache: HashMap<ClientId, ClientVisibility>,
     pub replication_mode: ReplicationMode,
-    // TODO: currently, if the host removes Replicate, then the entity is not removed in the remote
-    //  it just keeps living but doesn't receive any updates. Should we make this configurable?
+    /// What happens to the remote entity if the `Replicate` component is removed (without despawning the entity)
+    pub on_replicate_removed: OnReplicateRemoved,
     pub replication_group: ReplicationGroup,
     /// If true, recursively add `Replicate` and `ParentSync` components to all children to make sure they are replicated
     /// If false, you can still replicate hierarchies, but in a more fine-grained manner. You will have to add the `Replicate`
@@ -48,6 +48,16 @@ pub struct Replicate<P: Protocol> {
     pub per_component_metadata: HashMap<P::ComponentKinds, PerComponentReplicationMetadata>,
 }
 
+/// What we need to know about the [`Replicate`] component of an entity to replicate its despawn
+/// (or the removal of the [`Replicate`] component), after the component is gone
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplicateCache {
+    /// Clients that currently have a copy of the entity
+    pub(crate) target: NetworkTarget,
+    pub(crate) group_id: ReplicationGroupId,
+    pub(crate) on_replicate_removed: OnReplicateRemoved,
+}
+
 /// This lets you specify how to customize the replication behaviour for a given component
 #[derive(Clone, Debug, PartialEq)]
 pub struct PerComponentReplicationMetadata {
@@ -77,6 +87,33 @@ impl<P: Protocol> Replicate<P> {
         self.replication_group.group_id(entity)
     }
 
+    /// Clients that currently have a copy of the entity, and that need to be notified if the
+    /// entity is despawned (or stops being replicated)
+    pub(crate) fn despawn_target(&self) -> NetworkTarget {
+        match self.replication_mode {
+            ReplicationMode::Room | ReplicationMode::Manual => NetworkTarget::Only(
+                self.replication_clients_cache
+                    .iter()
+                    .filter(|(client_id, visibility)| {
+                        self.replication_target.should_send_to(client_id)
+                            && !matches!(visibility, ClientVisibility::Lost)
+                    })
+                    .map(|(client_id, _)| *client_id)
+                    .collect(),
+            ),
+            ReplicationMode::NetworkTarget => self.replication_target.clone(),
+        }
+    }
+
+    /// The parts of the component that are still needed once the component is removed or the entity is despawned
+    pub(crate) fn replicate_cache(&self, entity: Entity) -> ReplicateCache {
+        ReplicateCache {
+            target: self.despawn_target(),
+            group_id: self.group_id(Some(entity)),
+            on_replicate_removed: self.on_replicate_removed,
+        }
+    }
+
     /// Returns true if we don't want to replicate the component
     pub fn is_disabled<C>(&self) -> bool
     where
@@ -266,11 +303,32 @@ pub struct ReplicationGroupId(pub u64);
 pub enum ReplicationMode {
     /// We will replicate this entity only to clients that are in the same room as the entity
     Room,
+    /// We will replicate this entity only to clients that were explicitly given visibility of the entity
+    /// via the [`VisibilityManager`](crate::server::visibility::VisibilityManager)
+    Manual,
     /// We will replicate this entity to clients using only the [`NetworkTarget`], without caring about rooms
     #[default]
     NetworkTarget,
 }
 
+/// Policy that decides what happens to the entity on the remote when the `Replicate` component is removed
+/// from an entity that is not despawned.
+///
+/// (If you want to stop replicating an entity without notifying the remote at all, you can also
+/// use the `remove_replicate` command)
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub enum OnReplicateRemoved {
+    /// Despawn the entity on the remote
+    DespawnRemote,
+    /// The entity keeps living on the remote, but won't receive any more updates
+    /// (the remote is not notified)
+    #[default]
+    KeepRemote,
+    /// The entity keeps living on the remote, but is not considered to be replicated anymore:
+    /// the remote stops tracking it and an `EntityDetachEvent` is emitted
+    DetachRemote,
+}
+
 impl<P: Protocol> Default for Replicate<P> {
     fn default() -> Self {
         #[allow(unused_mut)]
@@ -280,6 +338,7 @@ impl<P: Protocol> Default for Replicate<P> {
             interpolation_target: NetworkTarget::None,
             replication_clients_cache: HashMap::new(),
             replication_mode: ReplicationMode::default(),
+            on_replicate_removed: OnReplicateRemoved::default(),
             replication_group: Default::default(),
             replicate_hierarchy: true,
             per_component_metadata: HashMap::default(),
@@ -0,0 +1,191 @@
+//! Delta-compression for component updates
+//!
+//! By default, every component update sends the full serialized value of the component.
+//! Components that implement [`Diffable`] and are marked with `#[replication(delta)]` in the
+//! [`ComponentProtocol`](crate::protocol::component::ComponentProtocol) are instead sent as a delta
+//! against the last value that the remote has acknowledged receiving.
+//!
+//! ```rust,ignore
+//! #[component_protocol(protocol = "MyProtocol")]
+//! pub enum MyComponentsProtocol {
+//!     #[replication(delta)]
+//!     Inventory(Inventory),
+//! }
+//! ```
+//!
+//! The sender keeps track (for each entity and component) of the last value that was included in an update
+//! message that got acked. The receiver keeps a short history of the values it received for each entity and component,
+//! so that it can reconstruct the full value from the delta.
+//!
+//! If the receiver can't reconstruct a delta (for example because it never received its base value), it sends
+//! a [`DeltaReset`] back: the sender forgets its acked base value and sends the full value of the component again.
+use anyhow::Context;
+use bevy::prelude::Entity;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::shared::replication::components::ReplicationGroupId;
+use crate::shared::tick_manager::Tick;
+
+/// Maximum number of ticks between an update and the base value of its delta.
+///
+/// The sender sends the full value of a component if its acked base value is older than this,
+/// so the receiver only needs to keep the values received during this window.
+pub(crate) const DELTA_HISTORY_TICKS: u16 = 256;
+
+/// Trait for components that can be replicated by sending the difference between two values
+/// instead of the full value
+pub trait Diffable: Clone {
+    /// The type of the difference between two values of the component
+    type Delta: Serialize + DeserializeOwned;
+
+    /// Compute the delta that needs to be applied to `self` to get `new`
+    fn diff(&self, new: &Self) -> Self::Delta;
+
+    /// Apply a delta to `self`
+    fn apply_diff(&mut self, delta: &Self::Delta);
+}
+
+/// A delta-compressed component update
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ComponentDelta<K> {
+    pub(crate) kind: K,
+    /// Tick of the update (acked by the receiver) that contained the base value of the delta
+    pub(crate) base_tick: Tick,
+    /// The serialized [`Diffable::Delta`]
+    pub(crate) delta: Vec<u8>,
+}
+
+/// Sent back by the receiver for the delta-compressed components that it could not reconstruct.
+///
+/// The update packet was still acked, so the sender would otherwise use a value that the receiver
+/// never stored as the base of its next deltas.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct DeltaReset<K> {
+    /// Replication group of the update that could not be reconstructed
+    pub(crate) group_id: ReplicationGroupId,
+    /// Entities (of the sender) and kinds of the components that could not be reconstructed
+    pub(crate) components: Vec<(Entity, K)>,
+}
+
+#[doc(hidden)]
+/// Compute the serialized delta between two values of a component
+/// (used in the `#[component_protocol]` macro)
+pub fn serialize_diff<C: Diffable>(base: &C, new: &C) -> anyhow::Result<Vec<u8>> {
+    bitcode::serialize(&base.diff(new)).context("could not serialize component delta")
+}
+
+#[doc(hidden)]
+/// Apply a serialized delta to a component
+/// (used in the `#[component_protocol]` macro)
+pub fn apply_serialized_diff<C: Diffable>(base: &mut C, delta: &[u8]) -> anyhow::Result<()> {
+    let delta =
+        bitcode::deserialize::<C::Delta>(delta).context("could not deserialize component delta")?;
+    base.apply_diff(&delta);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::Duration;
+
+    use crate::prelude::client::*;
+    use crate::prelude::*;
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    #[test]
+    fn test_serialized_diff() -> anyhow::Result<()> {
+        let base = Component5(vec![1, 2, 3]);
+        let new = Component5(vec![1, 2, 3, 4, 5]);
+        let delta = serialize_diff(&base, &new)?;
+        let mut reconstructed = base.clone();
+        apply_serialized_diff(&mut reconstructed, &delta)?;
+        assert_eq!(reconstructed, new);
+        Ok(())
+    }
+
+    /// Updates for a delta-compressed component are sent as deltas against the last acked value,
+    /// and the client reconstructs the full value
+    #[test]
+    fn test_delta_compression() {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(0),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.0,
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default().speedup_factor(1.0),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            link_conditioner,
+            frame_duration,
+        );
+        stepper.init();
+
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((Component5(vec![0]), Replicate::default()))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_entity = *stepper
+            .client_app
+            .world
+            .resource::<ClientConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .unwrap();
+
+        for i in 1..10 {
+            stepper
+                .server_app
+                .world
+                .entity_mut(server_entity)
+                .get_mut::<Component5>()
+                .unwrap()
+                .0
+                .push(i);
+            stepper.frame_step();
+            stepper.frame_step();
+            assert_eq!(
+                stepper
+                    .client_app
+                    .world
+                    .entity(client_entity)
+                    .get::<Component5>()
+                    .unwrap(),
+                &Component5((0..=i).collect())
+            );
+        }
+
+        // the server has an acked base value for the component, so updates were sent as deltas
+        let server_connection_manager = stepper
+            .server_app
+            .world
+            .resource::<ServerConnectionManager>();
+        let replication_sender = &server_connection_manager
+            .connections
+            .values()
+            .next()
+            .unwrap()
+            .replication_sender;
+        assert!(replication_sender
+            .delta_acked_states
+            .get(&server_entity)
+            .map_or(false, |states| states
+                .contains_key(&MyComponentsProtocolKind::Component5)));
+    }
+}
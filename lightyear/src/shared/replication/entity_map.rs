@@ -109,6 +109,14 @@ impl RemoteEntityMap {
         }
     }
 
+    pub(super) fn remove_by_local(&mut self, local_entity: Entity) -> Option<Entity> {
+        let remote_entity = self.local_to_remote.remove(&local_entity);
+        if let Some(remote_entity) = remote_entity {
+            self.remote_to_local.remove(&remote_entity);
+        }
+        remote_entity
+    }
+
     pub(super) fn remove_by_remote(&mut self, remote_entity: Entity) -> Option<Entity> {
         let local_entity = self.remote_to_local.remove(&remote_entity);
         if let Some(local_entity) = local_entity {
@@ -131,12 +139,41 @@ impl RemoteEntityMap {
         self.remote_to_local.is_empty() && self.local_to_remote.is_empty()
     }
 
+    /// Map the remote entities referenced inside the value to the corresponding local entities.
+    ///
+    /// Returns `None` if some of the referenced remote entities don't exist locally (yet), instead of
+    /// keeping references to entities that are invalid in the local world.
+    pub(crate) fn try_map_entities<T: LightyearMapEntities + Clone>(&self, value: &T) -> Option<T> {
+        let mut mapper = ResolvingEntityMapper {
+            map: self,
+            unresolved: false,
+        };
+        let mut mapped = value.clone();
+        mapped.map_entities(&mut mapper);
+        (!mapper.unresolved).then_some(mapped)
+    }
+
     fn clear(&mut self) {
         self.local_to_remote.clear();
         self.remote_to_local.clear();
     }
 }
 
+/// Entity mapper that keeps track of whether some of the remote entities could not be mapped
+struct ResolvingEntityMapper<'a> {
+    map: &'a RemoteEntityMap,
+    unresolved: bool,
+}
+
+impl EntityMapper for ResolvingEntityMapper<'_> {
+    fn map_entity(&mut self, entity: Entity) -> Entity {
+        self.map.get_local(entity).copied().unwrap_or_else(|| {
+            self.unresolved = true;
+            entity
+        })
+    }
+}
+
 impl EntityMapper for RemoteEntityMap {
     fn map_entity(&mut self, entity: Entity) -> Entity {
         self.get_local(entity).copied().unwrap_or(entity)
@@ -167,6 +204,7 @@ mod tests {
             incoming_latency: Duration::from_millis(0),
             incoming_jitter: Duration::from_millis(0),
             incoming_loss: 0.0,
+            ..Default::default()
         };
         let sync_config = SyncConfig::default().speedup_factor(1.0);
         let prediction_config = PredictionConfig::default().disable(false);
@@ -240,4 +278,75 @@ mod tests {
         );
         Ok(())
     }
+
+    // A component references an entity that is not replicated yet:
+    // the component is only inserted on the client once the referenced entity has been replicated
+    #[test]
+    fn test_deferred_entity_mapping() {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(0),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.0,
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default().speedup_factor(1.0),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            link_conditioner,
+            frame_duration,
+        );
+        stepper.init();
+
+        let server_target = stepper.server_app.world.spawn(Component1(0.0)).id();
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((Component4(server_target), Replicate::default()))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_entity = *stepper
+            .client_app
+            .world
+            .resource::<ClientConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .unwrap();
+        // the reference cannot be mapped yet
+        assert!(stepper
+            .client_app
+            .world
+            .get::<Component4>(client_entity)
+            .is_none());
+
+        // the target starts being replicated (in a different replication group)
+        stepper
+            .server_app
+            .world
+            .entity_mut(server_target)
+            .insert(Replicate::default());
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_target = *stepper
+            .client_app
+            .world
+            .resource::<ClientConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_target)
+            .unwrap();
+        assert_eq!(
+            stepper.client_app.world.get::<Component4>(client_entity),
+            Some(&Component4(client_target))
+        );
+    }
 }
@@ -15,16 +15,20 @@ use crate::connection::netcode::ClientId;
 use crate::packet::message::MessageId;
 use crate::prelude::{NetworkTarget, Tick};
 use crate::protocol::Protocol;
-use crate::shared::replication::components::{Replicate, ReplicationGroupId};
+use crate::shared::replication::components::{Replicate, ReplicateCache, ReplicationGroupId};
+use crate::shared::replication::delta::ComponentDelta;
 
+pub mod authority;
 pub mod components;
 
 mod commands;
+pub mod delta;
 pub mod entity_map;
 pub(crate) mod hierarchy;
 pub mod metadata;
 pub(crate) mod plugin;
 pub(crate) mod receive;
+pub mod resources;
 pub(crate) mod send;
 pub mod systems;
 
@@ -50,6 +54,13 @@ pub mod systems;
 pub struct EntityActions<C, K: Hash + Eq> {
     pub(crate) spawn: bool,
     pub(crate) despawn: bool,
+    /// The entity is not replicated anymore, but should not be despawned
+    pub(crate) detach: bool,
+    /// The sender gives (true) or takes back (false) the authority over the entity to the receiver
+    pub(crate) authority: Option<bool>,
+    /// Set on the spawn of an entity that the sender was given authority over: the entity already exists
+    /// on the receiver (this is the receiver's local entity), so it should not be spawned again
+    pub(crate) authority_entity: Option<Entity>,
     // Cannot use HashSet because we would need ComponentProtocol to implement Hash + Eq
     pub(crate) insert: Vec<C>,
     pub(crate) remove: HashSet<K>,
@@ -62,6 +73,9 @@ impl<C, K: Hash + Eq> Default for EntityActions<C, K> {
         Self {
             spawn: false,
             despawn: false,
+            detach: false,
+            authority: None,
+            authority_entity: None,
             insert: Vec::new(),
             remove: HashSet::new(),
             updates: Vec::new(),
@@ -74,17 +88,24 @@ impl<C, K: Hash + Eq> Default for EntityActions<C, K> {
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct EntityActionMessage<C, K: Hash + Eq> {
     sequence_id: MessageId,
+    /// The sender stopped replaying the actions that were sent before this message, because the receiver fell too far behind.
+    /// The message contains the full state of the group: the receiver should discard the previous actions,
+    /// and despawn the entities of the group that are not part of this message.
+    pub(crate) resync: bool,
     // we use vec but the order of entities should not matter
     pub(crate) actions: Vec<(Entity, EntityActions<C, K>)>,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
-pub struct EntityUpdatesMessage<C> {
+pub struct EntityUpdatesMessage<C, K> {
     /// The last tick for which we sent an EntityActionsMessage for this group
     /// We set this to None after a certain amount of time without any new Actions, to signify on the receiver side
     /// that there is no ordering constraint with respect to Actions for this group (i.e. the Update can be applied immediately)
     last_action_tick: Option<Tick>,
     pub(crate) updates: Vec<(Entity, Vec<C>)>,
+    /// Updates for delta-compressed components. They are converted back into full component updates
+    /// as soon as they are received
+    pub(crate) deltas: Vec<(Entity, Vec<ComponentDelta<K>>)>,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
@@ -92,7 +113,7 @@ pub enum ReplicationMessageData<C, K: Hash + Eq> {
     /// All the entity actions (Spawn/despawn/inserts/removals) for a given group
     Actions(EntityActionMessage<C, K>),
     /// All the entity updates for a given group
-    Updates(EntityUpdatesMessage<C>),
+    Updates(EntityUpdatesMessage<C, K>),
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
@@ -133,7 +154,16 @@ pub trait ReplicationSend<P: Protocol>: Resource {
     fn prepare_entity_despawn(
         &mut self,
         entity: Entity,
-        replicate: &Replicate<P>,
+        group_id: ReplicationGroupId,
+        target: NetworkTarget,
+        system_current_tick: BevyTick,
+    ) -> Result<()>;
+
+    /// Stop replicating the entity, without despawning it on the remote
+    fn prepare_entity_detach(
+        &mut self,
+        entity: Entity,
+        group_id: ReplicationGroupId,
         target: NetworkTarget,
         system_current_tick: BevyTick,
     ) -> Result<()>;
@@ -180,7 +210,7 @@ pub trait ReplicationSend<P: Protocol>: Resource {
     /// But the receiving systems might expect both components to be present at the same time.
     fn buffer_replication_messages(&mut self, tick: Tick, bevy_tick: BevyTick) -> Result<()>;
 
-    fn get_mut_replicate_component_cache(&mut self) -> &mut EntityHashMap<Replicate<P>>;
+    fn get_mut_replicate_component_cache(&mut self) -> &mut EntityHashMap<ReplicateCache>;
 
     /// Do some regular cleanup on the internals of replication
     /// - account for tick wrapping by resetting some internal ticks for each replication group
@@ -211,6 +241,7 @@ mod tests {
             incoming_latency: Duration::from_millis(0),
             incoming_jitter: Duration::from_millis(0),
             incoming_loss: 0.0,
+            ..Default::default()
         };
         let sync_config = SyncConfig::default().speedup_factor(1.0);
         let prediction_config = PredictionConfig::default().disable(false);
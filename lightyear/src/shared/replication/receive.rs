@@ -6,18 +6,21 @@ use anyhow::Context;
 use bevy::ecs::entity::EntityHash;
 use bevy::prelude::{DespawnRecursiveExt, Entity, World};
 use bevy::reflect::Reflect;
-use bevy::utils::HashSet;
+use bevy::utils::{HashMap, HashSet};
 use tracing::{debug, error, trace, trace_span, warn};
 
 use crate::packet::message::MessageId;
 use crate::prelude::client::Confirmed;
-use crate::prelude::{LightyearMapEntities, Tick};
+use crate::prelude::{LightyearMapEntities, PreSpawnedPlayerObject, ShouldBePredicted, Tick};
 use crate::protocol::component::ComponentProtocol;
-use crate::protocol::component::{ComponentBehaviour, ComponentKindBehaviour};
+use crate::protocol::component::{ComponentBehaviour, ComponentKindBehaviour, FromType};
 use crate::protocol::Protocol;
 use crate::shared::events::connection::ConnectionEvents;
-use crate::shared::replication::components::ReplicationGroupId;
+use crate::shared::replication::authority::HasAuthority;
+use crate::shared::replication::components::ShouldBeInterpolated;
+use crate::shared::replication::components::{Replicate, ReplicationGroupId};
 
+use super::delta::{ComponentDelta, DeltaReset, DELTA_HISTORY_TICKS};
 use super::entity_map::RemoteEntityMap;
 use super::{
     EntityActionMessage, EntityUpdatesMessage, ReplicationMessage, ReplicationMessageData,
@@ -27,6 +30,9 @@ type EntityHashMap<K, V> = hashbrown::HashMap<K, V, EntityHash>;
 
 type EntityHashSet<K> = hashbrown::HashSet<K, EntityHash>;
 
+/// Number of ticks after which a deferred component is dropped if its referenced entities still don't exist
+pub(crate) const DEFERRED_COMPONENT_TIMEOUT_TICKS: u16 = 256;
+
 pub(crate) struct ReplicationReceiver<P: Protocol> {
     /// Map between local and remote entities. (used mostly on client because it's when we receive entity updates)
     pub remote_entity_map: RemoteEntityMap,
@@ -34,6 +40,26 @@ pub(crate) struct ReplicationReceiver<P: Protocol> {
     /// Map from remote entity to the replication group-id
     pub remote_entity_to_group: EntityHashMap<Entity, ReplicationGroupId>,
 
+    /// For each remote entity, the values of the delta-compressed components that we received, by remote tick.
+    /// Used to reconstruct the full value of the component from a delta.
+    pub delta_history:
+        EntityHashMap<Entity, HashMap<P::ComponentKinds, BTreeMap<Tick, P::Components>>>,
+    /// Delta-compressed components that we could not reconstruct, by replication group.
+    /// The sender must stop using its acked value as a base and send the full value again.
+    pub pending_delta_resets: EntityHashMap<ReplicationGroupId, Vec<(Entity, P::ComponentKinds)>>,
+
+    /// Components that reference remote entities that don't exist locally yet, by remote entity,
+    /// with the remote tick at which they were received.
+    /// They are applied once all the entities that they reference have been spawned, or dropped after
+    /// [`DEFERRED_COMPONENT_TIMEOUT_TICKS`].
+    pub deferred_components: EntityHashMap<Entity, Vec<(Tick, P::Components)>>,
+
+    /// Local entities that the remote was given authority over.
+    /// The remote is allowed to replicate its own version of these entities to us.
+    pub authority_granted: EntityHashSet<Entity>,
+    /// Local entities that we have authority over, with the corresponding remote entity
+    pub authority_entities: EntityHashMap<Entity, Entity>,
+
     // BOTH
     /// Buffer to so that we have an ordered receiver per group
     pub group_channels: EntityHashMap<ReplicationGroupId, GroupChannel<P>>,
@@ -45,6 +71,11 @@ impl<P: Protocol> ReplicationReceiver<P> {
             // RECEIVE
             remote_entity_map: RemoteEntityMap::default(),
             remote_entity_to_group: Default::default(),
+            delta_history: Default::default(),
+            pending_delta_resets: Default::default(),
+            deferred_components: Default::default(),
+            authority_granted: Default::default(),
+            authority_entities: Default::default(),
             // BOTH
             group_channels: Default::default(),
         }
@@ -57,6 +88,12 @@ impl<P: Protocol> ReplicationReceiver<P> {
         remote_tick: Tick,
     ) {
         trace!(?message, ?remote_tick, "Received replication message");
+        let mut message = message;
+        if let ReplicationMessageData::Updates(ref mut m) = message.data {
+            // NOTE: we do this even for updates that are too old to be applied, because the sender
+            //  could use them as the base value for future deltas as soon as they are acked
+            self.reconstruct_deltas(message.group_id, m, remote_tick);
+        }
         let channel = self.group_channels.entry(message.group_id).or_default();
         match message.data {
             ReplicationMessageData::Actions(m) => {
@@ -65,6 +102,15 @@ impl<P: Protocol> ReplicationReceiver<P> {
                     trace!(message_id= ?m.sequence_id, pending_message_id = ?channel.actions_pending_recv_message_id, "message is too old, ignored");
                     return;
                 }
+                // the remote stopped replaying the actions that were sent before this message:
+                // we won't receive them anymore, and don't need the ones we already buffered
+                if m.resync && m.sequence_id > channel.actions_pending_recv_message_id {
+                    debug!(group_id = ?message.group_id, message_id = ?m.sequence_id, "Received resync message: discarding previous actions");
+                    channel
+                        .actions_recv_message_buffer
+                        .retain(|message_id, _| *message_id >= m.sequence_id);
+                    channel.actions_pending_recv_message_id = m.sequence_id;
+                }
                 // update the list of entities in the group
                 m.actions
                     .iter()
@@ -111,6 +157,98 @@ impl<P: Protocol> ReplicationReceiver<P> {
         trace!(?channel, "group channel after buffering");
     }
 
+    /// Record the values of the delta-compressed components contained in an update message,
+    /// and convert the deltas back into full component values.
+    ///
+    /// A delta that can't be reconstructed is dropped and not recorded: a [`DeltaReset`] is sent back
+    /// so that the sender doesn't use it as the base of its next deltas.
+    fn reconstruct_deltas(
+        &mut self,
+        group_id: ReplicationGroupId,
+        message: &mut EntityUpdatesMessage<P::Components, P::ComponentKinds>,
+        remote_tick: Tick,
+    ) {
+        for (entity, components) in message.updates.iter() {
+            for component in components.iter().filter(|c| c.is_diffable()) {
+                let history = self
+                    .delta_history
+                    .entry(*entity)
+                    .or_default()
+                    .entry(component.into())
+                    .or_default();
+                history.insert(remote_tick, component.clone());
+                Self::prune_delta_history(history, remote_tick);
+            }
+        }
+        for (entity, deltas) in std::mem::take(&mut message.deltas) {
+            let mut reconstructed = Vec::with_capacity(deltas.len());
+            for delta in deltas {
+                // NOTE: we keep all the values received during the last DELTA_HISTORY_TICKS, not only the ones
+                //  newer than this base value, because an older update that was delayed could still use them
+                let Some(component) = self.reconstruct_delta(entity, &delta, remote_tick) else {
+                    let resets = self.pending_delta_resets.entry(group_id).or_default();
+                    if !resets.contains(&(entity, delta.kind)) {
+                        resets.push((entity, delta.kind));
+                    }
+                    continue;
+                };
+                reconstructed.push(component);
+            }
+            if reconstructed.is_empty() {
+                continue;
+            }
+            match message.updates.iter_mut().find(|(e, _)| *e == entity) {
+                Some((_, components)) => components.extend(reconstructed),
+                None => message.updates.push((entity, reconstructed)),
+            }
+        }
+    }
+
+    /// Apply a delta to its base value, and record the full value
+    fn reconstruct_delta(
+        &mut self,
+        entity: Entity,
+        delta: &ComponentDelta<P::ComponentKinds>,
+        remote_tick: Tick,
+    ) -> Option<P::Components> {
+        let Some(history) = self
+            .delta_history
+            .get_mut(&entity)
+            .and_then(|h| h.get_mut(&delta.kind))
+        else {
+            error!(remote_entity = ?entity, kind = ?delta.kind, "Received a delta for a component without any base value");
+            return None;
+        };
+        let Some(mut component) = history.get(&delta.base_tick).cloned() else {
+            error!(remote_entity = ?entity, kind = ?delta.kind, base_tick = ?delta.base_tick, "Could not find the base value for a delta");
+            return None;
+        };
+        if let Err(e) = component.apply_diff(&delta.delta) {
+            error!(remote_entity = ?entity, kind = ?delta.kind, "Could not apply delta: {:?}", e);
+            return None;
+        }
+        history.insert(remote_tick, component.clone());
+        Self::prune_delta_history(history, remote_tick);
+        Some(component)
+    }
+
+    /// Take the resets to send for the delta-compressed components that could not be reconstructed
+    pub(crate) fn take_delta_resets(&mut self) -> Vec<DeltaReset<P::ComponentKinds>> {
+        self.pending_delta_resets
+            .drain()
+            .map(|(group_id, components)| DeltaReset {
+                group_id,
+                components,
+            })
+            .collect()
+    }
+
+    /// The sender never computes a delta against a value that is more than [`DELTA_HISTORY_TICKS`] older
+    /// than the update, so we can drop the values that are older than that
+    fn prune_delta_history(history: &mut BTreeMap<Tick, P::Components>, remote_tick: Tick) {
+        history.retain(|tick, _| remote_tick - *tick <= DELTA_HISTORY_TICKS as i16);
+    }
+
     /// Return the list of replication messages that are ready to be applied to the World
     /// Also include the server_tick when that replication message was emitted
     ///
@@ -187,6 +325,278 @@ impl<P: Protocol> ReplicationReceiver<P> {
     //  use a OnceCell that gets set with the channel name mapping when the protocol is finalized?
     //  the other option is to have wrappers in Connection, but that's pretty ugly
 
+    /// The remote allows us to replicate our version of the `local_entity`
+    pub(crate) fn grant_authority(&mut self, local_entity: Entity) {
+        self.authority_granted.insert(local_entity);
+    }
+
+    /// The remote cannot replicate the `local_entity` anymore
+    pub(crate) fn revoke_authority(&mut self, local_entity: Entity) {
+        self.authority_granted.remove(&local_entity);
+        if let Some(remote_entity) = self.remote_entity_map.remove_by_local(local_entity) {
+            if let Some(group_id) = self.remote_entity_to_group.remove(&remote_entity) {
+                if let Some(group) = self.group_channels.get_mut(&group_id) {
+                    group.remote_entities.remove(&remote_entity);
+                }
+            }
+        }
+    }
+
+    /// The remote gave us (or took back) the authority over a replicated entity.
+    ///
+    /// The entity that we simulate is the `Predicted` entity if there is one, otherwise the `Confirmed` entity.
+    /// We start (or stop) replicating it to the remote.
+    ///
+    /// `resync` is true if the change was replayed in a resync message, in which case we might have applied it already.
+    fn apply_authority_change(
+        &mut self,
+        world: &mut World,
+        remote_entity: Entity,
+        has_authority: bool,
+        resync: bool,
+        events: &mut ConnectionEvents<P>,
+    ) {
+        if has_authority {
+            let Some(confirmed) = self.remote_entity_map.get_local(remote_entity).copied() else {
+                error!(
+                    ?remote_entity,
+                    "Received authority for an entity that does not exist"
+                );
+                return;
+            };
+            let local_entity = world
+                .get::<Confirmed>(confirmed)
+                .and_then(|confirmed| confirmed.predicted)
+                .unwrap_or(confirmed);
+            if self.authority_entities.get(&local_entity) == Some(&remote_entity) {
+                debug!(?remote_entity, ?local_entity, "Already have authority");
+                return;
+            }
+            let Some(mut entity_mut) = world.get_entity_mut(local_entity) else {
+                error!(
+                    ?local_entity,
+                    "Received authority for an entity that does not exist"
+                );
+                return;
+            };
+            debug!(?remote_entity, ?local_entity, "Received authority");
+            entity_mut.insert((HasAuthority, Replicate::<P>::default()));
+            self.authority_entities.insert(local_entity, remote_entity);
+            events.push_authority_change(local_entity, true);
+        } else {
+            let Some(local_entity) = self
+                .authority_entities
+                .iter()
+                .find_map(|(local, remote)| (*remote == remote_entity).then_some(*local))
+            else {
+                if resync {
+                    debug!(?remote_entity, "Already lost authority");
+                } else {
+                    error!(
+                        ?remote_entity,
+                        "Lost authority for an entity that we did not have authority over"
+                    );
+                }
+                return;
+            };
+            debug!(?remote_entity, ?local_entity, "Lost authority");
+            self.authority_entities.remove(&local_entity);
+            if let Some(mut entity_mut) = world.get_entity_mut(local_entity) {
+                entity_mut.remove::<(HasAuthority, Replicate<P>)>();
+            }
+            events.push_authority_change(local_entity, false);
+        }
+    }
+
+    /// Map the remote entities referenced inside the component to local entities.
+    ///
+    /// If some of the referenced entities haven't been spawned locally yet (for example because they
+    /// are part of a different replication group), the component is deferred until they are.
+    fn map_or_defer(
+        &mut self,
+        remote_entity: Entity,
+        component: P::Components,
+        tick: Tick,
+    ) -> Option<P::Components> {
+        // the new value of the component replaces any deferred value
+        self.remove_deferred(remote_entity, (&component).into());
+        let mapped = self.remote_entity_map.try_map_entities(&component);
+        if mapped.is_none() {
+            debug!(
+                ?remote_entity,
+                ?component,
+                "Deferring component that references entities that don't exist yet"
+            );
+            self.deferred_components
+                .entry(remote_entity)
+                .or_default()
+                .push((tick, component));
+        }
+        mapped
+    }
+
+    /// Remove the deferred value of the component, if there is one
+    fn remove_deferred(&mut self, remote_entity: Entity, kind: P::ComponentKinds) {
+        if let Some(deferred) = self.deferred_components.get_mut(&remote_entity) {
+            deferred.retain(|(_, c)| P::ComponentKinds::from(c) != kind);
+            if deferred.is_empty() {
+                self.deferred_components.remove(&remote_entity);
+            }
+        }
+    }
+
+    /// Apply the deferred components whose referenced entities now all exist locally,
+    /// and drop the ones that have been waiting for more than [`DEFERRED_COMPONENT_TIMEOUT_TICKS`]
+    fn apply_deferred_components(
+        &mut self,
+        world: &mut World,
+        tick: Tick,
+        events: &mut ConnectionEvents<P>,
+    ) {
+        if self.deferred_components.is_empty() {
+            return;
+        }
+        for (remote_entity, components) in std::mem::take(&mut self.deferred_components) {
+            let mut components = components
+                .into_iter()
+                .filter(|(deferred_tick, component)| {
+                    let expired = tick - *deferred_tick > DEFERRED_COMPONENT_TIMEOUT_TICKS as i16;
+                    if expired {
+                        warn!(
+                            ?remote_entity,
+                            kind = ?P::ComponentKinds::from(component),
+                            "Dropping deferred component: the entities it references were never received"
+                        );
+                    }
+                    !expired
+                })
+                .peekable();
+            if components.peek().is_none() {
+                continue;
+            }
+            let Some(local_entity) = self.remote_entity_map.get_local(remote_entity).copied()
+            else {
+                continue;
+            };
+            let existing_kinds = Self::protocol_kinds(world, local_entity);
+            let Some(mut local_entity_mut) = world.get_entity_mut(local_entity) else {
+                continue;
+            };
+            for (deferred_tick, component) in components {
+                let Some(mapped) = self.remote_entity_map.try_map_entities(&component) else {
+                    self.deferred_components
+                        .entry(remote_entity)
+                        .or_default()
+                        .push((deferred_tick, component));
+                    continue;
+                };
+                let kind: P::ComponentKinds = (&mapped).into();
+                debug!(?remote_entity, ?kind, "Applying deferred component");
+                if existing_kinds.contains(&kind) {
+                    events.push_update_component(local_entity, kind, Tick(0));
+                } else {
+                    events.push_insert_component(local_entity, kind, Tick(0));
+                }
+                mapped.insert(&mut local_entity_mut);
+            }
+        }
+    }
+
+    /// Despawn the local entity corresponding to the remote entity.
+    /// Returns false if the entity does not exist
+    fn despawn_remote_entity(
+        &mut self,
+        world: &mut World,
+        remote_entity: Entity,
+        group_id: ReplicationGroupId,
+        events: &mut ConnectionEvents<P>,
+    ) -> bool {
+        let Some(local_entity) = self.remote_entity_map.remove_by_remote(remote_entity) else {
+            return false;
+        };
+        if let Some(group) = self.group_channels.get_mut(&group_id) {
+            group.remote_entities.remove(&remote_entity);
+        }
+        self.remote_entity_to_group.remove(&remote_entity);
+        self.delta_history.remove(&remote_entity);
+        self.deferred_components.remove(&remote_entity);
+        self.authority_entities
+            .retain(|_, entity| *entity != remote_entity);
+        // the remote was only replicating its version of one of our entities: it stops, but can't despawn our entity
+        if self.authority_granted.contains(&local_entity) {
+            warn!(
+                ?remote_entity,
+                ?local_entity,
+                "Received despawn for an entity the remote has authority over: keeping the local entity"
+            );
+            return true;
+        }
+        // TODO: we despawn all children as well right now, but that might not be what we want?
+        if let Some(entity_mut) = world.get_entity_mut(local_entity) {
+            entity_mut.despawn_recursive();
+        }
+        events.push_despawn(local_entity);
+        true
+    }
+
+    /// The remote sent the full state of the group, because we fell too far behind.
+    ///
+    /// Despawn the entities of the group that are not part of the message anymore
+    /// (the entities that we have authority over are not replicated to us, so we keep them)
+    fn apply_resync(
+        &mut self,
+        world: &mut World,
+        group_id: ReplicationGroupId,
+        message: &EntityActionMessage<P::Components, P::ComponentKinds>,
+        events: &mut ConnectionEvents<P>,
+    ) {
+        let stale_entities = self
+            .remote_entity_to_group
+            .iter()
+            .filter(|(remote_entity, group)| {
+                **group == group_id
+                    && !message.actions.iter().any(|(e, _)| e == *remote_entity)
+                    && !self
+                        .authority_entities
+                        .values()
+                        .any(|e| e == *remote_entity)
+            })
+            .map(|(remote_entity, _)| *remote_entity)
+            .collect::<Vec<_>>();
+        for remote_entity in stale_entities {
+            debug!(
+                ?remote_entity,
+                "Despawning entity that is not part of the resync"
+            );
+            self.despawn_remote_entity(world, remote_entity, group_id, events);
+        }
+    }
+
+    /// Returns the kinds of the protocol components present on the local entity
+    fn protocol_kinds(world: &World, local_entity: Entity) -> HashSet<P::ComponentKinds> {
+        let Some(entity_ref) = world.get_entity(local_entity) else {
+            return HashSet::default();
+        };
+        P::Components::type_ids()
+            .into_iter()
+            .filter(|(type_id, _)| {
+                world
+                    .components()
+                    .get_id(*type_id)
+                    .map_or(false, |id| entity_ref.contains_id(id))
+            })
+            .map(|(_, kind)| kind)
+            .collect()
+    }
+
+    /// Metadata components that are only handled when the entity is spawned
+    /// (for example to spawn the Predicted entity)
+    fn is_spawn_metadata(kind: P::ComponentKinds) -> bool {
+        kind == <P::ComponentKinds as FromType<ShouldBePredicted>>::from_type()
+            || kind == <P::ComponentKinds as FromType<ShouldBeInterpolated>>::from_type()
+            || kind == <P::ComponentKinds as FromType<PreSpawnedPlayerObject>>::from_type()
+    }
+
     /// Apply any replication messages to the world, and emit an event
     /// I think we don't need to emit a tick with the event anymore, because
     /// we can access the tick via the replication manager
@@ -205,15 +615,37 @@ impl<P: Protocol> ReplicationReceiver<P> {
                 // NOTE: order matters here, because some components can depend on other entities.
                 // These components could even form a cycle, for example A.HasWeapon(B) and B.HasHolder(A)
                 // Our solution is to first handle spawn for all entities separately.
+
+                // resync: the message contains the full state of the group
+                let mut resynced_entities = EntityHashSet::default();
+                if m.resync {
+                    self.apply_resync(world, group_id, &m, events);
+                }
                 for (entity, actions) in m.actions.iter() {
                     debug!(remote_entity = ?entity, "Received entity actions");
                     assert!(!(actions.spawn && actions.despawn));
                     // spawn
                     if actions.spawn {
                         self.remote_entity_to_group.insert(*entity, group_id);
+                        // the remote is replicating an entity that we gave it authority over:
+                        // map it to our existing entity
+                        if let Some(local_entity) = actions.authority_entity {
+                            if self.authority_granted.contains(&local_entity) {
+                                debug!(remote_entity = ?entity, ?local_entity, "Received spawn for an entity the remote has authority over");
+                                self.remote_entity_map.insert(*entity, local_entity);
+                            } else {
+                                warn!(?local_entity, "Received spawn for an entity that the remote does not have authority over");
+                            }
+                            continue;
+                        }
                         if let Some(local_entity) = self.remote_entity_map.get_local(*entity) {
                             if world.get_entity(*local_entity).is_some() {
-                                warn!("Received spawn for an entity that already exists");
+                                if m.resync {
+                                    debug!(remote_entity = ?entity, "Received resync for an entity that already exists");
+                                    resynced_entities.insert(*entity);
+                                } else {
+                                    warn!("Received spawn for an entity that already exists");
+                                }
                                 continue;
                             }
                             warn!("Received spawn for an entity that is already in our entity mapping! Not spawning");
@@ -229,29 +661,69 @@ impl<P: Protocol> ReplicationReceiver<P> {
                     }
                 }
 
-                for (entity, actions) in m.actions.into_iter() {
+                for (entity, mut actions) in m.actions.into_iter() {
                     debug!(remote_entity = ?entity, "Received entity actions");
 
                     // despawn
                     if actions.despawn {
                         debug!(remote_entity = ?entity, "Received entity despawn");
+                        if !self.despawn_remote_entity(world, entity, group_id, events) {
+                            error!("Received despawn for an entity that does not exist")
+                        }
+                        continue;
+                    }
+
+                    // authority: the remote gives us (or takes back) the authority over the entity
+                    if let Some(has_authority) = actions.authority {
+                        self.apply_authority_change(world, entity, has_authority, m.resync, events);
+                    }
+
+                    // detach: the remote stopped replicating the entity, but we keep it alive
+                    if actions.detach {
+                        debug!(remote_entity = ?entity, "Received entity detach");
                         if let Some(local_entity) = self.remote_entity_map.remove_by_remote(entity)
                         {
                             if let Some(group) = self.group_channels.get_mut(&group_id) {
                                 group.remote_entities.remove(&entity);
                             }
-                            // TODO: we despawn all children as well right now, but that might not be what we want?
-                            if let Some(entity_mut) = world.get_entity_mut(local_entity) {
-                                entity_mut.despawn_recursive();
+                            // the entity is ours, it was not replicated from the remote
+                            if !self.authority_granted.contains(&local_entity) {
+                                events.push_detach(local_entity);
                             }
-                            events.push_despawn(local_entity);
                             self.remote_entity_to_group.remove(&entity);
+                            self.delta_history.remove(&entity);
+                        } else if m.resync {
+                            debug!(remote_entity = ?entity, "Received resync detach for an entity that was already detached");
                         } else {
-                            error!("Received despawn for an entity that does not exist")
+                            error!("Received detach for an entity that does not exist")
                         }
                         continue;
                     }
 
+                    // resync of an entity that already exists: the message contains all of its components
+                    let existing_kinds = if resynced_entities.contains(&entity) {
+                        self.remote_entity_map
+                            .get_local(entity)
+                            .map(|local_entity| Self::protocol_kinds(world, *local_entity))
+                    } else {
+                        None
+                    };
+                    if let Some(existing_kinds) = &existing_kinds {
+                        // the metadata components were already handled when the entity was first spawned
+                        actions
+                            .insert
+                            .retain(|c| !Self::is_spawn_metadata(c.into()));
+                        // remove the components that the remote entity doesn't have anymore
+                        let kinds = actions
+                            .insert
+                            .iter()
+                            .map(|c| c.into())
+                            .collect::<HashSet<P::ComponentKinds>>();
+                        actions.remove.extend(existing_kinds.iter().filter(|kind| {
+                            !kinds.contains(*kind) && !Self::is_spawn_metadata(**kind)
+                        }));
+                    }
+
                     // safety: we know by this point that the entity exists
                     let Ok(mut local_entity_mut) =
                         self.remote_entity_map.get_by_remote(world, entity)
@@ -267,15 +739,21 @@ impl<P: Protocol> ReplicationReceiver<P> {
                         .map(|c| c.into())
                         .collect::<HashSet<P::ComponentKinds>>();
                     debug!(remote_entity = ?entity, ?kinds, "Received InsertComponent");
-                    for mut component in actions.insert {
+                    for component in actions.insert {
                         // map any entities inside the component
-                        component.map_entities(&mut self.remote_entity_map);
+                        let Some(component) = self.map_or_defer(entity, component, tick) else {
+                            continue;
+                        };
+                        let kind: P::ComponentKinds = (&component).into();
                         // TODO: figure out what to do with tick here
-                        events.push_insert_component(
-                            local_entity_mut.id(),
-                            (&component).into(),
-                            Tick(0),
-                        );
+                        if existing_kinds
+                            .as_ref()
+                            .map_or(false, |kinds| kinds.contains(&kind))
+                        {
+                            events.push_update_component(local_entity_mut.id(), kind, Tick(0));
+                        } else {
+                            events.push_insert_component(local_entity_mut.id(), kind, Tick(0));
+                        }
                         component.insert(&mut local_entity_mut);
 
                         // TODO: special-case for pre-spawned entities: we receive them from a client, but then we
@@ -291,6 +769,10 @@ impl<P: Protocol> ReplicationReceiver<P> {
                     // removals
                     trace!(remote_entity = ?entity, ?actions.remove, "Received RemoveComponent");
                     for kind in actions.remove {
+                        self.remove_deferred(entity, kind);
+                        if let Some(history) = self.delta_history.get_mut(&entity) {
+                            history.remove(&kind);
+                        }
                         events.push_remove_component(local_entity_mut.id(), kind, Tick(0));
                         kind.remove(&mut local_entity_mut);
                     }
@@ -304,9 +786,11 @@ impl<P: Protocol> ReplicationReceiver<P> {
                         .map(|c| c.into())
                         .collect::<Vec<P::ComponentKinds>>();
                     debug!(remote_entity = ?entity, ?kinds, "Received UpdateComponent");
-                    for mut component in actions.updates {
+                    for component in actions.updates {
                         // map any entities inside the component
-                        component.map_entities(&mut self.remote_entity_map);
+                        let Some(component) = self.map_or_defer(entity, component, tick) else {
+                            continue;
+                        };
                         events.push_update_component(
                             local_entity_mut.id(),
                             (&component).into(),
@@ -324,9 +808,11 @@ impl<P: Protocol> ReplicationReceiver<P> {
                     if let Ok(mut local_entity) =
                         self.remote_entity_map.get_by_remote(world, entity)
                     {
-                        for mut component in components {
+                        for component in components {
                             // map any entities inside the component
-                            component.map_entities(&mut self.remote_entity_map);
+                            let Some(component) = self.map_or_defer(entity, component, tick) else {
+                                continue;
+                            };
                             events.push_update_component(
                                 local_entity.id(),
                                 (&component).into(),
@@ -344,6 +830,9 @@ impl<P: Protocol> ReplicationReceiver<P> {
             }
         }
 
+        // new entities might have been spawned: apply the components that were waiting for them
+        self.apply_deferred_components(world, tick, events);
+
         // update the Confirmed tick for all entities in the replication group
         // // TODO: maybe get the confirmed tick from the apply_world message directly?
         // let confirmed_tick = self.group_channels.get(&group_id).unwrap().latest_tick;
@@ -380,10 +869,10 @@ pub struct GroupChannel<P: Protocol> {
     // the first tick is the last_action_tick (we can only apply the update if the last action tick has been reached)
     // the second tick is the update's server tick when it was sent
     pub buffered_updates_with_last_action_tick:
-        BTreeMap<Tick, BTreeMap<Tick, EntityUpdatesMessage<P::Components>>>,
+        BTreeMap<Tick, BTreeMap<Tick, EntityUpdatesMessage<P::Components, P::ComponentKinds>>>,
     // updates for which there is no condition on the last_action_tick: we can apply them immediately
     pub buffered_updates_without_last_action_tick:
-        BTreeMap<Tick, EntityUpdatesMessage<P::Components>>,
+        BTreeMap<Tick, EntityUpdatesMessage<P::Components, P::ComponentKinds>>,
     /// remote tick of the latest update/action that we applied to the local group
     pub latest_tick: Option<Tick>,
 }
@@ -437,7 +926,9 @@ impl<P: Protocol> GroupChannel<P> {
         Some(message)
     }
 
-    fn read_buffered_updates(&mut self) -> Vec<(Tick, EntityUpdatesMessage<P::Components>)> {
+    fn read_buffered_updates(
+        &mut self,
+    ) -> Vec<(Tick, EntityUpdatesMessage<P::Components, P::ComponentKinds>)> {
         // if we haven't applied any actions (latest_tick is None) we cannot apply any updates
         let Some(latest_tick) = self.latest_tick else {
             return vec![];
@@ -504,6 +995,8 @@ impl<P: Protocol> GroupChannel<P> {
 
 #[cfg(test)]
 mod tests {
+    use crate::shared::replication::delta::ComponentDelta;
+    use crate::shared::replication::EntityActions;
     use crate::tests::protocol::*;
 
     use super::*;
@@ -520,6 +1013,7 @@ mod tests {
                 group_id,
                 data: ReplicationMessageData::Actions(EntityActionMessage {
                     sequence_id: MessageId(0) - 1,
+                    resync: false,
                     actions: Default::default(),
                 }),
             },
@@ -546,6 +1040,7 @@ mod tests {
                 group_id: ReplicationGroupId(0),
                 data: ReplicationMessageData::Actions(EntityActionMessage {
                     sequence_id: MessageId(0),
+                    resync: false,
                     actions: Default::default(),
                 }),
             },
@@ -566,6 +1061,7 @@ mod tests {
                 data: ReplicationMessageData::Updates(EntityUpdatesMessage {
                     last_action_tick: Some(Tick(0)),
                     updates: Default::default(),
+                    deltas: Default::default(),
                 }),
             },
             Tick(1),
@@ -587,6 +1083,7 @@ mod tests {
                 data: ReplicationMessageData::Updates(EntityUpdatesMessage {
                     last_action_tick: Some(Tick(2)),
                     updates: Default::default(),
+                    deltas: Default::default(),
                 }),
             },
             Tick(4),
@@ -613,6 +1110,7 @@ mod tests {
                 group_id: ReplicationGroupId(0),
                 data: ReplicationMessageData::Actions(EntityActionMessage {
                     sequence_id: MessageId(2),
+                    resync: false,
                     actions: Default::default(),
                 }),
             },
@@ -626,6 +1124,7 @@ mod tests {
                 group_id: ReplicationGroupId(0),
                 data: ReplicationMessageData::Actions(EntityActionMessage {
                     sequence_id: MessageId(1),
+                    resync: false,
                     actions: Default::default(),
                 }),
             },
@@ -638,4 +1137,342 @@ mod tests {
         assert_eq!(replication_data.get(1).unwrap().0, Tick(3));
         assert_eq!(replication_data.get(2).unwrap().0, Tick(4));
     }
+
+    /// A resync message contains the full state of the group, so we don't need to wait for the
+    /// actions messages that were sent before it
+    #[test]
+    fn test_recv_resync_message() {
+        let mut manager = ReplicationReceiver::<MyProtocol>::new();
+        let group_id = ReplicationGroupId(0);
+
+        // actions-1 is buffered, we are still waiting for actions-0
+        manager.recv_message(
+            ReplicationMessage {
+                group_id,
+                data: ReplicationMessageData::Actions(EntityActionMessage {
+                    sequence_id: MessageId(1),
+                    resync: false,
+                    actions: Default::default(),
+                }),
+            },
+            Tick(2),
+        );
+        assert!(manager.read_messages(Tick(10)).is_empty());
+
+        // recv a resync message
+        manager.recv_message(
+            ReplicationMessage {
+                group_id,
+                data: ReplicationMessageData::Actions(EntityActionMessage {
+                    sequence_id: MessageId(3),
+                    resync: true,
+                    actions: Default::default(),
+                }),
+            },
+            Tick(5),
+        );
+        let channel = manager.group_channels.get(&group_id).unwrap();
+        assert_eq!(channel.actions_pending_recv_message_id, MessageId(3));
+        assert_eq!(channel.actions_recv_message_buffer.len(), 1);
+
+        // the resync message can be read right away
+        let read_messages = manager.read_messages(Tick(10));
+        let replication_data = &read_messages.first().unwrap().1;
+        assert_eq!(replication_data.len(), 1);
+        assert_eq!(replication_data.first().unwrap().0, Tick(5));
+
+        // the older actions messages are now ignored
+        manager.recv_message(
+            ReplicationMessage {
+                group_id,
+                data: ReplicationMessageData::Actions(EntityActionMessage {
+                    sequence_id: MessageId(0),
+                    resync: false,
+                    actions: Default::default(),
+                }),
+            },
+            Tick(1),
+        );
+        assert!(manager.read_messages(Tick(10)).is_empty());
+    }
+
+    /// A component that references an entity from another replication group is only applied
+    /// once the referenced entity has been spawned
+    #[test]
+    fn test_deferred_entity_mapping() {
+        let mut manager = ReplicationReceiver::<MyProtocol>::new();
+        let mut world = World::default();
+        let mut events = ConnectionEvents::<MyProtocol>::new();
+        let remote_target = Entity::from_raw(10);
+        let remote_entity = Entity::from_raw(11);
+
+        // the entity that references the target is received first
+        manager.apply_world(
+            &mut world,
+            Tick(1),
+            ReplicationMessageData::Actions(EntityActionMessage {
+                sequence_id: MessageId(0),
+                resync: false,
+                actions: vec![(
+                    remote_entity,
+                    EntityActions {
+                        spawn: true,
+                        insert: vec![MyComponentsProtocol::Component4(Component4(remote_target))],
+                        ..Default::default()
+                    },
+                )],
+            }),
+            ReplicationGroupId(remote_entity.to_bits()),
+            &mut events,
+        );
+        let local_entity = *manager.remote_entity_map.get_local(remote_entity).unwrap();
+        assert!(world.get::<Component4>(local_entity).is_none());
+        assert_eq!(manager.deferred_components.len(), 1);
+
+        // the target gets spawned: the deferred component is applied
+        manager.apply_world(
+            &mut world,
+            Tick(2),
+            ReplicationMessageData::Actions(EntityActionMessage {
+                sequence_id: MessageId(0),
+                resync: false,
+                actions: vec![(
+                    remote_target,
+                    EntityActions {
+                        spawn: true,
+                        insert: vec![MyComponentsProtocol::Component1(Component1(1.0))],
+                        ..Default::default()
+                    },
+                )],
+            }),
+            ReplicationGroupId(remote_target.to_bits()),
+            &mut events,
+        );
+        let local_target = *manager.remote_entity_map.get_local(remote_target).unwrap();
+        assert_eq!(
+            world.get::<Component4>(local_entity),
+            Some(&Component4(local_target))
+        );
+        assert!(manager.deferred_components.is_empty());
+
+        // a component that references an entity that is never received is dropped after a while
+        let remote_missing = Entity::from_raw(12);
+        manager.apply_world(
+            &mut world,
+            Tick(3),
+            ReplicationMessageData::Actions(EntityActionMessage {
+                sequence_id: MessageId(1),
+                resync: false,
+                actions: vec![(
+                    remote_entity,
+                    EntityActions {
+                        insert: vec![MyComponentsProtocol::Component4(Component4(remote_missing))],
+                        ..Default::default()
+                    },
+                )],
+            }),
+            ReplicationGroupId(remote_entity.to_bits()),
+            &mut events,
+        );
+        assert_eq!(manager.deferred_components.len(), 1);
+        manager.apply_world(
+            &mut world,
+            Tick(4 + DEFERRED_COMPONENT_TIMEOUT_TICKS),
+            ReplicationMessageData::Actions(EntityActionMessage {
+                sequence_id: MessageId(1),
+                resync: false,
+                actions: vec![(
+                    remote_target,
+                    EntityActions {
+                        insert: vec![MyComponentsProtocol::Component1(Component1(2.0))],
+                        ..Default::default()
+                    },
+                )],
+            }),
+            ReplicationGroupId(remote_target.to_bits()),
+            &mut events,
+        );
+        assert!(manager.deferred_components.is_empty());
+        assert_eq!(
+            world.get::<Component4>(local_entity),
+            Some(&Component4(local_target))
+        );
+    }
+
+    #[test]
+    fn test_recv_delta_updates() {
+        let mut manager = ReplicationReceiver::<MyProtocol>::new();
+        let group_id = ReplicationGroupId(0);
+        let entity = Entity::from_raw(0);
+
+        // a full update is recorded in the delta history
+        manager.recv_message(
+            ReplicationMessage {
+                group_id,
+                data: ReplicationMessageData::Updates(EntityUpdatesMessage {
+                    last_action_tick: None,
+                    updates: vec![(
+                        entity,
+                        vec![MyComponentsProtocol::Component5(Component5(vec![1]))],
+                    )],
+                    deltas: vec![],
+                }),
+            },
+            Tick(1),
+        );
+
+        // a delta against that value is converted back into a full update
+        let base = MyComponentsProtocol::Component5(Component5(vec![1]));
+        let new = MyComponentsProtocol::Component5(Component5(vec![1, 2]));
+        manager.recv_message(
+            ReplicationMessage {
+                group_id,
+                data: ReplicationMessageData::Updates(EntityUpdatesMessage {
+                    last_action_tick: None,
+                    updates: vec![],
+                    deltas: vec![(
+                        entity,
+                        vec![ComponentDelta {
+                            kind: MyComponentsProtocolKind::Component5,
+                            base_tick: Tick(1),
+                            delta: base.diff(&new).unwrap(),
+                        }],
+                    )],
+                }),
+            },
+            Tick(3),
+        );
+        let buffered = &manager
+            .group_channels
+            .get(&group_id)
+            .unwrap()
+            .buffered_updates_without_last_action_tick;
+        assert_eq!(
+            buffered.get(&Tick(3)).unwrap().updates,
+            vec![(entity, vec![new.clone()])]
+        );
+        assert_eq!(
+            manager
+                .delta_history
+                .get(&entity)
+                .unwrap()
+                .get(&MyComponentsProtocolKind::Component5)
+                .unwrap()
+                .get(&Tick(3)),
+            Some(&new)
+        );
+
+        // full updates prune the values that are too old to be used as a base value
+        manager.recv_message(
+            ReplicationMessage {
+                group_id,
+                data: ReplicationMessageData::Updates(EntityUpdatesMessage {
+                    last_action_tick: None,
+                    updates: vec![(entity, vec![new.clone()])],
+                    deltas: vec![],
+                }),
+            },
+            Tick(4 + DELTA_HISTORY_TICKS),
+        );
+        assert_eq!(
+            manager
+                .delta_history
+                .get(&entity)
+                .unwrap()
+                .get(&MyComponentsProtocolKind::Component5)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    /// Deltas are reconstructed even if the packets arrive out of order, and the deltas whose base value is missing
+    /// (because the packet was lost) are not recorded but reset
+    #[test]
+    fn test_recv_delta_updates_reordered_and_dropped() {
+        let mut manager = ReplicationReceiver::<MyProtocol>::new();
+        let group_id = ReplicationGroupId(0);
+        let entity = Entity::from_raw(0);
+        let update = |updates, deltas| ReplicationMessage {
+            group_id,
+            data: ReplicationMessageData::Updates(EntityUpdatesMessage {
+                last_action_tick: None,
+                updates,
+                deltas,
+            }),
+        };
+        let value = |v: Vec<u8>| MyComponentsProtocol::Component5(Component5(v));
+        let delta = |base_tick: Tick, base: &MyComponentsProtocol, new: &MyComponentsProtocol| {
+            vec![(
+                entity,
+                vec![ComponentDelta {
+                    kind: MyComponentsProtocolKind::Component5,
+                    base_tick,
+                    delta: base.diff(new).unwrap(),
+                }],
+            )]
+        };
+        manager.recv_message(
+            update(vec![(entity, vec![value(vec![1])])], vec![]),
+            Tick(1),
+        );
+        manager.recv_message(
+            update(vec![(entity, vec![value(vec![1, 2])])], vec![]),
+            Tick(2),
+        );
+
+        // the update of tick 5 (delta against tick 2) arrives before the update of tick 4 (delta against tick 1)
+        manager.recv_message(
+            update(
+                vec![],
+                delta(Tick(2), &value(vec![1, 2]), &value(vec![1, 2, 5])),
+            ),
+            Tick(5),
+        );
+        manager.recv_message(
+            update(vec![], delta(Tick(1), &value(vec![1]), &value(vec![1, 4]))),
+            Tick(4),
+        );
+        let history = manager
+            .delta_history
+            .get(&entity)
+            .unwrap()
+            .get(&MyComponentsProtocolKind::Component5)
+            .unwrap();
+        assert_eq!(history.get(&Tick(4)), Some(&value(vec![1, 4])));
+        assert_eq!(history.get(&Tick(5)), Some(&value(vec![1, 2, 5])));
+        assert!(manager.take_delta_resets().is_empty());
+
+        // the update of tick 6 was lost: the delta against it can't be reconstructed
+        manager.recv_message(
+            update(
+                vec![],
+                delta(Tick(6), &value(vec![1, 6]), &value(vec![1, 7])),
+            ),
+            Tick(7),
+        );
+        let history = manager
+            .delta_history
+            .get(&entity)
+            .unwrap()
+            .get(&MyComponentsProtocolKind::Component5)
+            .unwrap();
+        assert!(history.get(&Tick(7)).is_none());
+        assert!(manager
+            .group_channels
+            .get(&group_id)
+            .unwrap()
+            .buffered_updates_without_last_action_tick
+            .get(&Tick(7))
+            .unwrap()
+            .updates
+            .is_empty());
+        assert_eq!(
+            manager.take_delta_resets(),
+            vec![DeltaReset {
+                group_id,
+                components: vec![(entity, MyComponentsProtocolKind::Component5)],
+            }]
+        );
+    }
 }
@@ -0,0 +1,286 @@
+//! Replication of bevy [`Resource`]s from the server to the clients
+//!
+//! To replicate a resource `R`, add a [`ReplicateResource<R>`] variant marked with `#[resource]` to your message protocol:
+//! ```rust,ignore
+//! #[derive(Resource, Message, Serialize, Deserialize, Clone, Debug, PartialEq)]
+//! pub struct Scoreboard(pub Vec<u32>);
+//!
+//! #[message_protocol(protocol = "MyProtocol")]
+//! pub enum MyMessageProtocol {
+//!     Message1(Message1),
+//!     #[resource]
+//!     Scoreboard(ReplicateResource<Scoreboard>),
+//! }
+//! ```
+//!
+//! Then insert a [`ReplicateResourceSettings<R>`] on the server to start replicating the resource:
+//! ```rust,ignore
+//! commands.insert_resource(ReplicateResourceSettings::<Scoreboard>::new::<Channel1>(NetworkTarget::All));
+//! ```
+//! Every time the resource is inserted, modified or removed on the server, the change is sent to the target clients
+//! on the chosen channel. Clients that connect later receive the current value of the resource.
+//!
+//! On the client, the resource is inserted/updated/removed automatically, and the events
+//! [`ResourceInsertEvent<R>`](crate::client::events::ResourceInsertEvent), [`ResourceUpdateEvent<R>`](crate::client::events::ResourceUpdateEvent)
+//! and [`ResourceRemoveEvent<R>`](crate::client::events::ResourceRemoveEvent) are emitted.
+use std::marker::PhantomData;
+
+use bevy::ecs::entity::EntityMapper;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error};
+
+use crate::channel::builder::Channel;
+use crate::client::events::MessageEvent;
+use crate::packet::message::Message;
+use crate::prelude::{ChannelKind, LightyearMapEntities, MainSet, Named, NetworkTarget};
+use crate::protocol::Protocol;
+use crate::shared::events::components::{
+    ResourceInsertEvent, ResourceRemoveEvent, ResourceUpdateEvent,
+};
+use crate::shared::sets::ReplicationSet;
+
+/// Message used to replicate the resource `R` from the server to the clients.
+///
+/// Add a `ReplicateResource<R>` variant marked with `#[resource]` to the message protocol to enable the replication of `R`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ReplicateResource<R> {
+    /// The resource was inserted or updated
+    Update(R),
+    /// The resource was removed
+    Remove,
+}
+
+// the `message_protocol` macro names the `#[resource]` variants after the resource they replicate
+impl<R> Named for ReplicateResource<R> {
+    const NAME: &'static str = "ReplicateResource";
+}
+
+/// Message that replicates a resource.
+///
+/// Used by the `message_protocol` macro to find the resource replicated by a `#[resource]` variant.
+pub trait ResourceMessage {
+    type Resource;
+}
+
+impl<R> ResourceMessage for ReplicateResource<R> {
+    type Resource = R;
+}
+
+impl<R: LightyearMapEntities> LightyearMapEntities for ReplicateResource<R> {
+    fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+        if let ReplicateResource::Update(resource) = self {
+            resource.map_entities(entity_mapper);
+        }
+    }
+}
+
+/// Insert this resource on the server to replicate the resource `R` to the clients.
+///
+/// Removing it stops the replication (the clients keep the last value they received).
+#[derive(Resource, Debug)]
+pub struct ReplicateResourceSettings<R> {
+    /// The clients that the resource will be replicated to
+    pub target: NetworkTarget,
+    /// The channel used to send the resource updates
+    pub channel: ChannelKind,
+    _marker: PhantomData<R>,
+}
+
+impl<R> ReplicateResourceSettings<R> {
+    pub fn new<C: Channel>(target: NetworkTarget) -> Self {
+        Self {
+            target,
+            channel: ChannelKind::of::<C>(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Add the systems that send the updates of the resource `R` to the clients
+pub fn add_resource_send_systems<P: Protocol, R: Resource + Message + Clone>(app: &mut App)
+where
+    P::Message: From<ReplicateResource<R>>,
+{
+    app.add_systems(
+        PostUpdate,
+        send_resource_updates::<P, R>.in_set(ReplicationSet::SendComponentUpdates),
+    );
+}
+
+/// Add the systems that apply the resource `R` received from the server
+pub fn add_resource_receive_systems<P: Protocol, R: Resource + Message + Clone>(app: &mut App) {
+    app.add_event::<ResourceInsertEvent<R>>()
+        .add_event::<ResourceUpdateEvent<R>>()
+        .add_event::<ResourceRemoveEvent<R>>()
+        .add_systems(
+            PreUpdate,
+            receive_resource_updates::<R>.after(MainSet::ReceiveFlush),
+        );
+}
+
+/// Send the resource to the target clients when it is inserted or modified (or when new clients connect),
+/// and notify them when it is removed
+fn send_resource_updates<P: Protocol, R: Resource + Message + Clone>(
+    settings: Option<Res<ReplicateResourceSettings<R>>>,
+    resource: Option<Res<R>>,
+    mut connection_manager: ResMut<crate::server::connection::ConnectionManager<P>>,
+    // true if the resource existed the last time the system ran
+    mut present: Local<bool>,
+) where
+    P::Message: From<ReplicateResource<R>>,
+{
+    let Some(settings) = settings else {
+        *present = resource.is_some();
+        return;
+    };
+    let message = match &resource {
+        Some(resource) => {
+            let target = if resource.is_changed() || settings.is_changed() {
+                Some(settings.target.clone())
+            } else if connection_manager.new_clients.is_empty() {
+                None
+            } else {
+                // only send the resource to the clients that just connected
+                let mut target = settings.target.clone();
+                target.intersection(NetworkTarget::Only(connection_manager.new_clients.clone()));
+                Some(target)
+            };
+            target.map(|target| (ReplicateResource::Update(R::clone(resource)), target))
+        }
+        None if *present => Some((ReplicateResource::Remove, settings.target.clone())),
+        None => None,
+    };
+    *present = resource.is_some();
+    if let Some((message, target)) = message {
+        debug!(
+            resource = R::NAME,
+            ?target,
+            "Sending resource replication message"
+        );
+        let _ = connection_manager
+            .buffer_message(message.into(), settings.channel, target)
+            .map_err(|e| {
+                error!("error sending resource update: {:?}", e);
+            });
+    }
+}
+
+/// Apply the latest resource message received from the server
+fn receive_resource_updates<R: Resource + Message + Clone>(
+    mut commands: Commands,
+    mut messages: EventReader<MessageEvent<ReplicateResource<R>>>,
+    resource: Option<ResMut<R>>,
+    mut insert_events: EventWriter<ResourceInsertEvent<R>>,
+    mut update_events: EventWriter<ResourceUpdateEvent<R>>,
+    mut remove_events: EventWriter<ResourceRemoveEvent<R>>,
+) {
+    // only the latest message matters
+    let Some(message) = messages.read().last() else {
+        return;
+    };
+    match (message.message(), resource) {
+        (ReplicateResource::Update(value), Some(mut resource)) => {
+            *resource = value.clone();
+            update_events.send(ResourceUpdateEvent::new(()));
+        }
+        (ReplicateResource::Update(value), None) => {
+            commands.insert_resource(value.clone());
+            insert_events.send(ResourceInsertEvent::new(()));
+        }
+        (ReplicateResource::Remove, Some(_)) => {
+            commands.remove_resource::<R>();
+            remove_events.send(ResourceRemoveEvent::new(()));
+        }
+        (ReplicateResource::Remove, None) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::Duration;
+
+    use crate::prelude::client::*;
+    use crate::prelude::*;
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use bevy::prelude::Events;
+
+    use crate::protocol::message::MessageProtocol;
+
+    use super::{ReplicateResource, ReplicateResourceSettings};
+
+    /// The name of the message is generated by the protocol macro, and includes the resource
+    #[test]
+    fn test_resource_message_name() {
+        let message = MyMessageProtocol::Resource1(ReplicateResource::Remove);
+        assert_eq!(message.name(), "ReplicateResource<Resource1>");
+    }
+
+    #[test]
+    fn test_resource_replication() {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(0),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.0,
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default().speedup_factor(1.0),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            link_conditioner,
+            frame_duration,
+        );
+        // the resource exists before the client connects
+        stepper.server_app.world.insert_resource(Resource1(1.0));
+        stepper
+            .server_app
+            .world
+            .insert_resource(ReplicateResourceSettings::<Resource1>::new::<Channel1>(
+                NetworkTarget::All,
+            ));
+        stepper.init();
+        stepper.frame_step();
+        stepper.frame_step();
+        assert_eq!(
+            stepper.client_app.world.get_resource::<Resource1>(),
+            Some(&Resource1(1.0))
+        );
+
+        // update
+        stepper.server_app.world.resource_mut::<Resource1>().0 = 2.0;
+        stepper.frame_step();
+        stepper.frame_step();
+        assert_eq!(
+            stepper.client_app.world.get_resource::<Resource1>(),
+            Some(&Resource1(2.0))
+        );
+        assert_eq!(
+            stepper
+                .client_app
+                .world
+                .resource::<Events<ResourceUpdateEvent<Resource1>>>()
+                .len(),
+            1
+        );
+
+        // remove
+        stepper.server_app.world.remove_resource::<Resource1>();
+        stepper.frame_step();
+        stepper.frame_step();
+        assert!(stepper
+            .client_app
+            .world
+            .get_resource::<Resource1>()
+            .is_none());
+    }
+}
@@ -1,4 +1,5 @@
 //! General struct handling replication
+use std::collections::VecDeque;
 use std::iter::Extend;
 
 use anyhow::Context;
@@ -17,7 +18,8 @@ use crate::protocol::channel::ChannelKind;
 use crate::protocol::component::ComponentProtocol;
 use crate::protocol::component::{ComponentBehaviour, ComponentKindBehaviour};
 use crate::protocol::Protocol;
-use crate::shared::replication::components::{Replicate, ReplicationGroupId};
+use crate::shared::replication::components::{Replicate, ReplicateCache, ReplicationGroupId};
+use crate::shared::replication::delta::{ComponentDelta, DeltaReset, DELTA_HISTORY_TICKS};
 
 use super::{EntityActionMessage, EntityActions, EntityUpdatesMessage, ReplicationMessageData};
 
@@ -30,9 +32,11 @@ pub(crate) struct ReplicationSender<P: Protocol> {
     //  in general, we should have some parts of replication-sender/receiver that are shared across all connections!
     /// Stores the last `Replicate` component for each replicated entity owned by the current world (the world that sends replication updates)
     /// Needed to know the value of the Replicate component after the entity gets despawned, to know how we replicate the EntityDespawn
-    pub replicate_component_cache: EntityHashMap<Entity, Replicate<P>>,
+    pub replicate_component_cache: EntityHashMap<Entity, ReplicateCache>,
     /// Get notified whenever a message-id that was sent has been received by the remote
     pub updates_ack_tracker: Receiver<MessageId>,
+    /// Get notified whenever an actions message that was sent has been received by the remote
+    pub actions_ack_tracker: Receiver<MessageId>,
 
     /// Map from message-id to the corresponding group-id that sent this update message, as well as the bevy ChangeTick
     /// when we sent the message. (so that when it's acked, we know we only need to include updates that happened after that tick,
@@ -53,26 +57,82 @@ pub(crate) struct ReplicationSender<P: Protocol> {
     /// Buffer to so that we have an ordered receiver per group
     pub group_channels: EntityHashMap<ReplicationGroupId, GroupChannel>,
 
+    // DELTA COMPRESSION
+    /// For each entity, the latest value of each delta-compressed component that was included in an update
+    /// message acked by the remote, along with the tick of that message.
+    /// Updates for delta-compressed components are sent as a delta against this value.
+    pub delta_acked_states:
+        EntityHashMap<Entity, HashMap<P::ComponentKinds, (Tick, P::Components)>>,
+    /// Full values of the delta-compressed components included in update messages that haven't been acked yet
+    pub delta_sent_states: HashMap<MessageId, (Tick, Vec<(Entity, P::Components)>)>,
+    /// Full values of the delta-compressed components included in the update messages created during `finalize`
+    /// (we only know the message-id once the message is buffered)
+    pub pending_delta_states:
+        EntityHashMap<ReplicationGroupId, (Tick, Vec<(Entity, P::Components)>)>,
+
+    // RESYNC
+    /// Group and tick at which the update messages that haven't been acked yet were sent
+    pub unacked_update_ticks: HashMap<MessageId, (ReplicationGroupId, Tick)>,
+    /// True if we sent a resync to the remote, and haven't received any ack since then
+    pub resync_pending: bool,
+    /// Groups for which the next actions message will be a resync message
+    pub resync_groups: EntityHashSet<ReplicationGroupId>,
+    /// For each group, the actions messages that the remote might not have applied yet, in the order they were sent.
+    /// The remote applies the actions messages of a group in order, so a message is only known to be applied
+    /// once it and all the previous messages of the group have been acked.
+    pub unapplied_actions: EntityHashMap<ReplicationGroupId, VecDeque<UnappliedActions>>,
+    /// Map from the message-id of an actions message to the group that sent it
+    pub actions_message_id_to_group_id: HashMap<MessageId, ReplicationGroupId>,
+    /// Detach and authority actions of the actions messages created during `finalize`
+    /// (we only know the message-id once the message is buffered)
+    pub pending_control_actions:
+        EntityHashMap<ReplicationGroupId, Vec<(Entity, bool, Option<bool>)>>,
+
     // PRIORITY
     /// Get notified whenever a message for a given ReplicationGroup was actually sent
     /// (sometimes they might not be sent because of bandwidth constraints
     pub message_send_receiver: Receiver<MessageId>,
 }
 
+/// An actions message that the remote might not have applied yet.
+///
+/// A resync discards the actions that the remote hasn't applied, so the detach and authority actions
+/// of the message (which can't be deduced from the state of the group) must be sent again.
+#[derive(Debug)]
+pub(crate) struct UnappliedActions {
+    message_id: MessageId,
+    acked: bool,
+    /// The (entity, detach, authority) actions of the message
+    control_actions: Vec<(Entity, bool, Option<bool>)>,
+}
+
 impl<P: Protocol> ReplicationSender<P> {
     pub(crate) fn new(
         updates_ack_tracker: Receiver<MessageId>,
+        actions_ack_tracker: Receiver<MessageId>,
         message_send_receiver: Receiver<MessageId>,
     ) -> Self {
         Self {
             // SEND
             replicate_component_cache: EntityHashMap::default(),
             updates_ack_tracker,
+            actions_ack_tracker,
             updates_message_id_to_group_id: Default::default(),
             pending_actions: EntityHashMap::default(),
             pending_updates: EntityHashMap::default(),
             pending_unique_components: EntityHashMap::default(),
             group_channels: Default::default(),
+            // DELTA COMPRESSION
+            delta_acked_states: EntityHashMap::default(),
+            delta_sent_states: HashMap::default(),
+            pending_delta_states: EntityHashMap::default(),
+            // RESYNC
+            unacked_update_ticks: HashMap::default(),
+            resync_pending: false,
+            resync_groups: EntityHashSet::default(),
+            unapplied_actions: EntityHashMap::default(),
+            actions_message_id_to_group_id: HashMap::default(),
+            pending_control_actions: EntityHashMap::default(),
             // PRIORITY
             message_send_receiver,
         }
@@ -132,8 +192,187 @@ impl<P: Protocol> ReplicationSender<P> {
             } else {
                 error!("Received an update message-id ack but we know the corresponding group id");
             }
+            self.recv_delta_ack(message_id);
+            self.recv_resync_ack(message_id);
+        }
+        while let Ok(message_id) = self.actions_ack_tracker.try_recv() {
+            self.recv_actions_ack(message_id);
+        }
+    }
+
+    /// An actions message was acked: forget the messages of the group that the remote has applied
+    fn recv_actions_ack(&mut self, message_id: MessageId) {
+        let Some(group_id) = self.actions_message_id_to_group_id.remove(&message_id) else {
+            return;
+        };
+        let Some(messages) = self.unapplied_actions.get_mut(&group_id) else {
+            return;
+        };
+        if let Some(message) = messages.iter_mut().find(|m| m.message_id == message_id) {
+            message.acked = true;
+        }
+        while messages.front().map_or(false, |m| m.acked) {
+            messages.pop_front();
+        }
+    }
+
+    /// An update message was acked: the remote is up-to-date at least until the tick of that message.
+    fn recv_resync_ack(&mut self, message_id: MessageId) {
+        // acks for messages sent before the resync are ignored
+        let Some((group_id, tick)) = self.unacked_update_ticks.remove(&message_id) else {
+            return;
+        };
+        self.resync_pending = false;
+        // the older messages of the group were either acked or lost, and the remote is at least
+        // up-to-date until `tick`, so the older messages of the other groups are not a sign of lag either
+        self.unacked_update_ticks
+            .retain(|_, (sent_group_id, sent_tick)| {
+                if *sent_group_id == group_id {
+                    *sent_tick > tick
+                } else {
+                    *sent_tick >= tick
+                }
+            });
+    }
+
+    /// Returns true if the remote hasn't acked any of the update messages that we sent
+    /// more than `threshold_ticks` ticks ago.
+    ///
+    /// A single stale message is not enough: it could just be the last update of a group that got lost,
+    /// so we also require a more recent message to have gone unacked for more than `threshold_ticks`.
+    pub(crate) fn is_lagging(&self, tick: Tick, threshold_ticks: u16) -> bool {
+        !self.resync_pending
+            && self
+                .unacked_update_ticks
+                .values()
+                .filter(|(_, sent_tick)| tick - *sent_tick > threshold_ticks as i16)
+                .nth(1)
+                .is_some()
+    }
+
+    /// The remote fell too far behind: instead of replaying all the messages it missed, the next actions message
+    /// of every group will contain the full state of the group.
+    ///
+    /// (the full state of the entities must be prepared separately, as for a newly connected remote)
+    pub(crate) fn prepare_resync(&mut self) {
+        self.resync_groups
+            .extend(self.group_channels.keys().copied());
+        self.resync_pending = true;
+        self.unacked_update_ticks.clear();
+        // the remote won't keep any base values for the delta-compressed components
+        self.delta_acked_states.clear();
+        self.delta_sent_states.clear();
+        self.pending_delta_states.clear();
+        // the remote discards the actions it hasn't applied yet: send the detach and authority actions again
+        // (the newest action for an entity wins, including the ones that were prepared since the last send)
+        self.actions_message_id_to_group_id.clear();
+        for (group_id, messages) in self.unapplied_actions.drain() {
+            for message in messages.into_iter().rev() {
+                for (entity, detach, authority) in message.control_actions {
+                    let actions = self
+                        .pending_actions
+                        .entry(group_id)
+                        .or_default()
+                        .entry(entity)
+                        .or_default();
+                    actions.detach |= detach;
+                    actions.authority = actions.authority.or(authority);
+                }
+            }
         }
     }
+
+    /// Keep track of an actions message that was buffered for sending, so that its detach and authority actions
+    /// can be sent again if the remote needs a resync before applying it
+    pub(crate) fn track_actions_message(
+        &mut self,
+        message_id: MessageId,
+        group_id: ReplicationGroupId,
+    ) {
+        let control_actions = self
+            .pending_control_actions
+            .remove(&group_id)
+            .unwrap_or_default();
+        self.actions_message_id_to_group_id
+            .insert(message_id, group_id);
+        self.unapplied_actions
+            .entry(group_id)
+            .or_default()
+            .push_back(UnappliedActions {
+                message_id,
+                acked: false,
+                control_actions,
+            });
+    }
+
+    /// Keep track of an update message that was buffered for sending, so that we can handle
+    /// receiving an ACK for that message later
+    pub(crate) fn track_update_message(
+        &mut self,
+        message_id: MessageId,
+        group_id: ReplicationGroupId,
+        bevy_tick: BevyTick,
+        tick: Tick,
+    ) {
+        self.updates_message_id_to_group_id
+            .insert(message_id, (group_id, bevy_tick));
+        self.unacked_update_ticks
+            .insert(message_id, (group_id, tick));
+        if let Some(states) = self.pending_delta_states.remove(&group_id) {
+            self.delta_sent_states.insert(message_id, states);
+        }
+        // messages whose ack got lost can't be used as base values anymore once they are too old
+        self.delta_sent_states
+            .retain(|_, (sent_tick, _)| tick - *sent_tick <= DELTA_HISTORY_TICKS as i16);
+    }
+
+    /// An update message was acked: the delta-compressed component values that it contained
+    /// become the new base values for the delta computations
+    fn recv_delta_ack(&mut self, message_id: MessageId) {
+        let Some((tick, states)) = self.delta_sent_states.remove(&message_id) else {
+            return;
+        };
+        for (entity, component) in states {
+            let kind: P::ComponentKinds = (&component).into();
+            let acked_states = self.delta_acked_states.entry(entity).or_default();
+            if acked_states
+                .get(&kind)
+                .map_or(true, |(acked_tick, _)| tick > *acked_tick)
+            {
+                acked_states.insert(kind, (tick, component));
+            }
+        }
+        // messages sent before the acked message can never become base values anymore
+        self.delta_sent_states
+            .retain(|_, (sent_tick, _)| *sent_tick >= tick);
+    }
+
+    /// The remote could not reconstruct some delta-compressed components: it never stored the values that
+    /// we would use as base, so we send the full values of the group again.
+    pub(crate) fn recv_delta_reset(&mut self, reset: DeltaReset<P::ComponentKinds>) {
+        debug!(?reset, "Received delta reset");
+        for (entity, kind) in reset.components {
+            if let Some(acked_states) = self.delta_acked_states.get_mut(&entity) {
+                acked_states.remove(&kind);
+            }
+            // the unacked messages could contain values that the remote did not store either
+            let is_reset = |(e, component): &(Entity, P::Components)| {
+                let component_kind: P::ComponentKinds = component.into();
+                *e == entity && component_kind == kind
+            };
+            self.delta_sent_states
+                .values_mut()
+                .for_each(|(_, states)| states.retain(|state| !is_reset(state)));
+            self.pending_delta_states
+                .values_mut()
+                .for_each(|(_, states)| states.retain(|state| !is_reset(state)));
+        }
+        // the component might not change anymore: collect all the components of the group again
+        self.group_channels
+            .entry(reset.group_id)
+            .or_default()
+            .collect_changes_since_this_tick = None;
+    }
 }
 
 /// We want:
@@ -169,6 +408,7 @@ impl<P: Protocol> ReplicationSender<P> {
     }
 
     pub(crate) fn prepare_entity_despawn(&mut self, entity: Entity, group_id: ReplicationGroupId) {
+        self.clear_delta_states(entity);
         self.pending_actions
             .entry(group_id)
             .or_default()
@@ -177,6 +417,61 @@ impl<P: Protocol> ReplicationSender<P> {
             .despawn = true;
     }
 
+    /// Host has stopped replicating an entity, but the remote should not despawn it
+    pub(crate) fn prepare_entity_detach(&mut self, entity: Entity, group_id: ReplicationGroupId) {
+        self.clear_delta_states(entity);
+        self.pending_actions
+            .entry(group_id)
+            .or_default()
+            .entry(entity)
+            .or_default()
+            .detach = true;
+    }
+
+    /// Give or take back the authority over the entity to the remote
+    pub(crate) fn prepare_authority_change(
+        &mut self,
+        entity: Entity,
+        group_id: ReplicationGroupId,
+        has_authority: bool,
+    ) {
+        self.pending_actions
+            .entry(group_id)
+            .or_default()
+            .entry(entity)
+            .or_default()
+            .authority = Some(has_authority);
+    }
+
+    /// We are spawning an entity that we were given authority over: the remote should re-use
+    /// its existing entity `remote_entity` instead of spawning a new one
+    pub(crate) fn prepare_authority_spawn(
+        &mut self,
+        entity: Entity,
+        group_id: ReplicationGroupId,
+        remote_entity: Entity,
+    ) {
+        self.pending_actions
+            .entry(group_id)
+            .or_default()
+            .entry(entity)
+            .or_default()
+            .authority_entity = Some(remote_entity);
+    }
+
+    /// The remote won't keep any base values for this entity
+    fn clear_delta_states(&mut self, entity: Entity) {
+        self.delta_acked_states.remove(&entity);
+        self.delta_sent_states.values_mut().for_each(|(_, states)| {
+            states.retain(|(e, _)| *e != entity);
+        });
+        self.pending_delta_states
+            .values_mut()
+            .for_each(|(_, states)| {
+                states.retain(|(e, _)| *e != entity);
+            });
+    }
+
     // we want to send all component inserts that happen together for the same entity in a single message
     // (because otherwise the inserts might be received at different packets/ticks by the remote, and
     // the remote might expect the components insert to be received at the same time)
@@ -333,7 +628,14 @@ impl<P: Protocol> ReplicationSender<P> {
         f32,
     )> {
         let mut messages = Vec::new();
+        // states of update messages from the previous send that were never buffered
+        self.pending_delta_states.clear();
+        self.pending_control_actions.clear();
 
+        // groups that are being resynced must send an actions message, even if it's empty
+        for group_id in self.resync_groups.iter() {
+            self.pending_actions.entry(*group_id).or_default();
+        }
         for (group_id, mut actions) in self.pending_actions.drain() {
             trace!(?group_id, "pending actions: {:?}", actions);
             // add any updates for that group
@@ -347,6 +649,14 @@ impl<P: Protocol> ReplicationSender<P> {
                         .extend(components.into_iter());
                 }
             }
+            self.pending_control_actions.insert(
+                group_id,
+                actions
+                    .iter()
+                    .filter(|(_, a)| a.detach || a.authority.is_some())
+                    .map(|(entity, a)| (*entity, a.detach, a.authority))
+                    .collect(),
+            );
             let channel = self.group_channels.entry(group_id).or_default();
             let priority = channel
                 .accumulated_priority
@@ -359,6 +669,7 @@ impl<P: Protocol> ReplicationSender<P> {
                 group_id,
                 ReplicationMessageData::Actions(EntityActionMessage {
                     sequence_id: message_id,
+                    resync: self.resync_groups.remove(&group_id),
                     // TODO: maybe we can just send the HashMap directly?
                     actions: Vec::from_iter(actions.into_iter()),
                 }),
@@ -369,6 +680,52 @@ impl<P: Protocol> ReplicationSender<P> {
         // send the remaining updates
         for (group_id, updates) in self.pending_updates.drain() {
             trace!(?group_id, "pending updates: {:?}", updates);
+            // replace the updates of delta-compressed components with deltas against the last acked value
+            let mut full_updates = Vec::with_capacity(updates.len());
+            let mut delta_updates = Vec::new();
+            let mut delta_states = Vec::new();
+            for (entity, components) in updates {
+                let acked_states = self.delta_acked_states.get(&entity);
+                let mut entity_full_updates = Vec::new();
+                let mut entity_delta_updates = Vec::new();
+                for component in components {
+                    if !component.is_diffable() {
+                        entity_full_updates.push(component);
+                        continue;
+                    }
+                    let kind: P::ComponentKinds = (&component).into();
+                    delta_states.push((entity, component.clone()));
+                    let delta = acked_states
+                        .and_then(|states| states.get(&kind))
+                        // the remote only keeps the recent values of the component
+                        .filter(|(base_tick, _)| tick - *base_tick <= DELTA_HISTORY_TICKS as i16)
+                        .and_then(|(base_tick, base)| {
+                            base.diff(&component)
+                                .map_err(|e| error!(?kind, "could not compute delta: {:?}", e))
+                                .ok()
+                                .map(|delta| ComponentDelta {
+                                    kind,
+                                    base_tick: *base_tick,
+                                    delta,
+                                })
+                        });
+                    match delta {
+                        Some(delta) => entity_delta_updates.push(delta),
+                        // we don't have a base value acked by the remote, send the full value
+                        None => entity_full_updates.push(component),
+                    }
+                }
+                if !entity_full_updates.is_empty() {
+                    full_updates.push((entity, entity_full_updates));
+                }
+                if !entity_delta_updates.is_empty() {
+                    delta_updates.push((entity, entity_delta_updates));
+                }
+            }
+            if !delta_states.is_empty() {
+                self.pending_delta_states
+                    .insert(group_id, (tick, delta_states));
+            }
             let channel = self.group_channels.entry(group_id).or_default();
             let priority = channel
                 .accumulated_priority
@@ -380,7 +737,8 @@ impl<P: Protocol> ReplicationSender<P> {
                     // SAFETY: the last action tick is always set because we send Actions before Updates
                     last_action_tick: channel.last_action_tick,
                     // TODO: maybe we can just send the HashMap directly?
-                    updates: Vec::from_iter(updates.into_iter()),
+                    updates: full_updates,
+                    deltas: delta_updates,
                 }),
                 priority,
             ));
@@ -449,12 +807,15 @@ mod tests {
 
     use super::*;
 
-    // TODO: add tests for replication with entity relations!
     #[test]
     fn test_buffer_replication_messages() {
         // create fake channels for receiving updates about acks and sends
         let (sender, receiver) = crossbeam_channel::unbounded();
-        let mut manager = ReplicationSender::<MyProtocol>::new(receiver.clone(), receiver);
+        let mut manager = ReplicationSender::<MyProtocol>::new(
+            receiver.clone(),
+            crossbeam_channel::unbounded().1,
+            receiver,
+        );
 
         let entity_1 = Entity::from_raw(0);
         let entity_2 = Entity::from_raw(1);
@@ -521,6 +882,9 @@ mod tests {
                     EntityActions {
                         spawn: true,
                         despawn: false,
+                        detach: false,
+                        authority: None,
+                        authority_entity: None,
                         insert: vec![MyComponentsProtocol::Component1(Component1(1.0))],
                         remove: HashSet::from_iter(vec![MyComponentsProtocolKind::Component2]),
                         updates: vec![MyComponentsProtocol::Component3(Component3(3.0))],
@@ -531,6 +895,9 @@ mod tests {
                     EntityActions {
                         spawn: false,
                         despawn: false,
+                        detach: false,
+                        authority: None,
+                        authority_entity: None,
                         insert: vec![],
                         remove: HashSet::default(),
                         updates: vec![MyComponentsProtocol::Component2(Component2(4.0))],
@@ -551,6 +918,7 @@ mod tests {
                         entity_3,
                         vec![MyComponentsProtocol::Component3(Component3(5.0))]
                     )],
+                    deltas: vec![],
                 }),
                 1.0
             )
@@ -572,4 +940,227 @@ mod tests {
             Some(Tick(2))
         );
     }
+
+    #[test]
+    fn test_delta_compression_against_acked_state() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut manager = ReplicationSender::<MyProtocol>::new(
+            receiver.clone(),
+            crossbeam_channel::unbounded().1,
+            receiver,
+        );
+        let entity = Entity::from_raw(0);
+        let group = ReplicationGroupId(0);
+
+        // no acked state: the full value is sent
+        manager.prepare_entity_update(
+            entity,
+            group,
+            MyComponentsProtocol::Component5(Component5(vec![1, 2])),
+        );
+        let message = manager.finalize(Tick(1));
+        let ReplicationMessageData::Updates(ref updates) = message.first().unwrap().2 else {
+            panic!()
+        };
+        assert_eq!(
+            updates.updates,
+            vec![(
+                entity,
+                vec![MyComponentsProtocol::Component5(Component5(vec![1, 2]))]
+            )]
+        );
+        assert!(updates.deltas.is_empty());
+        manager.track_update_message(MessageId(0), group, BevyTick::new(0), Tick(1));
+
+        // the message gets acked: its value becomes the base value
+        sender.send(MessageId(0)).unwrap();
+        manager.recv_update_acks();
+        assert_eq!(
+            manager
+                .delta_acked_states
+                .get(&entity)
+                .unwrap()
+                .get(&MyComponentsProtocolKind::Component5),
+            Some(&(
+                Tick(1),
+                MyComponentsProtocol::Component5(Component5(vec![1, 2]))
+            ))
+        );
+        assert!(manager.delta_sent_states.is_empty());
+
+        // new updates are sent as deltas against the acked value
+        manager.prepare_entity_update(
+            entity,
+            group,
+            MyComponentsProtocol::Component5(Component5(vec![1, 2, 3])),
+        );
+        let message = manager.finalize(Tick(2));
+        let ReplicationMessageData::Updates(ref updates) = message.first().unwrap().2 else {
+            panic!()
+        };
+        assert!(updates.updates.is_empty());
+        let (delta_entity, deltas) = updates.deltas.first().unwrap();
+        assert_eq!(delta_entity, &entity);
+        let delta = deltas.first().unwrap();
+        assert_eq!(delta.kind, MyComponentsProtocolKind::Component5);
+        assert_eq!(delta.base_tick, Tick(1));
+        let mut reconstructed = MyComponentsProtocol::Component5(Component5(vec![1, 2]));
+        reconstructed.apply_diff(&delta.delta).unwrap();
+        assert_eq!(
+            reconstructed,
+            MyComponentsProtocol::Component5(Component5(vec![1, 2, 3]))
+        );
+    }
+
+    /// The remote could not reconstruct a delta: the acked value and the unacked values are not used as base anymore,
+    /// and the full value is sent again
+    #[test]
+    fn test_delta_reset() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut manager = ReplicationSender::<MyProtocol>::new(
+            receiver.clone(),
+            crossbeam_channel::unbounded().1,
+            receiver,
+        );
+        let entity = Entity::from_raw(0);
+        let group = ReplicationGroupId(0);
+
+        manager.prepare_entity_update(
+            entity,
+            group,
+            MyComponentsProtocol::Component5(Component5(vec![1])),
+        );
+        manager.finalize(Tick(1));
+        manager.track_update_message(MessageId(0), group, BevyTick::new(0), Tick(1));
+        sender.send(MessageId(0)).unwrap();
+        manager.recv_update_acks();
+        manager.prepare_entity_update(
+            entity,
+            group,
+            MyComponentsProtocol::Component5(Component5(vec![1, 2])),
+        );
+        manager.finalize(Tick(2));
+        manager.track_update_message(MessageId(1), group, BevyTick::new(1), Tick(2));
+
+        manager.recv_delta_reset(DeltaReset {
+            group_id: group,
+            components: vec![(entity, MyComponentsProtocolKind::Component5)],
+        });
+        assert!(manager.delta_acked_states.get(&entity).unwrap().is_empty());
+        assert!(manager
+            .group_channels
+            .get(&group)
+            .unwrap()
+            .collect_changes_since_this_tick
+            .is_none());
+
+        // the ack of the update that contained a delta doesn't make it a base value
+        sender.send(MessageId(1)).unwrap();
+        manager.recv_update_acks();
+        assert!(manager.delta_acked_states.get(&entity).unwrap().is_empty());
+
+        // the full value is sent
+        manager.prepare_entity_update(
+            entity,
+            group,
+            MyComponentsProtocol::Component5(Component5(vec![1, 2, 3])),
+        );
+        let message = manager.finalize(Tick(3));
+        let ReplicationMessageData::Updates(ref updates) = message.first().unwrap().2 else {
+            panic!()
+        };
+        assert_eq!(
+            updates.updates,
+            vec![(
+                entity,
+                vec![MyComponentsProtocol::Component5(Component5(vec![1, 2, 3]))]
+            )]
+        );
+        assert!(updates.deltas.is_empty());
+    }
+
+    /// The remote discards the actions messages that it hasn't applied when it receives a resync:
+    /// their detach and authority actions are sent again in the resync
+    #[test]
+    fn test_resync_replays_unapplied_actions() {
+        let (_, receiver) = crossbeam_channel::unbounded();
+        let (actions_ack_sender, actions_ack_receiver) = crossbeam_channel::unbounded();
+        let mut manager =
+            ReplicationSender::<MyProtocol>::new(receiver.clone(), actions_ack_receiver, receiver);
+        let entity_1 = Entity::from_raw(0);
+        let entity_2 = Entity::from_raw(1);
+        let entity_3 = Entity::from_raw(2);
+        let group = ReplicationGroupId(0);
+
+        // the first message is acked and applied
+        manager.prepare_authority_change(entity_3, group, true);
+        manager.finalize(Tick(1));
+        manager.track_actions_message(MessageId(0), group);
+        actions_ack_sender.send(MessageId(0)).unwrap();
+        manager.recv_update_acks();
+        assert!(manager.unapplied_actions.get(&group).unwrap().is_empty());
+
+        // the second message is lost, the third message is acked but can't be applied before the second one
+        manager.prepare_authority_change(entity_1, group, true);
+        manager.finalize(Tick(2));
+        manager.track_actions_message(MessageId(1), group);
+        manager.prepare_entity_detach(entity_2, group);
+        manager.prepare_authority_change(entity_1, group, false);
+        manager.finalize(Tick(3));
+        manager.track_actions_message(MessageId(2), group);
+        actions_ack_sender.send(MessageId(2)).unwrap();
+        manager.recv_update_acks();
+        assert_eq!(manager.unapplied_actions.get(&group).unwrap().len(), 2);
+
+        manager.prepare_resync();
+        assert!(manager.unapplied_actions.is_empty());
+        let actions = manager.pending_actions.get(&group).unwrap();
+        assert_eq!(actions.len(), 2);
+        // the most recent authority change wins
+        assert_eq!(actions.get(&entity_1).unwrap().authority, Some(false));
+        assert!(actions.get(&entity_2).unwrap().detach);
+        let message = manager.finalize(Tick(4));
+        let ReplicationMessageData::Actions(ref actions) = message.first().unwrap().2 else {
+            panic!()
+        };
+        assert!(actions.resync);
+    }
+
+    #[test]
+    fn test_is_lagging() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut manager = ReplicationSender::<MyProtocol>::new(
+            receiver.clone(),
+            crossbeam_channel::unbounded().1,
+            receiver,
+        );
+        let group_1 = ReplicationGroupId(1);
+        let group_2 = ReplicationGroupId(2);
+
+        // the last update of a group got lost: not a sign of lag
+        manager.track_update_message(MessageId(0), group_1, BevyTick::new(0), Tick(0));
+        assert!(!manager.is_lagging(Tick(20), 10));
+
+        // a newer update of the group is acked: the lost update is not tracked anymore
+        manager.track_update_message(MessageId(1), group_1, BevyTick::new(0), Tick(25));
+        sender.send(MessageId(1)).unwrap();
+        manager.recv_update_acks();
+        assert!(manager.unacked_update_ticks.is_empty());
+
+        // the remote stops acking the updates of any group
+        manager.track_update_message(MessageId(2), group_1, BevyTick::new(0), Tick(30));
+        manager.track_update_message(MessageId(3), group_2, BevyTick::new(0), Tick(32));
+        assert!(!manager.is_lagging(Tick(41), 10));
+        assert!(manager.is_lagging(Tick(43), 10));
+
+        // no new resync until the remote acks a message again
+        manager.prepare_resync();
+        manager.track_update_message(MessageId(4), group_2, BevyTick::new(0), Tick(45));
+        manager.track_update_message(MessageId(5), group_2, BevyTick::new(0), Tick(46));
+        assert!(!manager.is_lagging(Tick(60), 10));
+        sender.send(MessageId(5)).unwrap();
+        manager.recv_update_acks();
+        assert!(!manager.resync_pending);
+        assert!(manager.unacked_update_ticks.is_empty());
+    }
 }
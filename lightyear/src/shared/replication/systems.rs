@@ -4,7 +4,7 @@ use std::ops::Deref;
 use bevy::ecs::entity::Entities;
 use bevy::ecs::system::SystemChangeTick;
 use bevy::prelude::{
-    Added, App, Commands, Component, DetectChanges, Entity, IntoSystemConfigs, PostUpdate,
+    App, Changed, Commands, Component, DetectChanges, Entity, Has, IntoSystemConfigs, PostUpdate,
     PreUpdate, Query, Ref, RemovedComponents, Res, ResMut, Without,
 };
 use tracing::{debug, error, info, trace};
@@ -13,15 +13,19 @@ use crate::_reexport::FromType;
 use crate::prelude::{MainSet, NetworkTarget, TickManager};
 use crate::protocol::Protocol;
 use crate::server::room::ClientVisibility;
-use crate::shared::replication::components::{DespawnTracker, Replicate, ReplicationMode};
+use crate::shared::replication::components::{
+    DespawnTracker, OnReplicateRemoved, Replicate, ReplicationMode,
+};
 use crate::shared::replication::ReplicationSend;
 use crate::shared::sets::ReplicationSet;
 
 // TODO: run these systems only if there is at least 1 remote connected!!! (so we don't burn CPU when there are no connections)
 
 /// For every entity that removes their Replicate component but are not despawned, remove the component
-/// from our replicate cache (so that the entity's despawns are no longer replicated)
+/// from our replicate cache (so that the entity's despawns are no longer replicated), and
+/// notify the remote according to the entity's [`OnReplicateRemoved`] policy
 fn handle_replicate_remove<P: Protocol, R: ReplicationSend<P>>(
+    system_bevy_ticks: SystemChangeTick,
     mut sender: ResMut<R>,
     mut query: RemovedComponents<Replicate<P>>,
     entity_check: &Entities,
@@ -29,27 +33,53 @@ fn handle_replicate_remove<P: Protocol, R: ReplicationSend<P>>(
     for entity in query.read() {
         if entity_check.contains(entity) {
             debug!("handling replicate component remove (delete from cache)");
-            sender.get_mut_replicate_component_cache().remove(&entity);
+            let Some(cache) = sender.get_mut_replicate_component_cache().remove(&entity) else {
+                continue;
+            };
+            // only the clients that currently see the entity need to be notified
+            let result = match cache.on_replicate_removed {
+                OnReplicateRemoved::KeepRemote => Ok(()),
+                OnReplicateRemoved::DespawnRemote => sender.prepare_entity_despawn(
+                    entity,
+                    cache.group_id,
+                    cache.target,
+                    system_bevy_ticks.this_run(),
+                ),
+                OnReplicateRemoved::DetachRemote => sender.prepare_entity_detach(
+                    entity,
+                    cache.group_id,
+                    cache.target,
+                    system_bevy_ticks.this_run(),
+                ),
+            };
+            let _ = result.map_err(|e| {
+                error!("error handling replicate remove: {:?}", e);
+            });
         }
     }
 }
 
-// TODO: maybe only store in the replicate_component_cache the things we need for despawn, which are just replication-target and group-id?
-//  the rest is a waste of memory
 /// This system adds DespawnTracker to each entity that was every replicated,
 /// so that we can track when they are despawned
 /// (we have a distinction between removing Replicate, which just stops replication; and despawning the entity)
+///
+/// The parts of `Replicate` needed to replicate the despawn are cached (and kept up-to-date), so that despawns
+/// are sent to the clients that currently see the entity
 fn add_despawn_tracker<P: Protocol, R: ReplicationSend<P>>(
     mut sender: ResMut<R>,
     mut commands: Commands,
-    query: Query<(Entity, &Replicate<P>), (Added<Replicate<P>>, Without<DespawnTracker>)>,
+    query: Query<(Entity, &Replicate<P>, Has<DespawnTracker>), Changed<Replicate<P>>>,
 ) {
-    for (entity, replicate) in query.iter() {
-        debug!("ADDING DESPAWN TRACKER");
-        commands.entity(entity).insert(DespawnTracker);
-        sender
-            .get_mut_replicate_component_cache()
-            .insert(entity, replicate.clone());
+    for (entity, replicate, has_despawn_tracker) in query.iter() {
+        if !has_despawn_tracker {
+            debug!("ADDING DESPAWN TRACKER");
+            commands.entity(entity).insert(DespawnTracker);
+        }
+        let cache = replicate.replicate_cache(entity);
+        let caches = sender.get_mut_replicate_component_cache();
+        if caches.get(&entity) != Some(&cache) {
+            caches.insert(entity, cache);
+        }
     }
 }
 
@@ -63,7 +93,10 @@ fn send_entity_despawn<P: Protocol, R: ReplicationSend<P>>(
 ) {
     // Despawn entities for clients that lost visibility
     query.iter().for_each(|(entity, replicate)| {
-        if matches!(replicate.replication_mode, ReplicationMode::Room) {
+        if matches!(
+            replicate.replication_mode,
+            ReplicationMode::Room | ReplicationMode::Manual
+        ) {
             replicate
                 .replication_clients_cache
                 .iter()
@@ -76,7 +109,7 @@ fn send_entity_despawn<P: Protocol, R: ReplicationSend<P>>(
                         let _ = sender
                             .prepare_entity_despawn(
                                 entity,
-                                replicate,
+                                replicate.group_id(Some(entity)),
                                 NetworkTarget::Only(vec![*client_id]),
                                 system_bevy_ticks.this_run(),
                             )
@@ -92,14 +125,14 @@ fn send_entity_despawn<P: Protocol, R: ReplicationSend<P>>(
     for entity in despawn_removed.read() {
         trace!("despawn tracker removed!");
         // only replicate the despawn if the entity still had a Replicate component
-        if let Some(replicate) = sender.get_mut_replicate_component_cache().remove(&entity) {
+        if let Some(cache) = sender.get_mut_replicate_component_cache().remove(&entity) {
             // TODO: DO NOT SEND ENTITY DESPAWN TO THE CLIENT WHO JUST DISCONNECTED!
             trace!("send entity despawn");
             let _ = sender
                 .prepare_entity_despawn(
                     entity,
-                    &replicate,
-                    replicate.replication_target.clone(),
+                    cache.group_id,
+                    cache.target,
                     system_bevy_ticks.this_run(),
                 )
                 // TODO: bubble up errors to user via ConnectionEvents
@@ -121,7 +154,7 @@ fn send_entity_spawn<P: Protocol, R: ReplicationSend<P>>(
         match replicate.replication_mode {
             // for room mode, no need to handle newly-connected clients specially; they just need
             // to be added to the correct room
-            ReplicationMode::Room => {
+            ReplicationMode::Room | ReplicationMode::Manual => {
                 replicate
                     .replication_clients_cache
                     .iter()
@@ -147,9 +180,6 @@ fn send_entity_spawn<P: Protocol, R: ReplicationSend<P>>(
                                     // only try to replicate if the replicate component was just added
                                     if replicate.is_added() {
                                         debug!("send entity spawn to maintained");
-                                        sender
-                                            .get_mut_replicate_component_cache()
-                                            .insert(entity, replicate.clone());
                                         let _ = sender
                                             .prepare_entity_spawn(
                                                 entity,
@@ -193,9 +223,6 @@ fn send_entity_spawn<P: Protocol, R: ReplicationSend<P>>(
                 // only try to replicate if the replicate component was just added
                 if replicate.is_added() {
                     trace!(?entity, "send entity spawn");
-                    sender
-                        .get_mut_replicate_component_cache()
-                        .insert(entity, replicate.clone());
                     let _ = sender
                         .prepare_entity_spawn(
                             entity,
@@ -237,7 +264,7 @@ fn send_component_update<C: Component + Clone, P: Protocol, R: ReplicationSend<P
             return;
         }
         match replicate.replication_mode {
-            ReplicationMode::Room => {
+            ReplicationMode::Room | ReplicationMode::Manual => {
                 replicate
                     .replication_clients_cache
                     .iter()
@@ -385,7 +412,7 @@ fn send_component_removed<C: Component + Clone, P: Protocol, R: ReplicationSend<
                 return;
             }
             match replicate.replication_mode {
-                ReplicationMode::Room => {
+                ReplicationMode::Room | ReplicationMode::Manual => {
                     replicate.replication_clients_cache.iter().for_each(
                         |(client_id, visibility)| {
                             if replicate.replication_target.should_send_to(client_id) {
@@ -486,3 +513,140 @@ pub(crate) fn cleanup<P: Protocol, R: ReplicationSend<P>>(
     let tick = tick_manager.tick();
     sender.cleanup(tick);
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::Events;
+    use bevy::utils::Duration;
+
+    use crate::prelude::client::*;
+    use crate::prelude::*;
+    use crate::tests::protocol::Replicate;
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    fn setup() -> BevyStepper {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(0),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.0,
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default().speedup_factor(1.0),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            link_conditioner,
+            frame_duration,
+        );
+        stepper.init();
+        stepper
+    }
+
+    /// Spawn a replicated entity on the server, update its policy, then remove its Replicate component.
+    /// Returns the local entity on the client
+    fn remove_replicate(stepper: &mut BevyStepper, policy: OnReplicateRemoved) -> Entity {
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((Component1(1.0), Replicate::default()))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        // the policy is read when Replicate is removed, not when it was added
+        stepper
+            .server_app
+            .world
+            .entity_mut(server_entity)
+            .get_mut::<Replicate>()
+            .unwrap()
+            .on_replicate_removed = policy;
+        stepper.frame_step();
+        let client_entity = *stepper
+            .client_app
+            .world
+            .resource::<ClientConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .unwrap();
+
+        stepper
+            .server_app
+            .world
+            .entity_mut(server_entity)
+            .remove::<Replicate>();
+        stepper.frame_step();
+        stepper.frame_step();
+        client_entity
+    }
+
+    #[test]
+    fn test_replicate_removed_keep_remote() {
+        let mut stepper = setup();
+        let client_entity = remove_replicate(&mut stepper, OnReplicateRemoved::KeepRemote);
+        // the entity still exists and is still mapped to the server entity, but doesn't get updates
+        assert!(stepper.client_app.world.get_entity(client_entity).is_some());
+        assert!(stepper
+            .client_app
+            .world
+            .resource::<ClientConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_remote(client_entity)
+            .is_some());
+        assert!(stepper
+            .client_app
+            .world
+            .resource::<Events<EntityDetachEvent>>()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_replicate_removed_despawn_remote() {
+        let mut stepper = setup();
+        let client_entity = remove_replicate(&mut stepper, OnReplicateRemoved::DespawnRemote);
+        assert!(stepper.client_app.world.get_entity(client_entity).is_none());
+    }
+
+    #[test]
+    fn test_replicate_removed_detach_remote() {
+        let mut stepper = setup();
+        let client_entity = remove_replicate(&mut stepper, OnReplicateRemoved::DetachRemote);
+        // the entity still exists, but is not replicated anymore
+        assert_eq!(
+            stepper
+                .client_app
+                .world
+                .entity(client_entity)
+                .get::<Component1>(),
+            Some(&Component1(1.0))
+        );
+        assert!(stepper
+            .client_app
+            .world
+            .resource::<ClientConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_remote(client_entity)
+            .is_none());
+        let mut events = stepper
+            .client_app
+            .world
+            .resource_mut::<Events<EntityDetachEvent>>();
+        let detached = events
+            .drain()
+            .map(|event| event.entity())
+            .collect::<Vec<_>>();
+        assert_eq!(detached, vec![client_entity]);
+    }
+}
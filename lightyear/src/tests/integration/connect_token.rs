@@ -0,0 +1,115 @@
+use std::net::SocketAddr;
+
+use bevy::utils::Duration;
+
+use crate::prelude::client::*;
+use crate::prelude::server::{ConnectTokenIssuer, ServerConfig};
+use crate::prelude::*;
+use crate::tests::protocol::*;
+use crate::tests::stepper::{BevyStepper, Step};
+
+/// The client fetches its connect token from a token service before connecting to the server
+#[test]
+// the net configs have other variants with the `steam` feature
+#[allow(irrefutable_let_patterns)]
+fn test_connect_with_requested_token() {
+    let frame_duration = Duration::from_millis(10);
+    let tick_duration = Duration::from_millis(10);
+    let shared_config = SharedConfig {
+        tick: TickConfig::new(tick_duration),
+        ..Default::default()
+    };
+    let mut stepper = BevyStepper::new(
+        shared_config,
+        SyncConfig::default(),
+        PredictionConfig::default(),
+        InterpolationConfig::default(),
+        LinkConditionerConfig::default(),
+        frame_duration,
+    );
+
+    // start a token service that uses the same key as the server
+    let server::NetConfig::Netcode { config, .. } =
+        &stepper.server_app.world.resource::<ServerConfig>().net[0]
+    else {
+        panic!("expected a netcode server");
+    };
+    let issuer = ConnectTokenIssuer::new(
+        SocketAddr::from(([127, 0, 0, 1], 0)),
+        config.protocol_id,
+        config.private_key.unwrap(),
+        |credentials| (credentials == b"secret").then_some(222),
+    );
+    let listener = issuer.listen("127.0.0.1:0").unwrap();
+
+    // the client is denied a token if its credentials are invalid
+    let mut client_config = stepper.client_app.world.resource::<ClientConfig>().clone();
+    let NetConfig::Netcode { auth, .. } = &mut client_config.net else {
+        panic!("expected a netcode client");
+    };
+    *auth = Authentication::TokenService {
+        token_server_addr: listener.local_addr(),
+        credentials: b"wrong".to_vec(),
+    };
+    // the token is requested in the background, the failure is reported when updating the client
+    let mut connection = client_config.net.clone().build_client();
+    connection.connect().unwrap();
+    let start = std::time::Instant::now();
+    while connection.disconnect_reason().is_none() {
+        assert!(start.elapsed() < Duration::from_secs(5));
+        let _ = connection.try_update(0.0);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert!(!connection.is_connected());
+    assert_eq!(
+        connection.disconnect_reason(),
+        Some(DisconnectReason::TokenRequestDenied)
+    );
+
+    let NetConfig::Netcode { auth, .. } = &mut client_config.net else {
+        unreachable!()
+    };
+    *auth = Authentication::TokenService {
+        token_server_addr: listener.local_addr(),
+        credentials: b"secret".to_vec(),
+    };
+    stepper
+        .client_app
+        .world
+        .insert_resource(client_config.net.clone().build_client());
+    stepper.client_app.world.insert_resource(client_config);
+    stepper
+        .client_app
+        .world
+        .resource_mut::<ClientConnection>()
+        .connect()
+        .unwrap();
+    // the token is received in the background, so we keep stepping until the client is synced
+    let start = std::time::Instant::now();
+    while !stepper
+        .client_app
+        .world
+        .resource::<ClientConnectionManager>()
+        .is_synced()
+    {
+        assert!(start.elapsed() < Duration::from_secs(5));
+        stepper.frame_step();
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    assert!(stepper
+        .client_app
+        .world
+        .resource::<ClientConnection>()
+        .is_connected());
+    assert_eq!(
+        stepper.client_app.world.resource::<ClientConnection>().id(),
+        222
+    );
+    assert!(stepper
+        .server_app
+        .world
+        .resource::<ServerConnectionManager>()
+        .connection(222)
+        .is_ok());
+}
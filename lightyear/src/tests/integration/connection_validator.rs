@@ -0,0 +1,154 @@
+use bevy::prelude::*;
+use bevy::utils::Duration;
+
+use crate::connection::netcode::{ConnectToken, USER_DATA_BYTES};
+use crate::prelude::client::*;
+use crate::prelude::server::{ServerConfig, ServerConnections};
+use crate::prelude::*;
+use crate::tests::protocol::*;
+use crate::tests::stepper::{BevyStepper, Step};
+
+/// Code used by the validator to deny clients with an outdated version
+const OUTDATED_VERSION: u8 = 3;
+const VERSION: u8 = 2;
+
+/// Build a stepper where the server only accepts clients whose connect token contains the current version,
+/// and where the client connects with a token containing `version`
+// the net configs have other variants with the `steam` feature
+#[allow(irrefutable_let_patterns)]
+fn setup(version: u8) -> BevyStepper {
+    let frame_duration = Duration::from_millis(10);
+    let tick_duration = Duration::from_millis(10);
+    let shared_config = SharedConfig {
+        tick: TickConfig::new(tick_duration),
+        ..Default::default()
+    };
+    let mut stepper = BevyStepper::new(
+        shared_config,
+        SyncConfig::default(),
+        PredictionConfig::default(),
+        InterpolationConfig::default(),
+        LinkConditionerConfig::default(),
+        frame_duration,
+    );
+
+    // restart the server with a validator
+    let mut server_config = stepper.server_app.world.resource::<ServerConfig>().clone();
+    let server::NetConfig::Netcode { config, .. } = &mut server_config.net[0] else {
+        panic!("expected a netcode server");
+    };
+    *config = config.clone().with_validator(|_, user_data| {
+        if user_data[0] == VERSION {
+            Ok(())
+        } else {
+            Err(OUTDATED_VERSION)
+        }
+    });
+    stepper
+        .server_app
+        .world
+        .insert_resource(ServerConnections::new(server_config.net.clone()));
+    stepper.server_app.world.insert_resource(server_config);
+
+    // connect with a token that contains the client's version
+    let mut client_config = stepper.client_app.world.resource::<ClientConfig>().clone();
+    let NetConfig::Netcode { auth, .. } = &mut client_config.net else {
+        panic!("expected a netcode client");
+    };
+    let Authentication::Manual {
+        server_addr,
+        protocol_id,
+        private_key,
+        client_id,
+    } = auth.clone()
+    else {
+        panic!("expected manual authentication");
+    };
+    let mut user_data = [0; USER_DATA_BYTES];
+    user_data[0] = version;
+    let token = ConnectToken::build(server_addr, protocol_id, client_id, private_key)
+        .user_data(user_data)
+        .generate()
+        .unwrap();
+    *auth = Authentication::Token(token);
+    client_config.reconnect = ReconnectConfig::default()
+        .with_max_attempts(5)
+        .with_initial_delay(Duration::from_millis(100));
+    stepper
+        .client_app
+        .world
+        .insert_resource(client_config.net.clone().build_client());
+    stepper.client_app.world.insert_resource(client_config);
+    stepper
+}
+
+/// The user data of the connect token is available in the server's `ConnectEvent`
+#[test]
+fn test_connect_event_user_data() {
+    let mut stepper = setup(VERSION);
+    stepper
+        .client_app
+        .world
+        .resource_mut::<ClientConnection>()
+        .connect()
+        .unwrap();
+    let mut server_events = vec![];
+    let mut client_connected = false;
+    for _ in 0..100 {
+        stepper.frame_step();
+        server_events.extend(
+            stepper
+                .server_app
+                .world
+                .resource_mut::<Events<server::ConnectEvent>>()
+                .drain(),
+        );
+        client_connected |= stepper
+            .client_app
+            .world
+            .resource_mut::<Events<ConnectEvent>>()
+            .drain()
+            .count()
+            > 0;
+    }
+
+    assert!(client_connected);
+    assert_eq!(server_events.len(), 1);
+    assert_eq!(*server_events[0].context(), 111);
+    assert_eq!(server_events[0].user_data().unwrap()[0], VERSION);
+}
+
+/// The validator denies the connection, and the client receives the reason in its `DisconnectEvent`
+#[test]
+fn test_validator_denies_connection() {
+    let mut stepper = setup(VERSION - 1);
+    stepper
+        .client_app
+        .world
+        .resource_mut::<ClientConnection>()
+        .connect()
+        .unwrap();
+    let mut reasons = vec![];
+    for _ in 0..100 {
+        stepper.frame_step();
+        let mut events = stepper
+            .client_app
+            .world
+            .resource_mut::<Events<DisconnectEvent>>();
+        reasons.extend(events.drain().map(|event| event.reason()));
+    }
+
+    // the client is denied once, and doesn't try to reconnect
+    assert_eq!(reasons, vec![DisconnectReason::Denied(OUTDATED_VERSION)]);
+    let netclient = stepper.client_app.world.resource::<ClientConnection>();
+    assert!(!netclient.is_connected());
+    assert_eq!(
+        netclient.disconnect_reason(),
+        Some(DisconnectReason::Denied(OUTDATED_VERSION))
+    );
+    assert!(stepper
+        .server_app
+        .world
+        .resource::<Events<server::ConnectEvent>>()
+        .is_empty());
+}
@@ -0,0 +1,145 @@
+use bevy::prelude::*;
+use bevy::utils::Duration;
+
+use crate::prelude::client::*;
+use crate::prelude::server::{ServerConfig, ServerConnections};
+use crate::prelude::*;
+use crate::tests::protocol::*;
+use crate::tests::stepper::{BevyStepper, Step};
+
+fn setup() -> BevyStepper {
+    let frame_duration = Duration::from_millis(10);
+    let tick_duration = Duration::from_millis(10);
+    let shared_config = SharedConfig {
+        tick: TickConfig::new(tick_duration),
+        ..Default::default()
+    };
+    let mut stepper = BevyStepper::new(
+        shared_config,
+        SyncConfig::default(),
+        PredictionConfig::default(),
+        InterpolationConfig::default(),
+        LinkConditionerConfig::default(),
+        frame_duration,
+    );
+    stepper
+        .client_app
+        .world
+        .resource_mut::<ClientConfig>()
+        .reconnect = ReconnectConfig::default()
+        .with_max_attempts(5)
+        .with_initial_delay(Duration::from_millis(100));
+    stepper
+        .server_app
+        .world
+        .resource_mut::<ServerConfig>()
+        .connection =
+        server::ConnectionConfig::default().with_disconnect_grace_period(Duration::from_secs(20));
+    stepper.init();
+    stepper
+}
+
+/// Step the apps and collect the reasons of the disconnect events emitted on the client and on the server
+fn collect_disconnect_reasons(
+    stepper: &mut BevyStepper,
+    frames: usize,
+) -> (Vec<DisconnectReason>, Vec<(ClientId, DisconnectReason)>) {
+    let mut client_reasons = vec![];
+    let mut server_reasons = vec![];
+    for _ in 0..frames {
+        stepper.frame_step();
+        client_reasons.extend(
+            stepper
+                .client_app
+                .world
+                .resource_mut::<Events<DisconnectEvent>>()
+                .drain()
+                .map(|event| event.reason()),
+        );
+        server_reasons.extend(
+            stepper
+                .server_app
+                .world
+                .resource_mut::<Events<server::DisconnectEvent>>()
+                .drain()
+                .map(|event| (*event.context(), event.reason())),
+        );
+    }
+    (client_reasons, server_reasons)
+}
+
+/// The server kicks the client: both sides receive the reason, the session is removed
+/// without waiting for the grace period, and the client does not try to reconnect
+#[test]
+fn test_kick_client() {
+    let mut stepper = setup();
+    let client_id = 111;
+    stepper
+        .server_app
+        .world
+        .resource_mut::<ServerConnections>()
+        .kick(client_id, 4)
+        .unwrap();
+
+    let (client_reasons, server_reasons) = collect_disconnect_reasons(&mut stepper, 100);
+    assert_eq!(client_reasons, vec![DisconnectReason::Kicked(4)]);
+    assert_eq!(
+        server_reasons,
+        vec![(client_id, DisconnectReason::Kicked(4))]
+    );
+    assert!(!stepper
+        .client_app
+        .world
+        .resource::<ClientConnection>()
+        .is_connected());
+    let connection_manager = stepper
+        .server_app
+        .world
+        .resource::<ServerConnectionManager>();
+    assert!(!connection_manager.is_suspended(client_id));
+    assert!(connection_manager.connection(client_id).is_err());
+}
+
+/// The client disconnects voluntarily
+#[test]
+fn test_client_disconnect() {
+    let mut stepper = setup();
+    stepper
+        .client_app
+        .world
+        .resource_mut::<ClientConnection>()
+        .disconnect()
+        .unwrap();
+
+    let (client_reasons, server_reasons) = collect_disconnect_reasons(&mut stepper, 100);
+    assert_eq!(client_reasons, vec![DisconnectReason::ClientDisconnected]);
+    assert_eq!(
+        server_reasons,
+        vec![(111, DisconnectReason::ClientDisconnected)]
+    );
+}
+
+/// The server stops hearing from the client: the disconnection is reported as a timeout once the grace period expires
+#[test]
+fn test_timeout_reason() {
+    let mut stepper = setup();
+    stepper
+        .server_app
+        .world
+        .resource_mut::<ServerConfig>()
+        .connection = server::ConnectionConfig::default();
+    let mut server_reasons = vec![];
+    for _ in 0..400 {
+        stepper.advance_time(stepper.frame_duration);
+        stepper.server_app.update();
+        server_reasons.extend(
+            stepper
+                .server_app
+                .world
+                .resource_mut::<Events<server::DisconnectEvent>>()
+                .drain()
+                .map(|event| event.reason()),
+        );
+    }
+    assert_eq!(server_reasons, vec![DisconnectReason::Timeout]);
+}
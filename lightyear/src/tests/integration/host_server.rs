@@ -0,0 +1,296 @@
+use std::net::SocketAddr;
+
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use bevy::utils::Duration;
+
+use crate::client::components::Confirmed;
+use crate::connection::client::{ClientConnection, NetClient};
+use crate::prelude::client::{ClientConfig, InputSystemSet, Interpolated, Predicted};
+use crate::prelude::server::{NetcodeConfig, ServerConfig};
+use crate::prelude::*;
+use crate::tests::protocol::*;
+
+const LOCAL_CLIENT_ID: ClientId = 5;
+
+/// Inputs received by the server, with the tick at which they were received
+#[derive(Resource, Default)]
+struct ReceivedInputs(Vec<(Tick, ClientId, Option<MyInput>)>);
+
+fn press_input(mut connection: ResMut<ClientConnectionManager>, tick_manager: Res<TickManager>) {
+    let tick = tick_manager.tick();
+    connection.add_input(MyInput(tick.0 as i16), tick);
+}
+
+fn receive_inputs(
+    tick_manager: Res<TickManager>,
+    mut events: EventReader<server::InputEvent<MyInput>>,
+    mut received: ResMut<ReceivedInputs>,
+) {
+    for event in events.read() {
+        received
+            .0
+            .push((tick_manager.tick(), *event.context(), event.input().clone()));
+    }
+}
+
+/// Single app that runs the server and the local client
+struct HostServerStepper {
+    app: App,
+    frame_duration: Duration,
+    current_time: bevy::utils::Instant,
+}
+
+impl HostServerStepper {
+    fn new() -> Self {
+        let frame_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(Duration::from_millis(10)),
+            mode: Mode::HostServer,
+            ..Default::default()
+        };
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins.build());
+
+        // the server still accepts remote clients
+        let (_, to_server_recv) = crossbeam_channel::unbounded();
+        let (from_server_send, _) = crossbeam_channel::unbounded();
+        let server_io = IoConfig::from_transport(TransportConfig::Channels {
+            channels: vec![(
+                SocketAddr::from(([127, 0, 0, 1], 0)),
+                to_server_recv,
+                from_server_send,
+            )],
+        });
+        let config = ServerConfig {
+            shared: shared_config.clone(),
+            net: vec![server::NetConfig::Netcode {
+                config: NetcodeConfig::default().with_key(generate_key()),
+                io: server_io,
+            }],
+            ..Default::default()
+        };
+        app.add_plugins(server::ServerPlugin::new(server::PluginConfig::new(
+            config,
+            protocol(),
+        )));
+
+        let config = ClientConfig {
+            shared: shared_config,
+            net: client::NetConfig::Local {
+                id: LOCAL_CLIENT_ID,
+            },
+            ..Default::default()
+        };
+        app.add_plugins(client::ClientPlugin::new(client::PluginConfig::new(
+            config,
+            protocol(),
+        )));
+
+        let now = bevy::utils::Instant::now();
+        app.world
+            .resource_mut::<Time<Real>>()
+            .update_with_instant(now);
+        Self {
+            app,
+            frame_duration,
+            current_time: now,
+        }
+    }
+
+    fn frame_step(&mut self) {
+        self.current_time += self.frame_duration;
+        self.app
+            .insert_resource(TimeUpdateStrategy::ManualInstant(self.current_time));
+        mock_instant::MockClock::advance(self.frame_duration);
+        self.app.update();
+    }
+
+    /// Connect the local client, and return the ids of the server ConnectEvents
+    fn connect(&mut self) -> Vec<ClientId> {
+        self.app
+            .world
+            .resource_mut::<ClientConnection>()
+            .connect()
+            .unwrap();
+        self.frame_step();
+        self.app
+            .world
+            .resource_mut::<Events<server::ConnectEvent>>()
+            .drain()
+            .map(|event| *event.context())
+            .collect()
+    }
+}
+
+#[test]
+fn test_local_client_connect() {
+    let mut stepper = HostServerStepper::new();
+    assert_eq!(stepper.connect(), vec![LOCAL_CLIENT_ID]);
+    assert_eq!(
+        stepper
+            .app
+            .world
+            .resource_mut::<Events<client::ConnectEvent>>()
+            .drain()
+            .count(),
+        1
+    );
+    assert!(stepper
+        .app
+        .world
+        .resource::<ClientConnectionManager>()
+        .is_synced());
+    assert_eq!(
+        stepper
+            .app
+            .world
+            .resource::<client::GlobalMetadata>()
+            .client_id,
+        Some(LOCAL_CLIENT_ID)
+    );
+
+    // the local client disconnects
+    stepper
+        .app
+        .world
+        .resource_mut::<ClientConnection>()
+        .disconnect()
+        .unwrap();
+    stepper.frame_step();
+    let disconnects = stepper
+        .app
+        .world
+        .resource_mut::<Events<server::DisconnectEvent>>()
+        .drain()
+        .map(|event| (*event.context(), event.reason()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        disconnects,
+        vec![(LOCAL_CLIENT_ID, DisconnectReason::ClientDisconnected)]
+    );
+    assert!(stepper
+        .app
+        .world
+        .resource::<ServerConnectionManager>()
+        .connection(LOCAL_CLIENT_ID)
+        .is_err());
+}
+
+/// The replicated entities are not duplicated for the local client
+#[test]
+fn test_local_client_no_duplicate_entities() {
+    let mut stepper = HostServerStepper::new();
+    stepper.connect();
+
+    let predicted = stepper
+        .app
+        .world
+        .spawn((
+            Component1(0.0),
+            Replicate {
+                replication_target: NetworkTarget::All,
+                prediction_target: NetworkTarget::Single(LOCAL_CLIENT_ID),
+                ..default()
+            },
+        ))
+        .id();
+    let interpolated = stepper
+        .app
+        .world
+        .spawn((
+            Component1(0.0),
+            Replicate {
+                replication_target: NetworkTarget::All,
+                interpolation_target: NetworkTarget::All,
+                ..default()
+            },
+        ))
+        .id();
+    for _ in 0..10 {
+        stepper.frame_step();
+    }
+
+    let world = &mut stepper.app.world;
+    assert_eq!(world.query::<&Confirmed>().iter(world).count(), 0);
+    assert_eq!(world.query::<&Component1>().iter(world).count(), 2);
+    assert_eq!(
+        world.get::<Predicted>(predicted).unwrap().confirmed_entity,
+        Some(predicted)
+    );
+    assert!(world.get::<Interpolated>(predicted).is_none());
+    assert_eq!(
+        world
+            .get::<Interpolated>(interpolated)
+            .unwrap()
+            .confirmed_entity,
+        interpolated
+    );
+    assert!(world.get::<Predicted>(interpolated).is_none());
+}
+
+#[test]
+fn test_local_client_messages() {
+    let mut stepper = HostServerStepper::new();
+    stepper.connect();
+
+    // client -> server
+    stepper
+        .app
+        .world
+        .resource_mut::<ClientConnectionManager>()
+        .send_message::<Channel1, Message1>(Message1("client".to_string()))
+        .unwrap();
+    stepper.frame_step();
+    let received = stepper
+        .app
+        .world
+        .resource_mut::<Events<server::MessageEvent<Message1>>>()
+        .drain()
+        .map(|event| (*event.context(), event.message().0.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(received, vec![(LOCAL_CLIENT_ID, "client".to_string())]);
+
+    // server -> client
+    stepper
+        .app
+        .world
+        .resource_mut::<ServerConnectionManager>()
+        .send_message::<Channel1, Message2>(LOCAL_CLIENT_ID, Message2(3))
+        .unwrap();
+    stepper.frame_step();
+    let received = stepper
+        .app
+        .world
+        .resource_mut::<Events<client::MessageEvent<Message2>>>()
+        .drain()
+        .map(|event| event.message().0)
+        .collect::<Vec<_>>();
+    assert_eq!(received, vec![3]);
+}
+
+/// The inputs of the local client are received by the server on the tick where they are generated
+#[test]
+fn test_local_client_inputs() {
+    let mut stepper = HostServerStepper::new();
+    stepper
+        .app
+        .init_resource::<ReceivedInputs>()
+        .add_systems(
+            FixedPreUpdate,
+            press_input.in_set(InputSystemSet::BufferInputs),
+        )
+        .add_systems(FixedUpdate, receive_inputs);
+    stepper.connect();
+    stepper.app.world.resource_mut::<ReceivedInputs>().0.clear();
+
+    for _ in 0..10 {
+        stepper.frame_step();
+    }
+    let received = &stepper.app.world.resource::<ReceivedInputs>().0;
+    assert!(!received.is_empty());
+    for (tick, client_id, input) in received {
+        assert_eq!(*client_id, LOCAL_CLIENT_ID);
+        assert_eq!(*input, Some(MyInput(tick.0 as i16)));
+    }
+}
@@ -0,0 +1,120 @@
+use std::net::{SocketAddr, UdpSocket};
+
+use bevy::utils::Duration;
+
+use crate::prelude::client::*;
+use crate::prelude::server::{LanConfig, ServerConfig, ServerConnections};
+use crate::prelude::*;
+use crate::tests::protocol::*;
+use crate::tests::stepper::{BevyStepper, Step};
+
+/// The client finds the server on the local network and joins it without a pre-shared key
+#[test]
+// the net configs have other variants with the `steam` feature
+#[allow(irrefutable_let_patterns)]
+fn test_discover_and_connect() {
+    let frame_duration = Duration::from_millis(10);
+    let tick_duration = Duration::from_millis(10);
+    let shared_config = SharedConfig {
+        tick: TickConfig::new(tick_duration),
+        ..Default::default()
+    };
+    let mut stepper = BevyStepper::new(
+        shared_config,
+        SyncConfig::default(),
+        PredictionConfig::default(),
+        InterpolationConfig::default(),
+        LinkConditionerConfig::default(),
+        frame_duration,
+    );
+
+    // find a free port for the discovery responder
+    let discovery_addr = UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    // restart the server in LAN mode, with a random private key
+    let mut server_config = stepper.server_app.world.resource::<ServerConfig>().clone();
+    let server::NetConfig::Netcode { config, io } = server_config.net[0].clone() else {
+        panic!("expected a netcode server");
+    };
+    server_config.net[0] = server::NetConfig::Lan {
+        config: LanConfig::default()
+            .with_name("test server")
+            .with_discovery_addr(discovery_addr)
+            .with_max_players(4)
+            .with_netcode(server::NetcodeConfig {
+                private_key: None,
+                ..config
+            }),
+        io,
+    };
+    stepper
+        .server_app
+        .world
+        .insert_resource(ServerConnections::new(server_config.net.clone()));
+    stepper.server_app.world.insert_resource(server_config);
+
+    let servers = scan(discovery_addr, Duration::from_millis(200)).unwrap();
+    assert_eq!(servers.len(), 1);
+    assert_eq!(servers[0].name, "test server");
+    assert_eq!(servers[0].discovery_addr, discovery_addr);
+    assert_eq!(servers[0].players, 0);
+    assert_eq!(servers[0].max_players, 4);
+    assert_eq!(servers[0].protocol_hash, protocol().protocol_hash());
+
+    // join the server that was found
+    let mut client_config = stepper.client_app.world.resource::<ClientConfig>().clone();
+    let NetConfig::Netcode { config, io, .. } = client_config.net.clone() else {
+        panic!("expected a netcode client");
+    };
+    client_config.net = NetConfig::Lan {
+        discovery_addr: servers[0].discovery_addr,
+        session: LanSession::default(),
+        config,
+        io,
+    };
+    stepper
+        .client_app
+        .world
+        .insert_resource(client_config.net.clone().build_client());
+    stepper.client_app.world.insert_resource(client_config);
+    stepper
+        .client_app
+        .world
+        .resource_mut::<ClientConnection>()
+        .connect()
+        .unwrap();
+    // the token is negotiated in the background, so we keep stepping until the client is synced
+    let start = std::time::Instant::now();
+    while !stepper
+        .client_app
+        .world
+        .resource::<ClientConnectionManager>()
+        .is_synced()
+    {
+        assert!(start.elapsed() < Duration::from_secs(5));
+        stepper.frame_step();
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    // the server chooses the id of the client
+    let client_id = stepper.client_app.world.resource::<ClientConnection>().id();
+    assert_ne!(client_id, 0);
+    assert!(stepper
+        .client_app
+        .world
+        .resource::<ClientConnection>()
+        .is_connected());
+    assert!(stepper
+        .server_app
+        .world
+        .resource::<ServerConnectionManager>()
+        .connection(client_id)
+        .is_ok());
+
+    // the server advertises its new player
+    let servers = scan(discovery_addr, Duration::from_millis(200)).unwrap();
+    assert_eq!(servers[0].players, 1);
+}
@@ -1,2 +1,10 @@
+mod connect_token;
+mod connection_validator;
+mod disconnect;
+mod host_server;
+mod lan;
 mod multi_transport;
+mod protocol_mismatch;
+mod rate_limit;
+mod reconnection;
 mod tick_wrapping;
@@ -281,6 +281,7 @@ fn test_multi_transport() {
         incoming_latency: Duration::from_millis(20),
         incoming_jitter: Duration::from_millis(0),
         incoming_loss: 0.0,
+        ..Default::default()
     };
     let mut stepper = MultiBevyStepper::new(
         shared_config,
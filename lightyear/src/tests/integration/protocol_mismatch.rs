@@ -0,0 +1,112 @@
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use lightyear_macros::ChannelInternal;
+
+use crate::packet::compression::CompressionConfig;
+use crate::prelude::client::*;
+use crate::prelude::*;
+use crate::protocol::component::ComponentProtocol;
+use crate::protocol::message::MessageProtocol;
+use crate::tests::protocol::*;
+use crate::tests::stepper::{BevyStepper, Step};
+
+#[derive(ChannelInternal)]
+struct ExtraChannel;
+
+/// The client's protocol has an additional channel compared to the server's protocol
+fn client_protocol() -> MyProtocol {
+    let mut protocol = protocol();
+    protocol.add_channel::<ExtraChannel>(ChannelSettings::default());
+    protocol
+}
+
+/// The client uses the given protocol hash to connect to the server
+fn setup(client_protocol_hash: u64) -> BevyStepper {
+    let frame_duration = Duration::from_millis(10);
+    let tick_duration = Duration::from_millis(10);
+    let shared_config = SharedConfig {
+        tick: TickConfig::new(tick_duration),
+        ..Default::default()
+    };
+    let mut stepper = BevyStepper::new(
+        shared_config,
+        SyncConfig::default(),
+        PredictionConfig::default(),
+        InterpolationConfig::default(),
+        LinkConditionerConfig::default(),
+        frame_duration,
+    );
+    let mut client_config = stepper.client_app.world.resource::<ClientConfig>().clone();
+    client_config.net.set_protocol_hash(client_protocol_hash);
+    stepper
+        .client_app
+        .world
+        .insert_resource(client_config.net.clone().build_client());
+    stepper.client_app.world.insert_resource(client_config);
+    stepper
+}
+
+#[test]
+fn test_protocol_hash() {
+    assert_eq!(protocol().protocol_hash(), protocol().protocol_hash());
+    assert_ne!(
+        protocol().protocol_hash(),
+        client_protocol().protocol_hash()
+    );
+}
+
+/// The custom serializers are part of the protocol hash, since they change the encoding
+#[test]
+fn test_schema_includes_serializer() {
+    assert!(MyComponentsProtocol::schema().iter().any(
+        |entry| entry.starts_with("Component6") && entry.ends_with("with=Component6Quantizer")
+    ));
+    assert!(MyMessageProtocol::schema()
+        .iter()
+        .any(|entry| entry.contains("BitcodeSerializer")));
+}
+
+/// The server denies the connection of a client built with a different protocol
+#[test]
+fn test_protocol_mismatch_denies_connection() {
+    let stepper = setup(client_protocol().protocol_hash());
+    assert_connection_denied(stepper);
+}
+
+/// The server denies the connection of a client that uses a different compression
+#[test]
+fn test_compression_mismatch_denies_connection() {
+    let stepper = setup(CompressionConfig::Lz4.protocol_hash(protocol().protocol_hash()));
+    assert_connection_denied(stepper);
+}
+
+fn assert_connection_denied(mut stepper: BevyStepper) {
+    stepper
+        .client_app
+        .world
+        .resource_mut::<ClientConnection>()
+        .connect()
+        .unwrap();
+    let mut reasons = vec![];
+    for _ in 0..100 {
+        stepper.frame_step();
+        let mut events = stepper
+            .client_app
+            .world
+            .resource_mut::<Events<DisconnectEvent>>();
+        reasons.extend(events.drain().map(|event| event.reason()));
+    }
+
+    assert_eq!(reasons, vec![DisconnectReason::ProtocolMismatch]);
+    let netclient = stepper.client_app.world.resource::<ClientConnection>();
+    assert!(!netclient.is_connected());
+    assert_eq!(
+        netclient.disconnect_reason(),
+        Some(DisconnectReason::ProtocolMismatch)
+    );
+    assert!(stepper
+        .server_app
+        .world
+        .resource::<Events<server::ConnectEvent>>()
+        .is_empty());
+}
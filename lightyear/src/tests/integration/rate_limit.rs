@@ -0,0 +1,136 @@
+use bevy::prelude::*;
+use bevy::utils::Duration;
+
+use crate::prelude::client::*;
+use crate::prelude::server::{RateLimitConfig, RateLimitPolicy};
+use crate::prelude::*;
+use crate::server::rate_limit::ClientRateLimiter;
+use crate::tests::protocol::*;
+use crate::tests::stepper::{BevyStepper, Step};
+
+const CLIENT_ID: ClientId = 111;
+
+fn setup(rate_limit: RateLimitConfig) -> BevyStepper {
+    let frame_duration = Duration::from_millis(10);
+    let tick_duration = Duration::from_millis(10);
+    let shared_config = SharedConfig {
+        tick: TickConfig::new(tick_duration),
+        ..Default::default()
+    };
+    let mut stepper = BevyStepper::new(
+        shared_config,
+        SyncConfig::default(),
+        PredictionConfig::default(),
+        InterpolationConfig::default(),
+        LinkConditionerConfig::default(),
+        frame_duration,
+    );
+    stepper.init();
+    // only apply the limits once the client is connected, so that the handshake is not affected
+    let mut manager = stepper
+        .server_app
+        .world
+        .resource_mut::<ServerConnectionManager>();
+    manager.rate_limit_config = rate_limit.clone();
+    manager.connection_mut(CLIENT_ID).unwrap().rate_limiter = ClientRateLimiter::new(rate_limit);
+    stepper
+}
+
+/// Send `count` messages from the client in a single frame, and return the number of messages
+/// received by the server
+fn send_messages(stepper: &mut BevyStepper, count: usize) -> usize {
+    send_messages_on::<Channel1>(stepper, count)
+}
+
+fn send_messages_on<C: Channel>(stepper: &mut BevyStepper, count: usize) -> usize {
+    for i in 0..count {
+        stepper
+            .client_app
+            .world
+            .resource_mut::<ClientConnectionManager>()
+            .send_message::<C, Message1>(Message1(i.to_string()))
+            .unwrap();
+    }
+    let mut received = 0;
+    for _ in 0..20 {
+        stepper.frame_step();
+        received += stepper
+            .server_app
+            .world
+            .resource_mut::<Events<server::MessageEvent<Message1>>>()
+            .drain()
+            .count();
+    }
+    received
+}
+
+fn stats(stepper: &BevyStepper) -> server::RateLimitStats {
+    stepper
+        .server_app
+        .world
+        .resource::<ServerConnectionManager>()
+        .rate_limit_stats(CLIENT_ID)
+        .unwrap()
+}
+
+#[test]
+fn test_drop_messages_over_limit() {
+    let mut stepper = setup(RateLimitConfig::default().with_messages_per_channel_per_tick(2));
+    assert_eq!(send_messages(&mut stepper, 5), 2);
+    assert_eq!(stats(&stepper).dropped_messages, 3);
+    assert!(stepper
+        .client_app
+        .world
+        .resource::<ClientConnection>()
+        .is_connected());
+}
+
+/// The messages of channels that track acks are not limited, since the client would consider them delivered
+#[test]
+fn test_ack_channel_messages_not_limited() {
+    let mut stepper = setup(RateLimitConfig::default().with_messages_per_channel_per_tick(2));
+    assert_eq!(send_messages_on::<Channel2>(&mut stepper, 5), 5);
+    assert_eq!(stats(&stepper).dropped_messages, 0);
+}
+
+#[test]
+fn test_throttle_messages_over_limit() {
+    let mut stepper = setup(
+        RateLimitConfig::default()
+            .with_messages_per_channel_per_tick(2)
+            .with_policy(RateLimitPolicy::Throttle),
+    );
+    // the messages over the limit are delayed to the next ticks instead of being dropped
+    assert_eq!(send_messages(&mut stepper, 5), 5);
+    let stats = stats(&stepper);
+    assert_eq!(stats.throttled_messages, 3);
+    assert_eq!(stats.dropped_messages, 0);
+}
+
+#[test]
+fn test_disconnect_client_over_limit() {
+    let mut stepper = setup(
+        RateLimitConfig::default()
+            .with_packets_per_second(1)
+            .with_policy(RateLimitPolicy::Disconnect),
+    );
+    let mut client_reasons = vec![];
+    for _ in 0..100 {
+        stepper.frame_step();
+        client_reasons.extend(
+            stepper
+                .client_app
+                .world
+                .resource_mut::<Events<DisconnectEvent>>()
+                .drain()
+                .map(|event| event.reason()),
+        );
+    }
+    assert_eq!(client_reasons, vec![DisconnectReason::RateLimited]);
+    assert!(stepper
+        .server_app
+        .world
+        .resource::<ServerConnectionManager>()
+        .connection(CLIENT_ID)
+        .is_err());
+}
@@ -0,0 +1,190 @@
+use bevy::prelude::*;
+use bevy::utils::Duration;
+
+use crate::prelude::client::*;
+use crate::prelude::server::{DisconnectEvent, RoomManager, ServerConfig};
+use crate::prelude::*;
+use crate::tests::protocol::*;
+use crate::tests::stepper::{BevyStepper, Step};
+
+/// Advance the time and update only one of the apps, so that the other one doesn't receive or send any packet
+fn step_only(stepper: &mut BevyStepper, server: bool, duration: Duration) {
+    let frames = (duration.as_secs_f32() / stepper.frame_duration.as_secs_f32()) as u32;
+    for _ in 0..frames {
+        stepper.advance_time(stepper.frame_duration);
+        if server {
+            stepper.server_app.update();
+        } else {
+            stepper.client_app.update();
+        }
+    }
+}
+
+/// The connection is lost, the client reconnects automatically during the server's grace period,
+/// and the session is resumed: the client keeps its entities and receives what changed while it was gone.
+#[test]
+fn test_reconnect_during_grace_period() {
+    let frame_duration = Duration::from_millis(10);
+    let tick_duration = Duration::from_millis(10);
+    let shared_config = SharedConfig {
+        tick: TickConfig::new(tick_duration),
+        ..Default::default()
+    };
+    let link_conditioner = LinkConditionerConfig::default();
+    let mut stepper = BevyStepper::new(
+        shared_config,
+        SyncConfig::default().speedup_factor(1.0),
+        PredictionConfig::default(),
+        InterpolationConfig::default(),
+        link_conditioner,
+        frame_duration,
+    );
+    stepper
+        .client_app
+        .world
+        .resource_mut::<ClientConfig>()
+        .reconnect = ReconnectConfig::default()
+        .with_max_attempts(5)
+        .with_initial_delay(Duration::from_millis(100));
+    stepper
+        .server_app
+        .world
+        .resource_mut::<ServerConfig>()
+        .connection =
+        server::ConnectionConfig::default().with_disconnect_grace_period(Duration::from_secs(20));
+    stepper.init();
+
+    let client_id = 111;
+    let room_id = server::RoomId(0);
+    stepper
+        .server_app
+        .world
+        .resource_mut::<RoomManager>()
+        .room_mut(room_id)
+        .add_client(client_id);
+    let server_entity = stepper
+        .server_app
+        .world
+        .spawn((Component1(0.0), Replicate::default()))
+        .id();
+    stepper.frame_step();
+    stepper.frame_step();
+    let client_entity = *stepper
+        .client_app
+        .world
+        .resource::<ClientConnectionManager>()
+        .replication_receiver
+        .remote_entity_map
+        .get_local(server_entity)
+        .unwrap();
+
+    // the client stops communicating: the server times out the client and keeps its session
+    step_only(&mut stepper, true, Duration::from_secs(4));
+    assert!(stepper
+        .server_app
+        .world
+        .resource::<ServerConnectionManager>()
+        .is_suspended(client_id));
+    // the world changes while the client is gone
+    stepper
+        .server_app
+        .world
+        .entity_mut(server_entity)
+        .insert(Component1(1.0));
+    let new_server_entity = stepper
+        .server_app
+        .world
+        .spawn((Component2(2.0), Replicate::default()))
+        .id();
+    stepper.frame_step();
+
+    // the client times out as well, then reconnects
+    step_only(&mut stepper, false, Duration::from_secs(4));
+    assert!(!stepper
+        .client_app
+        .world
+        .resource::<ClientConnection>()
+        .is_connected());
+    for _ in 0..100 {
+        stepper.frame_step();
+    }
+    assert!(stepper
+        .client_app
+        .world
+        .resource::<ClientConnection>()
+        .is_connected());
+
+    // the session was resumed
+    let server_world = &stepper.server_app.world;
+    assert!(!server_world
+        .resource::<ServerConnectionManager>()
+        .is_suspended(client_id));
+    assert!(server_world
+        .resource::<Events<DisconnectEvent>>()
+        .is_empty());
+    assert!(server_world
+        .resource::<RoomManager>()
+        .room(room_id)
+        .has_client_id(client_id));
+    // the client kept its entity and received the updates it missed
+    assert_eq!(
+        stepper.client_app.world.get::<Component1>(client_entity),
+        Some(&Component1(1.0))
+    );
+    let new_client_entity = *stepper
+        .client_app
+        .world
+        .resource::<ClientConnectionManager>()
+        .replication_receiver
+        .remote_entity_map
+        .get_local(new_server_entity)
+        .unwrap();
+    assert_eq!(
+        stepper
+            .client_app
+            .world
+            .get::<Component2>(new_client_entity),
+        Some(&Component2(2.0))
+    );
+}
+
+/// If the client doesn't come back before the end of the grace period, the session is removed
+#[test]
+fn test_grace_period_expires() {
+    let frame_duration = Duration::from_millis(10);
+    let tick_duration = Duration::from_millis(10);
+    let shared_config = SharedConfig {
+        tick: TickConfig::new(tick_duration),
+        ..Default::default()
+    };
+    let mut stepper = BevyStepper::new(
+        shared_config,
+        SyncConfig::default().speedup_factor(1.0),
+        PredictionConfig::default(),
+        InterpolationConfig::default(),
+        LinkConditionerConfig::default(),
+        frame_duration,
+    );
+    stepper
+        .server_app
+        .world
+        .resource_mut::<ServerConfig>()
+        .connection =
+        server::ConnectionConfig::default().with_disconnect_grace_period(Duration::from_secs(2));
+    stepper.init();
+
+    let client_id = 111;
+    step_only(&mut stepper, true, Duration::from_secs(4));
+    assert!(stepper
+        .server_app
+        .world
+        .resource::<ServerConnectionManager>()
+        .is_suspended(client_id));
+    step_only(&mut stepper, true, Duration::from_secs(2));
+    let connection_manager = stepper
+        .server_app
+        .world
+        .resource::<ServerConnectionManager>();
+    assert!(!connection_manager.is_suspended(client_id));
+    assert!(connection_manager.connection(client_id).is_err());
+}
@@ -32,6 +32,7 @@ fn test_sync_after_tick_wrap() {
         incoming_latency: Duration::from_millis(20),
         incoming_jitter: Duration::from_millis(0),
         incoming_loss: 0.0,
+        ..Default::default()
     };
     let mut stepper = BevyStepper::new(
         shared_config,
@@ -123,6 +124,7 @@ fn test_sync_after_tick_half_wrap() {
         incoming_latency: Duration::from_millis(20),
         incoming_jitter: Duration::from_millis(0),
         incoming_loss: 0.0,
+        ..Default::default()
     };
     let mut stepper = BevyStepper::new(
         shared_config,
@@ -1,13 +1,17 @@
 use bevy::ecs::entity::MapEntities;
-use bevy::prelude::{default, Component, Entity, EntityMapper, Reflect};
+use bevy::prelude::{default, Component, Entity, EntityMapper, Reflect, Resource};
 use cfg_if::cfg_if;
 use derive_more::{Add, Mul};
 use std::ops::Mul;
 
-use serde::{Deserialize, Serialize};
+use bitcode::{Decode, Encode};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::_reexport::*;
+use crate::prelude::client::{CorrectionConfig, CorrectionMode};
 use crate::prelude::*;
+use crate::serialize::custom::SerializeWith;
+use crate::serialize::quantize::{dequantize_f32, quantize_f32};
 
 // Messages
 #[derive(MessageInternal, Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -16,10 +20,22 @@ pub struct Message1(pub String);
 #[derive(MessageInternal, Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Message2(pub u32);
 
+/// Message that is only serialized with bitcode
+#[derive(MessageInternal, Encode, Decode, Debug, PartialEq, Clone)]
+pub struct Message3(pub Vec<u8>);
+
+// Resources
+#[derive(Resource, MessageInternal, Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Resource1(pub f32);
+
 #[message_protocol_internal(protocol = "MyProtocol")]
 pub enum MyMessageProtocol {
     Message1(Message1),
     Message2(Message2),
+    #[serialize(bitcode)]
+    Message3(Message3),
+    #[resource]
+    Resource1(ReplicateResource<Resource1>),
 }
 
 // Components
@@ -49,6 +65,54 @@ impl LightyearMapEntities for Component4 {
     }
 }
 
+/// Component that is replicated with delta-compression
+#[derive(Component, MessageInternal, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Component5(pub Vec<u8>);
+
+impl Diffable for Component5 {
+    /// Length of the common prefix, and the elements that come after it
+    type Delta = (usize, Vec<u8>);
+
+    fn diff(&self, new: &Self) -> Self::Delta {
+        let common = self
+            .0
+            .iter()
+            .zip(new.0.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        (common, new.0[common..].to_vec())
+    }
+
+    fn apply_diff(&mut self, delta: &Self::Delta) {
+        self.0.truncate(delta.0);
+        self.0.extend_from_slice(&delta.1);
+    }
+}
+
+/// Component that is serialized with quantization
+#[derive(Component, MessageInternal, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Component6(pub f32, pub f32);
+
+pub struct Component6Quantizer;
+
+impl SerializeWith<Component6> for Component6Quantizer {
+    fn serialize<S: Serializer>(value: &Component6, serializer: S) -> Result<S::Ok, S::Error> {
+        (
+            quantize_f32(value.0, -100.0, 100.0),
+            quantize_f32(value.1, -100.0, 100.0),
+        )
+            .serialize(serializer)
+    }
+
+    fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Component6, D::Error> {
+        let (x, y) = <(u16, u16)>::deserialize(deserializer)?;
+        Ok(Component6(
+            dequantize_f32(x, -100.0, 100.0),
+            dequantize_f32(y, -100.0, 100.0),
+        ))
+    }
+}
+
 #[component_protocol_internal(protocol = "MyProtocol")]
 pub enum MyComponentsProtocol {
     #[sync(full)]
@@ -59,6 +123,41 @@ pub enum MyComponentsProtocol {
     Component3(Component3),
     #[sync(simple)]
     Component4(Component4),
+    #[replication(delta)]
+    Component5(Component5),
+    #[serialize(with = "Component6Quantizer")]
+    Component6(Component6),
+    #[sync(
+        full,
+        corrector = "InterpolatedCorrector",
+        should_rollback = "component7_should_rollback",
+        correction = "component7_correction"
+    )]
+    Component7(Component7),
+}
+
+/// Component that only triggers a rollback if the mismatch is bigger than a tolerance
+#[derive(Component, MessageInternal, Serialize, Deserialize, Clone, Debug, PartialEq, Add, Mul)]
+pub struct Component7(pub f32);
+
+impl Mul<f32> for &Component7 {
+    type Output = Component7;
+    fn mul(self, rhs: f32) -> Self::Output {
+        Component7(self.0 * rhs)
+    }
+}
+
+pub fn component7_should_rollback(predicted: &Component7, confirmed: &Component7) -> bool {
+    (predicted.0 - confirmed.0).abs() > 0.1
+}
+
+/// The error is smoothed out exponentially, unless it is too big
+pub fn component7_correction() -> CorrectionConfig<Component7> {
+    CorrectionConfig::default()
+        .with_mode(CorrectionMode::ErrorOffset {
+            half_life_ticks: 2.0,
+        })
+        .with_snap_threshold(|a, b| (a.0 - b.0).abs(), 10.0)
 }
 
 // Inputs
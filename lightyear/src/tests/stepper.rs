@@ -83,6 +83,9 @@ impl BevyStepper {
             net: vec![net_config],
             ping: PingConfig::default(),
             packet: Default::default(),
+            replication: Default::default(),
+            connection: Default::default(),
+            rate_limit: Default::default(),
         };
         let plugin_config = server::PluginConfig::new(config, protocol());
         let plugin = server::ServerPlugin::new(plugin_config);
@@ -110,6 +113,7 @@ impl BevyStepper {
             prediction: prediction_config,
             interpolation: interpolation_config,
             packet: Default::default(),
+            reconnect: Default::default(),
         };
         let plugin_config = client::PluginConfig::new(config, protocol());
         let plugin = client::ClientPlugin::new(plugin_config);
@@ -6,14 +6,14 @@ use std::io::Result;
 use std::net::SocketAddr;
 
 use cfg_if::cfg_if;
-use rand;
-use rand::{thread_rng, Rng};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
-use crate::transport::PacketReceiver;
+use crate::transport::{PacketReceiver, PacketSender};
 use crate::utils::ready_buffer::ReadyBuffer;
 
 cfg_if! {
-    if #[cfg(any(test))] {
+    if #[cfg(any(test, feature = "mock_time"))] {
         use mock_instant::Instant;
     } else {
         use bevy::utils::Instant;
@@ -21,7 +21,10 @@ cfg_if! {
 }
 
 /// Contains configuration required to initialize a LinkConditioner
-#[derive(Clone, Debug)]
+///
+/// The incoming conditions are applied to the packets received by the [`Io`](crate::transport::io::Io),
+/// the outgoing conditions are applied to the packets that it sends.
+#[derive(Clone, Debug, Default)]
 pub struct LinkConditionerConfig {
     /// Delay to receive incoming messages in milliseconds (half the RTT)
     pub incoming_latency: Duration,
@@ -32,81 +35,288 @@ pub struct LinkConditionerConfig {
     /// The % chance that an incoming packet will be dropped.
     /// Represented as a value between 0 and 1
     pub incoming_loss: f32,
+    /// Bursty loss of incoming packets. If set, it is used instead of `incoming_loss`
+    pub incoming_burst_loss: Option<GilbertElliottLoss>,
+    /// The % chance that an incoming packet will be received twice
+    pub incoming_duplication: f32,
+    /// The % chance that an incoming packet will be delayed by an additional `reordering_delay`,
+    /// so that it is received after packets that were sent after it
+    pub incoming_reordering: f32,
+    /// Maximum incoming bandwidth in bytes per second.
+    /// Packets that exceed the bandwidth are queued until the link is free
+    pub incoming_bandwidth: Option<u32>,
+    /// Delay to send outgoing messages (half the RTT)
+    pub outgoing_latency: Duration,
+    /// The maximum additional random latency added to or subtracted from `outgoing_latency`
+    pub outgoing_jitter: Duration,
+    /// The % chance that an outgoing packet will be dropped
+    pub outgoing_loss: f32,
+    /// Bursty loss of outgoing packets. If set, it is used instead of `outgoing_loss`
+    pub outgoing_burst_loss: Option<GilbertElliottLoss>,
+    /// The % chance that an outgoing packet will be sent twice
+    pub outgoing_duplication: f32,
+    /// The % chance that an outgoing packet will be delayed by an additional `reordering_delay`
+    pub outgoing_reordering: f32,
+    /// Maximum outgoing bandwidth in bytes per second
+    pub outgoing_bandwidth: Option<u32>,
+    /// Additional delay of the packets that are reordered
+    pub reordering_delay: Duration,
+    /// Seed of the random number generator used by the conditioner.
+    /// Combined with the `mock_time` feature, this makes the conditioning deterministic
+    pub seed: Option<u64>,
+}
+
+/// Gilbert-Elliott loss model, which simulates bursts of packet loss.
+///
+/// The link alternates between a 'good' state and a 'bad' state, and each state has its own loss probability.
+/// The transitions between the states are evaluated for every packet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GilbertElliottLoss {
+    /// Probability to go from the good state to the bad state
+    pub good_to_bad: f32,
+    /// Probability to go from the bad state to the good state
+    pub bad_to_good: f32,
+    /// The % chance that a packet is dropped in the good state
+    pub good_loss: f32,
+    /// The % chance that a packet is dropped in the bad state
+    pub bad_loss: f32,
+}
+
+type Packet = (SocketAddr, Box<[u8]>);
+
+/// Conditions applied to the packets flowing in one direction
+#[derive(Clone, Debug)]
+struct Conditions {
+    latency: Duration,
+    jitter: Duration,
+    loss: f32,
+    burst_loss: Option<GilbertElliottLoss>,
+    duplication: f32,
+    reordering: f32,
+    reordering_delay: Duration,
+    bandwidth: Option<u32>,
+}
+
+impl LinkConditionerConfig {
+    fn incoming(&self) -> Conditions {
+        Conditions {
+            latency: self.incoming_latency,
+            jitter: self.incoming_jitter,
+            loss: self.incoming_loss,
+            burst_loss: self.incoming_burst_loss,
+            duplication: self.incoming_duplication,
+            reordering: self.incoming_reordering,
+            reordering_delay: self.reordering_delay,
+            bandwidth: self.incoming_bandwidth,
+        }
+    }
+
+    fn outgoing(&self) -> Conditions {
+        Conditions {
+            latency: self.outgoing_latency,
+            jitter: self.outgoing_jitter,
+            loss: self.outgoing_loss,
+            burst_loss: self.outgoing_burst_loss,
+            duplication: self.outgoing_duplication,
+            reordering: self.outgoing_reordering,
+            reordering_delay: self.reordering_delay,
+            bandwidth: self.outgoing_bandwidth,
+        }
+    }
+
+    /// Returns true if the outgoing packets are not modified by the conditioner
+    pub(crate) fn is_outgoing_noop(&self) -> bool {
+        self.outgoing_latency.is_zero()
+            && self.outgoing_jitter.is_zero()
+            && self.outgoing_loss == 0.0
+            && self.outgoing_burst_loss.is_none()
+            && self.outgoing_duplication == 0.0
+            && self.outgoing_reordering == 0.0
+            && self.outgoing_bandwidth.is_none()
+    }
+
+    fn rng(&self, stream: u64) -> StdRng {
+        match self.seed {
+            // use a different stream for each direction
+            Some(seed) => StdRng::seed_from_u64(seed.wrapping_add(stream)),
+            None => StdRng::from_entropy(),
+        }
+    }
+}
+
+/// Applies the [`Conditions`] to a stream of packets
+struct Conditioner {
+    conditions: Conditions,
+    rng: StdRng,
+    /// Current state of the Gilbert-Elliott loss model
+    in_bad_state: bool,
+    /// Instant at which the link will have finished transmitting the queued packets (for the bandwidth limit)
+    link_busy_until: Option<Instant>,
+    /// Packets waiting to be delivered, ordered by delivery time then by arrival order
+    time_queue: ReadyBuffer<(Instant, u64), Packet>,
+    next_id: u64,
+}
+
+impl Conditioner {
+    fn new(conditions: Conditions, rng: StdRng) -> Self {
+        Self {
+            conditions,
+            rng,
+            in_bad_state: false,
+            link_busy_until: None,
+            time_queue: ReadyBuffer::new(),
+            next_id: 0,
+        }
+    }
+
+    fn is_lost(&mut self) -> bool {
+        let loss = match self.conditions.burst_loss {
+            Some(model) => {
+                let transition = if self.in_bad_state {
+                    model.bad_to_good
+                } else {
+                    model.good_to_bad
+                };
+                if self.rng.gen_bool(transition as f64) {
+                    self.in_bad_state = !self.in_bad_state;
+                }
+                if self.in_bad_state {
+                    model.bad_loss
+                } else {
+                    model.good_loss
+                }
+            }
+            None => self.conditions.loss,
+        };
+        self.rng.gen_range(0.0..1.0) < loss
+    }
+
+    /// Compute the instant at which a packet sent now will be delivered
+    fn delivery_time(&mut self, now: Instant) -> Instant {
+        let mut latency = self.conditions.latency.as_millis() as i64;
+        if !self.conditions.jitter.is_zero() {
+            let jitter = self.conditions.jitter.as_millis() as i64;
+            latency += self.rng.gen_range(-jitter..=jitter);
+        }
+        if self.conditions.reordering > 0.0
+            && self.rng.gen_range(0.0..1.0) < self.conditions.reordering
+        {
+            latency += self.conditions.reordering_delay.as_millis() as i64;
+        }
+        now + Duration::from_millis(latency.max(0) as u64)
+    }
+
+    /// Add a packet to the queue of packets to deliver, or drop it
+    fn condition(&mut self, packet: Packet) {
+        if self.is_lost() {
+            return;
+        }
+        let mut now = Instant::now();
+        if let Some(bandwidth) = self.conditions.bandwidth {
+            // the packet can only be transmitted once the previous packets are done transmitting
+            let start = self.link_busy_until.map_or(now, |busy| busy.max(now));
+            let transmission = Duration::from_secs_f64(packet.1.len() as f64 / bandwidth as f64);
+            self.link_busy_until = Some(start + transmission);
+            now = start + transmission;
+        }
+        if self.conditions.duplication > 0.0
+            && self.rng.gen_range(0.0..1.0) < self.conditions.duplication
+        {
+            let time = self.delivery_time(now);
+            self.push(time, packet.clone());
+        }
+        let time = self.delivery_time(now);
+        self.push(time, packet);
+    }
+
+    fn push(&mut self, time: Instant, packet: Packet) {
+        self.time_queue.add_item((time, self.next_id), packet);
+        self.next_id += 1;
+    }
+
+    /// Pop a packet that is ready to be delivered
+    fn pop_ready(&mut self) -> Option<Packet> {
+        self.time_queue
+            .pop_item(&(Instant::now(), u64::MAX))
+            .map(|(_, packet)| packet)
+    }
 }
 
-// Conditions a packet-receiver T that sends packets P
-pub struct ConditionedPacketReceiver<T: PacketReceiver, P: Eq> {
+/// Conditions the packets received by the packet-receiver `T`
+pub struct ConditionedPacketReceiver<T: PacketReceiver> {
     packet_receiver: T,
-    config: LinkConditionerConfig,
-    pub time_queue: ReadyBuffer<Instant, P>,
-    last_packet: Option<P>,
+    conditioner: Conditioner,
+    last_packet: Option<Packet>,
 }
 
-impl<T: PacketReceiver, P: Eq> ConditionedPacketReceiver<T, P> {
+impl<T: PacketReceiver> ConditionedPacketReceiver<T> {
     pub fn new(packet_receiver: T, link_conditioner_config: LinkConditionerConfig) -> Self {
         ConditionedPacketReceiver {
             packet_receiver,
-            config: link_conditioner_config,
-            time_queue: ReadyBuffer::new(),
+            conditioner: Conditioner::new(
+                link_conditioner_config.incoming(),
+                link_conditioner_config.rng(0),
+            ),
             last_packet: None,
         }
     }
 }
 
-// Condition a packet by potentially adding latency/jitter/loss to it
-fn condition_packet<P: Eq>(
-    config: &LinkConditionerConfig,
-    time_queue: &mut ReadyBuffer<Instant, P>,
-    packet: P,
-) {
-    let mut rng = thread_rng();
-    if rng.gen_range(0.0..1.0) <= config.incoming_loss {
-        return;
-    }
-    let mut latency: i32 = config.incoming_latency.as_millis() as i32;
-    // TODO: how can i use the virtual time here?
-    let mut packet_timestamp = Instant::now();
-    if config.incoming_jitter > Duration::default() {
-        let jitter: i32 = config.incoming_jitter.as_millis() as i32;
-        latency += rng.gen_range(-jitter..jitter);
-    }
-    if latency > 0 {
-        packet_timestamp += Duration::from_millis(latency as u64);
-    }
-    time_queue.add_item(packet_timestamp, packet);
-}
-
-impl<T: PacketReceiver> PacketReceiver for ConditionedPacketReceiver<T, (SocketAddr, Box<[u8]>)> {
+impl<T: PacketReceiver> PacketReceiver for ConditionedPacketReceiver<T> {
     fn recv(&mut self) -> Result<Option<(&mut [u8], SocketAddr)>> {
-        loop {
-            // keep trying to receive packets from the inner packet receiver
-            match self.packet_receiver.recv() {
-                Ok(option) => match option {
-                    None => break,
-                    // add conditioning (put the packets in the time queue)
-                    Some((data, addr)) => condition_packet(
-                        &self.config,
-                        &mut self.time_queue,
-                        (addr, data.to_vec().into_boxed_slice()),
-                    ),
-                },
-                Err(err) => {
-                    return Err(err);
-                }
-            }
+        // keep trying to receive packets from the inner packet receiver
+        // and add conditioning (put the packets in the time queue)
+        while let Some((data, addr)) = self.packet_receiver.recv()? {
+            self.conditioner
+                .condition((addr, data.to_vec().into_boxed_slice()));
         }
         // only return a packet if it is ready to be returned
-        match self.time_queue.pop_item(&Instant::now()) {
-            Some((_, (addr, data))) => {
+        match self.conditioner.pop_ready() {
+            Some(packet) => {
                 // we use `last_packet` to get ownership of the data
-                self.last_packet = Some((addr, data));
-                Ok(Some((self.last_packet.as_mut().unwrap().1.as_mut(), addr)))
+                let (addr, data) = self.last_packet.insert(packet);
+                Ok(Some((data.as_mut(), *addr)))
             }
             None => Ok(None),
         }
     }
 }
 
+/// Conditions the packets sent by the packet-sender `T`
+///
+/// Delayed packets are sent when [`PacketSender::flush`] is called.
+pub struct ConditionedPacketSender<T: PacketSender> {
+    packet_sender: T,
+    conditioner: Conditioner,
+}
+
+impl<T: PacketSender> ConditionedPacketSender<T> {
+    pub fn new(packet_sender: T, link_conditioner_config: LinkConditionerConfig) -> Self {
+        ConditionedPacketSender {
+            packet_sender,
+            conditioner: Conditioner::new(
+                link_conditioner_config.outgoing(),
+                link_conditioner_config.rng(1),
+            ),
+        }
+    }
+}
+
+impl<T: PacketSender> PacketSender for ConditionedPacketSender<T> {
+    fn send(&mut self, payload: &[u8], address: &SocketAddr) -> Result<()> {
+        self.conditioner
+            .condition((*address, payload.to_vec().into_boxed_slice()));
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        while let Some((addr, data)) = self.conditioner.pop_ready() {
+            self.packet_sender.send(&data, &addr)?;
+        }
+        self.packet_sender.flush()
+    }
+}
+
 impl LinkConditionerConfig {
     /// Creates a new LinkConditionerConfig
     pub fn new(incoming_latency: Duration, incoming_jitter: Duration, incoming_loss: f32) -> Self {
@@ -114,6 +324,7 @@ impl LinkConditionerConfig {
             incoming_latency,
             incoming_jitter,
             incoming_loss,
+            ..Default::default()
         }
     }
 
@@ -124,6 +335,7 @@ impl LinkConditionerConfig {
             incoming_latency: Duration::from_millis(40),
             incoming_jitter: Duration::from_millis(6),
             incoming_loss: 0.002,
+            ..Default::default()
         }
     }
 
@@ -134,6 +346,7 @@ impl LinkConditionerConfig {
             incoming_latency: Duration::from_millis(170),
             incoming_jitter: Duration::from_millis(45),
             incoming_loss: 0.02,
+            ..Default::default()
         }
     }
 
@@ -144,6 +357,177 @@ impl LinkConditionerConfig {
             incoming_latency: Duration::from_millis(300),
             incoming_jitter: Duration::from_millis(84),
             incoming_loss: 0.04,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a new `LinkConditioner` that simulates a good wifi connection:
+    /// low latency, some jitter and rare short bursts of loss
+    pub fn good_wifi() -> Self {
+        let burst_loss = GilbertElliottLoss {
+            good_to_bad: 0.005,
+            bad_to_good: 0.5,
+            good_loss: 0.001,
+            bad_loss: 0.3,
+        };
+        LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(10),
+            incoming_jitter: Duration::from_millis(5),
+            incoming_burst_loss: Some(burst_loss),
+            incoming_reordering: 0.001,
+            outgoing_latency: Duration::from_millis(10),
+            outgoing_jitter: Duration::from_millis(5),
+            outgoing_burst_loss: Some(burst_loss),
+            outgoing_reordering: 0.001,
+            reordering_delay: Duration::from_millis(10),
+            ..Default::default()
+        }
+    }
+
+    /// Creates a new `LinkConditioner` that simulates a poor 4G mobile connection:
+    /// high and variable latency, long bursts of loss, duplication, reordering and limited uplink bandwidth
+    pub fn poor_4g() -> Self {
+        let burst_loss = GilbertElliottLoss {
+            good_to_bad: 0.02,
+            bad_to_good: 0.2,
+            good_loss: 0.01,
+            bad_loss: 0.5,
+        };
+        LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(100),
+            incoming_jitter: Duration::from_millis(40),
+            incoming_burst_loss: Some(burst_loss),
+            incoming_duplication: 0.01,
+            incoming_reordering: 0.02,
+            incoming_bandwidth: Some(1_000_000),
+            outgoing_latency: Duration::from_millis(100),
+            outgoing_jitter: Duration::from_millis(40),
+            outgoing_burst_loss: Some(burst_loss),
+            outgoing_duplication: 0.01,
+            outgoing_reordering: 0.02,
+            outgoing_bandwidth: Some(250_000),
+            reordering_delay: Duration::from_millis(30),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossbeam_channel::unbounded;
+    use mock_instant::MockClock;
+
+    use crate::transport::local::LocalChannel;
+    use crate::transport::{Transport, LOCAL_SOCKET};
+
+    use super::*;
+
+    /// Send `n` packets through a conditioned sender, and return the packets that were received
+    /// after waiting long enough for all of them to be delivered
+    fn send_conditioned(config: LinkConditionerConfig, n: u8) -> Vec<u8> {
+        let (send, recv) = unbounded();
+        let (sender, mut receiver) = LocalChannel::new(recv, send).listen();
+        let mut sender = ConditionedPacketSender::new(sender, config);
+        for i in 0..n {
+            sender.send(&[i], &LOCAL_SOCKET).unwrap();
+        }
+        MockClock::advance(Duration::from_secs(10));
+        sender.flush().unwrap();
+        let mut received = vec![];
+        while let Some((data, _)) = receiver.recv().unwrap() {
+            received.push(data[0]);
+        }
+        received
+    }
+
+    #[test]
+    fn test_outgoing_latency() {
+        let (send, recv) = unbounded();
+        let (sender, mut receiver) = LocalChannel::new(recv, send).listen();
+        let mut sender = ConditionedPacketSender::new(
+            sender,
+            LinkConditionerConfig {
+                outgoing_latency: Duration::from_millis(100),
+                ..Default::default()
+            },
+        );
+        sender.send(&[1], &LOCAL_SOCKET).unwrap();
+        sender.send(&[2], &LOCAL_SOCKET).unwrap();
+        MockClock::advance(Duration::from_millis(50));
+        sender.flush().unwrap();
+        assert!(receiver.recv().unwrap().is_none());
+
+        MockClock::advance(Duration::from_millis(50));
+        sender.flush().unwrap();
+        // packets with the same delivery time keep their order
+        assert_eq!(receiver.recv().unwrap().unwrap().0, &[1]);
+        assert_eq!(receiver.recv().unwrap().unwrap().0, &[2]);
+    }
+
+    #[test]
+    fn test_deterministic_with_seed() {
+        let config = LinkConditionerConfig {
+            outgoing_jitter: Duration::from_millis(50),
+            outgoing_burst_loss: Some(GilbertElliottLoss {
+                good_to_bad: 0.1,
+                bad_to_good: 0.3,
+                good_loss: 0.0,
+                bad_loss: 0.8,
+            }),
+            outgoing_duplication: 0.1,
+            outgoing_reordering: 0.1,
+            reordering_delay: Duration::from_millis(100),
+            seed: Some(7),
+            ..Default::default()
+        };
+        let received = send_conditioned(config.clone(), 200);
+        assert_eq!(received, send_conditioned(config, 200));
+        // some packets were lost, duplicated and reordered
+        assert_ne!(received.len(), 200);
+        assert!((0..200u8).any(|i| received.iter().filter(|r| **r == i).count() == 2));
+        assert!(received.windows(2).any(|w| w[0] > w[1]));
+    }
+
+    #[test]
+    fn test_burst_loss() {
+        let config = LinkConditionerConfig {
+            outgoing_burst_loss: Some(GilbertElliottLoss {
+                good_to_bad: 0.05,
+                bad_to_good: 0.2,
+                good_loss: 0.0,
+                bad_loss: 1.0,
+            }),
+            seed: Some(1),
+            ..Default::default()
+        };
+        let received = send_conditioned(config, 200);
+        // the losses happen in bursts
+        let longest_gap = received.windows(2).map(|w| w[1] - w[0]).max().unwrap();
+        assert!(longest_gap > 2);
+    }
+
+    #[test]
+    fn test_bandwidth_limit() {
+        let (send, recv) = unbounded();
+        let (sender, mut receiver) = LocalChannel::new(recv, send).listen();
+        let mut sender = ConditionedPacketSender::new(
+            sender,
+            LinkConditionerConfig {
+                // 1000 bytes per second: a 100-byte packet takes 100ms to transmit
+                outgoing_bandwidth: Some(1000),
+                ..Default::default()
+            },
+        );
+        for _ in 0..3 {
+            sender.send(&[0; 100], &LOCAL_SOCKET).unwrap();
         }
+        MockClock::advance(Duration::from_millis(150));
+        sender.flush().unwrap();
+        assert!(receiver.recv().unwrap().is_some());
+        assert!(receiver.recv().unwrap().is_none());
+        MockClock::advance(Duration::from_millis(150));
+        sender.flush().unwrap();
+        assert!(receiver.recv().unwrap().is_some());
+        assert!(receiver.recv().unwrap().is_some());
     }
 }
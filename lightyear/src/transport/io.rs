@@ -1,5 +1,7 @@
 //! Wrapper around a transport, that can perform additional transformations such as
-//! bandwidth monitoring or compression
+//! bandwidth monitoring.
+//!
+//! (the packets are compressed before reaching the io, see [`CompressionConfig`](crate::packet::compression::CompressionConfig))
 use bevy::app::{App, Plugin};
 use bevy::diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic};
 use bevy::prelude::{Real, Res, Resource, Time};
@@ -14,8 +16,11 @@ use metrics;
 use tracing::info;
 
 use super::LOCAL_SOCKET;
+use crate::packet::compression::CompressionStats;
 use crate::transport::channels::Channels;
-use crate::transport::conditioner::{ConditionedPacketReceiver, LinkConditionerConfig};
+use crate::transport::conditioner::{
+    ConditionedPacketReceiver, ConditionedPacketSender, LinkConditionerConfig,
+};
 use crate::transport::dummy::DummyIo;
 use crate::transport::local::LocalChannel;
 use crate::transport::{PacketReceiver, PacketSender, Transport};
@@ -217,12 +222,12 @@ impl IoConfig {
 
     pub fn get_io(self) -> Io {
         let mut io = self.transport.get_io();
+        // the conditioner is applied first, so that it sees the packets as they are sent on the network
         if let Some(conditioner) = self.conditioner {
-            io = Io::new(
-                io.local_addr,
-                io.sender,
-                Box::new(ConditionedPacketReceiver::new(io.receiver, conditioner)),
-            );
+            if !conditioner.is_outgoing_noop() {
+                io.sender = Box::new(ConditionedPacketSender::new(io.sender, conditioner.clone()));
+            }
+            io.receiver = Box::new(ConditionedPacketReceiver::new(io.receiver, conditioner));
         }
         io
     }
@@ -248,6 +253,18 @@ pub struct IoStats {
     pub bytes_received: usize,
     pub packets_sent: usize,
     pub packets_received: usize,
+    /// Stats of the packets compressed by the connection (the io only sees the compressed packets)
+    pub compression: CompressionStats,
+}
+
+impl std::ops::AddAssign for IoStats {
+    fn add_assign(&mut self, other: Self) {
+        self.bytes_sent += other.bytes_sent;
+        self.bytes_received += other.bytes_received;
+        self.packets_sent += other.packets_sent;
+        self.packets_received += other.packets_received;
+        self.compression += other.compression;
+    }
 }
 
 impl Io {
@@ -292,7 +309,7 @@ impl Debug for Io {
 
 impl PacketReceiver for Io {
     fn recv(&mut self) -> Result<Option<(&mut [u8], SocketAddr)>> {
-        // todo: compression + bandwidth monitoring
+        // todo: bandwidth monitoring
         self.receiver.recv().map(|x| {
             if let Some((ref buffer, _)) = x {
                 #[cfg(feature = "metrics")]
@@ -310,7 +327,7 @@ impl PacketReceiver for Io {
 
 impl PacketSender for Io {
     fn send(&mut self, payload: &[u8], address: &SocketAddr) -> Result<()> {
-        // todo: compression + bandwidth monitoring
+        // todo: bandwidth monitoring
         #[cfg(feature = "metrics")]
         {
             metrics::counter!("transport.packets_sent").increment(1);
@@ -320,6 +337,10 @@ impl PacketSender for Io {
         self.stats.packets_sent += 1;
         self.sender.send(payload, address)
     }
+
+    fn flush(&mut self) -> Result<()> {
+        self.sender.flush()
+    }
 }
 
 pub struct IoDiagnosticsPlugin;
@@ -335,6 +356,13 @@ impl IoDiagnosticsPlugin {
     /// How many bytes do we send per second
     pub const PACKETS_OUT: DiagnosticPath = DiagnosticPath::const_new("packets sent per second");
 
+    /// Compression ratio of the received packets
+    pub const COMPRESSION_RATIO_IN: DiagnosticPath =
+        DiagnosticPath::const_new("compression ratio of received packets");
+    /// Compression ratio of the sent packets
+    pub const COMPRESSION_RATIO_OUT: DiagnosticPath =
+        DiagnosticPath::const_new("compression ratio of sent packets");
+
     /// Max diagnostic history length.
     pub const DIAGNOSTIC_HISTORY_LEN: usize = 60;
 
@@ -359,6 +387,12 @@ impl IoDiagnosticsPlugin {
         diagnostics.add_measurement(&Self::PACKETS_OUT, || {
             stats.packets_sent as f64 / delta_seconds
         });
+        if let Some(ratio) = stats.compression.compression_ratio_received() {
+            diagnostics.add_measurement(&Self::COMPRESSION_RATIO_IN, || ratio);
+        }
+        if let Some(ratio) = stats.compression.compression_ratio_sent() {
+            diagnostics.add_measurement(&Self::COMPRESSION_RATIO_OUT, || ratio);
+        }
         *stats = IoStats::default()
     }
 }
@@ -381,6 +415,14 @@ impl Plugin for IoDiagnosticsPlugin {
             Diagnostic::new(IoDiagnosticsPlugin::PACKETS_OUT)
                 .with_max_history_length(IoDiagnosticsPlugin::DIAGNOSTIC_HISTORY_LEN),
         );
+        app.register_diagnostic(
+            Diagnostic::new(IoDiagnosticsPlugin::COMPRESSION_RATIO_IN)
+                .with_max_history_length(IoDiagnosticsPlugin::DIAGNOSTIC_HISTORY_LEN),
+        );
+        app.register_diagnostic(
+            Diagnostic::new(IoDiagnosticsPlugin::COMPRESSION_RATIO_OUT)
+                .with_max_history_length(IoDiagnosticsPlugin::DIAGNOSTIC_HISTORY_LEN),
+        );
     }
 }
 
@@ -388,6 +430,10 @@ impl PacketSender for Box<dyn PacketSender + Send + Sync> {
     fn send(&mut self, payload: &[u8], address: &SocketAddr) -> Result<()> {
         (**self).send(payload, address)
     }
+
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
 }
 
 impl PacketReceiver for Box<dyn PacketReceiver + Send + Sync> {
@@ -56,12 +56,23 @@ pub trait Transport {
 pub trait PacketSender: Send + Sync {
     /// Send data on the socket to the remote address
     fn send(&mut self, payload: &[u8], address: &SocketAddr) -> Result<()>;
+
+    /// Send the packets that were buffered by the sender (for example the packets delayed by the link conditioner).
+    ///
+    /// This is called every frame.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
 }
 
 impl PacketSender for Box<dyn PacketSender> {
     fn send(&mut self, payload: &[u8], address: &SocketAddr) -> Result<()> {
         (**self).send(payload, address)
     }
+
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
 }
 
 /// Receive data from a remote address
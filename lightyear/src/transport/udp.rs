@@ -137,6 +137,7 @@ mod tests {
                 incoming_latency: Duration::from_millis(100),
                 incoming_jitter: Duration::from_millis(0),
                 incoming_loss: 0.0,
+                ..Default::default()
             },
         );
 
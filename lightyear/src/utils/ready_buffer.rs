@@ -8,26 +8,47 @@ use std::{cmp::Ordering, collections::BinaryHeap};
 /// when the key associated with the item is less than or equal to the current key
 ///
 /// The most recent item (by associated key) is returned first
+///
+/// The buffer can be bounded with [`ReadyBuffer::with_capacity`]: when it is full, adding an item drops the item
+/// with the smallest key.
 #[derive(Clone, Default, Debug)]
 pub struct ReadyBuffer<K: Ord, T: PartialEq> {
     // TODO: compare performance with a SequenceBuffer of fixed size
-    // TODO: add a maximum size to the buffer. The elements that are farther away from being ready dont' get added?
     /// min heap: we pop the items with smallest key first
     pub heap: BinaryHeap<ItemWithReadyKey<K, T>>,
+    /// Maximum number of items in the buffer (unbounded if None)
+    capacity: Option<usize>,
 }
 
 impl<K: Ord, T: PartialEq> ReadyBuffer<K, T> {
     pub fn new() -> Self {
         Self {
             heap: BinaryHeap::default(),
+            capacity: None,
+        }
+    }
+
+    /// Create a buffer that holds at most `capacity` items (at least 1)
+    pub fn with_capacity(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            heap: BinaryHeap::with_capacity(capacity + 1),
+            capacity: Some(capacity),
         }
     }
 }
 
 impl<K: Ord, T: PartialEq> ReadyBuffer<K, T> {
-    /// Adds an item to the heap marked by time
+    /// Adds an item to the heap marked by time.
+    /// If the buffer is full, the item with the smallest key is dropped
     pub fn add_item(&mut self, key: K, item: T) {
         self.heap.push(ItemWithReadyKey { key, item });
+        if self
+            .capacity
+            .map_or(false, |capacity| self.heap.len() > capacity)
+        {
+            self.heap.pop();
+        }
     }
 
     /// Returns whether or not there is an item that is ready to be returned
@@ -59,7 +80,7 @@ impl<K: Ord, T: PartialEq> ReadyBuffer<K, T> {
     /// with a key older or equal to the provided key
     /// (i.e. if we have keys 1, 4, 6, pop_until(5) will pop 1, 4 and return the value for key 4)
     /// /// (i.e. if we have keys 1, 4, 6, pop_until(4) will pop 1, 4 and return the value for key 4)
-    pub(crate) fn pop_until(&mut self, key: &K) -> Option<(K, T)> {
+    pub fn pop_until(&mut self, key: &K) -> Option<(K, T)> {
         if self.heap.is_empty() {
             return None;
         }
@@ -77,6 +98,16 @@ impl<K: Ord, T: PartialEq> ReadyBuffer<K, T> {
         val
     }
 
+    /// Pop all items that are older than the provided key, except the most recent item with a key older or equal
+    /// to the provided key, which is kept in the buffer and returned
+    /// (i.e. if we have keys 1, 4, 6, clear_until(5) will pop 1 and return the value for key 4)
+    pub fn clear_until(&mut self, key: &K) -> Option<(&K, &T)> {
+        let (key, item) = self.pop_until(key)?;
+        // all the remaining items are more recent, so the item is at the top of the heap
+        self.heap.push(ItemWithReadyKey { key, item });
+        self.heap.peek().map(|item| (&item.key, &item.item))
+    }
+
     /// Pop all items that are older or equal than the provided key, then return all the values that were popped
     pub(crate) fn drain_until(&mut self, key: &K) -> Vec<(K, T)> {
         if self.heap.is_empty() {
@@ -135,6 +166,11 @@ impl<K: Ord, T: PartialEq> ReadyBuffer<K, T> {
     pub fn is_empty(&self) -> bool {
         self.heap.is_empty()
     }
+
+    /// Remove all the items from the buffer, but keep its capacity
+    pub fn clear(&mut self) {
+        self.heap.clear();
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -225,6 +261,39 @@ mod tests {
         assert_eq!(buffer.len(), 1);
     }
 
+    #[test]
+    fn test_clear_until() {
+        let mut buffer = ReadyBuffer::new();
+        assert_eq!(buffer.clear_until(&Tick(0)), None);
+
+        buffer.add_item(Tick(1), 1);
+        buffer.add_item(Tick(4), 4);
+        buffer.add_item(Tick(6), 6);
+        assert_eq!(buffer.clear_until(&Tick(0)), None);
+        assert_eq!(buffer.clear_until(&Tick(5)), Some((&Tick(4), &4)));
+        assert_eq!(buffer.len(), 2);
+        // the value is kept for the next keys
+        assert_eq!(buffer.clear_until(&Tick(5)), Some((&Tick(4), &4)));
+        assert_eq!(buffer.clear_until(&Tick(6)), Some((&Tick(6), &6)));
+        assert_eq!(buffer.len(), 1);
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut buffer = ReadyBuffer::with_capacity(2);
+        buffer.add_item(Tick(2), 2);
+        buffer.add_item(Tick(1), 1);
+        // the item with the smallest key is dropped
+        buffer.add_item(Tick(3), 3);
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.pop_until(&Tick(2)), Some((Tick(2), 2)));
+        // an item that is older than the whole buffer is dropped right away
+        buffer.add_item(Tick(4), 4);
+        buffer.add_item(Tick(0), 0);
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.pop_until(&Tick(0)), None);
+    }
+
     #[test]
     fn test_drain_until() {
         let mut buffer = ReadyBuffer::new();
@@ -1,3 +1,4 @@
+use crate::shared::convert_serialize_attributes;
 use darling::ast::NestedMeta;
 use darling::util::PathList;
 use darling::{Error, FromField, FromMeta};
@@ -18,7 +19,7 @@ struct MacroAttrs {
     derive: PathList,
 }
 
-const ATTRIBUTES: &[&str] = &["sync"];
+const ATTRIBUTES: &[&str] = &["sync", "replication", "serialize"];
 
 #[derive(Debug, FromField)]
 #[darling(attributes(sync))]
@@ -42,6 +43,27 @@ struct SyncField {
     lerp: Option<Ident>,
     #[darling(default)]
     corrector: Option<Ident>,
+    /// Function `fn(&C, &C) -> bool` that decides if a mismatch between the predicted and the confirmed values
+    /// triggers a rollback
+    #[darling(default)]
+    should_rollback: Option<syn::Path>,
+    /// Function `fn() -> CorrectionConfig<C>` that configures the visual correction after a rollback
+    #[darling(default)]
+    correction: Option<syn::Path>,
+}
+
+#[derive(Debug, FromField)]
+#[darling(attributes(replication))]
+struct ReplicationField {
+    // name of the enum field
+    ident: Option<Ident>,
+
+    // type of the field
+    ty: Type,
+
+    /// If true, component updates are sent as a delta against the last acked value
+    #[darling(default)]
+    delta: bool,
 }
 
 impl SyncField {
@@ -142,7 +164,17 @@ pub fn component_protocol_impl(
 
     // Helper Properties
     let fields = get_fields(&input);
-    let input_without_attributes = strip_attributes(&input);
+    let mut input_without_attributes = input.clone();
+    let serializers = match input_without_attributes
+        .variants
+        .iter_mut()
+        .map(|variant| convert_serialize_attributes(variant, &shared_crate_name))
+        .collect::<syn::Result<Vec<_>>>()
+    {
+        Ok(serializers) => serializers,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let input_without_attributes = strip_attributes(&input_without_attributes);
 
     // Use darling to parse the attributes for each field
     let sync_fields: Vec<SyncField> = fields
@@ -153,6 +185,10 @@ pub fn component_protocol_impl(
     for field in &sync_fields {
         field.check_is_valid();
     }
+    let replication_fields: Vec<ReplicationField> = fields
+        .iter()
+        .map(|field| FromField::from_field(field).unwrap())
+        .collect();
 
     // Names
     let enum_name = &input.ident;
@@ -178,6 +214,8 @@ pub fn component_protocol_impl(
     let insert_method = insert_method(&input, &fields);
     let update_method = update_method(&input, &fields);
     let type_ids_method = type_ids_method(&fields, &enum_kind_name);
+    let delta_methods = delta_methods(&replication_fields, &shared_crate_name);
+    let schema_method = schema_method(&sync_fields, &replication_fields, &serializers);
 
     // EnumKind methods
     let enum_kind = get_enum_kind(&input, &enum_kind_name);
@@ -209,12 +247,14 @@ pub fn component_protocol_impl(
                 type Protocol = #protocol;
 
                 #type_ids_method
+                #schema_method
                 #insert_method
                 #update_method
                 #add_systems_method
                 #add_events_method
                 #push_component_events_method
                 #add_sync_systems_method
+                #delta_methods
 
                 // #mode_method
             }
@@ -374,6 +414,34 @@ fn add_events_method(fields: &Vec<Field>) -> TokenStream {
     }
 }
 
+fn schema_method(
+    sync_fields: &[SyncField],
+    replication_fields: &[ReplicationField],
+    serializers: &[Option<String>],
+) -> TokenStream {
+    let entries = sync_fields
+        .iter()
+        .zip(replication_fields.iter())
+        .zip(serializers)
+        .map(|((sync_field, replication_field), serializer)| {
+            let ident = &sync_field.ident;
+            let ty = &sync_field.ty;
+            let mode = sync_field.get_mode_tokens();
+            let delta = replication_field.delta;
+            let entry = quote! {#ident(#ty) #mode delta=#delta}.to_string();
+            // the serializer changes the encoding of the component
+            match serializer {
+                Some(serializer) => format!("{} with={}", entry, serializer),
+                None => entry,
+            }
+        });
+    quote! {
+        fn schema() -> &'static [&'static str] {
+            &[#(#entries),*]
+        }
+    }
+}
+
 fn sync_metadata_impl(fields: &Vec<SyncField>, enum_name: &Ident) -> TokenStream {
     let mut body = quote! {};
     for field in fields {
@@ -415,6 +483,22 @@ fn sync_metadata_impl(fields: &Vec<SyncField>, enum_name: &Ident) -> TokenStream
         if corrector == "InterpolatedCorrector" {
             corrector = interpolator.clone();
         }
+        // rollback check (by default, we use the trait's implementation that compares with PartialEq)
+        let should_rollback = field.should_rollback.as_ref().map(|should_rollback| {
+            quote! {
+                fn should_rollback(predicted: &#component_type, confirmed: &#component_type) -> bool {
+                    #should_rollback(predicted, confirmed)
+                }
+            }
+        });
+        // visual correction (by default, we use the trait's implementation)
+        let correction = field.correction.as_ref().map(|correction| {
+            quote! {
+                fn correction() -> CorrectionConfig<#component_type> {
+                    #correction()
+                }
+            }
+        });
         body = quote! {
             #body
             impl SyncMetadata<#component_type> for #enum_name {
@@ -423,6 +507,8 @@ fn sync_metadata_impl(fields: &Vec<SyncField>, enum_name: &Ident) -> TokenStream
                 fn mode() -> ComponentSyncMode {
                     #mode
                 }
+                #should_rollback
+                #correction
             }
         }
     }
@@ -469,6 +555,42 @@ fn add_sync_systems_method(fields: &Vec<SyncField>, protocol_name: &Ident) -> To
     }
 }
 
+fn delta_methods(fields: &[ReplicationField], shared_crate_name: &TokenStream) -> TokenStream {
+    let delta_idents: Vec<&Ident> = fields
+        .iter()
+        .filter(|field| field.delta)
+        .map(|field| field.ident.as_ref().unwrap())
+        .collect();
+    let is_diffable_body = if delta_idents.is_empty() {
+        quote! { false }
+    } else {
+        quote! { matches!(self, #(Self::#delta_idents(_))|*) }
+    };
+    quote! {
+        fn is_diffable(&self) -> bool {
+            #is_diffable_body
+        }
+
+        fn diff(&self, new: &Self) -> #shared_crate_name::_reexport::anyhow::Result<Vec<u8>> {
+            match (self, new) {
+                #((Self::#delta_idents(base), Self::#delta_idents(new)) => serialize_diff(base, new),)*
+                _ => Err(#shared_crate_name::_reexport::anyhow::anyhow!(
+                    "cannot compute a delta from {:?} to {:?}", self, new
+                )),
+            }
+        }
+
+        fn apply_diff(&mut self, delta: &[u8]) -> #shared_crate_name::_reexport::anyhow::Result<()> {
+            match self {
+                #(Self::#delta_idents(base) => apply_serialized_diff(base, delta),)*
+                _ => Err(#shared_crate_name::_reexport::anyhow::anyhow!(
+                    "{:?} is not replicated with delta-compression", self
+                )),
+            }
+        }
+    }
+}
+
 fn encode_method() -> TokenStream {
     quote! {
         fn encode(&self, writer: &mut impl WriteBuffer) -> anyhow::Result<()> {
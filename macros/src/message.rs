@@ -1,4 +1,4 @@
-use crate::shared::generate_unique_ident;
+use crate::shared::{convert_serialize_attributes, generate_unique_ident};
 use darling::ast::NestedMeta;
 use darling::util::PathList;
 use darling::{Error, FromDeriveInput, FromMeta};
@@ -7,7 +7,7 @@ use quote::{format_ident, quote};
 use std::ops::Deref;
 use syn::{
     parse_macro_input, parse_quote, parse_quote_spanned, DeriveInput, Field, Fields, GenericParam,
-    Generics, ItemEnum, LifetimeParam, LitStr,
+    Generics, ItemEnum, LifetimeParam, LitStr, Variant,
 };
 
 #[derive(Debug, FromDeriveInput)]
@@ -123,6 +123,20 @@ pub fn message_protocol_impl(
 
     // Helper Properties
     let fields = get_fields(&input);
+    let resources = input
+        .variants
+        .iter_mut()
+        .map(take_resource_attribute)
+        .collect::<Vec<_>>();
+    let serializers = match input
+        .variants
+        .iter_mut()
+        .map(|variant| convert_serialize_attributes(variant, &shared_crate_name))
+        .collect::<syn::Result<Vec<_>>>()
+    {
+        Ok(serializers) => serializers,
+        Err(e) => return e.to_compile_error().into(),
+    };
 
     // Names
     let enum_name = &input.ident;
@@ -137,8 +151,10 @@ pub fn message_protocol_impl(
     let message_kind_method = message_kind_method(&input, &fields);
     let input_message_kind_method = input_message_kind_method(&input);
     let add_events_method = add_events_method(&fields);
+    let add_resource_systems_methods = add_resource_systems_methods(&fields, &resources, protocol);
     let push_message_events_method = push_message_events_method(&fields, protocol);
-    let name_method = name_method(&input, &fields);
+    let name_method = name_method(&input, &fields, &resources);
+    let schema_method = schema_method(&fields, &serializers);
     let map_entities_impl = map_entities_impl(&input);
     let encode_method = encode_method();
     let decode_method = decode_method();
@@ -153,6 +169,7 @@ pub fn message_protocol_impl(
             use #shared_crate_name::_reexport::*;
             use #shared_crate_name::prelude::*;
             use #shared_crate_name::shared::events::systems::push_message_events;
+            use #shared_crate_name::shared::replication::resources::{add_resource_send_systems, add_resource_receive_systems, ResourceMessage};
 
             #[derive(Serialize, Deserialize, Clone, PartialEq)]
             #extra_derives
@@ -162,9 +179,11 @@ pub fn message_protocol_impl(
                 type Protocol = #protocol;
 
                 #name_method
+                #schema_method
                 #message_kind_method
                 #input_message_kind_method
                 #add_events_method
+                #add_resource_systems_methods
                 #push_message_events_method
             }
 
@@ -328,15 +347,75 @@ fn add_events_method(fields: &Vec<Field>) -> TokenStream {
     }
 }
 
-fn name_method(input: &ItemEnum, fields: &Vec<Field>) -> TokenStream {
+/// Returns true if the variant is marked with the `#[resource]` attribute, which means that it is a
+/// `ReplicateResource<R>` message used to replicate the resource `R`.
+///
+/// The `#[resource]` attribute is removed from the variant.
+fn take_resource_attribute(variant: &mut Variant) -> bool {
+    let len = variant.attrs.len();
+    variant
+        .attrs
+        .retain(|attr| !attr.path().is_ident("resource"));
+    variant.attrs.len() != len
+}
+
+/// Stable name of a `#[resource]` message, generated from the type written in the protocol
+fn resource_message_name(field: &Field) -> String {
+    let ty = &field.ty;
+    quote! {#ty}.to_string().replace(' ', "")
+}
+
+fn add_resource_systems_methods(
+    fields: &[Field],
+    resources: &[bool],
+    protocol_name: &Ident,
+) -> TokenStream {
+    let mut send_body = quote! {};
+    let mut receive_body = quote! {};
+    for field in fields
+        .iter()
+        .zip(resources)
+        .filter_map(|(field, resource)| resource.then_some(field))
+    {
+        let message_type = &field.ty;
+        let resource_type = quote! {<#message_type as ResourceMessage>::Resource};
+        send_body = quote! {
+            #send_body
+            add_resource_send_systems::<#protocol_name, #resource_type>(app);
+        };
+        receive_body = quote! {
+            #receive_body
+            add_resource_receive_systems::<#protocol_name, #resource_type>(app);
+        };
+    }
+    quote! {
+        fn add_resource_send_systems(app: &mut App) {
+            #send_body
+        }
+        fn add_resource_receive_systems(app: &mut App) {
+            #receive_body
+        }
+    }
+}
+
+fn name_method(input: &ItemEnum, fields: &[Field], resources: &[bool]) -> TokenStream {
     let enum_name = &input.ident;
     let mut body = quote! {};
-    for field in fields.iter() {
+    for (field, resource) in fields.iter().zip(resources) {
         let ident = &field.ident;
-        body = quote! {
-            #body
-            &#enum_name::#ident(ref x) => x.name(),
-        };
+        if *resource {
+            // include the resource in the name, so that the messages for different resources can be told apart
+            let name = resource_message_name(field);
+            body = quote! {
+                #body
+                &#enum_name::#ident(_) => #name,
+            };
+        } else {
+            body = quote! {
+                #body
+                &#enum_name::#ident(ref x) => x.name(),
+            };
+        }
     }
 
     quote! {
@@ -348,6 +427,24 @@ fn name_method(input: &ItemEnum, fields: &Vec<Field>) -> TokenStream {
     }
 }
 
+fn schema_method(fields: &[Field], serializers: &[Option<String>]) -> TokenStream {
+    let entries = fields.iter().zip(serializers).map(|(field, serializer)| {
+        let ident = &field.ident;
+        let ty = &field.ty;
+        let entry = quote! {#ident(#ty)}.to_string();
+        // the serializer changes the encoding of the message
+        match serializer {
+            Some(serializer) => format!("{} with={}", entry, serializer),
+            None => entry,
+        }
+    });
+    quote! {
+        fn schema() -> &'static [&'static str] {
+            &[#(#entries),*]
+        }
+    }
+}
+
 fn map_entities_impl(input: &ItemEnum) -> TokenStream {
     let enum_name = &input.ident;
     let variants = input.variants.iter().map(|v| v.ident.clone());
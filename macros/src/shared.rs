@@ -1,5 +1,5 @@
-use proc_macro2::{Ident, Span};
-use syn::{Data, DeriveInput, Fields};
+use proc_macro2::{Ident, Span, TokenStream};
+use syn::{parse_quote, Data, DeriveInput, Fields, LitStr, Variant};
 
 pub enum StructType {
     Struct,
@@ -25,3 +25,68 @@ pub(crate) fn generate_unique_ident(prefix: &str) -> Ident {
 
     Ident::new(&ident, Span::call_site())
 }
+
+/// Convert the `#[serialize(...)]` attribute of a protocol enum variant into the corresponding serde attribute:
+/// - `#[serialize(with = "MySerializer")]`: the variant is serialized with `MySerializer`, which implements `SerializeWith<T>`
+/// - `#[serialize(bitcode)]`: the variant is serialized with its bitcode `Encode`/`Decode` implementation
+///
+/// The `#[serialize(...)]` attribute is removed from the variant.
+/// Returns the path of the serializer (to include it in the protocol schema), or an error
+/// (to be emitted as a compile error) if the attribute is malformed.
+pub(crate) fn convert_serialize_attributes(
+    variant: &mut Variant,
+    shared_crate_name: &TokenStream,
+) -> syn::Result<Option<String>> {
+    let mut serializer: Option<String> = None;
+    let mut error: Option<syn::Error> = None;
+    variant.attrs.retain(|attr| {
+        if !attr.path().is_ident("serialize") {
+            return true;
+        }
+        let parsed = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("with") {
+                let path: LitStr = meta.value()?.parse()?;
+                serializer = Some(path.value());
+                Ok(())
+            } else if meta.path.is_ident("bitcode") {
+                serializer = Some(format!(
+                    "{}::serialize::custom::BitcodeSerializer",
+                    shared_crate_name
+                ));
+                Ok(())
+            } else {
+                Err(meta.error("expected `with = \"...\"` or `bitcode`"))
+            }
+        });
+        if let Err(e) = parsed {
+            match error.as_mut() {
+                Some(error) => error.combine(e),
+                None => error = Some(e),
+            }
+        }
+        false
+    });
+    if let Some(error) = error {
+        return Err(error);
+    }
+    if let Some(serializer) = &serializer {
+        let serialize_with = LitStr::new(
+            &format!(
+                "{}::serialize::custom::serialize_with::<{}, _, _>",
+                shared_crate_name, serializer
+            ),
+            Span::call_site(),
+        );
+        let deserialize_with = LitStr::new(
+            &format!(
+                "{}::serialize::custom::deserialize_with::<{}, _, _>",
+                shared_crate_name, serializer
+            ),
+            Span::call_site(),
+        );
+        variant.attrs.push(parse_quote! {
+            #[serde(serialize_with = #serialize_with, deserialize_with = #deserialize_with)]
+        });
+    }
+    Ok(serializer)
+}